@@ -0,0 +1,268 @@
+// lighting.rs -- Flux FOV example
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use flux_fov::{
+    kernel::{self, Illumination},
+    FluxField, Fov, Smoke, SmokeCloud,
+};
+use macroquad::prelude::*;
+use std::rc::Rc;
+
+const TILE_SIZE: f32 = 24.0;
+const LIGHT_RADIUS: usize = 12;
+
+const MAP: &[&str] = &[
+    "########################################",
+    "#..........#...........#...............#",
+    "#..........#...........#...............#",
+    "#..........#.....#.....#.....######....#",
+    "#................#...........#....#....#",
+    "#..........#.....#.....#.....#....#....#",
+    "#..........#.....#.....#.....##.###....#",
+    "######.#####.....#######...............#",
+    "#..........#...........#...............#",
+    "#..........#...........#....#.....#....#",
+    "#.....#....######.######...............#",
+    "#.....#................................#",
+    "#.....#................................#",
+    "#.....##########.......######.####.....#",
+    "#..............#.......#.........#.....#",
+    "#..............#.......#.........#.....#",
+    "#..............#.......#.........#.....#",
+    "#......................#...............#",
+    "#..............#.......#.........#.....#",
+    "########################################",
+];
+
+// A torch burning at a fixed map position.
+struct Torch {
+    x: i32,
+    y: i32,
+    color: Vec3,
+    // The phase of the flicker so that the torches do not pulse in unison.
+    phase: f32,
+}
+
+struct Map {
+    w: i32,
+    h: i32,
+    walls: Vec<bool>,
+}
+
+impl Map {
+    fn parse(rows: &[&str]) -> Self {
+        let w = rows[0].len() as i32;
+        let h = rows.len() as i32;
+        let walls = rows
+            .iter()
+            .flat_map(|row| row.bytes().map(|b| b == b'#'))
+            .collect();
+        Map { w, h, walls }
+    }
+
+    fn contains(&self, x: i32, y: i32) -> bool {
+        0 <= x && x < self.w && 0 <= y && y < self.h
+    }
+
+    fn is_wall(&self, x: i32, y: i32) -> bool {
+        !self.contains(x, y) || self.walls[(y * self.w + x) as usize]
+    }
+}
+
+// The lighting stack: soft shadows cast through the walls and the smoke,
+// attenuated with a smooth falloff towards the edge of the light radius, and
+// accumulated into the per-tile `light` in the color of the light.
+fn add_light(
+    light: &mut [Vec3],
+    fov: &mut Fov<Illumination, Rc<FluxField>>,
+    map: &Map,
+    smoke: &Smoke,
+    (x0, y0): (i32, i32),
+    color: Vec3,
+) {
+    let opacity = |x: i32, y: i32| {
+        let (map_x, map_y) = (x0 + x, y0 + y);
+        if map.is_wall(map_x, map_y) {
+            1.0
+        } else {
+            smoke.opacity(map_x, map_y)
+        }
+    };
+    fov.update(kernel::soft_shadow(opacity));
+    let radius = LIGHT_RADIUS as i32;
+    let radius_sq = (radius * radius) as f32;
+    for y in -radius..=radius {
+        for x in -radius..=radius {
+            let (map_x, map_y) = (x0 + x, y0 + y);
+            if !map.contains(map_x, map_y) {
+                continue;
+            }
+            let t = 1.0 - (x * x + y * y) as f32 / radius_sq;
+            if t <= 0.0 {
+                continue;
+            }
+            let brightness = fov.at(x, y).brightness.min(1.0);
+            light[(map_y * map.w + map_x) as usize] += color * brightness * t * t;
+        }
+    }
+}
+
+#[macroquad::main("Flux FOV lighting")]
+async fn main() {
+    let map = Map::parse(MAP);
+    let flux_field = Rc::new(FluxField::new(LIGHT_RADIUS));
+    let mut fov = Fov::new(flux_field, LIGHT_RADIUS, Illumination::default());
+    let torches = [
+        Torch {
+            x: 5,
+            y: 4,
+            color: vec3(1.0, 0.55, 0.2),
+            phase: 0.0,
+        },
+        Torch {
+            x: 20,
+            y: 2,
+            color: vec3(0.3, 0.5, 1.0),
+            phase: 1.3,
+        },
+        Torch {
+            x: 32,
+            y: 4,
+            color: vec3(0.3, 1.0, 0.4),
+            phase: 2.1,
+        },
+        Torch {
+            x: 10,
+            y: 15,
+            color: vec3(1.0, 0.3, 0.8),
+            phase: 3.7,
+        },
+        Torch {
+            x: 28,
+            y: 15,
+            color: vec3(1.0, 0.9, 0.5),
+            phase: 5.2,
+        },
+    ];
+    let mut smoke = Smoke::new(0.02);
+    let (mut player_x, mut player_y) = (20, 11);
+    let mut light = vec![Vec3::ZERO; (map.w * map.h) as usize];
+    let mut pixels = vec![0; light.len() * 4];
+
+    loop {
+        if is_key_pressed(KeyCode::Escape) {
+            break;
+        }
+        let (dx, dy) = if is_key_pressed(KeyCode::Left) {
+            (-1, 0)
+        } else if is_key_pressed(KeyCode::Right) {
+            (1, 0)
+        } else if is_key_pressed(KeyCode::Up) {
+            (0, -1)
+        } else if is_key_pressed(KeyCode::Down) {
+            (0, 1)
+        } else {
+            (0, 0)
+        };
+        if (dx, dy) != (0, 0) {
+            if !map.is_wall(player_x + dx, player_y + dy) {
+                player_x += dx;
+                player_y += dy;
+            }
+            smoke.advance();
+        }
+        if is_key_pressed(KeyCode::F) {
+            smoke.add(SmokeCloud {
+                center: (player_x, player_y),
+                radius: 1.0,
+                density: 0.35,
+                growth: 0.5,
+                max_radius: 4.0,
+                decay: 0.9,
+            });
+        }
+
+        let time = get_time() as f32;
+        for cell in light.iter_mut() {
+            *cell = Vec3::ZERO;
+        }
+        for torch in torches.iter() {
+            let flicker = 0.85 + 0.1 * (time * 7.0 + torch.phase).sin();
+            let color = torch.color * flicker;
+            add_light(
+                &mut light,
+                &mut fov,
+                &map,
+                &smoke,
+                (torch.x, torch.y),
+                color,
+            );
+        }
+        // A dim lantern carried by the player.
+        let lantern = vec3(0.5, 0.5, 0.45);
+        add_light(
+            &mut light,
+            &mut fov,
+            &map,
+            &smoke,
+            (player_x, player_y),
+            lantern,
+        );
+
+        // One texel per tile; the linear filtering of the texture smooths
+        // the light between the tiles.
+        for y in 0..map.h {
+            for x in 0..map.w {
+                let ix = (y * map.w + x) as usize;
+                let base = if map.is_wall(x, y) {
+                    vec3(0.55, 0.5, 0.45)
+                } else {
+                    vec3(0.3, 0.28, 0.25)
+                };
+                let fog = vec3(0.6, 0.6, 0.65) * smoke.opacity(x, y);
+                let lit = (base + fog) * light[ix];
+                pixels[ix * 4] = (lit.x.min(1.0) * 255.0) as u8;
+                pixels[ix * 4 + 1] = (lit.y.min(1.0) * 255.0) as u8;
+                pixels[ix * 4 + 2] = (lit.z.min(1.0) * 255.0) as u8;
+                pixels[ix * 4 + 3] = 255;
+            }
+        }
+        let texture = Texture2D::from_rgba8(map.w as u16, map.h as u16, &pixels);
+        texture.set_filter(FilterMode::Linear);
+
+        clear_background(BLACK);
+        let params = DrawTextureParams {
+            dest_size: Some(vec2(map.w as f32 * TILE_SIZE, map.h as f32 * TILE_SIZE)),
+            ..Default::default()
+        };
+        draw_texture_ex(&texture, 0.0, 0.0, WHITE, params);
+        draw_rectangle(
+            (player_x as f32 + 0.25) * TILE_SIZE,
+            (player_y as f32 + 0.25) * TILE_SIZE,
+            TILE_SIZE * 0.5,
+            TILE_SIZE * 0.5,
+            WHITE,
+        );
+        draw_text(
+            "arrows: move   f: smoke   esc: quit",
+            8.0,
+            map.h as f32 * TILE_SIZE + 20.0,
+            20.0,
+            GRAY,
+        );
+        next_frame().await;
+    }
+}
@@ -0,0 +1,211 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{FluxField, Fov};
+
+/// How a stimulus spreads; see `AlertnessMap::add_stimulus`.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Spread {
+    /// The fraction of the alertness left per cell of distance travelled.
+    pub decay: f32,
+    /// The fraction of the alertness passing through an opaque cell, e.g.,
+    /// 0.0 for a sighting and more for a noise heard through the walls.
+    /// Partially opaque cells pass on a proportional part.
+    pub wall_transmission: f32,
+}
+
+/// A map-sized grid of suspicion spread outward from the stimuli the AI has
+/// noticed, such as noises and sighted positions.
+///
+/// Each tile holds the strongest alertness reaching it from any stimulus.
+/// The alertness fades each turn with `fade`, and the AI can walk up the
+/// gradient towards the source with `climb`:
+///
+/// ```
+/// # use flux_fov::{AlertnessMap, FluxField, Fov, Spread};
+/// let mut field = Fov::new(Box::new(FluxField::new(8)), 8, 0.0_f32);
+/// let mut map = AlertnessMap::new(20, 10);
+/// let spread = Spread {
+///     decay: 0.9,
+///     wall_transmission: 0.25,
+/// };
+/// map.add_stimulus(12, 5, 1.0, &spread, &mut field, |_, _| 0.0);
+/// assert_eq!(map.climb(8, 5), Some((9, 5)));
+/// map.fade(0.5);
+/// assert_eq!(map.at(12, 5), 0.5);
+/// ```
+///
+pub struct AlertnessMap {
+    width: usize,
+    height: usize,
+    alertness: Vec<f32>,
+}
+
+impl AlertnessMap {
+    /// Constructs a `width` by `height` map with no alertness.
+    ///
+    pub fn new(width: usize, height: usize) -> Self {
+        AlertnessMap {
+            width,
+            height,
+            alertness: vec![0.0; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The alertness at the given tile.
+    pub fn at(&self, x: usize, y: usize) -> f32 {
+        assert!(x < self.width && y < self.height, "tile out of range");
+        self.alertness[y * self.width + x]
+    }
+
+    /// Expose the alertness of the tiles as a slice in row-major order.
+    pub fn as_slice(&self) -> &[f32] {
+        self.alertness.as_slice()
+    }
+
+    /// Spread a stimulus of the given `intensity` from the tile (`x`, `y`)
+    /// over the tiles within the radius of `field`.
+    ///
+    /// The alertness travels along the flux losing all but `spread.decay`
+    /// per cell of Euclidean distance and, in the cells with the `opacity`
+    /// (in map coordinates) of 1.0, all but `spread.wall_transmission`.  The
+    /// `field` is only scratch space for the spread.
+    ///
+    pub fn add_stimulus<X, O>(
+        &mut self,
+        x: i32,
+        y: i32,
+        intensity: f32,
+        spread: &Spread,
+        field: &mut Fov<f32, X>,
+        opacity: O,
+    ) where
+        X: AsRef<FluxField>,
+        O: Fn(i32, i32) -> f32,
+    {
+        let loss = 1.0 - spread.wall_transmission;
+        field.update(|dx, dy, influxes| {
+            if dx == 0 && dy == 0 {
+                1.0
+            } else {
+                let input: f32 = influxes.iter().map(|f| f.weight * f.value).sum();
+                input * (1.0 - loss * opacity(x + dx, y + dy))
+            }
+        });
+        // Apply the decay by the distance so that the alertness rises
+        // strictly towards the source for `climb` to follow.
+        let radius = field.radius() as i32;
+        for dy in -radius..radius + 1 {
+            let map_y = y + dy;
+            if map_y < 0 || map_y >= self.height as i32 {
+                continue;
+            }
+            for dx in -radius..radius + 1 {
+                let map_x = x + dx;
+                if map_x < 0 || map_x >= self.width as i32 {
+                    continue;
+                }
+                let distance = ((dx * dx + dy * dy) as f32).sqrt();
+                let value = intensity * field.at(dx, dy) * spread.decay.powf(distance);
+                let a = &mut self.alertness[map_y as usize * self.width + map_x as usize];
+                *a = a.max(value);
+            }
+        }
+    }
+
+    /// Multiply the alertness of every tile by `factor`, e.g., once per turn.
+    ///
+    pub fn fade(&mut self, factor: f32) {
+        for a in self.alertness.iter_mut() {
+            *a *= factor;
+        }
+    }
+
+    /// The neighbor of the tile (`x`, `y`), diagonals included, with the
+    /// highest alertness if higher than that of the tile itself, i.e., the
+    /// next step towards the source of the suspicion.
+    ///
+    pub fn climb(&self, x: i32, y: i32) -> Option<(i32, i32)> {
+        let mut best = self.at(x as usize, y as usize);
+        let mut step = None;
+        for dy in -1..2 {
+            for dx in -1..2 {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= self.width as i32 || ny >= self.height as i32 {
+                    continue;
+                }
+                let a = self.at(nx as usize, ny as usize);
+                if a > best {
+                    best = a;
+                    step = Some((nx, ny));
+                }
+            }
+        }
+        step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn alertness_spreads_through_walls_weakened() {
+        let flux_field = Rc::new(FluxField::new(6));
+        let mut field = Fov::new(flux_field, 6, 0.0);
+        let wall = |x: i32, _| if x == 6 { 1.0 } else { 0.0 };
+        let mut map = AlertnessMap::new(12, 7);
+        let sighting = Spread {
+            decay: 1.0,
+            wall_transmission: 0.0,
+        };
+        map.add_stimulus(3, 3, 1.0, &sighting, &mut field, wall);
+        assert!((map.at(5, 3) - 1.0).abs() < 1e-5);
+        assert_eq!(map.at(7, 3), 0.0);
+        let noise = Spread {
+            decay: 0.8,
+            wall_transmission: 0.5,
+        };
+        map.add_stimulus(3, 3, 1.0, &noise, &mut field, wall);
+        // The stronger stimulus wins.
+        assert!((map.at(5, 3) - 1.0).abs() < 1e-5);
+        let behind = 0.8_f32.powi(4) * 0.5;
+        assert!((map.at(7, 3) - behind).abs() < 1e-5);
+        assert_eq!(map.climb(8, 3), Some((7, 3)));
+        assert_eq!(map.climb(3, 3), None);
+        map.fade(0.5);
+        assert!((map.at(7, 3) - behind * 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    #[should_panic(expected = "tile out of range")]
+    fn at_out_of_range() {
+        // Within the map but past the right edge of the first row.
+        let map = AlertnessMap::new(4, 3);
+        map.at(4, 0);
+    }
+}
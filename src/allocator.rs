@@ -0,0 +1,115 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! The allocators of the buffers of the fields of vision; see
+//! `Fov::new_in`.
+//!
+//! With the `allocator-api` feature these are the `Allocator` trait and the
+//! `Global` allocator of the `allocator-api2` crate, so a buffer can live in
+//! any allocator implementing the trait, e.g., a frame arena.  Without the
+//! feature the global allocator is the only one.
+
+#[cfg(feature = "allocator-api")]
+pub use allocator_api2::alloc::{Allocator, Global};
+
+// The buffer of a field of vision.
+#[cfg(feature = "allocator-api")]
+pub(crate) type Buffer<T, A = Global> = allocator_api2::vec::Vec<T, A>;
+
+#[cfg(not(feature = "allocator-api"))]
+pub use self::global::{Allocator, Global};
+
+#[cfg(not(feature = "allocator-api"))]
+pub(crate) use self::global::Buffer;
+
+#[cfg(not(feature = "allocator-api"))]
+mod global {
+
+    use std::marker::PhantomData;
+    use std::ops::{Deref, DerefMut};
+
+    /// An allocator for the buffers of the fields of vision.
+    ///
+    /// Enable the `allocator-api` feature for allocators other than the
+    /// global one.
+    ///
+    pub trait Allocator: private::Sealed {}
+
+    /// The global allocator.
+    ///
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct Global;
+
+    impl Allocator for Global {}
+
+    impl private::Sealed for Global {}
+
+    mod private {
+        pub trait Sealed {}
+    }
+
+    // The buffer of a field of vision; a standard vector as there is only
+    // the global allocator.
+    pub(crate) struct Buffer<T, A = Global> {
+        vec: Vec<T>,
+        alloc: PhantomData<A>,
+    }
+
+    impl<T, A> Buffer<T, A> {
+        pub(crate) fn with_capacity_in(capacity: usize, _alloc: A) -> Self {
+            Buffer {
+                vec: Vec::with_capacity(capacity),
+                alloc: PhantomData,
+            }
+        }
+    }
+
+    impl<T> Buffer<T, Global> {
+        pub(crate) fn with_capacity(capacity: usize) -> Self {
+            Buffer::with_capacity_in(capacity, Global)
+        }
+
+        pub(crate) unsafe fn from_raw_parts(ptr: *mut T, len: usize, capacity: usize) -> Self {
+            Buffer {
+                vec: Vec::from_raw_parts(ptr, len, capacity),
+                alloc: PhantomData,
+            }
+        }
+    }
+
+    impl<T: Clone, A> Clone for Buffer<T, A> {
+        fn clone(&self) -> Self {
+            Buffer {
+                vec: self.vec.clone(),
+                alloc: PhantomData,
+            }
+        }
+    }
+
+    impl<T, A> Deref for Buffer<T, A> {
+        type Target = Vec<T>;
+
+        fn deref(&self) -> &Vec<T> {
+            &self.vec
+        }
+    }
+
+    impl<T, A> DerefMut for Buffer<T, A> {
+        fn deref_mut(&mut self) -> &mut Vec<T> {
+            &mut self.vec
+        }
+    }
+}
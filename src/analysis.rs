@@ -0,0 +1,384 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Measuring the approximation error of flux based fields of vision.
+//!
+//! The flux fields approximate the rays cast from the origin and hence the
+//! visibility they give differs somewhat from that of casting a single exact
+//! ray from the center of the origin to the center of each cell.  This
+//! module casts the exact rays on the same map and reports how much and
+//! where the two differ, e.g., for tuning the flux field parameters and the
+//! kernels.
+//!
+//! It also measures the typical artifacts of the flux fields themselves,
+//! asymmetry, banding, and seams between the octants, so that changes to the
+//! construction of the look-up tables can be evaluated objectively.
+
+use crate::{FluxField, Fov};
+
+/// The fraction of light reaching the cell `(x, y)` along the exact ray from
+/// the center of the origin to the center of the cell.
+///
+/// The light passes the fraction `1.0 - opacity` through each cell whose
+/// interior the ray crosses, excluding the origin and the cell itself.  A
+/// ray passing exactly through a corner touches the two cells beside the
+/// corner only at a point and passes between them.
+///
+pub fn transmittance<F>(x: i32, y: i32, opacity: F) -> f32
+where
+    F: Fn(i32, i32) -> f32,
+{
+    let mut light = 1.0;
+    for (cx, cy) in ray_cells(x, y) {
+        if (cx, cy) == (x, y) {
+            break;
+        }
+        light *= 1.0 - opacity(cx, cy);
+    }
+    light
+}
+
+// The cells whose interior the exact ray from the center of the origin to the
+// center of the cell `(x, y)` crosses in order, excluding the origin and
+// including the cell itself.
+pub(crate) fn ray_cells(x: i32, y: i32) -> impl Iterator<Item = (i32, i32)> {
+    let (sx, sy) = (x.signum(), y.signum());
+    let (ax, ay) = (x.unsigned_abs() as i64, y.unsigned_abs() as i64);
+    let (mut ix, mut iy) = (0, 0);
+    std::iter::from_fn(move || {
+        if ix == ax && iy == ay {
+            return None;
+        }
+        // The ray leaves the current cell through its side x = ix + 0.5 at
+        // t = (2 ix + 1) / (2 ax) and through its side y = iy + 0.5 at t =
+        // (2 iy + 1) / (2 ay).  Compare the two without dividing.
+        let x_side = (2 * ix + 1) * ay;
+        let y_side = (2 * iy + 1) * ax;
+        if x_side <= y_side {
+            ix += 1;
+        }
+        if y_side <= x_side {
+            iy += 1;
+        }
+        Some((sx * ix as i32, sy * iy as i32))
+    })
+}
+
+/// The per-cell divergence of a flux based field of vision from exact
+/// raycasting.
+///
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    radius: i32,
+    exact: Vec<f32>,
+    errors: Vec<f32>,
+}
+
+/// The summary statistics of a `Divergence`.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Summary {
+    /// The mean of the signed errors; positive if the flux field sees more
+    /// than the exact rays.
+    pub bias: f32,
+    /// The mean of the absolute errors.
+    pub mean_abs: f32,
+    /// The root mean square of the errors.
+    pub rms: f32,
+    /// The largest absolute error.
+    pub max_abs: f32,
+}
+
+impl Divergence {
+    /// The radius of the compared field.
+    pub fn radius(&self) -> usize {
+        self.radius as usize
+    }
+
+    /// The exact visibility of the cell.
+    pub fn exact(&self, x: i32, y: i32) -> f32 {
+        self.exact[self.index(x, y)]
+    }
+
+    /// The error of the cell, i.e., the flux based visibility minus the exact
+    /// one.
+    pub fn at(&self, x: i32, y: i32) -> f32 {
+        self.errors[self.index(x, y)]
+    }
+
+    /// The cells whose absolute error exceeds `tolerance` with their errors.
+    ///
+    pub fn exceeding(&self, tolerance: f32) -> Vec<(i32, i32, f32)> {
+        let width = 2 * self.radius + 1;
+        self.errors
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.abs() > tolerance)
+            .map(|(ix, &e)| {
+                let ix = ix as i32;
+                (ix % width - self.radius, ix / width - self.radius, e)
+            })
+            .collect()
+    }
+
+    /// The summary statistics of the errors over all the cells.
+    ///
+    pub fn summary(&self) -> Summary {
+        let n = self.errors.len() as f32;
+        let mut sum = 0.0;
+        let mut sum_abs = 0.0;
+        let mut sum_sq = 0.0;
+        let mut max_abs: f32 = 0.0;
+        for &e in &self.errors {
+            sum += e;
+            sum_abs += e.abs();
+            sum_sq += e * e;
+            max_abs = max_abs.max(e.abs());
+        }
+        Summary {
+            bias: sum / n,
+            mean_abs: sum_abs / n,
+            rms: (sum_sq / n).sqrt(),
+            max_abs,
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        assert!(
+            x.abs() <= self.radius && y.abs() <= self.radius,
+            "cell out of range"
+        );
+        ((y + self.radius) * (2 * self.radius + 1) + x + self.radius) as usize
+    }
+}
+
+/// Compare a flux based field of vision against exact raycasting on the same
+/// map.
+///
+/// The `opacity` is the map relative to the origin of the field as for the
+/// kernels and `visibility` extracts the fraction of light reaching a cell,
+/// from 0.0 to 1.0, from its value, e.g., the brightness of
+/// `kernel::soft_shadow` or 0.0 or 1.0 from the visibility of
+/// `kernel::threshold`.
+///
+pub fn compare<T, X, O, V>(fov: &Fov<T, X>, opacity: O, visibility: V) -> Divergence
+where
+    X: AsRef<FluxField>,
+    O: Fn(i32, i32) -> f32,
+    V: Fn(&T) -> f32,
+{
+    let radius = fov.radius() as i32;
+    let mut exact = Vec::with_capacity(fov.as_slice().len());
+    let mut errors = Vec::with_capacity(fov.as_slice().len());
+    for y in -radius..radius + 1 {
+        for x in -radius..radius + 1 {
+            let e = transmittance(x, y, &opacity);
+            exact.push(e);
+            errors.push(visibility(fov.at(x, y)) - e);
+        }
+    }
+    Divergence {
+        radius,
+        exact,
+        errors,
+    }
+}
+
+/// The number of cells whose value differs by more than `tolerance` from
+/// the value of any of its mirror images across the axes and the diagonals.
+///
+/// On a map that is symmetric about the origin, e.g., an open one, the field
+/// should be symmetric as well and every such cell is an artifact.
+///
+pub fn asymmetry_count<T, X, V>(fov: &Fov<T, X>, value: V, tolerance: f32) -> usize
+where
+    X: AsRef<FluxField>,
+    V: Fn(&T) -> f32,
+{
+    let radius = fov.radius() as i32;
+    let mut count = 0;
+    for y in -radius..radius + 1 {
+        for x in -radius..radius + 1 {
+            let v = value(fov.at(x, y));
+            let images = [
+                (-x, y),
+                (x, -y),
+                (-x, -y),
+                (y, x),
+                (-y, x),
+                (y, -x),
+                (-y, -x),
+            ];
+            if images
+                .iter()
+                .any(|&(ix, iy)| (value(fov.at(ix, iy)) - v).abs() > tolerance)
+            {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+/// The variance of the ring-to-ring changes in the mean value of the rings
+/// of cells around the origin.
+///
+/// The rings are the cells at the same Chebyshev distance from the origin.
+/// A smooth falloff changes the ring means steadily and has little variance
+/// whereas banding makes them alternate.  Zero for fields of radius below 2.
+///
+pub fn ring_to_ring_variance<T, X, V>(fov: &Fov<T, X>, value: V) -> f32
+where
+    X: AsRef<FluxField>,
+    V: Fn(&T) -> f32,
+{
+    let radius = fov.radius() as i32;
+    let means: Vec<f32> = (0..radius + 1)
+        .map(|k| {
+            let (sum, n) = crate::query::ring_cells(k).fold((0.0, 0), |(sum, n), (x, y)| {
+                (sum + value(fov.at(x, y)), n + 1)
+            });
+            sum / n as f32
+        })
+        .collect();
+    let changes: Vec<f32> = means.windows(2).map(|w| w[1] - w[0]).collect();
+    if changes.len() < 2 {
+        return 0.0;
+    }
+    let n = changes.len() as f32;
+    let mean = changes.iter().sum::<f32>() / n;
+    changes.iter().map(|c| (c - mean) * (c - mean)).sum::<f32>() / n
+}
+
+/// The mean discontinuity at the seams between the octants.
+///
+/// The cells on the axes and the diagonals are updated differently from the
+/// interior cells of the octants and differences between the two show up as
+/// straight lines radiating from the origin.  The discontinuity of a seam
+/// cell is the absolute difference between its value and the mean of its
+/// two neighbors in the same ring.  The cells next to the origin have no
+/// interior neighbors and are left out.  Zero for fields of radius below 2.
+///
+pub fn seam_discontinuity<T, X, V>(fov: &Fov<T, X>, value: V) -> f32
+where
+    X: AsRef<FluxField>,
+    V: Fn(&T) -> f32,
+{
+    let radius = fov.radius() as i32;
+    let v = |x, y| value(fov.at(x, y));
+    let mut sum = 0.0;
+    let mut n = 0;
+    for k in 2..radius + 1 {
+        for &(sx, sy) in &[(1, 1), (-1, 1), (1, -1), (-1, -1)] {
+            // The axes, each visited twice, and the diagonals.
+            let (x, y) = (sx * k, 0);
+            sum += (v(x, y) - (v(x, -1) + v(x, 1)) / 2.0).abs();
+            let (x, y) = (0, sy * k);
+            sum += (v(x, y) - (v(-1, y) + v(1, y)) / 2.0).abs();
+            let (x, y) = (sx * k, sy * k);
+            sum += (v(x, y) - (v(x - sx, y) + v(x, y - sy)) / 2.0).abs();
+            n += 3;
+        }
+    }
+    if n == 0 {
+        0.0
+    } else {
+        sum / n as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel;
+
+    #[test]
+    fn artifact_metrics() {
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, Default::default());
+        fov.update(kernel::soft_shadow(|_, _| 0.0));
+        let brightness = |v: &kernel::Illumination| v.brightness;
+        assert_eq!(asymmetry_count(&fov, brightness, 1e-6), 0);
+        assert!(ring_to_ring_variance(&fov, brightness) < 1e-10);
+        assert!(seam_discontinuity(&fov, brightness) < 1e-6);
+        let mut fov = Fov::new(Box::new(FluxField::new(4)), 4, (0, 0));
+        fov.update(|x, y, _| (x, y));
+        // Only the origin has the same x coordinate as all its images.
+        assert_eq!(asymmetry_count(&fov, |&(x, _)| x as f32, 0.5), 80);
+        let ring = |&(x, y): &(i32, i32)| x.abs().max(y.abs()) as f32;
+        assert_eq!(asymmetry_count(&fov, ring, 0.0), 0);
+        assert_eq!(ring_to_ring_variance(&fov, ring), 0.0);
+        assert_eq!(ring_to_ring_variance(&fov, |v| ring(v) % 2.0), 1.0);
+        let seam = |&(x, y): &(i32, i32)| (x == 0 || y == 0 || x.abs() == y.abs()) as u8 as f32;
+        assert_eq!(seam_discontinuity(&fov, seam), 1.0);
+        assert_eq!(seam_discontinuity(&fov, ring), 0.0);
+    }
+
+    #[test]
+    fn exact_rays() {
+        let wall = |x, y| if (x, y) == (1, 0) { 1.0 } else { 0.0 };
+        assert_eq!(transmittance(0, 0, wall), 1.0);
+        assert_eq!(transmittance(1, 0, wall), 1.0);
+        assert_eq!(transmittance(3, 0, wall), 0.0);
+        assert_eq!(transmittance(-3, 0, wall), 1.0);
+        assert_eq!(transmittance(2, 1, wall), 0.0);
+        assert_eq!(transmittance(2, 2, wall), 1.0);
+        assert_eq!(transmittance(1, 3, wall), 1.0);
+        let smoke = |x: i32, y: i32| {
+            if x.abs() == 1 || y.abs() == 1 {
+                0.5
+            } else {
+                0.0
+            }
+        };
+        // Through (1, 1) only.
+        assert_eq!(transmittance(-3, -3, smoke), 0.5);
+        // Through (1, 0) and (1, 1).
+        assert_eq!(transmittance(3, 1, smoke), 0.25);
+    }
+
+    #[test]
+    fn open_map_has_no_divergence() {
+        let mut fov = Fov::new(Box::new(FluxField::new(8)), 8, Default::default());
+        fov.update(kernel::soft_shadow(|_, _| 0.0));
+        let divergence = compare(&fov, |_, _| 0.0, |v: &kernel::Illumination| v.brightness);
+        assert!(divergence.summary().max_abs < 1e-5);
+        assert!(divergence.exceeding(1e-5).is_empty());
+    }
+
+    #[test]
+    fn pillar_divergence() {
+        let pillar = |x, y| if (x, y) == (2, 0) { 1.0 } else { 0.0 };
+        let mut fov = Fov::new(Box::new(FluxField::new(8)), 8, Default::default());
+        fov.update(kernel::soft_shadow(pillar));
+        let divergence = compare(&fov, pillar, |v: &kernel::Illumination| v.brightness);
+        assert_eq!(divergence.exact(8, 0), 0.0);
+        assert_eq!(divergence.exact(8, 2), 0.0);
+        assert_eq!(divergence.exact(8, 4), 1.0);
+        assert_eq!(divergence.at(0, 0), 0.0);
+        // The soft shadow spreads the penumbra to the cells the exact rays
+        // see fully and lets some light behind the pillar.
+        let summary = divergence.summary();
+        assert!(summary.max_abs > 0.0 && summary.max_abs <= 1.0);
+        assert!(summary.mean_abs <= summary.rms && summary.rms <= summary.max_abs);
+        let exceeding = divergence.exceeding(0.1);
+        assert!(!exceeding.is_empty());
+        for &(x, y, e) in &exceeding {
+            assert!(x > 2);
+            assert_eq!(divergence.at(x, y), e);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::allocator::Allocator;
+use crate::kernel::{Illumination, Visibility};
+use crate::{FluxField, Fov};
+
+/// A cell value that a field of vision can expose through `AnyFov`.
+///
+/// Implemented for the cell values of the standard kernels and for the
+/// plain masks and light levels.
+///
+pub trait AnyCell {
+    /// The size of the cell in the byte-level export.
+    const BYTES: usize;
+
+    /// Whether the cell counts as visible.
+    fn is_visible(&self) -> bool;
+
+    /// Append the little-endian bytes of the cell to `out`.
+    fn write_bytes(&self, out: &mut Vec<u8>);
+}
+
+impl AnyCell for bool {
+    const BYTES: usize = 1;
+
+    fn is_visible(&self) -> bool {
+        *self
+    }
+
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+}
+
+impl AnyCell for u8 {
+    const BYTES: usize = 1;
+
+    fn is_visible(&self) -> bool {
+        *self > 0
+    }
+
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        out.push(*self);
+    }
+}
+
+impl AnyCell for f32 {
+    const BYTES: usize = 4;
+
+    fn is_visible(&self) -> bool {
+        *self > 0.0
+    }
+
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_le_bytes());
+    }
+}
+
+/// Red, green, and blue light.
+impl AnyCell for [f32; 3] {
+    const BYTES: usize = 12;
+
+    fn is_visible(&self) -> bool {
+        self.iter().any(|&c| c > 0.0)
+    }
+
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        for c in self.iter() {
+            out.extend_from_slice(&c.to_le_bytes());
+        }
+    }
+}
+
+/// Exported as the `is_visible` byte followed by the `ray_output`.
+impl AnyCell for Visibility {
+    const BYTES: usize = 5;
+
+    fn is_visible(&self) -> bool {
+        self.is_visible
+    }
+
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        out.push(self.is_visible as u8);
+        out.extend_from_slice(&self.ray_output.to_le_bytes());
+    }
+}
+
+/// Exported as the `brightness` followed by the `ray_output`.
+impl AnyCell for Illumination {
+    const BYTES: usize = 8;
+
+    fn is_visible(&self) -> bool {
+        self.brightness > 0.0
+    }
+
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.brightness.to_le_bytes());
+        out.extend_from_slice(&self.ray_output.to_le_bytes());
+    }
+}
+
+/// An object-safe view of a field of vision regardless of its cell type,
+/// e.g., for holding masks, light levels, and colored light in a single
+/// `Vec<Box<dyn AnyFov>>`.
+///
+/// The cells are addressed relative to the origin as with `Fov::at`, but
+/// the cells outside the field are simply not visible rather than out of
+/// range.
+///
+/// ```
+/// # use flux_fov::{AnyFov, FluxField, Fov};
+/// # use std::rc::Rc;
+/// let flux_field = Rc::new(FluxField::new(4));
+/// let fields: Vec<Box<dyn AnyFov>> = vec![
+///     Box::new(Fov::new(flux_field.clone(), 4, true)),
+///     Box::new(Fov::new(flux_field.clone(), 2, 0.5_f32)),
+///     Box::new(Fov::new(flux_field, 3, [0.0_f32, 0.0, 0.0])),
+/// ];
+/// let visible: Vec<bool> = fields.iter().map(|f| f.is_visible(2, 2)).collect();
+/// assert_eq!(visible, [true, true, false]);
+/// assert_eq!(fields[1].to_bytes().len(), 5 * 5 * 4);
+/// ```
+///
+pub trait AnyFov {
+    /// The radius of the field of vision.
+    fn radius(&self) -> usize;
+
+    /// The width and the height of the field of vision in cells.
+    fn width(&self) -> usize {
+        2 * self.radius() + 1
+    }
+
+    /// Whether the cell (`x`, `y`) relative to the origin is visible.
+    fn is_visible(&self, x: i32, y: i32) -> bool;
+
+    /// The size of a cell in the byte-level export.
+    fn bytes_per_cell(&self) -> usize;
+
+    /// Append the cells to `out` in row-major order starting from the cell
+    /// (-radius, -radius), each cell in the little-endian layout of its
+    /// type; see `AnyCell`.
+    ///
+    fn write_bytes(&self, out: &mut Vec<u8>);
+
+    /// The cells as bytes; see `write_bytes`.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.width() * self.width() * self.bytes_per_cell());
+        self.write_bytes(&mut out);
+        out
+    }
+}
+
+impl<T, X, A> AnyFov for Fov<T, X, A>
+where
+    T: AnyCell,
+    X: AsRef<FluxField>,
+    A: Allocator,
+{
+    fn radius(&self) -> usize {
+        Fov::radius(self)
+    }
+
+    fn is_visible(&self, x: i32, y: i32) -> bool {
+        let radius = Fov::radius(self) as i32;
+        x.abs() <= radius && y.abs() <= radius && self.at(x, y).is_visible()
+    }
+
+    fn bytes_per_cell(&self) -> usize {
+        T::BYTES
+    }
+
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        out.reserve(self.as_slice().len() * T::BYTES);
+        for cell in self.as_slice() {
+            cell.write_bytes(out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel;
+    use std::rc::Rc;
+
+    #[test]
+    fn heterogeneous_fields_behind_one_trait() {
+        let flux_field = Rc::new(FluxField::new(5));
+        let mut mask = Fov::new(flux_field.clone(), 5, Visibility::default());
+        mask.update(kernel::threshold(
+            0.5,
+            |x, _| if x == 2 { 1.0 } else { 0.0 },
+        ));
+        let mut light = Fov::new(flux_field, 5, Illumination::default());
+        light.update(kernel::soft_shadow(|x, _| if x == 2 { 1.0 } else { 0.0 }));
+        let fields: Vec<Box<dyn AnyFov>> = vec![Box::new(mask), Box::new(light)];
+        for field in fields.iter() {
+            assert_eq!(field.width(), 11);
+            assert!(field.is_visible(0, 0));
+            assert!(field.is_visible(2, 0));
+            assert!(!field.is_visible(4, 0));
+            assert!(!field.is_visible(6, 0));
+            let bytes = field.to_bytes();
+            assert_eq!(bytes.len(), 11 * 11 * field.bytes_per_cell());
+        }
+        let bytes = fields[1].to_bytes();
+        let origin = (5 * 11 + 5) * 8;
+        assert_eq!(bytes[origin..origin + 4], 1.0_f32.to_le_bytes());
+    }
+}
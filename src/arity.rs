@@ -0,0 +1,100 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{FluxField, Fov, Influx};
+
+/// The influxes into a cell as an array of a fixed size.
+///
+/// The number of influxes depends only on where the cell is and on the
+/// kind of the flux field so a kernel matching on the arity can index the
+/// arrays without bounds checks or iterating over a slice.
+///
+pub enum Influxes<'a, T> {
+    /// The origin receives no flux.
+    Origin,
+    /// An edge cell receives flux straight from its neighbor.
+    One(&'a [Influx<T>; 1]),
+    /// An interior cell of a two-tap field or a diagonal edge cell of a
+    /// four-connected field.
+    Two(&'a [Influx<T>; 2]),
+    /// An interior cell of a three-tap field.
+    Three(&'a [Influx<T>; 3]),
+}
+
+impl<'a, T> Influxes<'a, T> {
+    /// The influxes as a slice.
+    pub fn as_slice(&self) -> &'a [Influx<T>] {
+        match *self {
+            Influxes::Origin => &[],
+            Influxes::One(influxes) => influxes,
+            Influxes::Two(influxes) => influxes,
+            Influxes::Three(influxes) => influxes,
+        }
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Update the field of vision with a function receiving the influxes
+    /// as fixed size arrays; see `Influxes`.
+    ///
+    pub fn update_arity<F>(&mut self, update_fn: F)
+    where
+        F: FnMut(i32, i32, Influxes<&T>) -> T,
+    {
+        let mut update_fn = update_fn;
+        self.update_cells_within_arity(
+            |_, _| true,
+            |x, y, _, cell, influxes| *cell = update_fn(x, y, influxes),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::all_params;
+
+    #[test]
+    fn arity_matches_slices() {
+        for params in all_params() {
+            let flux_field = std::rc::Rc::new(FluxField::with_params(6, params));
+            let mut expected = Fov::new(flux_field.clone(), 6, 0.0);
+            expected.update(|x, y, influxes| {
+                if x == 0 && y == 0 {
+                    1.0
+                } else {
+                    influxes.iter().map(|f| f.weight * f.value).sum::<f32>() * 0.9
+                }
+            });
+            let mut actual = Fov::new(flux_field, 6, 0.0);
+            actual.update_arity(|_, _, influxes| match influxes {
+                Influxes::Origin => 1.0,
+                Influxes::One([a]) => a.weight * a.value * 0.9,
+                Influxes::Two([a, b]) => (a.weight * a.value + b.weight * b.value) * 0.9,
+                Influxes::Three(fs) => influxes_sum(fs) * 0.9,
+            });
+            assert_eq!(actual.as_slice(), expected.as_slice());
+        }
+    }
+
+    fn influxes_sum(fs: &[Influx<&f32>; 3]) -> f32 {
+        fs[0].weight * fs[0].value + fs[1].weight * fs[1].value + fs[2].weight * fs[2].value
+    }
+}
@@ -0,0 +1,569 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// The flux field asset is a compact, self-describing, and checksummed
+// serialization of a flux field for asset pipelines.  Unlike the table
+// written with `write_table` it records the parameters the field was built
+// with, supports the three-tap and four-connected fields, and quantizes the
+// weights into 16 bits.  The layout is:
+//
+//     offset    size  content
+//     0         8     magic "FLUXFOVA"
+//     8         4     format version, currently 2
+//     12        4     radius of the flux field
+//     16        1     ray distribution: 0 uniform, 1 cosine, 2 area corrected
+//     17        1     connectivity: 0 eight, 1 four
+//     18        1     three-tap mode: 0 or 1
+//     19        1     rays: 0 uniformly spaced angles, 1 Bresenham lines
+//     20        4     supersampling
+//     24        4     number of weights N, (radius - 1) * radius / 2
+//     28        4     number of lateral weights L, either 0 or N
+//     32        4     number of rays
+//     36        4     ray radius as a multiple of the radius
+//     40        2N    the quantized diagonal weights
+//     40+2N     2L    the quantized sums of the diagonal and lateral weights
+//     40+2N+2L  4     CRC-32 of all the preceding bytes
+//
+// The radius is at most `MAX_RADIUS` and, as a sanity limit against corrupted
+// headers making us cast the moon, the supersampling at most 256 and the
+// number of rays at most 2^20.  The ray radius must keep the rays within the
+// `i32` coordinates when the field is grown up to `MAX_RADIUS`.
+//
+// The version 1 lacks the rays, the weights following the header right at
+// the offset 32, and reads with the default rays.  The rays are needed only
+// for growing the field with `grow_to`.
+//
+// All the numbers are little-endian.  A weight w is quantized as the `u16`
+// round(w * 65535).  The lateral weights are stored as the sums so that the
+// quantized weights of a cell still sum to at most one.
+//
+// The cache written with `to_bytes` has the same layout except that the magic
+// is "FLUXFOVC" and the weights are stored as is as 4N and 4L bytes of `f32`
+// so that the field read back is identical to the original.
+
+use std::io::{self, Read, Write};
+
+use crate::{lut_len, Connectivity, FluxField, FluxParams, Lut, RayDistribution, Rays, MAX_RADIUS};
+
+const ASSET_MAGIC: &[u8; 8] = b"FLUXFOVA";
+const CACHE_MAGIC: &[u8; 8] = b"FLUXFOVC";
+const ASSET_VERSION: u32 = 2;
+const ASSET_HEADER_LEN: usize = 40;
+// The header of the version 1 which is a prefix of the current one.
+const ASSET_HEADER_LEN_V1: usize = 32;
+const QUANTUM: f32 = u16::MAX as f32;
+const ASSET_MAX_SUPERSAMPLING: usize = 256;
+const ASSET_MAX_RAY_COUNT: usize = 1 << 20;
+// The targets of the rays and four times their phases must fit in an `i32`.
+const ASSET_MAX_RAY_RADIUS_SCALE: usize = i32::MAX as usize / 4 / MAX_RADIUS;
+
+impl FluxField {
+    /// Write the flux field as a compact, versioned, and checksummed asset.
+    ///
+    /// The weights are quantized into 16 bits so the field read back with
+    /// `read_from` differs from this one by at most 1/131070 per weight.
+    ///
+    pub fn write_to<W>(&self, writer: W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let mut bytes = self.header(
+            ASSET_MAGIC,
+            ASSET_HEADER_LEN + 2 * (self.flux_lut.len() + self.lateral_lut.len()) + 4,
+        );
+        for &w in self.flux_lut.iter() {
+            bytes.extend_from_slice(&quantize(w).to_le_bytes());
+        }
+        for (&l, &w) in self.lateral_lut.iter().zip(self.flux_lut.iter()) {
+            bytes.extend_from_slice(&quantize(w + l).to_le_bytes());
+        }
+        let checksum = crc32(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        let mut writer = writer;
+        writer.write_all(&bytes)
+    }
+
+    /// Read a flux field asset written with `write_to`.
+    ///
+    /// Reads exactly the asset so it can be embedded in a larger stream.
+    /// Fails with `InvalidData` if the asset is of an unknown version, is
+    /// corrupted, or doesn't pass `validate`.
+    ///
+    pub fn read_from<R>(reader: R) -> io::Result<FluxField>
+    where
+        R: Read,
+    {
+        let invalid = |msg| Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+        let mut reader = reader;
+        let mut bytes = vec![0; ASSET_HEADER_LEN_V1];
+        reader.read_exact(&mut bytes)?;
+        if &bytes[..8] != ASSET_MAGIC {
+            return invalid("not a flux field asset");
+        }
+        let header_len = match header_len(word(&bytes, 8)) {
+            Some(header_len) => header_len,
+            None => return invalid("unsupported flux field asset version"),
+        };
+        bytes.resize(header_len, 0);
+        reader.read_exact(&mut bytes[ASSET_HEADER_LEN_V1..])?;
+        let (radius, len, lateral_len) = read_sizes(&bytes, "asset")?;
+        // Read the rest through `take` so that a corrupted header cannot
+        // make us allocate more than the stream actually holds.
+        let rest = 2 * (len as u64 + lateral_len as u64) + 4;
+        reader.take(rest).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != header_len as u64 + rest {
+            return invalid("flux field asset is truncated");
+        }
+        let (data, checksum) = bytes.split_at(bytes.len() - 4);
+        if crc32(data) != word(checksum, 0) {
+            return invalid("flux field asset checksum mismatch");
+        }
+        let params = read_params(&bytes)?;
+        let rays = read_rays(&bytes[..header_len])?;
+        let quantized: Vec<u16> = data[header_len..]
+            .chunks(2)
+            .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+            .collect();
+        let (diagonal, sums) = quantized.split_at(len);
+        let flux_lut: Vec<f32> = diagonal.iter().map(|&q| q as f32 / QUANTUM).collect();
+        let lateral_lut = sums
+            .iter()
+            .zip(diagonal.iter().zip(flux_lut.iter()))
+            .map(|(&s, (&q, &w))| {
+                // Clamp away the rounding of the division.
+                (s.saturating_sub(q) as f32 / QUANTUM).min(1.0 - w)
+            })
+            .collect();
+        let mut flux_field = FluxField::from_lut(radius, Lut::Owned(flux_lut));
+        flux_field.params = params;
+        flux_field.lateral_lut = lateral_lut;
+        flux_field.rays = rays;
+        flux_field
+            .validate()
+            .map(|_| flux_field)
+            .map_err(|report| io::Error::new(io::ErrorKind::InvalidData, report))
+    }
+
+    /// Serialize the flux field losslessly, e.g., to cache it on disk or to
+    /// ship it precomputed instead of casting the rays at startup.
+    ///
+    /// Unlike the asset written with `write_to` the weights are not
+    /// quantized so the field read back with `from_bytes` is identical to
+    /// this one.
+    ///
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header(
+            CACHE_MAGIC,
+            ASSET_HEADER_LEN + 4 * (self.flux_lut.len() + self.lateral_lut.len()) + 4,
+        );
+        for w in self.flux_lut.iter().chain(self.lateral_lut.iter()) {
+            bytes.extend_from_slice(&w.to_le_bytes());
+        }
+        let checksum = crc32(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    /// Deserialize a flux field serialized with `to_bytes`.
+    ///
+    /// Fails with `InvalidData` if the bytes are of an unknown version, are
+    /// corrupted, have a size not matching the radius, or don't pass
+    /// `validate`.
+    ///
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<FluxField> {
+        let invalid = |msg| Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+        if bytes.len() < ASSET_HEADER_LEN_V1 || &bytes[..8] != CACHE_MAGIC {
+            return invalid("not a flux field cache");
+        }
+        let header_len = match header_len(word(bytes, 8)) {
+            Some(header_len) => header_len,
+            None => return invalid("unsupported flux field cache version"),
+        };
+        let (radius, len, lateral_len) = read_sizes(bytes, "cache")?;
+        if bytes.len() as u64 != header_len as u64 + 4 * (len as u64 + lateral_len as u64) + 4 {
+            return invalid("flux field cache length doesn't match its header");
+        }
+        let (data, checksum) = bytes.split_at(bytes.len() - 4);
+        if crc32(data) != word(checksum, 0) {
+            return invalid("flux field cache checksum mismatch");
+        }
+        let params = read_params(bytes)?;
+        let rays = read_rays(&bytes[..header_len])?;
+        let weights: Vec<f32> = data[header_len..]
+            .chunks(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect();
+        let (flux_lut, lateral_lut) = weights.split_at(len);
+        let mut flux_field = FluxField::from_lut(radius, Lut::Owned(flux_lut.to_vec()));
+        flux_field.params = params;
+        flux_field.lateral_lut = lateral_lut.to_vec();
+        flux_field.rays = rays;
+        flux_field
+            .validate()
+            .map(|_| flux_field)
+            .map_err(|report| io::Error::new(io::ErrorKind::InvalidData, report))
+    }
+
+    // The header shared by the asset and the cache.
+    fn header(&self, magic: &[u8; 8], capacity: usize) -> Vec<u8> {
+        let params = &self.params;
+        let mut bytes = Vec::with_capacity(capacity);
+        bytes.extend_from_slice(magic);
+        bytes.extend_from_slice(&ASSET_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(self.radius as u32).to_le_bytes());
+        bytes.push(match params.distribution {
+            RayDistribution::Uniform => 0,
+            RayDistribution::Cosine => 1,
+            RayDistribution::AreaCorrected => 2,
+        });
+        bytes.push(match params.connectivity {
+            Connectivity::Eight => 0,
+            Connectivity::Four => 1,
+        });
+        bytes.push(params.three_tap as u8);
+        bytes.push(params.bresenham as u8);
+        bytes.extend_from_slice(&(params.supersampling as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.flux_lut.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.lateral_lut.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.rays.count as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.rays.radius_scale as u32).to_le_bytes());
+        bytes
+    }
+}
+
+// The length of the header of the given format version if it is known.
+fn header_len(version: u32) -> Option<usize> {
+    match version {
+        1 => Some(ASSET_HEADER_LEN_V1),
+        ASSET_VERSION => Some(ASSET_HEADER_LEN),
+        _ => None,
+    }
+}
+
+// Read the radius and the numbers of the weights and the lateral weights
+// from the header shared by the asset and the cache.
+fn read_sizes(header: &[u8], kind: &str) -> io::Result<(usize, usize, usize)> {
+    let invalid = |msg| Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+    let radius = word(header, 12) as usize;
+    if radius > MAX_RADIUS {
+        return invalid(format!("flux field {} radius out of range", kind));
+    }
+    // Within `MAX_RADIUS` the table length fits in a `u32`, so the lengths
+    // from the header are compared as they are even on 32-bit targets.
+    let len = word(header, 24);
+    let lateral_len = word(header, 28);
+    if u64::from(len) != lut_len(radius) as u64 || (lateral_len != 0 && lateral_len != len) {
+        return invalid(format!("flux field {} size doesn't match its radius", kind));
+    }
+    Ok((radius, len as usize, lateral_len as usize))
+}
+
+// Read the parameters from the header shared by the asset and the cache.
+fn read_params(header: &[u8]) -> io::Result<FluxParams> {
+    let invalid = |msg| Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+    Ok(FluxParams {
+        distribution: match header[16] {
+            0 => RayDistribution::Uniform,
+            1 => RayDistribution::Cosine,
+            2 => RayDistribution::AreaCorrected,
+            _ => return invalid("unknown ray distribution in flux field asset"),
+        },
+        connectivity: match header[17] {
+            0 => Connectivity::Eight,
+            1 => Connectivity::Four,
+            _ => return invalid("unknown connectivity in flux field asset"),
+        },
+        three_tap: match header[18] {
+            0 => false,
+            1 => true,
+            _ => return invalid("unknown three-tap mode in flux field asset"),
+        },
+        bresenham: match header[19] {
+            0 => false,
+            1 => true,
+            _ => return invalid("unknown rays in flux field asset"),
+        },
+        supersampling: match word(header, 20) as usize {
+            supersampling @ 1..=ASSET_MAX_SUPERSAMPLING => supersampling,
+            _ => return invalid("invalid supersampling in flux field asset"),
+        },
+    })
+}
+
+// Read the rays from the header shared by the asset and the cache, or the
+// default ones from a header of the version 1.
+fn read_rays(header: &[u8]) -> io::Result<Rays> {
+    if header.len() == ASSET_HEADER_LEN_V1 {
+        return Ok(Rays::default());
+    }
+    let rays = Rays {
+        count: word(header, 32) as usize,
+        radius_scale: word(header, 36) as usize,
+    };
+    // As checked by `FluxFieldBuilder::build` plus the sanity limits.
+    if !(2..=ASSET_MAX_RAY_COUNT).contains(&rays.count)
+        || !(2..=ASSET_MAX_RAY_RADIUS_SCALE).contains(&rays.radius_scale)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid rays in flux field asset",
+        ));
+    }
+    Ok(rays)
+}
+
+fn word(bytes: &[u8], offset: usize) -> u32 {
+    let mut buf = [0; 4];
+    buf.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(buf)
+}
+
+fn quantize(w: f32) -> u16 {
+    (w.clamp(0.0, 1.0) * QUANTUM).round() as u16
+}
+
+// The CRC-32 of the bytes as in zlib and PNG.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn round_trip(flux_field: &FluxField) -> FluxField {
+        let mut bytes = Vec::new();
+        flux_field.write_to(&mut bytes).unwrap();
+        FluxField::read_from(&bytes[..]).unwrap()
+    }
+
+    #[test]
+    fn checksum() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn asset_round_trip() {
+        for params in [
+            FluxParams::default(),
+            FluxParams {
+                distribution: RayDistribution::Cosine,
+                three_tap: true,
+                ..FluxParams::default()
+            },
+            FluxParams {
+                connectivity: Connectivity::Four,
+                ..FluxParams::default()
+            },
+            FluxParams {
+                bresenham: true,
+                ..FluxParams::default()
+            },
+        ] {
+            let flux_field = FluxField::with_params(12, params);
+            let copy = round_trip(&flux_field);
+            assert_eq!(copy.radius(), 12);
+            assert_eq!(copy.params(), &params);
+            assert_eq!(copy.lateral_lut.len(), flux_field.lateral_lut.len());
+            for (a, b) in copy.flux_lut.iter().zip(flux_field.flux_lut.iter()) {
+                assert!((a - b).abs() <= 0.5 / QUANTUM);
+            }
+            for (a, b) in copy.lateral_lut.iter().zip(flux_field.lateral_lut.iter()) {
+                assert!((a - b).abs() <= 1.0 / QUANTUM);
+            }
+            assert!(copy.verify_normalization());
+        }
+    }
+
+    #[test]
+    fn asset_is_compact() {
+        let flux_field = FluxField::new(20);
+        let mut table = Vec::new();
+        flux_field.write_table(&mut table).unwrap();
+        let mut asset = Vec::new();
+        flux_field.write_to(&mut asset).unwrap();
+        assert_eq!(asset.len(), ASSET_HEADER_LEN + 2 * 190 + 4);
+        assert!(asset.len() < table.len());
+    }
+
+    #[test]
+    fn asset_can_be_embedded_in_a_stream() {
+        let mut bytes = Vec::new();
+        FluxField::new(3).write_to(&mut bytes).unwrap();
+        FluxField::new(5).write_to(&mut bytes).unwrap();
+        let mut reader = &bytes[..];
+        assert_eq!(FluxField::read_from(&mut reader).unwrap().radius(), 3);
+        assert_eq!(FluxField::read_from(&mut reader).unwrap().radius(), 5);
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn corrupted_assets_are_rejected() {
+        let mut bytes = Vec::new();
+        FluxField::new(6).write_to(&mut bytes).unwrap();
+        let error = |bytes: &[u8]| match FluxField::read_from(bytes) {
+            Ok(_) => panic!("accepted a corrupted asset"),
+            Err(error) => error.to_string(),
+        };
+        let mut flipped = bytes.clone();
+        flipped[40] ^= 1;
+        assert_eq!(error(&flipped), "flux field asset checksum mismatch");
+        assert_eq!(
+            error(&bytes[..bytes.len() - 1]),
+            "flux field asset is truncated"
+        );
+        let mut version = bytes.clone();
+        version[8] = 3;
+        assert_eq!(error(&version), "unsupported flux field asset version");
+        // Patch the header keeping the checksum valid.
+        let patched = |offset: usize, patch: &[u8]| {
+            let mut bytes = bytes.clone();
+            bytes[offset..offset + patch.len()].copy_from_slice(patch);
+            let at = bytes.len() - 4;
+            let checksum = crc32(&bytes[..at]);
+            bytes[at..].copy_from_slice(&checksum.to_le_bytes());
+            bytes
+        };
+        assert_eq!(
+            error(&patched(18, &[2])),
+            "unknown three-tap mode in flux field asset"
+        );
+        assert_eq!(
+            error(&patched(20, &0u32.to_le_bytes())),
+            "invalid supersampling in flux field asset"
+        );
+        assert_eq!(
+            error(&patched(20, &u32::MAX.to_le_bytes())),
+            "invalid supersampling in flux field asset"
+        );
+        assert_eq!(
+            error(&patched(32, &u32::MAX.to_le_bytes())),
+            "invalid rays in flux field asset"
+        );
+        assert_eq!(
+            error(&patched(36, &u32::MAX.to_le_bytes())),
+            "invalid rays in flux field asset"
+        );
+        let mut radius = bytes.clone();
+        radius[12] = 200;
+        assert_eq!(
+            error(&radius),
+            "flux field asset size doesn't match its radius"
+        );
+        radius[12..16].copy_from_slice(&(MAX_RADIUS as u32 + 1).to_le_bytes());
+        assert_eq!(error(&radius), "flux field asset radius out of range");
+        assert_eq!(error(b"FLUXFOV\0"), "failed to fill whole buffer");
+    }
+
+    #[test]
+    fn cache_round_trip() {
+        for params in [
+            FluxParams::default(),
+            FluxParams {
+                distribution: RayDistribution::AreaCorrected,
+                three_tap: true,
+                ..FluxParams::default()
+            },
+            FluxParams {
+                connectivity: Connectivity::Four,
+                ..FluxParams::default()
+            },
+        ] {
+            let flux_field = FluxField::with_params(15, params);
+            let bytes = flux_field.to_bytes();
+            assert_eq!(
+                bytes.len(),
+                ASSET_HEADER_LEN
+                    + 4 * (flux_field.flux_lut.len() + flux_field.lateral_lut.len())
+                    + 4
+            );
+            let copy = FluxField::from_bytes(&bytes).unwrap();
+            assert_eq!(copy.radius(), 15);
+            assert_eq!(copy.params(), &params);
+            assert_eq!(&copy.flux_lut[..], &flux_field.flux_lut[..]);
+            assert_eq!(copy.lateral_lut, flux_field.lateral_lut);
+        }
+    }
+
+    #[test]
+    fn rays_are_kept_for_growing() {
+        let build = || {
+            FluxField::builder(8)
+                .ray_count(3000)
+                .ray_radius_scale(40)
+                .build()
+                .unwrap()
+        };
+        let mut flux_field = build();
+        let bytes = flux_field.to_bytes();
+        let mut copy = FluxField::from_bytes(&bytes).unwrap();
+        assert_eq!(copy.rays, flux_field.rays);
+        let mut asset = Vec::new();
+        flux_field.write_to(&mut asset).unwrap();
+        assert_eq!(
+            FluxField::read_from(&asset[..]).unwrap().rays,
+            flux_field.rays
+        );
+        flux_field.grow_to(12);
+        copy.grow_to(12);
+        assert_eq!(&copy.flux_lut[..], &flux_field.flux_lut[..]);
+        // The version 1 has no rays and reads with the default ones.
+        let mut v1 = [&bytes[..32], &bytes[40..bytes.len() - 4]].concat();
+        v1[8] = 1;
+        let checksum = crc32(&v1);
+        v1.extend_from_slice(&checksum.to_le_bytes());
+        assert_eq!(FluxField::from_bytes(&v1).unwrap().rays, Rays::default());
+    }
+
+    #[test]
+    fn corrupted_caches_are_rejected() {
+        let bytes = FluxField::new(6).to_bytes();
+        let error = |bytes: &[u8]| match FluxField::from_bytes(bytes) {
+            Ok(_) => panic!("accepted a corrupted cache"),
+            Err(error) => error.to_string(),
+        };
+        let mut flipped = bytes.clone();
+        flipped[40] ^= 1;
+        assert_eq!(error(&flipped), "flux field cache checksum mismatch");
+        assert_eq!(
+            error(&bytes[..bytes.len() - 1]),
+            "flux field cache length doesn't match its header"
+        );
+        let mut version = bytes.clone();
+        version[8] = 3;
+        assert_eq!(error(&version), "unsupported flux field cache version");
+        let mut radius = bytes.clone();
+        radius[12] = 7;
+        assert_eq!(
+            error(&radius),
+            "flux field cache size doesn't match its radius"
+        );
+        radius[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(error(&radius), "flux field cache radius out of range");
+        let mut asset = Vec::new();
+        FluxField::new(6).write_to(&mut asset).unwrap();
+        assert_eq!(error(&asset), "not a flux field cache");
+        assert_eq!(error(b"FLUXFOVC"), "not a flux field cache");
+    }
+}
@@ -0,0 +1,98 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{FluxField, Fov, Influx};
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Update the field of vision while recording derived data into an
+    /// auxiliary field.
+    ///
+    /// The `update_fn` returns the new value of the cell together with the
+    /// derived data, e.g., the direction the light comes from as computed by
+    /// `incoming_direction`, which is stored into the corresponding cell of
+    /// `aux`.  The auxiliary field must have the same radius.
+    ///
+    pub fn update_with_aux<A, Y, F>(&mut self, aux: &mut Fov<A, Y>, update_fn: F)
+    where
+        Y: AsRef<FluxField>,
+        F: FnMut(i32, i32, &[Influx<&T>]) -> (T, A),
+    {
+        assert_eq!(self.radius, aux.radius, "radius mismatch");
+        let mut update_fn = update_fn;
+        let (width, ix_origin) = (self.width, self.ix_origin);
+        let aux_data = &mut aux.data;
+        self.update_cells(|x, y, _, cell, influxes| {
+            let (value, derived) = update_fn(x, y, influxes);
+            *cell = value;
+            aux_data[(ix_origin + width * y as isize + x as isize) as usize] = derived;
+        });
+    }
+}
+
+/// The average direction of travel of the influxes weighted by their
+/// weights and the given `amount`, e.g., the amount of light they carry.
+///
+/// Returns (0.0, 0.0) if nothing flows in.  The direction is not
+/// normalized; its length tells how uniform the direction of the influxes
+/// is.
+///
+pub fn incoming_direction<T, F>(influxes: &[Influx<&T>], amount: F) -> (f32, f32)
+where
+    F: Fn(&T) -> f32,
+{
+    let mut total = 0.0;
+    let (mut dx, mut dy) = (0.0, 0.0);
+    for f in influxes {
+        let a = f.weight * amount(f.value);
+        total += a;
+        dx += a * f.dx as f32;
+        dy += a * f.dy as f32;
+    }
+    if total > 0.0 {
+        (dx / total, dy / total)
+    } else {
+        (0.0, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel::{self, Illumination};
+    use std::rc::Rc;
+
+    #[test]
+    fn light_direction() {
+        let flux_field = Rc::new(FluxField::new(4));
+        let mut light = Fov::new(flux_field.clone(), 4, Illumination::default());
+        let mut direction = Fov::new(flux_field, 4, (0.0, 0.0));
+        let mut kernel = kernel::soft_shadow(|_, _| 0.0);
+        light.update_with_aux(&mut direction, |x, y, influxes| {
+            let d = incoming_direction(influxes, |v| v.ray_output);
+            (kernel(x, y, influxes), d)
+        });
+        assert_eq!(*direction.at(0, 0), (0.0, 0.0));
+        assert_eq!(*direction.at(3, 0), (1.0, 0.0));
+        assert_eq!(*direction.at(-2, -2), (-1.0, -1.0));
+        let (dx, dy) = *direction.at(4, 1);
+        assert!(dx == 1.0 && 0.0 < dy && dy < 1.0);
+        assert_eq!(light.at(4, 1).brightness, 1.0);
+    }
+}
@@ -0,0 +1,206 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::sweep::sweep;
+use crate::{FluxField, Influx};
+
+pub(crate) const WORD_BITS: usize = 64;
+
+/// A bit-packed boolean field of vision.
+///
+/// Works like `Fov<bool, X>` but stores a single bit per grid cell.
+///
+#[derive(Clone)]
+pub struct FovBits<X>
+where
+    X: AsRef<FluxField>,
+{
+    flux_field: X,
+    radius: isize,
+    width: isize,
+    ix_origin: isize,
+    words: Vec<u64>,
+}
+
+impl<X> FovBits<X>
+where
+    X: AsRef<FluxField>,
+{
+    pub fn new(flux_field: X, radius: usize, init: bool) -> Self {
+        assert!(radius <= flux_field.as_ref().radius);
+        let radius = radius as isize;
+        let width = radius * 2 + 1;
+        let ix_origin = radius * (width + 1);
+        let size = (width * width) as usize;
+        let mut bits = FovBits {
+            flux_field,
+            radius,
+            width,
+            ix_origin,
+            words: vec![0; size.div_ceil(WORD_BITS)],
+        };
+        if init {
+            for ix in 0..size {
+                bits.set(ix, true);
+            }
+        }
+        bits
+    }
+
+    /// The value of at the given grid cell.
+    pub fn at(&self, x: i32, y: i32) -> bool {
+        let radius = self.radius as u32;
+        assert!(
+            x.unsigned_abs() <= radius && y.unsigned_abs() <= radius,
+            "cell out of range"
+        );
+        let ix = self.ix_origin + (self.width * y as isize) + x as isize;
+        self.get(ix as usize)
+    }
+
+    /// Expose the underlying bits as a slice of words.
+    ///
+    /// The cells are stored in the same order as in `Fov` starting from the
+    /// least significant bit of the first word.
+    ///
+    pub fn as_words(&self) -> &[u64] {
+        self.words.as_slice()
+    }
+
+    /// Update the field of vision with the given function.
+    ///
+    pub fn update<F>(&mut self, update_fn: F)
+    where
+        F: FnMut(i32, i32, &[Influx<&bool>]) -> bool,
+    {
+        let mut update_fn = update_fn;
+        let words = &mut self.words;
+        sweep(
+            self.radius,
+            self.width,
+            self.ix_origin,
+            self.flux_field.as_ref(),
+            |step| {
+                let mut influxes = [
+                    Influx {
+                        weight: 0.0,
+                        dx: 0,
+                        dy: 0,
+                        value: &false,
+                    },
+                    Influx {
+                        weight: 0.0,
+                        dx: 0,
+                        dy: 0,
+                        value: &false,
+                    },
+                    Influx {
+                        weight: 0.0,
+                        dx: 0,
+                        dy: 0,
+                        value: &false,
+                    },
+                ];
+                let taps = step.taps();
+                for (influx, tap) in influxes.iter_mut().zip(taps.iter()) {
+                    influx.weight = tap.weight;
+                    influx.dx = tap.dx;
+                    influx.dy = tap.dy;
+                    influx.value = if get(words, tap.ix) { &true } else { &false };
+                }
+                let value = update_fn(step.x, step.y, &influxes[..taps.len()]);
+                set(words, step.ix, value);
+            },
+        );
+    }
+
+    #[inline]
+    fn get(&self, ix: usize) -> bool {
+        get(&self.words, ix)
+    }
+
+    #[inline]
+    fn set(&mut self, ix: usize, value: bool) {
+        set(&mut self.words, ix, value)
+    }
+}
+
+#[inline]
+pub(crate) fn get(words: &[u64], ix: usize) -> bool {
+    words[ix / WORD_BITS] & (1 << (ix % WORD_BITS)) != 0
+}
+
+#[inline]
+pub(crate) fn set(words: &mut [u64], ix: usize, value: bool) {
+    let mask = 1 << (ix % WORD_BITS);
+    if value {
+        words[ix / WORD_BITS] |= mask;
+    } else {
+        words[ix / WORD_BITS] &= !mask;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Fov;
+    use std::rc::Rc;
+
+    #[test]
+    fn bits_match_bool_field() {
+        let flux_field = Rc::new(FluxField::new(6));
+        let walls = [(2, 1), (-1, -3), (0, 2)];
+        let kernel = |x: i32, y: i32, influxes: &[Influx<&bool>]| {
+            if x == 0 && y == 0 {
+                true
+            } else if walls.contains(&(x, y)) {
+                false
+            } else {
+                let input: f32 = influxes
+                    .iter()
+                    .map(|f| if *f.value { f.weight } else { 0.0 })
+                    .sum();
+                input > 0.5
+            }
+        };
+        let mut fov = Fov::new(flux_field.clone(), 6, false);
+        fov.update(kernel);
+        let mut bits = FovBits::new(flux_field, 6, false);
+        bits.update(kernel);
+        for y in -6..7 {
+            for x in -6..7 {
+                assert_eq!(bits.at(x, y), *fov.at(x, y), "at ({}, {})", x, y);
+            }
+        }
+        assert_eq!(bits.as_words().len(), 3);
+    }
+
+    #[test]
+    fn bits_init() {
+        let bits = FovBits::new(Box::new(FluxField::new(2)), 2, true);
+        assert!(bits.at(-2, -2) && bits.at(2, 2));
+        assert_eq!(bits.as_words(), &[(1 << 25) - 1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cell out of range")]
+    fn at_out_of_range() {
+        // Within the words but past the right edge of the field.
+        let bits = FovBits::new(Box::new(FluxField::new(2)), 2, true);
+        bits.at(3, 0);
+    }
+}
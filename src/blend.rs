@@ -0,0 +1,168 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::compose::for_each_tile;
+use crate::{FluxField, Fov};
+
+/// The way a source value is combined with a destination value.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendMode {
+    /// Sum the source and the destination.
+    Add,
+    /// Take the larger of the source and the destination.
+    Max,
+    /// Interpolate linearly from the destination towards the source by the
+    /// given opacity; 0.0 leaves the destination untouched and 1.0 replaces
+    /// it with the source.
+    Alpha(f32),
+}
+
+impl BlendMode {
+    /// Blend the source value `src` onto the destination value `dst`.
+    #[inline]
+    pub fn blend(self, dst: f32, src: f32) -> f32 {
+        match self {
+            BlendMode::Add => dst + src,
+            BlendMode::Max => dst.max(src),
+            BlendMode::Alpha(a) => dst + (src - dst) * a,
+        }
+    }
+}
+
+impl<X> Fov<f32, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Blend `other` onto this field in place.
+    ///
+    /// Both fields must have the same radius.
+    ///
+    pub fn accumulate_in_place<Y>(&mut self, other: &Fov<f32, Y>, mode: BlendMode)
+    where
+        Y: AsRef<FluxField>,
+    {
+        assert_eq!(self.radius, other.radius, "radius mismatch");
+        for (dst, src) in self.data.iter_mut().zip(other.data.iter()) {
+            *dst = mode.blend(*dst, *src);
+        }
+    }
+
+    /// Blend `other` onto a copy of this field.
+    ///
+    /// The copy shares the flux field with this one.  Both fields must have
+    /// the same radius.
+    ///
+    pub fn accumulate<Y>(&self, other: &Fov<f32, Y>, mode: BlendMode) -> Fov<f32, X>
+    where
+        X: Clone,
+        Y: AsRef<FluxField>,
+    {
+        let mut result = Fov {
+            flux_field: self.flux_field.clone(),
+            radius: self.radius,
+            width: self.width,
+            ix_origin: self.ix_origin,
+            data: self.data.clone(),
+        };
+        result.accumulate_in_place(other, mode);
+        result
+    }
+
+    /// Blend this field onto a caller-owned map-sized buffer with the field
+    /// centered at the map cell `origin`.
+    ///
+    /// The buffer holds the rows of the map, `map_width` cells each, one
+    /// after another.  The cells of the field falling outside the map are
+    /// ignored.
+    ///
+    pub fn accumulate_into(
+        &self,
+        buffer: &mut [f32],
+        map_width: usize,
+        origin: (i32, i32),
+        mode: BlendMode,
+    ) {
+        assert!(
+            map_width > 0 && buffer.len().is_multiple_of(map_width),
+            "buffer size mismatch"
+        );
+        let map_height = buffer.len() / map_width;
+        let (x, y) = origin;
+        for_each_tile(map_width, map_height, x, y, self, |ix, &value| {
+            buffer[ix] = mode.blend(buffer[ix], value);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn accumulate_into_map_buffer() {
+        let flux_field = Rc::new(FluxField::new(1));
+        let mut light = Fov::new(flux_field, 1, 0.5);
+        light.update(|x, y, _| if x == 0 && y == 0 { 1.0 } else { 0.5 });
+        let mut buffer = vec![0.25; 4 * 3];
+        light.accumulate_into(&mut buffer, 4, (0, 0), BlendMode::Add);
+        light.accumulate_into(&mut buffer, 4, (3, 2), BlendMode::Max);
+        assert_eq!(
+            buffer,
+            vec![1.25, 0.75, 0.25, 0.25, 0.75, 0.75, 0.5, 0.5, 0.25, 0.25, 0.5, 1.0]
+        );
+    }
+
+    fn field(flux_field: &Rc<FluxField>, value: f32) -> Fov<f32, Rc<FluxField>> {
+        let mut fov = Fov::new(flux_field.clone(), 2, 0.0);
+        fov.update(|x, _, _| if x < 0 { 0.0 } else { value });
+        fov
+    }
+
+    #[test]
+    fn blend_modes() {
+        assert_eq!(BlendMode::Add.blend(0.25, 0.5), 0.75);
+        assert_eq!(BlendMode::Max.blend(0.25, 0.5), 0.5);
+        assert_eq!(BlendMode::Max.blend(0.5, 0.25), 0.5);
+        assert_eq!(BlendMode::Alpha(0.0).blend(0.25, 0.75), 0.25);
+        assert_eq!(BlendMode::Alpha(0.5).blend(0.25, 0.75), 0.5);
+        assert_eq!(BlendMode::Alpha(1.0).blend(0.25, 0.75), 0.75);
+    }
+
+    #[test]
+    fn accumulate_fields() {
+        let flux_field = Rc::new(FluxField::new(2));
+        let mut a = field(&flux_field, 0.25);
+        let b = field(&flux_field, 0.5);
+        let c = a.accumulate(&b, BlendMode::Add);
+        assert_eq!(*c.at(1, 1), 0.75);
+        assert_eq!(*c.at(-1, 1), 0.0);
+        assert_eq!(*a.at(1, 1), 0.25);
+        a.accumulate_in_place(&b, BlendMode::Max);
+        assert_eq!(*a.at(2, -2), 0.5);
+    }
+
+    #[test]
+    #[should_panic(expected = "radius mismatch")]
+    fn accumulate_radius_mismatch() {
+        let flux_field = Rc::new(FluxField::new(3));
+        let mut a = Fov::new(flux_field.clone(), 2, 0.0);
+        let b = Fov::new(flux_field, 3, 0.0);
+        a.accumulate_in_place(&b, BlendMode::Add);
+    }
+}
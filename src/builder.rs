@@ -0,0 +1,389 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::{FluxField, FluxParams, Fov, RayDistribution, Rays, ValidationReport, MAX_RADIUS};
+
+/// The reason `FovBuilder::build` failed.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildError {
+    /// No flux field was given.
+    MissingFluxField,
+    /// No initial value was given.
+    MissingInit,
+    /// The radius exceeds that of the flux field.
+    RadiusTooLarge { radius: usize, max: usize },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BuildError::MissingFluxField => write!(f, "no flux field given"),
+            BuildError::MissingInit => write!(f, "no initial value given"),
+            BuildError::RadiusTooLarge { radius, max } => write!(
+                f,
+                "radius {} exceeds the radius {} of the flux field",
+                radius, max
+            ),
+        }
+    }
+}
+
+impl Error for BuildError {}
+
+/// The reason `FluxFieldBuilder::build` failed.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub enum FluxBuildError {
+    /// The radius exceeds `MAX_RADIUS`.
+    RadiusOutOfRange { radius: usize },
+    /// Fewer than two rays were asked for.
+    TooFewRays { count: usize },
+    /// The rays would be too short to cross the whole field; the ray
+    /// radius must be at least twice the radius of the flux field.
+    RayRadiusTooShort { scale: usize },
+    /// No sub-rays were asked for.
+    NoSupersampling,
+    /// The rays were too sparse to reach every cell, or the table was
+    /// otherwise invalid.
+    Invalid(ValidationReport),
+}
+
+impl fmt::Display for FluxBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FluxBuildError::RadiusOutOfRange { radius } => write!(
+                f,
+                "radius {} exceeds the maximum radius {}",
+                radius, MAX_RADIUS
+            ),
+            FluxBuildError::TooFewRays { count } => {
+                write!(f, "{} rays are too few, at least 2 are needed", count)
+            }
+            FluxBuildError::RayRadiusTooShort { scale } => write!(
+                f,
+                "ray radius scale {} is too short, at least 2 is needed",
+                scale
+            ),
+            FluxBuildError::NoSupersampling => write!(f, "supersampling must be at least 1"),
+            FluxBuildError::Invalid(report) => report.fmt(f),
+        }
+    }
+}
+
+impl Error for FluxBuildError {}
+
+enum Init<'a, T> {
+    Value(T),
+    With(Box<dyn Fn(i32, i32) -> T + 'a>),
+}
+
+/// A builder for fields of vision; see `Fov::builder`.
+///
+pub struct FovBuilder<'a, T, X> {
+    flux_field: Option<X>,
+    radius: Option<usize>,
+    init: Option<Init<'a, T>>,
+}
+
+impl<'a, T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Start building a field of vision.
+    ///
+    /// ```
+    /// # use flux_fov::{FluxField, Fov};
+    /// # use std::rc::Rc;
+    /// # fn main() -> Result<(), flux_fov::BuildError> {
+    /// let flux_field = Rc::new(FluxField::new(12));
+    /// let fov = Fov::builder()
+    ///     .flux(flux_field)
+    ///     .radius(8)
+    ///     .init_with(|x, y| x * y)
+    ///     .build()?;
+    /// assert_eq!(*fov.at(2, -3), -6);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    pub fn builder() -> FovBuilder<'a, T, X> {
+        FovBuilder {
+            flux_field: None,
+            radius: None,
+            init: None,
+        }
+    }
+}
+
+impl<'a, T, X> FovBuilder<'a, T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// The flux field to use; required.
+    pub fn flux(mut self, flux_field: X) -> Self {
+        self.flux_field = Some(flux_field);
+        self
+    }
+
+    /// The radius of the field of vision; defaults to that of the flux
+    /// field.
+    pub fn radius(mut self, radius: usize) -> Self {
+        self.radius = Some(radius);
+        self
+    }
+
+    /// Initialize every cell with the given value.
+    pub fn init(mut self, value: T) -> Self {
+        self.init = Some(Init::Value(value));
+        self
+    }
+
+    /// Initialize every cell with the value computed from its coordinates.
+    pub fn init_with<F>(mut self, init: F) -> Self
+    where
+        F: Fn(i32, i32) -> T + 'a,
+    {
+        self.init = Some(Init::With(Box::new(init)));
+        self
+    }
+
+    /// Construct the field of vision.
+    pub fn build(self) -> Result<Fov<T, X>, BuildError>
+    where
+        T: Clone,
+    {
+        let flux_field = self.flux_field.ok_or(BuildError::MissingFluxField)?;
+        let max = flux_field.as_ref().radius();
+        let radius = self.radius.unwrap_or(max);
+        if radius > max {
+            return Err(BuildError::RadiusTooLarge { radius, max });
+        }
+        match self.init.ok_or(BuildError::MissingInit)? {
+            Init::Value(value) => Ok(Fov::new(flux_field, radius, value)),
+            Init::With(init) => {
+                let r = radius as i32;
+                let data = (-r..r + 1)
+                    .flat_map(|y| (-r..r + 1).map(move |x| (x, y)))
+                    .map(|(x, y)| init(x, y))
+                    .collect();
+                Ok(Fov::from_parts(flux_field, radius, data))
+            }
+        }
+    }
+}
+
+/// A builder for flux fields exposing the rays cast to build the look-up
+/// table; see `FluxField::builder`.
+///
+/// The weights of the table are estimated by casting rays from the origin
+/// and counting how they move from cell to cell.  More rays give smoother
+/// weights and longer rays more evenly spaced ones at the cost of a longer
+/// build, which matters mostly for very large radii.
+///
+pub struct FluxFieldBuilder {
+    radius: usize,
+    params: FluxParams,
+    rays: Rays,
+}
+
+impl FluxField {
+    /// Start building a flux field covering the area within `radius`.
+    ///
+    /// ```
+    /// # use flux_fov::FluxField;
+    /// # fn main() -> Result<(), flux_fov::FluxBuildError> {
+    /// let flux_field = FluxField::builder(60)
+    ///     .ray_count(2_000)
+    ///     .ray_radius_scale(20)
+    ///     .build()?;
+    /// assert_eq!(flux_field.radius(), 60);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    pub fn builder(radius: usize) -> FluxFieldBuilder {
+        FluxFieldBuilder {
+            radius,
+            params: FluxParams::default(),
+            rays: Rays::default(),
+        }
+    }
+}
+
+impl FluxFieldBuilder {
+    /// Use the given parameters; defaults to `FluxParams::default()`.
+    pub fn params(mut self, params: FluxParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// The weighting of the rays; see `FluxParams::distribution`.
+    pub fn distribution(mut self, distribution: RayDistribution) -> Self {
+        self.params.distribution = distribution;
+        self
+    }
+
+    /// The number of sub-rays per ray; see `FluxParams::supersampling`.
+    pub fn supersampling(mut self, supersampling: usize) -> Self {
+        self.params.supersampling = supersampling;
+        self
+    }
+
+    /// The number of rays cast across an octant; defaults to 10,000.
+    ///
+    /// The build time is proportional to the ray count.  The rays must be
+    /// dense enough to pass through every cell of the outermost column so
+    /// the count should be well above the radius.  Ignored in the Bresenham
+    /// mode that casts a line to every cell.
+    ///
+    pub fn ray_count(mut self, count: usize) -> Self {
+        self.rays.count = count;
+        self
+    }
+
+    /// The length of the rays as a multiple of the radius; defaults to 100.
+    ///
+    /// The rays are cast towards the cells on the circle of this radius so
+    /// the longer they are, the more evenly spread their angles.  The build
+    /// time doesn't depend on the length.
+    ///
+    pub fn ray_radius_scale(mut self, scale: usize) -> Self {
+        self.rays.radius_scale = scale;
+        self
+    }
+
+    /// Build the flux field.
+    ///
+    /// The rays are also used to grow the field later with
+    /// `FluxField::grow_to`.
+    ///
+    pub fn build(self) -> Result<FluxField, FluxBuildError> {
+        if self.radius > MAX_RADIUS {
+            return Err(FluxBuildError::RadiusOutOfRange {
+                radius: self.radius,
+            });
+        }
+        if self.rays.count < 2 {
+            return Err(FluxBuildError::TooFewRays {
+                count: self.rays.count,
+            });
+        }
+        if self.rays.radius_scale < 2 {
+            return Err(FluxBuildError::RayRadiusTooShort {
+                scale: self.rays.radius_scale,
+            });
+        }
+        if self.params.supersampling == 0 {
+            return Err(FluxBuildError::NoSupersampling);
+        }
+        let flux_field = FluxField::with_rays(self.radius, self.params, self.rays);
+        flux_field
+            .validate()
+            .map(|_| flux_field)
+            .map_err(FluxBuildError::Invalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn build_errors() {
+        let missing = Fov::<i32, Box<FluxField>>::builder().init(0).build();
+        assert_eq!(missing.err(), Some(BuildError::MissingFluxField));
+        let missing = Fov::<i32, _>::builder()
+            .flux(Box::new(FluxField::new(2)))
+            .build();
+        assert_eq!(missing.err(), Some(BuildError::MissingInit));
+        let too_large = Fov::builder()
+            .flux(Box::new(FluxField::new(2)))
+            .radius(3)
+            .init(0)
+            .build();
+        assert_eq!(
+            too_large.err(),
+            Some(BuildError::RadiusTooLarge { radius: 3, max: 2 })
+        );
+    }
+
+    #[test]
+    fn build_defaults_to_flux_field_radius() {
+        let fov = Fov::builder()
+            .flux(Box::new(FluxField::new(3)))
+            .init(7)
+            .build()
+            .unwrap();
+        assert_eq!(fov.radius(), 3);
+        assert_eq!(*fov.at(-3, 3), 7);
+    }
+
+    #[test]
+    fn flux_field_builder() {
+        let default = FluxField::builder(12).build().unwrap();
+        assert_eq!(&default.flux_lut[..], &FluxField::new(12).flux_lut[..]);
+        let coarse = FluxField::builder(12)
+            .ray_count(200)
+            .ray_radius_scale(4)
+            .build()
+            .unwrap();
+        assert!(coarse.verify_normalization());
+        assert_ne!(&coarse.flux_lut[..], &default.flux_lut[..]);
+        // Growing casts the same rays so the new columns are as if built at
+        // once.
+        let mut grown = FluxField::builder(8)
+            .ray_count(200)
+            .ray_radius_scale(4)
+            .build()
+            .unwrap();
+        grown.grow_to(12);
+        let bits = |lut: &[f32]| lut.iter().map(|w| w.to_bits()).collect::<Vec<_>>();
+        let old = crate::lut_len(8);
+        assert_eq!(bits(&grown.flux_lut[old..]), bits(&coarse.flux_lut[old..]));
+    }
+
+    #[test]
+    fn flux_field_build_errors() {
+        let error = |builder: FluxFieldBuilder| builder.build().err().unwrap();
+        assert_eq!(
+            error(FluxField::builder(MAX_RADIUS + 1)),
+            FluxBuildError::RadiusOutOfRange {
+                radius: MAX_RADIUS + 1
+            }
+        );
+        assert_eq!(
+            error(FluxField::builder(5).ray_count(1)),
+            FluxBuildError::TooFewRays { count: 1 }
+        );
+        assert_eq!(
+            error(FluxField::builder(5).ray_radius_scale(1)),
+            FluxBuildError::RayRadiusTooShort { scale: 1 }
+        );
+        assert_eq!(
+            error(FluxField::builder(5).supersampling(0)),
+            FluxBuildError::NoSupersampling
+        );
+        match error(FluxField::builder(40).ray_count(3)) {
+            FluxBuildError::Invalid(_) => (),
+            other => panic!("{}", other),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// Run-time checks of the pointer walk of `Fov::update` against the safe,
+// index based sweep.  With the `debug-checks` feature every update asserts
+// that the walk visits the same cells in the same order, with the same
+// sectors and the same influxes read from the same cells, as the sweep.
+// The checks are independent of the update function so any kernel works
+// as a test case when modifying the walk.
+
+use std::mem;
+
+use crate::allocator::Allocator;
+use crate::sweep::{sweep_within, Step};
+use crate::{FluxField, Fov, Influx, Sector};
+
+pub(crate) struct SweepCheck {
+    steps: Vec<Step>,
+    visited: Vec<bool>,
+    // The address of the first cell and the size of a cell; the addresses
+    // are not checked for zero-sized cells.
+    base: usize,
+    size: usize,
+    n: usize,
+}
+
+impl SweepCheck {
+    pub fn new<T, X, A, P>(fov: &Fov<T, X, A>, within: P) -> Self
+    where
+        X: AsRef<FluxField>,
+        A: Allocator,
+        P: Fn(i32, i32) -> bool,
+    {
+        let mut steps = Vec::with_capacity(fov.data.len());
+        sweep_within(
+            fov.radius,
+            fov.width,
+            fov.ix_origin,
+            fov.flux_field.as_ref(),
+            within,
+            |step| steps.push(*step),
+        );
+        SweepCheck {
+            steps,
+            visited: vec![false; fov.data.len()],
+            base: fov.data.as_ptr() as usize,
+            size: mem::size_of::<T>(),
+            n: 0,
+        }
+    }
+
+    // The buffer index of the cell at the given reference, if known.
+    fn ix<T>(&self, cell: &T) -> Option<usize> {
+        if self.size == 0 {
+            return None;
+        }
+        let offset = (cell as *const T as usize).wrapping_sub(self.base);
+        assert!(
+            offset.is_multiple_of(self.size) && offset / self.size < self.visited.len(),
+            "cell outside the field"
+        );
+        Some(offset / self.size)
+    }
+
+    pub fn visit<T>(&mut self, x: i32, y: i32, sector: Sector, cell: &T, influxes: &[Influx<&T>]) {
+        let step = self
+            .steps
+            .get(self.n)
+            .expect("more cells than in the sweep");
+        let n = self.n;
+        assert_eq!((x, y), (step.x, step.y), "cell {} out of order", n);
+        assert_eq!(sector, step.sector, "sector of ({}, {})", x, y);
+        if let Some(ix) = self.ix(cell) {
+            assert_eq!(ix, step.ix, "address of ({}, {})", x, y);
+        }
+        assert!(!self.visited[step.ix], "({}, {}) visited twice", x, y);
+        let taps = step.taps();
+        assert_eq!(influxes.len(), taps.len(), "influx count of ({}, {})", x, y);
+        for (influx, tap) in influxes.iter().zip(taps) {
+            assert_eq!(
+                (influx.dx, influx.dy, influx.weight.to_bits()),
+                (tap.dx, tap.dy, tap.weight.to_bits()),
+                "influx into ({}, {})",
+                x,
+                y
+            );
+            if let Some(ix) = self.ix(influx.value) {
+                assert_eq!(ix, tap.ix, "influx source of ({}, {})", x, y);
+            }
+            assert!(
+                self.visited[tap.ix],
+                "influx into ({}, {}) from a cell not yet updated",
+                x, y
+            );
+        }
+        self.visited[step.ix] = true;
+        self.n += 1;
+    }
+
+    pub fn finish(&self) {
+        assert_eq!(self.n, self.steps.len(), "cells missed by the update");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::all_params;
+
+    #[test]
+    fn pointer_walk_matches_sweep() {
+        for params in all_params() {
+            let flux_field = std::rc::Rc::new(FluxField::with_params(9, params));
+            for radius in [0, 1, 2, 5, 9] {
+                let mut fov = Fov::new(flux_field.clone(), radius, 0.0f32);
+                fov.update(|x, y, influxes| {
+                    if x == 0 && y == 0 {
+                        1.0
+                    } else {
+                        influxes.iter().map(|f| f.weight * f.value).sum()
+                    }
+                });
+                let mut fov = Fov::new(flux_field.clone(), radius, ());
+                fov.update(|_, _, _| ());
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "influx source of (1, 0)")]
+    fn wrong_influx_is_caught() {
+        let fov = Fov::new(Box::new(FluxField::new(2)), 2, 0u8);
+        let mut check = SweepCheck::new(&fov, |_, _| true);
+        check.visit(0, 0, Sector::Origin, fov.at(0, 0), &[]);
+        let influx = Influx {
+            weight: 1.0,
+            dx: 1,
+            dy: 0,
+            value: fov.at(0, 1),
+        };
+        check.visit(
+            1,
+            0,
+            Sector::Edge {
+                index: 0,
+                column: 1,
+            },
+            fov.at(1, 0),
+            &[influx],
+        );
+    }
+}
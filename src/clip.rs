@@ -0,0 +1,137 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{FluxField, Fov, Influx, Rect};
+
+impl<T, X> Fov<T, X>
+where
+    T: Clone,
+    X: AsRef<FluxField>,
+{
+    /// Update the field of vision skipping the cells outside the map.
+    ///
+    /// The `bounds` of the map are relative to the origin.  The cells outside
+    /// them are set to `outside` without calling `update_fn`, the same value
+    /// a kernel would typically return for them, and the rings of cells
+    /// lying wholly outside the map are not swept at all.  Standing near the
+    /// edge of the map this often skips half of the field.  The cells
+    /// within the bounds receive the same values as with `update`.
+    ///
+    /// ```
+    /// # use flux_fov::{FluxField, Fov, Rect};
+    /// // The map is 40 by 30 tiles and the observer stands at (2, 28).
+    /// let bounds = Rect::new(-2, -28, 40, 30);
+    /// let mut fov = Fov::new(Box::new(FluxField::new(8)), 8, 0);
+    /// let mut calls = 0;
+    /// fov.update_clipped(&bounds, -1, |_, _, _| {
+    ///     calls += 1;
+    ///     1
+    /// });
+    /// assert_eq!(calls, 11 * 10);
+    /// assert_eq!(*fov.at(-3, 0), -1);
+    /// ```
+    ///
+    pub fn update_clipped<F>(&mut self, bounds: &Rect, outside: T, update_fn: F)
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        let r = self.radius as i32;
+        let area = bounds.intersect(&Rect::new(-r, -r, 2 * r + 1, 2 * r + 1));
+        // The outermost ring with cells within the bounds.
+        let last_ring = if area.is_empty() {
+            -1
+        } else {
+            area.x
+                .abs()
+                .max((area.x + area.width - 1).abs())
+                .max(area.y.abs())
+                .max((area.y + area.height - 1).abs())
+        };
+        let mut kernel = |x: i32, y: i32, cell: &mut T, influxes: &[Influx<&T>]| {
+            *cell = if area.contains(x, y) {
+                update_fn(x, y, influxes)
+            } else {
+                outside.clone()
+            };
+        };
+        if last_ring == r {
+            self.update_cells(|x, y, _, cell, influxes| kernel(x, y, cell, influxes));
+            return;
+        }
+        for k in 0..last_ring + 1 {
+            self.update_cells_of_ring(k, |x, y, _, cell, influxes| kernel(x, y, cell, influxes));
+        }
+        for y in -r..r + 1 {
+            for x in -r..r + 1 {
+                if x.abs().max(y.abs()) > last_ring {
+                    let ix = self.index(x, y);
+                    self.data[ix] = outside.clone();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel::{self, Visibility};
+    use crate::testing::all_params;
+    use std::rc::Rc;
+
+    #[test]
+    fn clipped_update_matches_full_update() {
+        for params in all_params() {
+            let flux_field = Rc::new(FluxField::with_params(7, params));
+            let opacity = |x: i32, y: i32| if (x * 3 + y) % 5 == 0 { 0.9 } else { 0.1 };
+            for bounds in [
+                Rect::new(-7, -7, 15, 15),
+                Rect::new(-2, -3, 20, 20),
+                Rect::new(-1, -1, 3, 4),
+                Rect::new(3, 3, 10, 10),
+            ] {
+                let mut full = Fov::new(flux_field.clone(), 7, Visibility::default());
+                full.update(kernel::threshold(0.5, |x, y| {
+                    if bounds.contains(x, y) {
+                        opacity(x, y)
+                    } else {
+                        1.0
+                    }
+                }));
+                let mut clipped = Fov::new(flux_field.clone(), 7, Visibility::default());
+                let mut calls = 0;
+                let mut kernel = kernel::threshold(0.5, opacity);
+                clipped.update_clipped(&bounds, Visibility::default(), |x, y, influxes| {
+                    calls += 1;
+                    kernel(x, y, influxes)
+                });
+                let area = bounds.intersect(&Rect::new(-7, -7, 15, 15));
+                assert_eq!(calls, (area.width * area.height) as usize);
+                for y in -7..8 {
+                    for x in -7..8 {
+                        if bounds.contains(x, y) {
+                            assert_eq!(clipped.at(x, y), full.at(x, y), "{:?}", bounds);
+                        } else {
+                            assert_eq!(clipped.at(x, y), &Visibility::default());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
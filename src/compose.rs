@@ -0,0 +1,421 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::kernel::{self, Illumination};
+use crate::{BlendMode, CellMask, FluxField, Fov};
+
+/// How an observer sees in the dark; see `BrightnessGrid::perceive`.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NightVision {
+    /// The brightness a tile needs for the observer to see it.
+    pub min_brightness: f32,
+    /// The Euclidean distance within which the observer sees the cells in
+    /// its line of sight regardless of the light, 0.0 for none.
+    pub dark_vision: f32,
+}
+
+/// How the lit tiles bounce the light back; see
+/// `BrightnessGrid::add_bounce`.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounce {
+    /// The brightness a tile needs to act as a secondary emitter.
+    pub threshold: f32,
+    /// The fraction of the light the emitters reflect.
+    pub reflectance: f32,
+    /// The spacing of the emitters in tiles; only the tiles whose
+    /// coordinates are both multiples of the stride emit, each for the
+    /// `stride` by `stride` block around it.
+    pub stride: usize,
+}
+
+/// A map-sized grid of per-tile brightness.
+///
+/// The grid is the last step of a lighting pipeline: start from an ambient
+/// level, add the point-light fields, mask out what the player can't see and
+/// clamp the result:
+///
+/// ```
+/// # use flux_fov::{BrightnessGrid, FluxField, Fov};
+/// # let torch = Fov::new(Box::new(FluxField::new(4)), 4, 0.5_f32);
+/// # let sight = Fov::new(Box::new(FluxField::new(4)), 4, true);
+/// let mut grid = BrightnessGrid::new(20, 10, 0.1);
+/// grid.add_light(5, 5, &torch);
+/// grid.mask(6, 5, &sight, |&visible| visible);
+/// grid.clamp(0.0, 1.0);
+/// assert_eq!(grid.at(5, 5), 0.6);
+/// ```
+///
+pub struct BrightnessGrid {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+}
+
+impl BrightnessGrid {
+    /// Constructs a `width` by `height` grid lit uniformly by `ambient`.
+    ///
+    pub fn new(width: usize, height: usize, ambient: f32) -> Self {
+        BrightnessGrid {
+            width,
+            height,
+            data: vec![ambient; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The brightness at the given tile.
+    pub fn at(&self, x: usize, y: usize) -> f32 {
+        assert!(x < self.width && y < self.height, "tile out of range");
+        self.data[y * self.width + x]
+    }
+
+    /// Expose the brightness of the tiles as a slice in row-major order.
+    pub fn as_slice(&self) -> &[f32] {
+        self.data.as_slice()
+    }
+
+    /// Add a light field centered at the tile (`x`, `y`).
+    ///
+    pub fn add_light<X>(&mut self, x: i32, y: i32, light: &Fov<f32, X>)
+    where
+        X: AsRef<FluxField>,
+    {
+        self.blend_light(x, y, light, BlendMode::Add);
+    }
+
+    /// Blend a light field centered at the tile (`x`, `y`) with the given
+    /// blend mode.
+    ///
+    pub fn blend_light<X>(&mut self, x: i32, y: i32, light: &Fov<f32, X>, mode: BlendMode)
+    where
+        X: AsRef<FluxField>,
+    {
+        let data = &mut self.data;
+        for_each_tile(self.width, self.height, x, y, light, |ix, &value| {
+            data[ix] = mode.blend(data[ix], value);
+        });
+    }
+
+    /// Darken every tile that isn't visible in the field of vision `sight`
+    /// centered at the tile (`x`, `y`).
+    ///
+    /// The tiles outside the field of vision are darkened too.
+    ///
+    pub fn mask<T, X, P>(&mut self, x: i32, y: i32, sight: &Fov<T, X>, is_visible: P)
+    where
+        X: AsRef<FluxField>,
+        P: Fn(&T) -> bool,
+    {
+        let mut visible = vec![false; self.data.len()];
+        for_each_tile(self.width, self.height, x, y, sight, |ix, value| {
+            visible[ix] = is_visible(value);
+        });
+        for (dst, &visible) in self.data.iter_mut().zip(visible.iter()) {
+            if !visible {
+                *dst = 0.0;
+            }
+        }
+    }
+
+    /// The cells the observer at the tile (`x`, `y`) actually perceives
+    /// given its field of vision `sight`.
+    ///
+    /// Being in the line of sight isn't enough in the dark: a cell visible
+    /// in `sight` is perceived only if its tile is lit to at least
+    /// `night.min_brightness` or if it lies within `night.dark_vision` of
+    /// the observer.  The cells off the map are never perceived.  The mask
+    /// is relative to the observer like `sight`.
+    ///
+    /// ```
+    /// # use flux_fov::{BrightnessGrid, FluxField, Fov, NightVision};
+    /// let sight = Fov::new(Box::new(FluxField::new(4)), 4, true);
+    /// let torch = Fov::new(Box::new(FluxField::new(1)), 1, 1.0_f32);
+    /// let mut grid = BrightnessGrid::new(20, 10, 0.0);
+    /// grid.add_light(12, 5, &torch);
+    /// let night = NightVision {
+    ///     min_brightness: 0.5,
+    ///     dark_vision: 1.5,
+    /// };
+    /// let seen = grid.perceive(8, 5, &sight, |&visible| visible, &night);
+    /// assert!(seen.contains(1, 1));
+    /// assert!(!seen.contains(2, 0));
+    /// assert!(seen.contains(4, 1));
+    /// ```
+    ///
+    pub fn perceive<T, X, P>(
+        &self,
+        x: i32,
+        y: i32,
+        sight: &Fov<T, X>,
+        is_visible: P,
+        night: &NightVision,
+    ) -> CellMask
+    where
+        X: AsRef<FluxField>,
+        P: Fn(&T) -> bool,
+    {
+        let radius = sight.radius as i32;
+        let dark_vision_sq = night.dark_vision * night.dark_vision;
+        let mut seen = CellMask::new(radius as usize, false);
+        for dy in -radius..radius + 1 {
+            for dx in -radius..radius + 1 {
+                let (map_x, map_y) = (x + dx, y + dy);
+                if map_x < 0
+                    || map_y < 0
+                    || map_x >= self.width as i32
+                    || map_y >= self.height as i32
+                    || !is_visible(sight.at(dx, dy))
+                {
+                    continue;
+                }
+                let lit = self.at(map_x as usize, map_y as usize) >= night.min_brightness;
+                let near = night.dark_vision > 0.0 && (dx * dx + dy * dy) as f32 <= dark_vision_sq;
+                if lit || near {
+                    seen.set(dx, dy, true);
+                }
+            }
+        }
+        seen
+    }
+
+    /// Approximate a single bounce of the light already in the grid.
+    ///
+    /// Every tile lit to at least `bounce.threshold` acts as a weak
+    /// secondary emitter: it casts soft shadows through `opacity` over the
+    /// field `light`, with its reflected light falling off with the inverse
+    /// square of the distance.  The bounced light is accumulated separately
+    /// and added once all the emitters are done, so it doesn't bounce again.
+    /// The falloff is normalized so that an open area lit uniformly to `b`
+    /// receives about `bounce.reflectance * b` from the bounce; together
+    /// with the direct light this softens the shadows behind pillars and
+    /// lights up the corners of rooms instead of leaving them pitch black.
+    ///
+    /// The `opacity` receives map coordinates; the opaque tiles don't emit.
+    /// The cost is a field update per emitter, so raise `bounce.stride` on
+    /// big maps.
+    ///
+    pub fn add_bounce<X, O>(
+        &mut self,
+        light: &mut Fov<Illumination, X>,
+        opacity: O,
+        bounce: &Bounce,
+    ) where
+        X: AsRef<FluxField>,
+        O: Fn(i32, i32) -> f32,
+    {
+        assert!(bounce.stride > 0, "zero stride");
+        let radius = light.radius as i32;
+        let mut norm = 0.0;
+        for dy in -radius..radius + 1 {
+            for dx in -radius..radius + 1 {
+                norm += 1.0 / (1 + dx * dx + dy * dy) as f32;
+            }
+        }
+        let scale = bounce.reflectance * (bounce.stride * bounce.stride) as f32 / norm;
+        let mut bounced = vec![0.0; self.data.len()];
+        for y in (0..self.height).step_by(bounce.stride) {
+            for x in (0..self.width).step_by(bounce.stride) {
+                let direct = self.at(x, y);
+                let (x, y) = (x as i32, y as i32);
+                if direct < bounce.threshold || opacity(x, y) >= 1.0 {
+                    continue;
+                }
+                light.update(kernel::soft_shadow(|dx, dy| opacity(x + dx, y + dy)));
+                let emission = direct * scale;
+                for dy in -radius..radius + 1 {
+                    for dx in -radius..radius + 1 {
+                        let (map_x, map_y) = (x + dx, y + dy);
+                        if map_x < 0
+                            || map_y < 0
+                            || map_x >= self.width as i32
+                            || map_y >= self.height as i32
+                        {
+                            continue;
+                        }
+                        let falloff = 1.0 / (1 + dx * dx + dy * dy) as f32;
+                        let ix = map_y as usize * self.width + map_x as usize;
+                        bounced[ix] += emission * falloff * light.at(dx, dy).brightness;
+                    }
+                }
+            }
+        }
+        for (dst, &b) in self.data.iter_mut().zip(bounced.iter()) {
+            *dst += b;
+        }
+    }
+
+    /// Clamp the brightness of every tile to the range [`min`, `max`].
+    ///
+    pub fn clamp(&mut self, min: f32, max: f32) {
+        for dst in self.data.iter_mut() {
+            *dst = dst.max(min).min(max);
+        }
+    }
+}
+
+// Call `f` with the grid index and the value of every cell of `fov` that falls
+// on the `width` by `height` grid when the field is centered at the tile
+// (`x`, `y`).
+pub(crate) fn for_each_tile<T, X, F>(
+    width: usize,
+    height: usize,
+    x: i32,
+    y: i32,
+    fov: &Fov<T, X>,
+    f: F,
+) where
+    X: AsRef<FluxField>,
+    F: FnMut(usize, &T),
+{
+    let mut f = f;
+    let radius = fov.radius as i32;
+    for dy in -radius..radius + 1 {
+        let map_y = y + dy;
+        if map_y < 0 || map_y >= height as i32 {
+            continue;
+        }
+        for dx in -radius..radius + 1 {
+            let map_x = x + dx;
+            if map_x < 0 || map_x >= width as i32 {
+                continue;
+            }
+            f(map_y as usize * width + map_x as usize, fov.at(dx, dy));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn compose_lights_and_mask() {
+        let flux_field = Rc::new(FluxField::new(2));
+        let torch = Fov::new(flux_field.clone(), 2, 0.5);
+        let lamp = Fov::new(flux_field.clone(), 1, 0.75);
+        let mut sight = Fov::new(flux_field, 2, false);
+        sight.update(|x, _, _| x >= 0);
+        let mut grid = BrightnessGrid::new(6, 4, 0.25);
+        grid.add_light(0, 0, &torch);
+        grid.add_light(1, 1, &lamp);
+        assert_eq!(grid.at(0, 0), 1.5);
+        assert_eq!(grid.at(2, 2), 1.5);
+        assert_eq!(grid.at(5, 3), 0.25);
+        grid.mask(2, 1, &sight, |&visible| visible);
+        grid.clamp(0.0, 1.0);
+        assert_eq!(grid.at(0, 0), 0.0);
+        assert_eq!(grid.at(2, 0), 1.0);
+        assert_eq!(grid.at(4, 3), 0.25);
+        assert_eq!(grid.at(5, 3), 0.0);
+    }
+
+    #[test]
+    fn night_vision() {
+        let flux_field = Rc::new(FluxField::new(3));
+        let mut sight = Fov::new(flux_field.clone(), 3, false);
+        sight.update(|x, _, _| x <= 1);
+        let mut grid = BrightnessGrid::new(8, 8, 0.0);
+        grid.add_light(0, 4, &Fov::new(flux_field, 1, 0.5));
+        let mut night = NightVision {
+            min_brightness: 0.25,
+            dark_vision: 0.0,
+        };
+        let seen = grid.perceive(3, 4, &sight, |&visible| visible, &night);
+        // Lit and in the line of sight.
+        assert!(seen.contains(-3, 0));
+        assert!(seen.contains(-2, 1));
+        // Dark, even the tile of the observer.
+        assert!(!seen.contains(0, 0));
+        assert!(!seen.contains(-1, 0));
+        night.dark_vision = 1.0;
+        let seen = grid.perceive(3, 4, &sight, |&visible| visible, &night);
+        assert!(seen.contains(0, 0));
+        assert!(seen.contains(-1, 0));
+        assert!(seen.contains(1, 0));
+        assert!(!seen.contains(-1, 1) && !seen.contains(1, 1));
+        // Not in the line of sight.
+        night.min_brightness = 0.0;
+        night.dark_vision = 3.0;
+        let seen = grid.perceive(3, 4, &sight, |&visible| visible, &night);
+        assert!(seen.contains(1, 2));
+        assert!(!seen.contains(2, 0));
+        // Off the map.
+        let seen = grid.perceive(6, 6, &sight, |&visible| visible, &night);
+        assert!(seen.contains(1, 1));
+        assert!(!seen.contains(1, 2));
+    }
+
+    #[test]
+    fn one_bounce_lights_the_shadow() {
+        // A lit room with a pillar at (4, 4) shadowing (5, 4)..(7, 4) from
+        // the torch at (1, 4).
+        let pillar = |x: i32, y: i32| {
+            if x < 0 || y < 0 || x > 8 || y > 8 || (x, y) == (4, 4) {
+                1.0
+            } else {
+                0.0
+            }
+        };
+        let flux_field = Rc::new(FluxField::new(8));
+        let mut torch = Fov::new(flux_field.clone(), 8, 0.0_f32);
+        torch.update(|x, y, influxes| {
+            if x == 0 && y == 0 {
+                1.0
+            } else {
+                let input: f32 = influxes.iter().map(|f| f.weight * f.value).sum();
+                input * (1.0 - pillar(1 + x, 4 + y))
+            }
+        });
+        let mut grid = BrightnessGrid::new(9, 9, 0.0);
+        grid.add_light(1, 4, &torch);
+        let direct = grid.at(6, 4);
+        assert!(direct < 0.01);
+        let mut light = Fov::new(flux_field, 6, Illumination::default());
+        let bounce = Bounce {
+            threshold: 0.5,
+            reflectance: 0.5,
+            stride: 1,
+        };
+        grid.add_bounce(&mut light, pillar, &bounce);
+        assert!(grid.at(6, 4) > direct + 0.05);
+        assert!(grid.at(6, 4) < 0.5);
+        // The bounce is additive.
+        assert!(grid.at(1, 4) > 1.0);
+        // Nothing bright enough, nothing bounced.
+        let mut dark = BrightnessGrid::new(9, 9, 0.25);
+        dark.add_bounce(&mut light, pillar, &bounce);
+        assert!(dark.as_slice().iter().all(|&b| b == 0.25));
+    }
+
+    #[test]
+    #[should_panic(expected = "tile out of range")]
+    fn at_out_of_range() {
+        // Within the grid but past the right edge of the first row.
+        let grid = BrightnessGrid::new(4, 3, 0.5);
+        grid.at(4, 0);
+    }
+}
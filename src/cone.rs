@@ -0,0 +1,375 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+use crate::sweep::{sweep_spans, Step, EDGES, INTERIORS};
+use crate::{FluxField, Fov, Influx};
+
+impl<T, X> Fov<T, X>
+where
+    T: Clone,
+    X: AsRef<FluxField>,
+{
+    /// Update only the cells within a narrow cone, e.g., for a spyglass.
+    ///
+    /// The cone opens from the origin towards `direction`, in radians from
+    /// the positive x-axis towards the positive y-axis, and covers the cells
+    /// whose centers are within `half_angle` of it plus a margin of a few
+    /// cells on both sides.  The half angle must be less than π/2.  Only the
+    /// cells covered by the cone are passed to `update_fn` so the cost of the
+    /// update scales with the area of the cone rather than that of the whole
+    /// field.
+    ///
+    /// The cells outside the cone keep their previous values except for
+    /// those right next to it that the cells within it receive flux from.
+    /// They are set to `outside` which should be the value of a cell passing
+    /// on no flux.  Hence the margin of the cone is darker than in a full
+    /// update.
+    ///
+    /// The flux of the four-connected fields diffuses too much for the cone
+    /// to stay lit so they are updated in full.
+    ///
+    pub fn update_cone<F>(&mut self, direction: f32, half_angle: f32, outside: T, update_fn: F)
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        assert!(
+            (0.0..FRAC_PI_2).contains(&half_angle),
+            "half angle out of range"
+        );
+        let mut update_fn = update_fn;
+        let flux_field = self.flux_field.as_ref();
+        if flux_field.is_four_connected() {
+            self.update(update_fn);
+            return;
+        }
+        // The margin keeps the darkness of the cells outside the cone from
+        // diffusing to its axis.  The lateral flux of the three-tap fields
+        // diffuses further.
+        let margin = if flux_field.is_three_tap() { 3 } else { 2 };
+        let cone = Cone::new(direction, half_angle, margin);
+        let data = &mut self.data;
+        cone.sweep(
+            self.radius,
+            self.width,
+            self.ix_origin,
+            flux_field,
+            |step| {
+                for tap in step.taps() {
+                    if !cone.covers(step.x - tap.dx, step.y - tap.dy) {
+                        data[tap.ix] = outside.clone();
+                    }
+                }
+                let value = step.with_influxes(
+                    |ix| &data[ix],
+                    |influxes| update_fn(step.x, step.y, influxes.as_slice()),
+                );
+                data[step.ix] = value;
+            },
+        );
+    }
+
+    /// Update only the cells within an angular sector, e.g., for creatures
+    /// with facing-based vision.
+    ///
+    /// The sector opens from the origin towards `facing`, in radians as in
+    /// `update_cone`, and covers the cells whose centers are within
+    /// `half_angle` of it, anywhere from 0 to π for the full circle.  Only
+    /// the cells within the sector are passed to `update_fn` and, like for
+    /// the cone, only the columns of the octants spanned by the sector are
+    /// walked.
+    ///
+    /// If `outside` is given the cells outside the sector that the cells on
+    /// its border receive flux from are set to it, so it should be the value
+    /// of a cell passing on no flux, and the border cells receive no flux
+    /// from outside.  The other cells outside keep their previous values.
+    /// Without `outside` the border cells receive flux from whatever values
+    /// the cells next to them hold.  The four-connected fields are updated
+    /// in full and then all the cells outside the sector are reset.
+    ///
+    pub fn update_sector<F>(
+        &mut self,
+        facing: f32,
+        half_angle: f32,
+        outside: Option<T>,
+        update_fn: F,
+    ) where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        assert!((0.0..=PI).contains(&half_angle), "half angle out of range");
+        let mut update_fn = update_fn;
+        let in_sector = |x: i32, y: i32| {
+            let mut delta = (y as f32).atan2(x as f32) - facing;
+            delta -= (delta / (2.0 * PI)).round() * 2.0 * PI;
+            (x, y) == (0, 0) || delta.abs() <= half_angle
+        };
+        if self.flux_field.as_ref().is_four_connected() {
+            self.update(update_fn);
+            if let Some(outside) = &outside {
+                let radius = self.radius as i32;
+                for y in -radius..radius + 1 {
+                    for x in -radius..radius + 1 {
+                        if !in_sector(x, y) {
+                            let ix = self.index(x, y);
+                            self.data[ix] = outside.clone();
+                        }
+                    }
+                }
+            }
+            return;
+        }
+        // The cone rules out most of the cells without the arc tangent; the
+        // margin only makes up for the rounding.
+        let cone = Cone::new(facing, half_angle, 1);
+        let data = &mut self.data;
+        cone.sweep(
+            self.radius,
+            self.width,
+            self.ix_origin,
+            self.flux_field.as_ref(),
+            |step| {
+                if !in_sector(step.x, step.y) {
+                    return;
+                }
+                if let Some(outside) = &outside {
+                    for tap in step.taps() {
+                        if !in_sector(step.x - tap.dx, step.y - tap.dy) {
+                            data[tap.ix] = outside.clone();
+                        }
+                    }
+                }
+                let value = step.with_influxes(
+                    |ix| &data[ix],
+                    |influxes| update_fn(step.x, step.y, influxes.as_slice()),
+                );
+                data[step.ix] = value;
+            },
+        );
+    }
+}
+
+// The cells covered by a cone in terms of the octant coordinates (u, v)
+// where the octant spans the angles from 0 (v = 0) to π/4 (v = u).
+struct Cone {
+    // The tangents of the angles bounding the cone within each octant or
+    // `None` if the cone doesn't reach the octant.
+    tangents: [Option<(f32, f32)>; 8],
+    // The number of cells added on both sides of the cone.
+    margin: i32,
+}
+
+impl Cone {
+    fn new(direction: f32, half_angle: f32, margin: i32) -> Self {
+        let (dir_x, dir_y) = (direction.cos(), direction.sin());
+        let mut tangents = [None; 8];
+        for (tangent, &(m_xu, m_xv, m_yu, m_yv)) in tangents.iter_mut().zip(INTERIORS.iter()) {
+            let local_x = m_xu as f32 * dir_x + m_yu as f32 * dir_y;
+            let local_y = m_xv as f32 * dir_x + m_yv as f32 * dir_y;
+            let center = local_y.atan2(local_x);
+            // A wide cone may reach the octant from across the negative
+            // x-axis, too.
+            for &center in &[center - 2.0 * PI, center, center + 2.0 * PI] {
+                let lo = (center - half_angle).max(0.0);
+                let hi = (center + half_angle).min(FRAC_PI_4);
+                if lo <= hi {
+                    *tangent = Some(match *tangent {
+                        Some((tan_lo, tan_hi)) => (lo.tan().min(tan_lo), hi.tan().max(tan_hi)),
+                        None => (lo.tan(), hi.tan()),
+                    });
+                }
+            }
+        }
+        Cone { tangents, margin }
+    }
+
+    // The range of the cells covered in the column `u` of the octant, the
+    // edges included.
+    fn range(&self, octant: usize, u: i32) -> Option<(i32, i32)> {
+        self.tangents[octant].map(|(tan_lo, tan_hi)| {
+            let a = (u as f32 * tan_lo).ceil() as i32 - self.margin;
+            let b = (u as f32 * tan_hi).floor() as i32 + self.margin;
+            (a.max(0), b.min(u))
+        })
+    }
+
+    fn covers_local(&self, octant: usize, u: i32, v: i32) -> bool {
+        match self.range(octant, u) {
+            Some((a, b)) => a <= v && v <= b,
+            None => false,
+        }
+    }
+
+    // Walk the cells of an eight-connected field covered by the cone in the
+    // order of the full update.
+    fn sweep<F>(&self, radius: isize, width: isize, ix_origin: isize, flux_field: &FluxField, f: F)
+    where
+        F: FnMut(&Step),
+    {
+        sweep_spans(
+            radius,
+            width,
+            ix_origin,
+            flux_field,
+            |edge, k| self.covers(EDGES[edge].0 * k, EDGES[edge].1 * k),
+            |octant, u| self.range(octant, u),
+            f,
+        );
+    }
+
+    // Whether the cone covers the cell (x, y) of the field.
+    fn covers(&self, x: i32, y: i32) -> bool {
+        (x, y) == (0, 0)
+            || INTERIORS
+                .iter()
+                .enumerate()
+                .any(|(octant, &(m_xu, m_xv, m_yu, m_yv))| {
+                    let u = m_xu * x + m_yu * y;
+                    let v = m_xv * x + m_yv * y;
+                    0 <= v && v <= u && self.covers_local(octant, u, v)
+                })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel::{self, Illumination};
+    use crate::testing::three_tap;
+    use crate::FluxParams;
+    use std::cell::Cell;
+
+    fn check_cone(params: FluxParams, direction: f32) {
+        let radius = 24;
+        let flux_field = std::rc::Rc::new(FluxField::with_params(radius, params));
+        let mut fov = Fov::new(flux_field, radius, Illumination::default());
+        let calls = Cell::new(0);
+        let mut kernel = kernel::soft_shadow(|_, _| 0.0);
+        fov.update_cone(direction, 0.1, Illumination::default(), |x, y, influxes| {
+            calls.set(calls.get() + 1);
+            kernel(x, y, influxes)
+        });
+        // The cone is lit along its axis but not outside.
+        let (dx, dy) = (direction.cos(), direction.sin());
+        for k in 1..radius as i32 + 1 {
+            let (x, y) = (
+                (k as f32 * dx).round() as i32,
+                (k as f32 * dy).round() as i32,
+            );
+            assert!(
+                fov.at(x, y).brightness > 0.95,
+                "({}, {}) {}",
+                x,
+                y,
+                direction
+            );
+            assert_eq!(fov.at(-x, -y).brightness, 0.0);
+        }
+        assert!(calls.get() < (2 * radius + 1).pow(2) / 8);
+    }
+
+    #[test]
+    fn cone_is_lit_along_axis() {
+        for &direction in &[0.0, 0.4, 0.785, 1.2, 2.0, 3.0, -2.5, -0.78] {
+            check_cone(FluxParams::default(), direction);
+            check_cone(three_tap(), direction);
+        }
+    }
+
+    #[test]
+    fn cone_visits_only_the_cone() {
+        let radius = 64;
+        let flux_field = FluxField::new(radius);
+        let width = 2 * radius as isize + 1;
+        let cone = Cone::new(0.4, 0.1, 2);
+        let mut visited = 0;
+        cone.sweep(
+            radius as isize,
+            width,
+            radius as isize * (width + 1),
+            &flux_field,
+            |step| {
+                assert!(cone.covers(step.x, step.y), "({}, {})", step.x, step.y);
+                visited += 1;
+            },
+        );
+        let r = radius as i32;
+        let covered = (-r..r + 1)
+            .flat_map(|y| (-r..r + 1).map(move |x| (x, y)))
+            .filter(|&(x, y)| cone.covers(x, y))
+            .count();
+        assert_eq!(visited, covered);
+        assert!(visited < (2 * radius + 1).pow(2) / 16);
+    }
+
+    #[test]
+    fn sector_updates_only_the_sector() {
+        let radius = 12;
+        let flux_field = std::rc::Rc::new(FluxField::new(radius));
+        let mut fov = Fov::new(flux_field, radius, Illumination::default());
+        let calls = Cell::new(0);
+        let mut kernel = kernel::soft_shadow(|_, _| 0.0);
+        let mut counted = |x: i32, y: i32, influxes: &[Influx<&Illumination>]| {
+            calls.set(calls.get() + 1);
+            kernel(x, y, influxes)
+        };
+        let facing = 2.0;
+        fov.update_sector(facing, 1.0, Some(Illumination::default()), &mut counted);
+        assert!(calls.get() < (2 * radius + 1).pow(2) / 3);
+        assert!(fov.at(-3, 6).brightness > 0.95);
+        assert_eq!(fov.at(3, -6).brightness, 0.0);
+        assert_eq!(fov.at(6, 0).brightness, 0.0);
+        // A wide sector reaching across the negative x-axis.
+        calls.set(0);
+        fov.update_sector(PI, 2.5, Some(Illumination::default()), &mut counted);
+        assert!(fov.at(-8, 0).brightness > 0.95);
+        assert!(fov.at(-1, -9).brightness > 0.95);
+        assert!(fov.at(-2, 10).brightness > 0.95);
+        assert_eq!(fov.at(9, 1).brightness, 0.0);
+        assert!(calls.get() < (2 * radius + 1).pow(2));
+        // Without a reset the cells outside are left as they are.
+        fov.update(kernel::soft_shadow(|_, _| 0.0));
+        fov.update_sector(0.0, 0.5, None, kernel::soft_shadow(|_, _| 1.0));
+        assert_eq!(fov.at(5, 0).brightness, 0.0);
+        assert!(fov.at(-5, 0).brightness > 0.95);
+        // Only the cells next to the border are reset.
+        fov.update(kernel::soft_shadow(|_, _| 0.0));
+        fov.update_sector(
+            0.0,
+            0.5,
+            Some(Illumination::default()),
+            kernel::soft_shadow(|_, _| 0.0),
+        );
+        assert!(fov.at(-5, 0).brightness > 0.95);
+        assert!(fov.at(5, 0).brightness > 0.95);
+        assert_eq!(fov.at(5, 3).brightness, 0.0);
+        // The full circle matches the full update.
+        let mut full = Fov::new(
+            std::rc::Rc::new(FluxField::new(radius)),
+            radius,
+            Illumination::default(),
+        );
+        full.update(kernel::soft_shadow(|x, _| (x == 3) as u8 as f32));
+        fov.update_sector(
+            1.0,
+            PI,
+            None,
+            kernel::soft_shadow(|x, _| (x == 3) as u8 as f32),
+        );
+        assert_eq!(fov.as_slice(), full.as_slice());
+    }
+}
@@ -0,0 +1,192 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+#[cfg(feature = "pgm")]
+use std::io::{self, Write};
+
+use crate::allocator::Allocator;
+use crate::sweep::sweep;
+use crate::{FluxField, Fov};
+
+// The characters of the ASCII renderings from the weight 0.0 to 1.0.
+const RAMP: &[u8] = b" .:-=+*#%@";
+
+impl FluxField {
+    /// The weight map of the flux field: the weight of the diagonal influx
+    /// of each cell, or for the four-connected fields the weight of the
+    /// horizontal influx, row by row in the order of `Fov::as_slice`.
+    ///
+    /// The map shows the distribution of the flux at a glance: the straight
+    /// edges are 0.0, the diagonal edges 1.0, and the artifacts in the
+    /// look-up table stand out as breaks in the gradient in between.
+    ///
+    pub fn weight_map(&self) -> Vec<f32> {
+        let radius = self.radius() as isize;
+        let width = 2 * radius + 1;
+        let four_connected = self.is_four_connected();
+        let mut map = vec![0.0; (width * width) as usize];
+        sweep(radius, width, radius * width + radius, self, |step| {
+            map[step.ix] = step
+                .taps()
+                .iter()
+                .filter(|tap| {
+                    if four_connected {
+                        tap.dy == 0
+                    } else {
+                        tap.dx != 0 && tap.dy != 0
+                    }
+                })
+                .map(|tap| tap.weight)
+                .sum();
+        });
+        map
+    }
+
+    /// Render the weight map of the flux field as ASCII art.
+    ///
+    /// Every cell is a character from `' '` for 0.0 to `'@'` for 1.0 and
+    /// every row ends with a newline; see `weight_map`.
+    ///
+    pub fn render_ascii(&self) -> String {
+        let width = 2 * self.radius() + 1;
+        render_ascii(width, &self.weight_map(), |&w| ramp(w))
+    }
+
+    /// Write the weight map of the flux field as a grayscale PGM image.
+    ///
+    /// Requires the `pgm` feature.
+    ///
+    #[cfg(feature = "pgm")]
+    pub fn write_pgm<W>(&self, writer: W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let width = 2 * self.radius() + 1;
+        write_pgm(writer, width, &self.weight_map(), |&w| w)
+    }
+}
+
+impl<T, X, A> Fov<T, X, A>
+where
+    X: AsRef<FluxField>,
+    A: Allocator,
+{
+    /// Render the field of vision as ASCII art, one character per cell.
+    ///
+    /// Every row ends with a newline.
+    ///
+    /// ```
+    /// # use flux_fov::{FluxField, Fov};
+    /// let mut fov = Fov::new(Box::new(FluxField::new(1)), 1, 0);
+    /// fov.update(|x, y, _| x + y);
+    /// let ascii = fov.render_ascii(|&n| if n > 0 { '#' } else { '.' });
+    /// assert_eq!(ascii, "...\n..#\n.##\n");
+    /// ```
+    ///
+    pub fn render_ascii<F>(&self, render_cell: F) -> String
+    where
+        F: Fn(&T) -> char,
+    {
+        render_ascii(self.width as usize, &self.data, render_cell)
+    }
+
+    /// Write the field of vision as a grayscale PGM image with
+    /// `brightness` mapping each cell to a value from 0.0 (black) to 1.0
+    /// (white), e.g., a heat map of the ray counts.  The values outside the
+    /// range are clamped.
+    ///
+    /// Requires the `pgm` feature.
+    ///
+    #[cfg(feature = "pgm")]
+    pub fn write_pgm<W, F>(&self, writer: W, brightness: F) -> io::Result<()>
+    where
+        W: Write,
+        F: Fn(&T) -> f32,
+    {
+        write_pgm(writer, self.width as usize, &self.data, brightness)
+    }
+}
+
+// The character of the weight `w` in the ramp.
+fn ramp(w: f32) -> char {
+    let n = RAMP.len() - 1;
+    RAMP[((w.clamp(0.0, 1.0) * n as f32).round() as usize).min(n)] as char
+}
+
+fn render_ascii<T, F>(width: usize, cells: &[T], render_cell: F) -> String
+where
+    F: Fn(&T) -> char,
+{
+    let mut ascii = String::with_capacity(cells.len() + cells.len() / width);
+    for row in cells.chunks(width) {
+        ascii.extend(row.iter().map(&render_cell));
+        ascii.push('\n');
+    }
+    ascii
+}
+
+// Write the cells as a binary PGM image with 8-bit samples.
+#[cfg(feature = "pgm")]
+fn write_pgm<W, T, F>(writer: W, width: usize, cells: &[T], brightness: F) -> io::Result<()>
+where
+    W: Write,
+    F: Fn(&T) -> f32,
+{
+    let mut writer = writer;
+    write!(writer, "P5\n{} {}\n255\n", width, cells.len() / width)?;
+    let samples: Vec<u8> = cells
+        .iter()
+        .map(|cell| (brightness(cell).clamp(0.0, 1.0) * 255.0).round() as u8)
+        .collect();
+    writer.write_all(&samples)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::four_connected;
+
+    #[test]
+    fn flux_field_ascii() {
+        let ascii = FluxField::new(3).render_ascii();
+        let rows: Vec<&str> = ascii.lines().collect();
+        assert_eq!(rows.len(), 7);
+        assert!(rows.iter().all(|row| row.len() == 7));
+        // The diagonal edges are the jumps all the way and the straight
+        // edges never jump.
+        assert_eq!(rows[0].as_bytes()[0], b'@');
+        assert_eq!(rows[6].as_bytes()[6], b'@');
+        assert_eq!(rows[3], "       ");
+        assert_eq!(rows[1], "#@+ +@#");
+        let four = FluxField::with_params(3, four_connected());
+        assert_eq!(four.render_ascii().lines().nth(3), Some("@@@ @@@"));
+    }
+
+    #[cfg(feature = "pgm")]
+    #[test]
+    fn pgm_heat_map() {
+        let mut fov = Fov::new(Box::new(FluxField::new(2)), 2, 0.0f32);
+        fov.update(|x, _, _| x as f32 / 2.0);
+        let mut pgm = Vec::new();
+        fov.write_pgm(&mut pgm, |&v| v).unwrap();
+        assert!(pgm.starts_with(b"P5\n5 5\n255\n"));
+        assert_eq!(&pgm[11..16], &[0, 0, 0, 128, 255]);
+        let mut pgm = Vec::new();
+        FluxField::new(60).write_pgm(&mut pgm).unwrap();
+        assert_eq!(pgm.len(), "P5\n121 121\n255\n".len() + 121 * 121);
+    }
+}
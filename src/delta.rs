@@ -0,0 +1,137 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// The delta encoding of the visibility changes between two updates is:
+//
+//     sequence number         varint
+//     radius                  varint
+//     number of changes N     varint
+//     N gaps                  varints
+//
+// The changes are the cells whose visibility toggled in the order of
+// `Fov::as_slice`.  Each is given as the gap from the previous changed cell,
+// the first one from the beginning of the field.  The varints are as in the
+// run-length encoding.
+
+use std::io;
+
+use crate::rle::{read_varint, write_varint};
+use crate::{FluxField, Fov, FovSnapshot, Influx};
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Update the field of vision and encode the cells whose visibility
+    /// changed.
+    ///
+    /// The visibility is determined with `is_visible` as in `update_notify`.
+    /// The encoding carries the given sequence number so that a client can
+    /// detect lost or reordered deltas.  Apply the delta to a mask with
+    /// `FovSnapshot::apply_delta`.
+    ///
+    pub fn update_delta<P, F>(&mut self, sequence: u64, is_visible: P, update_fn: F) -> Vec<u8>
+    where
+        P: Fn(&T) -> bool,
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut changed = Vec::new();
+        let width = self.width;
+        let ix_origin = self.ix_origin;
+        self.update_notify(
+            is_visible,
+            |x, y, _| changed.push((ix_origin + width * y as isize + x as isize) as u64),
+            update_fn,
+        );
+        changed.sort_unstable();
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, sequence);
+        write_varint(&mut bytes, self.radius as u64);
+        write_varint(&mut bytes, changed.len() as u64);
+        let mut prev = 0;
+        for ix in changed {
+            write_varint(&mut bytes, ix - prev);
+            prev = ix;
+        }
+        bytes
+    }
+}
+
+impl FovSnapshot<bool> {
+    /// Apply a delta encoded by `Fov::update_delta` to the mask.
+    ///
+    /// Returns the sequence number of the delta.  The mask is left untouched
+    /// if the delta is invalid.
+    ///
+    pub fn apply_delta(&mut self, bytes: &[u8]) -> io::Result<u64> {
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut pos = 0;
+        let sequence = read_varint(bytes, &mut pos)?;
+        if read_varint(bytes, &mut pos)? != self.radius as u64 {
+            return Err(invalid("radius mismatch"));
+        }
+        let count = read_varint(bytes, &mut pos)?;
+        let mut changed = Vec::new();
+        let mut ix: u64 = 0;
+        for n in 0..count {
+            let gap = read_varint(bytes, &mut pos)?;
+            if n > 0 && gap == 0 {
+                return Err(invalid("cell changed twice"));
+            }
+            ix = match ix.checked_add(gap) {
+                Some(ix) if ix < self.data.len() as u64 => ix,
+                _ => return Err(invalid("change past the end of the field")),
+            };
+            changed.push(ix as usize);
+        }
+        if pos != bytes.len() {
+            return Err(invalid("trailing bytes"));
+        }
+        for ix in changed {
+            self.data[ix] = !self.data[ix];
+        }
+        Ok(sequence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn deltas_track_visibility() {
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, false);
+        let mut mask = FovSnapshot::decode_rle(&fov.encode_rle(|&v| v)).unwrap();
+        for (sequence, wall) in [3, -2, 5, 5].iter().enumerate() {
+            let delta = fov.update_delta(
+                sequence as u64,
+                |&v| v,
+                |x, _, influxes| (x == 0 || x != *wall) && influxes.iter().all(|f| *f.value),
+            );
+            assert_eq!(mask.apply_delta(&delta).unwrap(), sequence as u64);
+            assert_eq!(mask.as_slice(), fov.as_slice());
+        }
+        let unchanged = fov.update_delta(
+            9,
+            |&v| v,
+            |x, _, influxes| (x == 0 || x != 5) && influxes.iter().all(|f| *f.value),
+        );
+        assert_eq!(unchanged, [9, 6, 0]);
+        let mut other = FovSnapshot::decode_rle(&[2, 25]).unwrap();
+        assert!(other.apply_delta(&unchanged).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// Bit-identical replacements for the transcendental functions used when
+// building flux fields.
+//
+// The standard `sin` and `cos` come from the platform's math library and
+// their results may differ in the last bits between platforms.  The basic
+// arithmetic operations on the other hand are exactly specified by IEEE 754
+// and Rust never fuses them into FMAs on its own.  So we evaluate the Taylor
+// polynomials with plain multiplications and additions in a fixed order.
+// The angles are always within [0, π/4] where 24 terms are more than enough
+// for double precision.
+
+// The sine and the cosine of `theta`.
+pub(crate) fn sin_cos(theta: f64) -> (f64, f64) {
+    debug_assert!((0.0..=std::f64::consts::FRAC_PI_4).contains(&theta));
+    let (mut sin, mut cos) = (0.0, 0.0);
+    // The term theta^n / n!.
+    let mut term = 1.0;
+    for n in 0..24 {
+        let signed = if n / 2 % 2 == 0 { term } else { -term };
+        if n % 2 == 0 {
+            cos += signed;
+        } else {
+            sin += signed;
+        }
+        term *= theta / (n + 1) as f64;
+    }
+    (sin, cos)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::FluxField;
+
+    #[test]
+    fn close_to_std() {
+        for i in 0..1001 {
+            let theta = i as f64 / 1000.0 * std::f64::consts::FRAC_PI_4;
+            let (sin, cos) = sin_cos(theta);
+            assert!((sin - theta.sin()).abs() <= 1e-15, "sin({})", theta);
+            assert!((cos - theta.cos()).abs() <= 1e-15, "cos({})", theta);
+        }
+    }
+
+    // The look-up tables must come out bit-identical on every platform.
+    #[test]
+    fn golden_flux_lut() {
+        let flux_field = FluxField::new(16);
+        let hash = flux_field
+            .flux_lut
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325_u64, |h, w| {
+                (h ^ w.to_bits() as u64).wrapping_mul(0x100_0000_01b3)
+            });
+        assert_eq!(hash, 0x2f1a_db59_b6f1_0eff);
+    }
+
+    // And so must the fixed-point weights rounded from them.
+    #[test]
+    fn golden_fixed_lut() {
+        let flux_field = FluxField::new(16);
+        let hash = flux_field
+            .fixed_lut()
+            .iter()
+            .flatten()
+            .fold(0xcbf2_9ce4_8422_2325_u64, |h, &w| {
+                (h ^ w as u64).wrapping_mul(0x100_0000_01b3)
+            });
+        assert_eq!(hash, 0x509e_6459_d74c_b85a);
+    }
+}
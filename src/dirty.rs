@@ -0,0 +1,219 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::allocator::Allocator;
+use crate::{FluxField, Fov, Influx};
+
+/// The scratch space of `Fov::update_dirty`.
+///
+/// Keep one around and pass it to every dirty update so that the updates
+/// don't allocate after the first one.
+///
+#[derive(Clone, Debug, Default)]
+pub struct DirtyUpdate {
+    // The number of the current update.  A cell is changed or dirty in the
+    // current update if its mark equals it, so the marks of the earlier
+    // updates need not be cleared.
+    generation: u32,
+    changed: Vec<u32>,
+    dirty: Vec<u32>,
+    // The cells marked by `Fov::shift_origin_dirty` for the next update.
+    pub(crate) pending: Vec<(i32, i32)>,
+    // The scratch space of `Fov::shift_origin_dirty`.
+    pub(crate) sources: Vec<u32>,
+}
+
+impl DirtyUpdate {
+    /// Constructs the scratch space; it is allocated on the first update.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // Start a new update of a field with `len` cells.
+    fn begin(&mut self, len: usize) {
+        if self.changed.len() != len || self.generation == u32::MAX {
+            self.changed.clear();
+            self.changed.resize(len, 0);
+            self.dirty.clear();
+            self.dirty.resize(len, 0);
+            self.generation = 0;
+        }
+        self.generation += 1;
+    }
+}
+
+impl<T, X, A> Fov<T, X, A>
+where
+    T: PartialEq,
+    X: AsRef<FluxField>,
+    A: Allocator,
+{
+    /// Update the field of vision after a few cells of the map changed,
+    /// recomputing only the cells downstream of them.
+    ///
+    /// The field must be up to date with respect to the map as it was before
+    /// the change, the origin must not have moved, and `update_fn` must be
+    /// the same function of the map as in the earlier updates.  The update
+    /// function is called for the changed cells given in the field
+    /// coordinates and then for the cells receiving flux from a cell whose
+    /// value changed.  The flux spreads outwards only so that a change
+    /// usually reaches the cells in the shadow cone behind it, and the
+    /// spreading stops wherever the recomputed value is equal to the old
+    /// one.  The changed cells outside the field are ignored.  The cells
+    /// marked by `Fov::shift_origin_dirty` since the last update count as
+    /// changed, too.
+    ///
+    /// Only the rings from the one of the changed cell nearest to the origin
+    /// outwards are walked, and the walk stops at the first ring past the
+    /// changed cells where no value changed.  The `scratch` space is reused
+    /// between the updates.
+    ///
+    /// Returns the number of cells recomputed.
+    ///
+    pub fn update_dirty<F>(
+        &mut self,
+        scratch: &mut DirtyUpdate,
+        changed_cells: &[(i32, i32)],
+        update_fn: F,
+    ) -> usize
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        let radius = self.radius as i32;
+        let width = self.width;
+        let ix_origin = self.ix_origin;
+        let index = move |x: i32, y: i32| (ix_origin + width * y as isize + x as isize) as usize;
+        scratch.begin(self.data.len());
+        let generation = scratch.generation;
+        let (changed, dirty) = (&mut scratch.changed, &mut scratch.dirty);
+        // The rings of the changed cells nearest to and farthest from the
+        // origin.
+        let (mut first_ring, mut last_ring) = (radius + 1, -1);
+        for &(x, y) in changed_cells.iter().chain(&scratch.pending) {
+            if x.abs() <= radius && y.abs() <= radius {
+                changed[index(x, y)] = generation;
+                let ring = x.abs().max(y.abs());
+                first_ring = first_ring.min(ring);
+                last_ring = last_ring.max(ring);
+            }
+        }
+        scratch.pending.clear();
+        let mut count = 0;
+        for ring in first_ring..radius + 1 {
+            let mut spreading = false;
+            self.update_cells_of_ring(ring, |x, y, _, cell, influxes| {
+                let ix = index(x, y);
+                if changed[ix] == generation
+                    || influxes
+                        .iter()
+                        .any(|f| dirty[index(x - f.dx, y - f.dy)] == generation)
+                {
+                    count += 1;
+                    let value = update_fn(x, y, influxes);
+                    if value != *cell {
+                        *cell = value;
+                        dirty[ix] = generation;
+                        spreading = true;
+                    }
+                }
+            });
+            // The next ring gets its flux from this one.
+            if !spreading && ring >= last_ring {
+                break;
+            }
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel::{self, Illumination};
+    use crate::testing::all_params;
+    use crate::FluxParams;
+
+    fn check_dirty(params: FluxParams) {
+        let radius = 20;
+        let flux_field = std::rc::Rc::new(FluxField::with_params(radius, params));
+        let mut walls = vec![(12, 5), (-14, 0), (0, -15)];
+        let opacity = |walls: &[(i32, i32)]| {
+            let walls = walls.to_vec();
+            move |x, y| walls.contains(&(x, y)) as u8 as f32
+        };
+        let mut fov = Fov::new(flux_field.clone(), radius, Illumination::default());
+        fov.update(kernel::soft_shadow(opacity(&walls)));
+        let mut full = Fov::new(flux_field, radius, Illumination::default());
+        let mut scratch = DirtyUpdate::new();
+        for (removed, added) in [
+            ((12, 5), (-11, 13)),
+            ((-14, 0), (16, -2)),
+            ((0, -15), (0, -15)),
+        ] {
+            walls.retain(|&cell| cell != removed);
+            walls.push(added);
+            let count = fov.update_dirty(
+                &mut scratch,
+                &[removed, added],
+                kernel::soft_shadow(opacity(&walls)),
+            );
+            full.update(kernel::soft_shadow(opacity(&walls)));
+            assert_eq!(fov.as_slice(), full.as_slice());
+            assert!(count < fov.as_slice().len() / 4, "{}", count);
+        }
+        // Nothing changes unless told.
+        walls.push((1, 1));
+        assert_eq!(
+            fov.update_dirty(&mut scratch, &[], kernel::soft_shadow(opacity(&walls))),
+            0
+        );
+        assert_eq!(fov.as_slice(), full.as_slice());
+        assert_eq!(
+            fov.update_dirty(
+                &mut scratch,
+                &[(99, 0)],
+                kernel::soft_shadow(opacity(&walls))
+            ),
+            0
+        );
+    }
+
+    #[test]
+    fn scratch_is_shared_between_fields() {
+        let walls = [(2, 1), (-3, 4)];
+        let opacity = |x, y| walls.contains(&(x, y)) as u8 as f32;
+        let mut scratch = DirtyUpdate::new();
+        // Also wrap the generation around on the way.
+        scratch.generation = u32::MAX - 2;
+        for &radius in &[5, 8, 5, 8, 3] {
+            let flux_field = Box::new(FluxField::new(radius));
+            let mut fov = Fov::new(flux_field, radius, Illumination::default());
+            fov.update_dirty(&mut scratch, &[(0, 0)], kernel::soft_shadow(opacity));
+            let mut full = Fov::new(Box::new(FluxField::new(radius)), radius, Default::default());
+            full.update(kernel::soft_shadow(opacity));
+            assert_eq!(fov.as_slice(), full.as_slice(), "radius {}", radius);
+        }
+    }
+
+    #[test]
+    fn dirty_update_matches_full_update() {
+        for params in all_params() {
+            check_dirty(params);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{FluxField, Fov};
+
+/// A field of vision reduced to blocks of cells; see `Fov::downsample`.
+///
+/// The blocks are addressed like the cells of a field of vision, relative to
+/// the block containing the origin.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Downsampled<U> {
+    factor: usize,
+    radius: i32,
+    data: Vec<U>,
+}
+
+impl<U> Downsampled<U> {
+    /// The side of the blocks in cells.
+    pub fn factor(&self) -> usize {
+        self.factor
+    }
+
+    /// The radius of the downsampled grid in blocks.
+    pub fn radius(&self) -> usize {
+        self.radius as usize
+    }
+
+    /// The value of the block (`bx`, `by`).
+    pub fn at(&self, bx: i32, by: i32) -> &U {
+        assert!(
+            bx.abs() <= self.radius && by.abs() <= self.radius,
+            "block out of range"
+        );
+        let width = 2 * self.radius + 1;
+        &self.data[((by + self.radius) * width + bx + self.radius) as usize]
+    }
+
+    /// Expose the blocks as a slice in row-major order.
+    pub fn as_slice(&self) -> &[U] {
+        self.data.as_slice()
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Reduce the field to blocks of `factor` by `factor` cells, e.g., for
+    /// minimaps or for coarse reasoning by the AI.
+    ///
+    /// The blocks are aligned so that the origin is at the center of the
+    /// block (0, 0), or just off the center for even factors.  The cell
+    /// (x, y) falls on the block (⌊(x + factor / 2) / factor⌋, ⌊(y + factor /
+    /// 2) / factor⌋).  The `reducer` receives the values of the cells of each
+    /// block in row-major order; the blocks on the border of the field
+    /// receive only the cells within the field, possibly none for even
+    /// factors.
+    ///
+    /// ```
+    /// # use flux_fov::{FluxField, Fov};
+    /// let mut fov = Fov::new(Box::new(FluxField::new(4)), 4, 0.0_f32);
+    /// fov.update(|x, _, _| x as f32);
+    /// let max = fov.downsample(3, |cells| cells.iter().fold(f32::MIN, |m, &&v| m.max(v)));
+    /// let any = fov.downsample(3, |cells| cells.iter().any(|&&v| v > 3.0));
+    /// assert_eq!(max.radius(), 1);
+    /// assert_eq!(*max.at(0, 0), 1.0);
+    /// assert_eq!(*max.at(1, -1), 4.0);
+    /// assert!(*any.at(1, 1) && !*any.at(0, 1));
+    /// ```
+    ///
+    pub fn downsample<U, F>(&self, factor: usize, reducer: F) -> Downsampled<U>
+    where
+        F: FnMut(&[&T]) -> U,
+    {
+        assert!(factor > 0, "zero downsampling factor");
+        let mut reducer = reducer;
+        let radius = self.radius as i32;
+        let f = factor as i32;
+        let half = f / 2;
+        let block = |c: i32| (c + half).div_euclid(f);
+        let block_radius = block(radius).max(-block(-radius));
+        let mut data =
+            Vec::with_capacity(((2 * block_radius + 1) * (2 * block_radius + 1)) as usize);
+        let mut cells = Vec::with_capacity(factor * factor);
+        for by in -block_radius..block_radius + 1 {
+            for bx in -block_radius..block_radius + 1 {
+                cells.clear();
+                let (x0, y0) = (bx * f - half, by * f - half);
+                for y in y0.max(-radius)..(y0 + f).min(radius + 1) {
+                    for x in x0.max(-radius)..(x0 + f).min(radius + 1) {
+                        cells.push(self.at(x, y));
+                    }
+                }
+                data.push(reducer(&cells));
+            }
+        }
+        Downsampled {
+            factor,
+            radius: block_radius,
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn downsample_blocks() {
+        let mut fov = Fov::new(Box::new(FluxField::new(5)), 5, (0, 0));
+        fov.update(|x, y, _| (x, y));
+        let blocks = fov.downsample(3, |cells| cells.iter().map(|&&c| c).collect::<Vec<_>>());
+        assert_eq!(blocks.factor(), 3);
+        assert_eq!(blocks.radius(), 2);
+        assert_eq!(blocks.as_slice().len(), 25);
+        assert_eq!(
+            blocks.at(0, 0),
+            &vec![
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (0, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1)
+            ]
+        );
+        // The block covers x from 5 to 7 and y from -7 to -5.
+        assert_eq!(blocks.at(2, -2), &vec![(5, -5)]);
+        let counts = fov.downsample(2, |cells| cells.len());
+        assert_eq!(counts.radius(), 3);
+        assert_eq!(*counts.at(0, 0), 4);
+        assert_eq!(*counts.at(3, 0), 2);
+        assert_eq!(*counts.at(3, 3), 1);
+        assert_eq!(*counts.at(-3, -3), 0);
+        assert_eq!(counts.as_slice().iter().sum::<usize>(), 121);
+        assert_eq!(
+            fov.downsample(1, |cells| *cells[0]).as_slice(),
+            fov.as_slice()
+        );
+    }
+}
@@ -0,0 +1,246 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{FluxField, Fov, Influx};
+
+/// A field of vision with an elliptical footprint, e.g., with a wider range
+/// horizontally than vertically, or a circular one.
+///
+/// The footprint covers the cells (x, y) with
+///
+/// ```text
+/// x² / (rx² + rx) + y² / (ry² + ry) <= 1
+/// ```
+///
+/// so that a circle is the same as `Metric::Euclidean`.  The cells outside
+/// the footprint are never visited by the update, so they cost nothing and
+/// keep their initial value.  The footprint shrinks towards the origin along
+/// both axes so every cell inside receives its flux from cells inside and
+/// the flux dependencies are respected:
+///
+/// ```
+/// # use flux_fov::{FluxField, Fov};
+/// let mut fov = Fov::new_elliptical(Box::new(FluxField::new(8)), 8, 3, false);
+/// let mut calls = 0;
+/// fov.update(|_, _, _| {
+///     calls += 1;
+///     true
+/// });
+/// assert!(*fov.at(8, 0) && *fov.at(0, 3));
+/// assert!(!*fov.at(0, 4) && !*fov.at(7, 2));
+/// assert_eq!(calls, fov.cell_count());
+/// ```
+///
+pub struct EllipticalFov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    fov: Fov<T, X>,
+    radius_x: usize,
+    radius_y: usize,
+    // The largest |x| within the footprint on each row from y = -R to R.
+    extents: Vec<i32>,
+}
+
+impl<T, X> Fov<T, X>
+where
+    T: Clone,
+    X: AsRef<FluxField>,
+{
+    /// Constructs a field of vision with an elliptical footprint reaching
+    /// `radius_x` cells along the x-axis and `radius_y` cells along the
+    /// y-axis; see `EllipticalFov`.
+    ///
+    /// The flux field must cover the larger of the two.
+    ///
+    pub fn new_elliptical(
+        flux_field: X,
+        radius_x: usize,
+        radius_y: usize,
+        init: T,
+    ) -> EllipticalFov<T, X> {
+        EllipticalFov::new(flux_field, radius_x, radius_y, init)
+    }
+}
+
+impl<T, X> EllipticalFov<T, X>
+where
+    T: Clone,
+    X: AsRef<FluxField>,
+{
+    /// Constructs a field of vision reaching `radius_x` cells along the
+    /// x-axis and `radius_y` cells along the y-axis; the same as
+    /// `Fov::new_elliptical`.
+    ///
+    /// The flux field must cover the larger of the two.
+    ///
+    pub fn new(flux_field: X, radius_x: usize, radius_y: usize, init: T) -> Self {
+        let radius = radius_x.max(radius_y);
+        let fov = Fov::new(flux_field, radius, init);
+        let (rx, ry) = (radius_x as i64, radius_y as i64);
+        let (ax, ay) = (rx * rx + rx, ry * ry + ry);
+        let extents = (-(radius as i64)..radius as i64 + 1)
+            .map(|y| {
+                if y.abs() > ry {
+                    return -1;
+                }
+                // The largest x with x² ay + y² ax <= ax ay.
+                let mut x = rx;
+                while x > 0 && x * x * ay + y * y * ax > ax * ay {
+                    x -= 1;
+                }
+                x as i32
+            })
+            .collect();
+        EllipticalFov {
+            fov,
+            radius_x,
+            radius_y,
+            extents,
+        }
+    }
+}
+
+impl<T, X> EllipticalFov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    pub fn radius_x(&self) -> usize {
+        self.radius_x
+    }
+
+    pub fn radius_y(&self) -> usize {
+        self.radius_y
+    }
+
+    /// Whether the cell is within the footprint.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        let radius = self.fov.radius() as i32;
+        y.abs() <= radius && x.abs() <= self.extents[(y + radius) as usize]
+    }
+
+    /// The number of cells within the footprint.
+    pub fn cell_count(&self) -> usize {
+        self.extents
+            .iter()
+            .map(|&extent| (2 * extent + 1).max(0) as usize)
+            .sum()
+    }
+
+    /// The value at the given grid cell.
+    ///
+    /// Panics if the cell is outside the square enclosing the footprint.
+    ///
+    pub fn at(&self, x: i32, y: i32) -> &T {
+        self.fov.at(x, y)
+    }
+
+    /// The underlying field of vision whose radius is the larger of the two.
+    pub fn fov(&self) -> &Fov<T, X> {
+        &self.fov
+    }
+
+    /// Update the cells within the footprint like `Fov::update` does.
+    ///
+    pub fn update<F>(&mut self, update_fn: F)
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        let radius = self.fov.radius() as i32;
+        let extents = &self.extents;
+        self.fov.update_cells_within(
+            |x, y| x.abs() <= extents[(y + radius) as usize],
+            |x, y, _, cell, influxes| *cell = update_fn(x, y, influxes),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel::{self, Illumination};
+    use crate::testing::all_params;
+    use crate::Metric;
+    use std::rc::Rc;
+
+    #[test]
+    fn circle_matches_euclidean_metric() {
+        let fov = EllipticalFov::new(Box::new(FluxField::new(9)), 9, 9, 0);
+        for y in -9..10 {
+            for x in -9..10 {
+                assert_eq!(fov.contains(x, y), Metric::Euclidean.contains(9, x, y));
+            }
+        }
+        let flat = EllipticalFov::new(Box::new(FluxField::new(5)), 5, 0, 0);
+        assert_eq!(flat.cell_count(), 11);
+        assert!(flat.contains(-5, 0) && !flat.contains(0, 1));
+    }
+
+    #[test]
+    fn corners_are_never_visited() {
+        for params in all_params() {
+            let mut ellipse =
+                Fov::new_elliptical(Box::new(FluxField::with_params(10, params)), 10, 4, 0);
+            let radius = 10;
+            let (extents, fov) = (&ellipse.extents, &mut ellipse.fov);
+            let mut visits = 0;
+            fov.update_cells_within(
+                |x, y| x.abs() <= extents[(y + radius) as usize],
+                |x, y, _, _, _| {
+                    assert!(x.abs() <= extents[(y + radius) as usize]);
+                    visits += 1;
+                },
+            );
+            assert_eq!(visits, ellipse.cell_count());
+        }
+    }
+
+    #[test]
+    fn ellipse_reads_only_cells_inside() {
+        let opacity = |x: i32, y: i32| ((x * 3 + y * 5).rem_euclid(9) == 0) as u8 as f32;
+        for params in all_params() {
+            let flux_field = Rc::new(FluxField::with_params(12, params));
+            let mut square = Fov::new(flux_field.clone(), 12, Illumination::default());
+            square.update(kernel::soft_shadow(opacity));
+            // Mark the cells outside with a brightness no kernel yields.
+            let outside = Illumination {
+                brightness: -1.0,
+                ray_output: -1.0,
+            };
+            let mut ellipse = EllipticalFov::new(flux_field, 12, 5, outside);
+            let mut kernel = kernel::soft_shadow(opacity);
+            let mut calls = 0;
+            ellipse.update(|x, y, influxes| {
+                calls += 1;
+                assert!(influxes.iter().all(|f| f.value.ray_output >= 0.0));
+                kernel(x, y, influxes)
+            });
+            assert_eq!(calls, ellipse.cell_count());
+            assert!(calls < 25 * 25 / 2);
+            for y in -12..13 {
+                for x in -12..13 {
+                    if ellipse.contains(x, y) {
+                        assert_eq!(ellipse.at(x, y), square.at(x, y));
+                    } else {
+                        assert_eq!(*ellipse.at(x, y), outside);
+                    }
+                }
+            }
+        }
+    }
+}
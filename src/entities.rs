@@ -0,0 +1,139 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use crate::{FluxField, Fov};
+
+/// The changes in the visibility of the entities reported by
+/// `EntityTracker::update`.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VisibilityEvents<K> {
+    /// The entities that became visible, in the order they were listed.
+    pub appeared: Vec<K>,
+    /// The entities that are no longer visible, including those no longer
+    /// listed, in no particular order.
+    pub disappeared: Vec<K>,
+}
+
+/// Keeps track of which entities are visible from one update to the next.
+///
+/// ```
+/// # use flux_fov::{EntityTracker, FluxField, Fov};
+/// let sight = Fov::new(Box::new(FluxField::new(4)), 4, true);
+/// let mut tracker = EntityTracker::new();
+/// let events = tracker.update(&sight, (10, 10), vec![("goblin", (12, 9))], |&v| v);
+/// assert_eq!(events.appeared, vec!["goblin"]);
+/// ```
+///
+#[derive(Clone, Debug, Default)]
+pub struct EntityTracker<K>
+where
+    K: Eq + Hash,
+{
+    visible: HashSet<K>,
+}
+
+impl<K> EntityTracker<K>
+where
+    K: Clone + Eq + Hash,
+{
+    /// Constructs a tracker with no entity visible.
+    pub fn new() -> Self {
+        EntityTracker {
+            visible: HashSet::new(),
+        }
+    }
+
+    /// Whether the entity was visible in the last update.
+    pub fn is_visible(&self, entity: &K) -> bool {
+        self.visible.contains(entity)
+    }
+
+    /// The entities visible in the last update.
+    pub fn visible(&self) -> impl Iterator<Item = &K> {
+        self.visible.iter()
+    }
+
+    /// Check which of the `entities`, given with their map positions, are
+    /// visible in the field `sight` centered at the map cell `origin` and
+    /// report the changes since the previous update.
+    ///
+    /// The entities outside the field are not visible.
+    ///
+    pub fn update<T, X, I, P>(
+        &mut self,
+        sight: &Fov<T, X>,
+        origin: (i32, i32),
+        entities: I,
+        is_visible: P,
+    ) -> VisibilityEvents<K>
+    where
+        X: AsRef<FluxField>,
+        I: IntoIterator<Item = (K, (i32, i32))>,
+        P: Fn(&T) -> bool,
+    {
+        let radius = sight.radius() as i32;
+        let mut visible = HashSet::new();
+        let mut appeared = Vec::new();
+        for (entity, (x, y)) in entities {
+            let (dx, dy) = (x - origin.0, y - origin.1);
+            if dx.abs() <= radius && dy.abs() <= radius && is_visible(sight.at(dx, dy)) {
+                if !self.visible.contains(&entity) && !visible.contains(&entity) {
+                    appeared.push(entity.clone());
+                }
+                visible.insert(entity);
+            }
+        }
+        let disappeared = self.visible.difference(&visible).cloned().collect();
+        self.visible = visible;
+        VisibilityEvents {
+            appeared,
+            disappeared,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn entities_appear_and_disappear() {
+        let mut sight = Fov::new(Box::new(FluxField::new(3)), 3, false);
+        sight.update(|x, _, _| x >= 0);
+        let mut tracker = EntityTracker::new();
+        let events = tracker.update(
+            &sight,
+            (5, 5),
+            vec![(1, (6, 5)), (2, (4, 5)), (3, (9, 5))],
+            |&v| v,
+        );
+        assert_eq!(events.appeared, vec![1]);
+        assert!(events.disappeared.is_empty());
+        let events = tracker.update(&sight, (3, 5), vec![(1, (6, 5)), (2, (4, 5))], |&v| v);
+        assert_eq!(events.appeared, vec![2]);
+        assert!(events.disappeared.is_empty());
+        let events = tracker.update(&sight, (5, 5), vec![(1, (7, 5))], |&v| v);
+        assert!(events.appeared.is_empty());
+        assert_eq!(events.disappeared, vec![2]);
+        assert!(tracker.is_visible(&1));
+        assert!(!tracker.is_visible(&2));
+    }
+}
@@ -0,0 +1,54 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::error;
+use std::fmt;
+
+/// The largest radius of a flux field.
+///
+/// The coordinates of the cells must fit in an `i32` and the rays cast to
+/// build the look-up table in a `usize` on every platform.
+///
+pub const MAX_RADIUS: usize = 1 << 15;
+
+/// The reason `FluxField::try_new` or `Fov::try_new` failed.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The radius of the flux field exceeds `MAX_RADIUS`.
+    RadiusOutOfRange { radius: usize },
+    /// The radius of the field of vision exceeds that of the flux field.
+    RadiusTooLarge { radius: usize, max: usize },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::RadiusOutOfRange { radius } => write!(
+                f,
+                "radius {} exceeds the maximum radius {}",
+                radius, MAX_RADIUS
+            ),
+            Error::RadiusTooLarge { radius, max } => write!(
+                f,
+                "radius {} exceeds the radius {} of the flux field",
+                radius, max
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {}
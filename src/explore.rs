@@ -0,0 +1,276 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::compose::for_each_tile;
+use crate::{FluxField, Fov};
+
+/// A fog-of-war layer remembering which map tiles have ever been seen.
+///
+pub struct ExploredMap {
+    width: usize,
+    height: usize,
+    explored: Vec<bool>,
+}
+
+impl ExploredMap {
+    /// Constructs a `width` by `height` map with nothing explored.
+    ///
+    pub fn new(width: usize, height: usize) -> Self {
+        ExploredMap {
+            width,
+            height,
+            explored: vec![false; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Whether the given tile has been explored.
+    pub fn is_explored(&self, x: usize, y: usize) -> bool {
+        assert!(x < self.width && y < self.height, "tile out of range");
+        self.explored[y * self.width + x]
+    }
+
+    /// Mark the given tile explored.
+    pub fn explore(&mut self, x: usize, y: usize) {
+        assert!(x < self.width && y < self.height, "tile out of range");
+        self.explored[y * self.width + x] = true;
+    }
+
+    /// Expose the explored flags as a slice in row-major order.
+    pub fn as_slice(&self) -> &[bool] {
+        self.explored.as_slice()
+    }
+
+    /// Mark explored every tile visible in the field of vision `sight`
+    /// centered at the tile (`x`, `y`).
+    ///
+    pub fn reveal<T, X, P>(&mut self, x: i32, y: i32, sight: &Fov<T, X>, is_visible: P)
+    where
+        X: AsRef<FluxField>,
+        P: Fn(&T) -> bool,
+    {
+        let explored = &mut self.explored;
+        for_each_tile(self.width, self.height, x, y, sight, |ix, value| {
+            if is_visible(value) {
+                explored[ix] = true;
+            }
+        });
+    }
+
+    /// The number of explored tiles.
+    pub fn explored_count(&self) -> usize {
+        self.explored.iter().filter(|&&e| e).count()
+    }
+
+    /// The percentage of the map explored, between 0.0 and 100.0.
+    ///
+    pub fn explored_percentage(&self) -> f32 {
+        if self.explored.is_empty() {
+            0.0
+        } else {
+            100.0 * self.explored_count() as f32 / self.explored.len() as f32
+        }
+    }
+
+    /// Whether the given tile is a frontier, that is an explored tile
+    /// adjacent (including diagonally) to an unexplored tile.
+    ///
+    pub fn is_frontier(&self, x: usize, y: usize) -> bool {
+        if !self.is_explored(x, y) {
+            return false;
+        }
+        let x = x as isize;
+        let y = y as isize;
+        for ny in y - 1..y + 2 {
+            for nx in x - 1..x + 2 {
+                if 0 <= nx
+                    && nx < self.width as isize
+                    && 0 <= ny
+                    && ny < self.height as isize
+                    && !self.is_explored(nx as usize, ny as usize)
+                {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Iterate over the frontier tiles in row-major order.
+    ///
+    pub fn frontiers(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let width = self.width;
+        (0..self.explored.len())
+            .map(move |ix| (ix % width, ix / width))
+            .filter(move |&(x, y)| self.is_frontier(x, y))
+    }
+
+    /// The number of frontier tiles.
+    pub fn frontier_count(&self) -> usize {
+        self.frontiers().count()
+    }
+
+    /// Group the frontier tiles into segments of tiles connected to each
+    /// other (including diagonally).
+    ///
+    /// The segments are ordered by their first tile in row-major order and
+    /// the tiles within each segment are in row-major order, too.
+    ///
+    pub fn frontier_segments(&self) -> Vec<FrontierSegment> {
+        let mut segment_of = vec![usize::MAX; self.explored.len()];
+        let mut segments = Vec::new();
+        let mut stack = Vec::new();
+        for (x, y) in self.frontiers() {
+            if segment_of[y * self.width + x] != usize::MAX {
+                continue;
+            }
+            let id = segments.len();
+            let mut tiles = Vec::new();
+            segment_of[y * self.width + x] = id;
+            stack.push((x, y));
+            while let Some((x, y)) = stack.pop() {
+                tiles.push((x, y));
+                for ny in y.saturating_sub(1)..(y + 2).min(self.height) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(self.width) {
+                        let ix = ny * self.width + nx;
+                        if segment_of[ix] == usize::MAX && self.is_frontier(nx, ny) {
+                            segment_of[ix] = id;
+                            stack.push((nx, ny));
+                        }
+                    }
+                }
+            }
+            tiles.sort_by_key(|&(x, y)| (y, x));
+            segments.push(FrontierSegment::new(tiles));
+        }
+        segments
+    }
+}
+
+/// A contiguous segment of frontier tiles.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrontierSegment {
+    tiles: Vec<(usize, usize)>,
+    centroid: (f32, f32),
+}
+
+impl FrontierSegment {
+    fn new(tiles: Vec<(usize, usize)>) -> Self {
+        let n = tiles.len() as f32;
+        let (sx, sy) = tiles.iter().fold((0.0, 0.0), |(sx, sy), &(x, y)| {
+            (sx + x as f32, sy + y as f32)
+        });
+        FrontierSegment {
+            tiles,
+            centroid: (sx / n, sy / n),
+        }
+    }
+
+    /// The tiles of the segment in row-major order.
+    pub fn tiles(&self) -> &[(usize, usize)] {
+        &self.tiles
+    }
+
+    /// The mean position of the tiles of the segment.  Note that it need
+    /// not be a tile of the segment itself.
+    ///
+    pub fn centroid(&self) -> (f32, f32) {
+        self.centroid
+    }
+
+    /// The frontier tile closest to the centroid, a natural target for
+    /// autoexplore.
+    ///
+    pub fn representative(&self) -> (usize, usize) {
+        let (cx, cy) = self.centroid;
+        let distance = |&(x, y): &(usize, usize)| (x as f32 - cx).powi(2) + (y as f32 - cy).powi(2);
+        *self
+            .tiles
+            .iter()
+            .min_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap())
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn exploration_statistics() {
+        let sight = Fov::new(Box::new(FluxField::new(1)), 1, true);
+        let mut map = ExploredMap::new(5, 4);
+        assert_eq!(map.explored_percentage(), 0.0);
+        map.reveal(0, 0, &sight, |&visible| visible);
+        assert_eq!(map.explored_count(), 4);
+        assert_eq!(map.explored_percentage(), 20.0);
+        assert_eq!(
+            map.frontiers().collect::<Vec<_>>(),
+            vec![(1, 0), (0, 1), (1, 1)]
+        );
+        map.reveal(3, 2, &sight, |&visible| visible);
+        assert_eq!(map.explored_count(), 13);
+        assert!(!map.is_frontier(4, 3));
+        assert_eq!(map.frontier_count(), 8);
+    }
+
+    #[test]
+    fn frontier_segments() {
+        let sight = Fov::new(Box::new(FluxField::new(1)), 1, true);
+        let mut map = ExploredMap::new(9, 5);
+        map.reveal(0, 0, &sight, |&visible| visible);
+        map.reveal(7, 3, &sight, |&visible| visible);
+        let segments = map.frontier_segments();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].tiles(), &[(1, 0), (0, 1), (1, 1)]);
+        assert_eq!(segments[0].centroid(), (2.0 / 3.0, 2.0 / 3.0));
+        assert_eq!(segments[0].representative(), (1, 1));
+        assert_eq!(
+            segments[1].tiles(),
+            &[(6, 2), (7, 2), (8, 2), (6, 3), (6, 4)]
+        );
+        assert_eq!(segments[1].representative(), (7, 2));
+        // Joining the explored areas merges the segments.
+        for x in 0..9 {
+            map.explore(x, 1);
+        }
+        assert_eq!(map.frontier_segments().len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "tile out of range")]
+    fn explore_out_of_range() {
+        // Within the map but past the right edge of the first row.
+        let mut map = ExploredMap::new(4, 3);
+        map.explore(4, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "tile out of range")]
+    fn is_explored_out_of_range() {
+        let map = ExploredMap::new(4, 3);
+        map.is_explored(4, 0);
+    }
+}
@@ -0,0 +1,249 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::allocator::Allocator;
+use crate::{FluxField, Fov, Influx};
+
+/// A curve of the fraction of the flux left at a distance from the origin;
+/// see `Fov::update_attenuated`.
+///
+pub trait Attenuation {
+    /// The fraction of the flux left at the Euclidean `distance` from the
+    /// origin of a field of the given `radius`.
+    fn at(&self, distance: f32, radius: f32) -> f32;
+
+    /// The fraction of the flux left at the cell (`x`, `y`) relative to the
+    /// origin.
+    fn at_cell(&self, x: i32, y: i32, radius: f32) -> f32 {
+        self.at(((x * x + y * y) as f32).sqrt(), radius)
+    }
+}
+
+/// How the light of a source dims with the distance.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Falloff {
+    /// No dimming; the light reaches the radius at full intensity.
+    Constant,
+    /// Dims linearly from the full intensity at the source to nothing at the
+    /// radius.
+    Linear,
+    /// Dims as 1 / (1 + d²) at the Euclidean distance d like a point light.
+    InverseSquare,
+    /// Dims smoothly from the full intensity at the source to nothing at the
+    /// radius along the smoothstep curve.
+    Smooth,
+}
+
+impl Falloff {
+    /// The fraction of the light left at the `distance` from a source of the
+    /// given `radius`.
+    ///
+    pub fn at(self, distance: f32, radius: f32) -> f32 {
+        let t = (distance / (radius + 1.0)).min(1.0);
+        match self {
+            Falloff::Constant => 1.0,
+            Falloff::Linear => 1.0 - t,
+            Falloff::InverseSquare => 1.0 / (1.0 + distance * distance),
+            Falloff::Smooth => 1.0 - t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+impl Attenuation for Falloff {
+    fn at(&self, distance: f32, radius: f32) -> f32 {
+        Falloff::at(*self, distance, radius)
+    }
+}
+
+/// A custom falloff curve given as a look-up table.
+///
+/// The samples are spaced evenly from the origin to one cell past the
+/// radius like the parameter of the built-in curves of `Falloff`, and the
+/// curve is interpolated linearly between them.  The last sample holds
+/// beyond.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct FalloffTable {
+    samples: Vec<f32>,
+}
+
+impl FalloffTable {
+    /// Constructs a table of the given samples.
+    ///
+    pub fn new(samples: Vec<f32>) -> Self {
+        assert!(samples.len() >= 2, "too few samples");
+        FalloffTable { samples }
+    }
+
+    /// Constructs a table of `len` samples of the curve `f` of the
+    /// parameter from 0.0 at the origin to 1.0 one cell past the radius.
+    ///
+    pub fn from_fn<F>(len: usize, f: F) -> Self
+    where
+        F: Fn(f32) -> f32,
+    {
+        assert!(len >= 2, "too few samples");
+        let last = (len - 1) as f32;
+        FalloffTable::new((0..len).map(|i| f(i as f32 / last)).collect())
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+}
+
+impl Attenuation for FalloffTable {
+    fn at(&self, distance: f32, radius: f32) -> f32 {
+        let last = self.samples.len() - 1;
+        let s = (distance / (radius + 1.0)).clamp(0.0, 1.0) * last as f32;
+        let i = (s as usize).min(last - 1);
+        let f = s - i as f32;
+        self.samples[i] * (1.0 - f) + self.samples[i + 1] * f
+    }
+}
+
+impl<T, X, A> Fov<T, X, A>
+where
+    X: AsRef<FluxField>,
+    A: Allocator,
+{
+    /// Update the field of vision with the flux attenuated by the distance
+    /// from the origin.
+    ///
+    /// Works like `update` but the weight of each influx is multiplied by
+    /// the attenuation of the cell relative to that of the cell the flux
+    /// comes from.  The attenuation doesn't compound along the way, so an
+    /// update function that passes on the weighted sum of the influxes sees
+    /// the flux of the origin scaled by the `curve` at the distance of the
+    /// cell.  The value of the origin is up to `update_fn` as usual.
+    ///
+    /// ```
+    /// # use flux_fov::{kernel, Falloff, FluxField, Fov};
+    /// let mut fov = Fov::new(Box::new(FluxField::new(8)), 8, Default::default());
+    /// // Soft-edged light with walls at x = 3.
+    /// fov.update_attenuated(&Falloff::Smooth, kernel::soft_shadow(|x, _| (x == 3) as u8 as f32));
+    /// assert!(fov.at(0, 1).brightness > 0.9);
+    /// assert!(fov.at(0, 8).brightness < 0.1);
+    /// assert_eq!(fov.at(5, 0).brightness, 0.0);
+    /// ```
+    ///
+    pub fn update_attenuated<C, F>(&mut self, curve: &C, update_fn: F)
+    where
+        C: Attenuation + ?Sized,
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        let radius = self.radius as i32;
+        let attenuation: Vec<f32> = (-radius..radius + 1)
+            .flat_map(|y| (-radius..radius + 1).map(move |x| (x, y)))
+            .map(|(x, y)| curve.at_cell(x, y, radius as f32))
+            .collect();
+        let width = self.width;
+        let ix_origin = self.ix_origin;
+        let index = move |x: i32, y: i32| (ix_origin + width * y as isize + x as isize) as usize;
+        self.update_cells(|x, y, _, cell, influxes| {
+            let to = attenuation[index(x, y)];
+            let attenuated = |f: &Influx<&T>| {
+                let from = attenuation[index(x - f.dx, y - f.dy)];
+                if from > 0.0 {
+                    f.weight * to / from
+                } else {
+                    0.0
+                }
+            };
+            *cell = match influxes {
+                [] => update_fn(x, y, &[]),
+                [a] => update_fn(x, y, &[reweight(a, attenuated(a))]),
+                [a, b] => update_fn(
+                    x,
+                    y,
+                    &[reweight(a, attenuated(a)), reweight(b, attenuated(b))],
+                ),
+                [a, b, c, ..] => update_fn(
+                    x,
+                    y,
+                    &[
+                        reweight(a, attenuated(a)),
+                        reweight(b, attenuated(b)),
+                        reweight(c, attenuated(c)),
+                    ],
+                ),
+            };
+        });
+    }
+}
+
+// The influx with the given weight.
+fn reweight<'a, T>(influx: &Influx<&'a T>, weight: f32) -> Influx<&'a T> {
+    Influx {
+        weight,
+        dx: influx.dx,
+        dy: influx.dy,
+        value: influx.value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn light(fov: &mut Fov<f32, Box<FluxField>>, curve: &dyn Attenuation) {
+        fov.update_attenuated(curve, |x, y, influxes| {
+            if x == 0 && y == 0 {
+                1.0
+            } else {
+                influxes.iter().map(|f| f.weight * f.value).sum()
+            }
+        });
+    }
+
+    #[test]
+    fn attenuation_follows_the_curve() {
+        let radius = 10;
+        let mut fov = Fov::new(Box::new(FluxField::new(radius)), radius, 0.0f32);
+        for curve in [
+            Falloff::Constant,
+            Falloff::Linear,
+            Falloff::InverseSquare,
+            Falloff::Smooth,
+        ] {
+            light(&mut fov, &curve);
+            for (x, y) in [(0, 0), (1, 0), (3, -4), (-7, 2), (10, 10), (0, 10)] {
+                let expected = curve.at_cell(x, y, radius as f32);
+                assert!((fov.at(x, y) - expected).abs() < 1e-5, "{:?}", curve);
+            }
+        }
+    }
+
+    #[test]
+    fn table_interpolates_samples() {
+        let table = FalloffTable::from_fn(5, |t| 1.0 - t);
+        assert_eq!(table.samples(), &[1.0, 0.75, 0.5, 0.25, 0.0]);
+        for distance in [0.0, 1.3, 4.0, 8.5, 11.0, 20.0] {
+            let expected = Falloff::Linear.at(distance, 10.0);
+            assert!((Attenuation::at(&table, distance, 10.0) - expected).abs() < 1e-6);
+        }
+        let steps = FalloffTable::new(vec![1.0, 1.0, 0.0]);
+        assert_eq!(steps.at(5.5, 10.0), 1.0);
+        assert_eq!(steps.at(8.25, 10.0), 0.5);
+        let mut fov = Fov::new(Box::new(FluxField::new(10)), 10, 0.0f32);
+        light(&mut fov, &steps);
+        assert_eq!(*fov.at(5, 0), 1.0);
+        assert_eq!(*fov.at(-10, 10), 0.0);
+    }
+}
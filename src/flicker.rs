@@ -0,0 +1,126 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{FluxField, Fov, Influx};
+
+/// A precomputed animation of a flickering or pulsing light.
+///
+/// The frames are computed once with full updates and then blended cheaply
+/// each frame of the game so that a purely cosmetic animation doesn't cost a
+/// flux update every frame.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightAnimation {
+    radius: usize,
+    frames: Vec<Vec<f32>>,
+}
+
+impl LightAnimation {
+    /// Precompute `frame_count` frames of the light.
+    ///
+    /// The `update_fn` is called like the update function of `Fov::update`
+    /// with the index of the frame as the first argument.  The `light` field
+    /// is used for the updates and is left holding the last frame.
+    ///
+    pub fn precompute<X, F>(light: &mut Fov<f32, X>, frame_count: usize, update_fn: F) -> Self
+    where
+        X: AsRef<FluxField>,
+        F: FnMut(usize, i32, i32, &[Influx<&f32>]) -> f32,
+    {
+        assert!(frame_count > 0, "no frames");
+        let mut update_fn = update_fn;
+        let frames = (0..frame_count)
+            .map(|frame| {
+                light.update(|x, y, influxes| update_fn(frame, x, y, influxes));
+                light.as_slice().to_vec()
+            })
+            .collect();
+        LightAnimation {
+            radius: light.radius(),
+            frames,
+        }
+    }
+
+    /// The number of frames.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Always false; an animation has at least one frame.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// The values of the given frame laid out as in `Fov::as_slice`.
+    pub fn frame(&self, frame: usize) -> &[f32] {
+        &self.frames[frame]
+    }
+
+    /// Write the light at the given `time` into `out`.
+    ///
+    /// The time is measured in frames and wraps around at the end of the
+    /// animation.  Between the frames the values are interpolated linearly,
+    /// the last frame blending back into the first.  The field `out` must
+    /// have the same radius as the one the animation was computed with.
+    ///
+    pub fn sample_into<X>(&self, time: f32, out: &mut Fov<f32, X>)
+    where
+        X: AsRef<FluxField>,
+    {
+        assert_eq!(self.radius, out.radius(), "radius mismatch");
+        let time = time.rem_euclid(self.frames.len() as f32);
+        let first = (time.floor() as usize).min(self.frames.len() - 1);
+        let second = (first + 1) % self.frames.len();
+        let t = time - first as f32;
+        for ((dst, &a), &b) in out
+            .data
+            .iter_mut()
+            .zip(self.frames[first].iter())
+            .zip(self.frames[second].iter())
+        {
+            *dst = a + (b - a) * t;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn flicker_between_frames() {
+        let mut light = Fov::new(Box::new(FluxField::new(3)), 3, 0.0);
+        let intensities = [1.0, 0.5, 0.75];
+        let animation = LightAnimation::precompute(&mut light, 3, |frame, x, y, influxes| {
+            if x == 0 && y == 0 {
+                intensities[frame]
+            } else {
+                influxes.iter().map(|f| f.weight * f.value).sum()
+            }
+        });
+        assert_eq!(animation.len(), 3);
+        assert_eq!(animation.frame(1)[0], 0.5);
+        animation.sample_into(1.0, &mut light);
+        assert_eq!(*light.at(3, -2), 0.5);
+        animation.sample_into(0.5, &mut light);
+        assert_eq!(*light.at(0, 0), 0.75);
+        animation.sample_into(2.5, &mut light);
+        assert_eq!(*light.at(-1, 2), 0.875);
+        animation.sample_into(-1.0, &mut light);
+        assert_eq!(*light.at(1, 1), 0.75);
+    }
+}
@@ -0,0 +1,84 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use ::half::f16;
+
+use crate::{FluxField, Fov, Influx};
+
+impl<X> Fov<f16, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Update a half precision field with a function working in single
+    /// precision.
+    ///
+    /// The influx values are widened to `f32` before calling the update
+    /// function and its result is narrowed back to `f16` when stored.
+    ///
+    pub fn update_widened<F>(&mut self, update_fn: F)
+    where
+        F: FnMut(i32, i32, &[Influx<f32>]) -> f32,
+    {
+        let mut update_fn = update_fn;
+        self.update_cells(|x, y, _, cell, influxes| {
+            let mut widened = [
+                Influx {
+                    weight: 0.0,
+                    dx: 0,
+                    dy: 0,
+                    value: 0.0,
+                },
+                Influx {
+                    weight: 0.0,
+                    dx: 0,
+                    dy: 0,
+                    value: 0.0,
+                },
+            ];
+            for (w, f) in widened.iter_mut().zip(influxes.iter()) {
+                w.weight = f.weight;
+                w.dx = f.dx;
+                w.dy = f.dy;
+                w.value = f.value.to_f32();
+            }
+            *cell = f16::from_f32(update_fn(x, y, &widened[..influxes.len()]));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn half_field_tracks_float_field() {
+        let mut fov = Fov::new(Box::new(FluxField::new(5)), 5, f16::ZERO);
+        fov.update_widened(|x, y, influxes| {
+            if x == 0 && y == 0 {
+                1.0
+            } else if (x, y) == (2, 1) {
+                0.0
+            } else {
+                influxes.iter().map(|f| f.weight * f.value).sum()
+            }
+        });
+        assert_eq!(*fov.at(5, 0), f16::ONE);
+        assert_eq!(*fov.at(2, 1), f16::ZERO);
+        let partial = fov.at(4, 2).to_f32();
+        assert!(0.0 < partial && partial < 1.0);
+    }
+}
@@ -0,0 +1,214 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::allocator::Allocator;
+use crate::sweep::sweep;
+use crate::{FluxField, Fov, Influx};
+
+/// A flux leaving a cell of the field of vision towards a neighbouring cell
+/// farther from the origin; see `Fov::update_with_flow`.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Outflux {
+    /// The weight of the flux in the target cell, i.e., the weight of the
+    /// matching influx of the target.
+    pub weight: f32,
+    /// The X-offset of the target cell from the current cell.
+    pub dx: i32,
+    /// The Y-offset of the target cell from the current cell.
+    pub dy: i32,
+}
+
+impl<T, X, A> Fov<T, X, A>
+where
+    X: AsRef<FluxField>,
+    A: Allocator,
+{
+    /// Update the field of vision with a function that also receives the
+    /// outfluxes of each cell, i.e., where the flux of the cell flows to.
+    ///
+    /// The influxes are the same as with `update`.  Each outflux of a cell
+    /// is the counterpart of an influx of the target cell at the offset
+    /// `(dx, dy)`: it carries the same weight, and the target sees it with
+    /// the same `dx` and `dy`.  The flux never flows towards the origin,
+    /// and on the rim of the field it flows only along the rim, as the
+    /// lateral flux of the three-tap mode.  The outfluxes depend only on the
+    /// flux field, so they are tabulated once per flux field on first use.
+    ///
+    /// ```
+    /// # use flux_fov::{FluxField, Fov};
+    /// let mut fov = Fov::new(Box::new(FluxField::new(4)), 4, 0.0_f32);
+    /// // How much of the flux of each cell stays inside the field.
+    /// fov.update_with_flow(|_, _, _, outfluxes| outfluxes.iter().map(|f| f.weight).sum());
+    /// assert_eq!(*fov.at(4, 1), 0.0);
+    /// assert!(*fov.at(0, 0) > 1.0);
+    /// ```
+    ///
+    pub fn update_with_flow<F>(&mut self, update_fn: F)
+    where
+        F: FnMut(i32, i32, &[Influx<&T>], &[Outflux]) -> T,
+    {
+        let mut update_fn = update_fn;
+        let flux_field = self.flux_field.as_ref();
+        let outfluxes = flux_field.outfluxes();
+        let radius = self.radius as i32;
+        let data = &mut self.data;
+        sweep(
+            self.radius,
+            self.width,
+            self.ix_origin,
+            flux_field,
+            |step| {
+                let value = step.with_influxes(
+                    |ix| &data[ix],
+                    |influxes| {
+                        let outfluxes = outfluxes.of(radius, step.x, step.y);
+                        update_fn(step.x, step.y, influxes.as_slice(), outfluxes)
+                    },
+                );
+                data[step.ix] = value;
+            },
+        );
+    }
+}
+
+// The outfluxes of the cells of a flux field as seen by a field of vision
+// of the full radius, grouped by the source cell in the order of the cells.
+// The outfluxes of a cell along its ring come first so that they alone can
+// be handed to the rim of a smaller field.
+pub(crate) struct Outfluxes {
+    radius: i32,
+    // The outfluxes of the cell `ix` are `outfluxes[starts[ix]..starts[ix +
+    // 1]]` and those along its ring the first `along[ix]` of them.
+    outfluxes: Vec<Outflux>,
+    starts: Vec<usize>,
+    along: Vec<u8>,
+}
+
+impl Outfluxes {
+    fn new(flux_field: &FluxField) -> Self {
+        let radius = flux_field.radius as isize;
+        let width = 2 * radius + 1;
+        let ring = |ix: usize| {
+            let (x, y) = (ix as isize % width - radius, ix as isize / width - radius);
+            x.abs().max(y.abs())
+        };
+        let mut sources = Vec::new();
+        sweep(radius, width, radius * (width + 1), flux_field, |step| {
+            for tap in step.taps() {
+                let outflux = Outflux {
+                    weight: tap.weight,
+                    dx: tap.dx,
+                    dy: tap.dy,
+                };
+                sources.push((tap.ix, ring(tap.ix) != ring(step.ix), outflux));
+            }
+        });
+        sources.sort_by_key(|&(ix, outwards, _)| (ix, outwards));
+        let len = (width * width) as usize;
+        let mut starts = vec![0; len + 1];
+        let mut along = vec![0; len];
+        for &(ix, outwards, _) in &sources {
+            starts[ix + 1] += 1;
+            if !outwards {
+                along[ix] += 1;
+            }
+        }
+        for ix in 0..len {
+            starts[ix + 1] += starts[ix];
+        }
+        Outfluxes {
+            radius: radius as i32,
+            outfluxes: sources.into_iter().map(|(_, _, f)| f).collect(),
+            starts,
+            along,
+        }
+    }
+
+    // The outfluxes of the cell (x, y) of a field of vision of the given
+    // radius.
+    fn of(&self, radius: i32, x: i32, y: i32) -> &[Outflux] {
+        let ix = ((y + self.radius) * (2 * self.radius + 1) + x + self.radius) as usize;
+        let start = self.starts[ix];
+        let end = if x.abs().max(y.abs()) == radius {
+            start + self.along[ix] as usize
+        } else {
+            self.starts[ix + 1]
+        };
+        &self.outfluxes[start..end]
+    }
+
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.outfluxes.capacity() * std::mem::size_of::<Outflux>()
+            + self.starts.capacity() * std::mem::size_of::<usize>()
+            + self.along.capacity()
+    }
+}
+
+impl FluxField {
+    // The outfluxes of the cells; computed on first use.
+    pub(crate) fn outfluxes(&self) -> &Outfluxes {
+        self.outfluxes.get_or_init(|| Outfluxes::new(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::all_params;
+    use crate::{Connectivity, FluxParams};
+
+    fn check_flow(params: FluxParams, radius: usize) {
+        // The field of vision may be smaller than the flux field.
+        let flux_field = Box::new(FluxField::with_params(7, params));
+        let mut fov = Fov::new(flux_field, radius, 0);
+        let mut influxes = Vec::new();
+        let mut outfluxes = Vec::new();
+        fov.update_with_flow(|x, y, ins, outs| {
+            for f in ins {
+                influxes.push((x - f.dx, y - f.dy, f.dx, f.dy, f.weight));
+            }
+            for f in outs {
+                outfluxes.push((x, y, f.dx, f.dy, f.weight));
+            }
+            if (x, y) == (0, 0) {
+                let expected = match params.connectivity {
+                    Connectivity::Eight => 8,
+                    Connectivity::Four => 4,
+                };
+                assert_eq!(outs.len(), expected);
+            }
+            let ring = x.abs().max(y.abs());
+            for f in outs {
+                let target = (x + f.dx).abs().max((y + f.dy).abs());
+                assert!(target >= ring && target <= radius as i32);
+            }
+            0
+        });
+        influxes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        outfluxes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(influxes, outfluxes);
+    }
+
+    #[test]
+    fn outfluxes_mirror_influxes() {
+        for params in all_params() {
+            check_flow(params, 7);
+            check_flow(params, 4);
+        }
+    }
+}
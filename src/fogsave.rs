@@ -0,0 +1,252 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// The save encoding of a fog-of-war layer is:
+//
+//     magic "FLUXFOGW"        8 bytes
+//     format version          varint, currently 1
+//     width                   varint
+//     height                  varint
+//     flags                   1 byte, bit 0 set if remembered tiles follow
+//     explored flags          runs
+//     remembered tiles        runs, only if the flag is set
+//
+// The explored flags are packed eight tiles per byte in row-major order, the
+// first tile in the least significant bit.  The remembered tiles are one
+// byte per explored tile in row-major order; the unexplored tiles are left
+// out.  Both are run-length encoded as (count varint, byte) pairs, so the
+// unexplored wilderness and the long stretches of the same remembered tile
+// cost a few bytes each.  The varints are as in `Fov::encode_rle`.
+
+use std::io;
+
+use crate::rle::{read_varint, write_varint};
+use crate::ExploredMap;
+
+const FOG_MAGIC: &[u8; 8] = b"FLUXFOGW";
+const FOG_VERSION: u64 = 1;
+// A sanity limit on the decoded map so that a corrupted header cannot make
+// us allocate the moon.  The runs let a header of a few bytes decode into a
+// map of this size, which takes a byte per tile for the explored flags and
+// another for the remembered tiles, so it is kept at 8192 by 8192 tiles.
+const FOG_MAX_TILES: u64 = 1 << 26;
+
+impl ExploredMap {
+    /// Encode the explored tiles compactly for saving, together with the
+    /// `remembered` contents of the tiles, one byte per tile in row-major
+    /// order, if given.
+    ///
+    /// Only the remembered bytes of the explored tiles are saved; the rest
+    /// decode as zeros.  A mostly unexplored or mostly uniform map takes a
+    /// few kilobytes regardless of its size.
+    ///
+    /// ```
+    /// # use flux_fov::ExploredMap;
+    /// let mut map = ExploredMap::new(1000, 1000);
+    /// for x in 0..100 {
+    ///     map.explore(x, 500);
+    /// }
+    /// let remembered = vec![b'.'; 1000 * 1000];
+    /// let bytes = map.encode(Some(&remembered));
+    /// assert!(bytes.len() < 100);
+    /// let (decoded, tiles) = ExploredMap::decode(&bytes).unwrap();
+    /// assert_eq!(decoded.as_slice(), map.as_slice());
+    /// assert_eq!(tiles.unwrap()[500 * 1000 + 99], b'.');
+    /// ```
+    ///
+    pub fn encode(&self, remembered: Option<&[u8]>) -> Vec<u8> {
+        let explored = self.as_slice();
+        if let Some(remembered) = remembered {
+            assert_eq!(remembered.len(), explored.len(), "remembered size mismatch");
+        }
+        let mut bytes = FOG_MAGIC.to_vec();
+        write_varint(&mut bytes, FOG_VERSION);
+        write_varint(&mut bytes, self.width() as u64);
+        write_varint(&mut bytes, self.height() as u64);
+        bytes.push(remembered.is_some() as u8);
+        let packed = explored.chunks(8).map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0, |byte, (i, &e)| byte | (e as u8) << i)
+        });
+        write_runs(&mut bytes, packed);
+        if let Some(remembered) = remembered {
+            let tiles = explored
+                .iter()
+                .zip(remembered.iter())
+                .filter(|(&e, _)| e)
+                .map(|(_, &tile)| tile);
+            write_runs(&mut bytes, tiles);
+        }
+        bytes
+    }
+
+    /// Decode a layer encoded with `encode` into the explored tiles and the
+    /// remembered contents of the tiles if they were saved.
+    ///
+    /// Fails on maps of more than 2^26 tiles, e.g., 8192 by 8192.
+    ///
+    pub fn decode(bytes: &[u8]) -> io::Result<(ExploredMap, Option<Vec<u8>>)> {
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        if bytes.len() < FOG_MAGIC.len() || &bytes[..FOG_MAGIC.len()] != FOG_MAGIC {
+            return Err(invalid("not a fog-of-war save"));
+        }
+        let mut pos = FOG_MAGIC.len();
+        if read_varint(bytes, &mut pos)? != FOG_VERSION {
+            return Err(invalid("unsupported fog-of-war save version"));
+        }
+        let width = read_varint(bytes, &mut pos)?;
+        let height = read_varint(bytes, &mut pos)?;
+        match width.checked_mul(height) {
+            Some(size) if size <= FOG_MAX_TILES => (),
+            _ => return Err(invalid("fog-of-war map too large")),
+        }
+        let (width, height) = (width as usize, height as usize);
+        let flags = match bytes.get(pos) {
+            Some(&flags) if flags <= 1 => flags,
+            Some(_) => return Err(invalid("unknown fog-of-war save flags")),
+            None => return Err(io::ErrorKind::UnexpectedEof.into()),
+        };
+        pos += 1;
+        let size = width * height;
+        let packed = read_runs(bytes, &mut pos, size.div_ceil(8))?;
+        let mut map = ExploredMap::new(width, height);
+        let mut explored_count = 0;
+        for ix in 0..size {
+            if packed[ix / 8] & (1 << (ix % 8)) != 0 {
+                map.explore(ix % width, ix / width);
+                explored_count += 1;
+            }
+        }
+        let remembered = if flags & 1 != 0 {
+            let tiles = read_runs(bytes, &mut pos, explored_count)?;
+            let mut tiles = tiles.into_iter();
+            let remembered = map
+                .as_slice()
+                .iter()
+                .map(|&e| if e { tiles.next().unwrap() } else { 0 })
+                .collect();
+            Some(remembered)
+        } else {
+            None
+        };
+        if pos != bytes.len() {
+            return Err(invalid("trailing bytes after the fog-of-war save"));
+        }
+        Ok((map, remembered))
+    }
+}
+
+// Write the bytes as (count, byte) runs.
+fn write_runs<I>(bytes: &mut Vec<u8>, values: I)
+where
+    I: Iterator<Item = u8>,
+{
+    let mut run: Option<(u64, u8)> = None;
+    for value in values {
+        run = match run {
+            Some((count, current)) if current == value => Some((count + 1, current)),
+            Some((count, current)) => {
+                write_varint(bytes, count);
+                bytes.push(current);
+                Some((1, value))
+            }
+            None => Some((1, value)),
+        };
+    }
+    if let Some((count, current)) = run {
+        write_varint(bytes, count);
+        bytes.push(current);
+    }
+}
+
+// Read exactly `len` bytes written with `write_runs`.
+fn read_runs(bytes: &[u8], pos: &mut usize, len: usize) -> io::Result<Vec<u8>> {
+    let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let mut values = Vec::new();
+    while values.len() < len {
+        let count = read_varint(bytes, pos)?;
+        let value = match bytes.get(*pos) {
+            Some(&value) => value,
+            None => return Err(io::ErrorKind::UnexpectedEof.into()),
+        };
+        *pos += 1;
+        if count == 0 || count > (len - values.len()) as u64 {
+            return Err(invalid("bad run in the fog-of-war save"));
+        }
+        values.resize(values.len() + count as usize, value);
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn round_trip_and_corruption() {
+        let mut map = ExploredMap::new(13, 7);
+        let mut remembered = vec![0; 13 * 7];
+        for (ix, tile) in remembered.iter_mut().enumerate() {
+            *tile = if ix % 5 == 0 { b'#' } else { b'.' };
+        }
+        for y in 1..5 {
+            for x in 2..11 {
+                map.explore(x, y);
+            }
+        }
+        let bytes = map.encode(None);
+        let (decoded, tiles) = ExploredMap::decode(&bytes).unwrap();
+        assert_eq!(decoded.as_slice(), map.as_slice());
+        assert_eq!((decoded.width(), decoded.height()), (13, 7));
+        assert!(tiles.is_none());
+
+        let bytes = map.encode(Some(&remembered));
+        let (decoded, tiles) = ExploredMap::decode(&bytes).unwrap();
+        assert_eq!(decoded.as_slice(), map.as_slice());
+        let tiles = tiles.unwrap();
+        for (ix, &e) in map.as_slice().iter().enumerate() {
+            assert_eq!(tiles[ix], if e { remembered[ix] } else { 0 });
+        }
+
+        assert!(ExploredMap::decode(&bytes[..bytes.len() - 1]).is_err());
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(ExploredMap::decode(&trailing).is_err());
+        let mut version = bytes.clone();
+        version[8] = 2;
+        assert!(ExploredMap::decode(&version).is_err());
+        assert!(ExploredMap::decode(b"FLUXFOVA").is_err());
+        let mut huge = FOG_MAGIC.to_vec();
+        for &value in &[FOG_VERSION, 1 << 14, 1 << 13] {
+            write_varint(&mut huge, value);
+        }
+        huge.push(0);
+        write_varint(&mut huge, 1 << 24);
+        huge.push(0);
+        assert!(ExploredMap::decode(&huge).is_err());
+    }
+
+    #[test]
+    fn empty_map() {
+        let map = ExploredMap::new(0, 0);
+        let (decoded, tiles) = ExploredMap::decode(&map.encode(Some(&[]))).unwrap();
+        assert_eq!(decoded.as_slice().len(), 0);
+        assert_eq!(tiles, Some(vec![]));
+    }
+}
@@ -0,0 +1,259 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// A GDExtension exposing the field of vision to GDScript as the class
+// `FluxFov`.  The crate is built as a dynamic library for Godot with
+//
+//     cargo rustc --release --features godot --crate-type cdylib
+//
+// and loaded with `godot/flux_fov.gdextension`.  The class works on a
+// map-sized opacity grid, set directly or read from a `TileMap`, and
+// computes map-sized arrays so that GDScript never deals with the
+// coordinates relative to the observer.
+
+// The glue generated by the macros of `godot` trips some lints.
+#![allow(clippy::result_large_err)]
+
+use std::rc::Rc;
+
+use godot::classes::{IRefCounted, RefCounted, TileMap};
+use godot::prelude::*;
+
+use crate::compose::for_each_tile;
+use crate::kernel::{self, Illumination, Visibility};
+use crate::{FluxField, Fov, OpacityGrid};
+
+// The largest radius accepted from GDScript.  The flux field and the fields
+// of vision are built on the engine thread and grow with the square of the
+// radius, so a radius anywhere near `MAX_RADIUS` would stall the game for
+// a long time or exhaust the memory.
+const MAX_GODOT_RADIUS: i64 = 1024;
+
+struct FluxFovExtension;
+
+#[gdextension]
+unsafe impl ExtensionLibrary for FluxFovExtension {}
+
+/// The field of vision of an observer on a tile map.
+///
+/// Usage from GDScript:
+///
+/// ```gdscript
+/// var fov = FluxFov.new()
+/// fov.set_radius(12)
+/// fov.load_tile_map($TileMap, 0, "opacity")
+/// var light = fov.compute_light(player_cell.x, player_cell.y)
+/// ```
+///
+#[derive(GodotClass)]
+#[class(base = RefCounted)]
+pub struct FluxFov {
+    flux_field: Rc<FluxField>,
+    radius: usize,
+    // The map tile at the top-left corner of the grid.
+    map_origin: (i32, i32),
+    opacity: OpacityGrid,
+}
+
+#[godot_api]
+impl IRefCounted for FluxFov {
+    fn init(_base: Base<RefCounted>) -> Self {
+        FluxFov {
+            flux_field: Rc::new(FluxField::new(8)),
+            radius: 8,
+            map_origin: (0, 0),
+            opacity: OpacityGrid::new(0, 0),
+        }
+    }
+}
+
+#[godot_api]
+impl FluxFov {
+    /// Set the radius of the field of vision, rebuilding the flux field.
+    /// A radius out of range is reported as an error and the old radius is
+    /// kept.
+    #[func]
+    fn set_radius(&mut self, radius: i64) {
+        if !(0..=MAX_GODOT_RADIUS).contains(&radius) {
+            godot_error!(
+                "FluxFov: radius {} out of range 0..={}",
+                radius,
+                MAX_GODOT_RADIUS
+            );
+            return;
+        }
+        match FluxField::try_new(radius as usize) {
+            Ok(flux_field) => {
+                self.flux_field = Rc::new(flux_field);
+                self.radius = radius as usize;
+            }
+            Err(error) => godot_error!("FluxFov: {}", error),
+        }
+    }
+
+    #[func]
+    fn get_radius(&self) -> i64 {
+        self.radius as i64
+    }
+
+    /// Set the opacity of the map from a row-major array of `width` by
+    /// `height` values from 0.0 (transparent) to 1.0 (opaque).
+    #[func]
+    fn set_opacity(&mut self, width: i64, height: i64, opacity: PackedFloat32Array) {
+        let (width, height) = (width.max(0) as usize, height.max(0) as usize);
+        self.map_origin = (0, 0);
+        self.opacity = grid_from_slice(width, height, opacity.as_slice());
+    }
+
+    /// Read the opacity of the map from the custom data layer named
+    /// `custom_data` of the tiles of the tile map `layer`.  The empty cells
+    /// and the tiles without the data are transparent.
+    #[func]
+    fn load_tile_map(&mut self, tile_map: Gd<TileMap>, layer: i64, custom_data: GString) {
+        let rect = tile_map.get_used_rect();
+        let (width, height) = (rect.size.x.max(0) as usize, rect.size.y.max(0) as usize);
+        self.map_origin = (rect.position.x, rect.position.y);
+        self.opacity = OpacityGrid::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let cell = Vector2i::new(rect.position.x + x as i32, rect.position.y + y as i32);
+                let opacity = tile_map
+                    .get_cell_tile_data(layer as i32, cell)
+                    .and_then(|data| data.get_custom_data(&custom_data).try_to::<f32>().ok())
+                    .unwrap_or(0.0);
+                self.opacity.set(x, y, opacity);
+            }
+        }
+    }
+
+    #[func]
+    fn get_map_width(&self) -> i64 {
+        self.opacity.width() as i64
+    }
+
+    #[func]
+    fn get_map_height(&self) -> i64 {
+        self.opacity.height() as i64
+    }
+
+    /// The fraction of light reaching each tile of the map from the
+    /// observer at the map cell (`x`, `y`) as a row-major array.  The tiles
+    /// beyond the radius are dark.
+    #[func]
+    fn compute_light(&self, x: i64, y: i64) -> PackedFloat32Array {
+        let origin = self.grid_cell(x, y);
+        PackedFloat32Array::from(
+            &light_map(&self.flux_field, self.radius, &self.opacity, origin)[..],
+        )
+    }
+
+    /// Whether each tile of the map is visible from the observer at the
+    /// map cell (`x`, `y`), 1 or 0, as a row-major array.
+    #[func]
+    fn compute_visible(&self, x: i64, y: i64, threshold: f32) -> PackedByteArray {
+        let origin = self.grid_cell(x, y);
+        let visible = visible_map(
+            &self.flux_field,
+            self.radius,
+            &self.opacity,
+            origin,
+            threshold,
+        );
+        PackedByteArray::from(&visible[..])
+    }
+}
+
+impl FluxFov {
+    fn grid_cell(&self, x: i64, y: i64) -> (i32, i32) {
+        (x as i32 - self.map_origin.0, y as i32 - self.map_origin.1)
+    }
+}
+
+fn grid_from_slice(width: usize, height: usize, opacity: &[f32]) -> OpacityGrid {
+    let mut grid = OpacityGrid::new(width, height);
+    for (ix, &o) in opacity.iter().take(width * height).enumerate() {
+        grid.set(ix % width, ix / width, o);
+    }
+    grid
+}
+
+fn light_map(
+    flux_field: &Rc<FluxField>,
+    radius: usize,
+    grid: &OpacityGrid,
+    origin: (i32, i32),
+) -> Vec<f32> {
+    let mut fov = Fov::new(flux_field.clone(), radius, Illumination::default());
+    fov.update(kernel::soft_shadow(grid.relative_to(origin)));
+    let mut light = vec![0.0; grid.width() * grid.height()];
+    for_each_tile(
+        grid.width(),
+        grid.height(),
+        origin.0,
+        origin.1,
+        &fov,
+        |ix, v| {
+            light[ix] = v.brightness;
+        },
+    );
+    light
+}
+
+fn visible_map(
+    flux_field: &Rc<FluxField>,
+    radius: usize,
+    grid: &OpacityGrid,
+    origin: (i32, i32),
+    threshold: f32,
+) -> Vec<u8> {
+    let mut fov = Fov::new(flux_field.clone(), radius, Visibility::default());
+    fov.update(kernel::threshold(threshold, grid.relative_to(origin)));
+    let mut visible = vec![0; grid.width() * grid.height()];
+    for_each_tile(
+        grid.width(),
+        grid.height(),
+        origin.0,
+        origin.1,
+        &fov,
+        |ix, v| {
+            visible[ix] = v.is_visible as u8;
+        },
+    );
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn map_sized_results() {
+        let mut opacity = vec![0.0; 8 * 5];
+        opacity[2 * 8 + 4] = 1.0;
+        let grid = grid_from_slice(8, 5, &opacity);
+        let flux_field = Rc::new(FluxField::new(3));
+        let light = light_map(&flux_field, 3, &grid, (2, 2));
+        assert_eq!(light.len(), 40);
+        assert_eq!(light[2 * 8 + 2], 1.0);
+        assert_eq!(light[2 * 8 + 5], 0.0);
+        assert_eq!(light[2 * 8 + 7], 0.0);
+        let visible = visible_map(&flux_field, 3, &grid, (2, 2), 0.5);
+        assert_eq!(visible[2 * 8 + 4], 1);
+        assert_eq!(visible[2 * 8 + 5], 0);
+        assert_eq!(visible[4], 1);
+    }
+}
@@ -0,0 +1,289 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::sweep::sweep;
+use crate::{FluxField, Influx};
+
+/// A weighted edge bringing flux into a node of a flux graph.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GraphEdge {
+    /// The node the flux comes from.
+    pub source: usize,
+    pub weight: f32,
+    /// The direction of the flux reported to the update function; free for
+    /// the user to choose.
+    pub dx: i32,
+    pub dy: i32,
+}
+
+/// A flux field over an arbitrary adjacency graph.
+///
+/// The grid based flux field fixes both the cells and the way the flux flows
+/// between them.  A flux graph lets the user define them instead so that
+/// maps with irregular connectivity, such as wraparound seams, graphs of
+/// rooms, or otherwise weird geometries, can reuse the same influx and
+/// update machinery.
+///
+/// The nodes are numbered in the order they are added, which is also the
+/// order they are updated in.  A node can receive flux only from the nodes
+/// added before it so the graph is acyclic by construction.  The nodes
+/// without incoming edges act as the sources of the flux.
+///
+#[derive(Clone, Debug)]
+pub struct FluxGraph {
+    edges: Vec<GraphEdge>,
+    offsets: Vec<usize>,
+}
+
+impl FluxGraph {
+    pub fn new() -> Self {
+        FluxGraph {
+            edges: Vec::new(),
+            offsets: vec![0],
+        }
+    }
+
+    /// Build a flux graph equivalent to the flux field over the area within
+    /// `radius`.
+    ///
+    /// Returns the graph and the coordinates of its nodes relative to the
+    /// origin, which is the node 0.
+    ///
+    pub fn from_flux_field(flux_field: &FluxField, radius: usize) -> (Self, Vec<(i32, i32)>) {
+        assert!(radius <= flux_field.radius());
+        let radius = radius as isize;
+        let width = 2 * radius + 1;
+        let ix_origin = radius * (width + 1);
+        let mut node_of_ix = vec![0; (width * width) as usize];
+        let mut graph = FluxGraph::new();
+        let mut coords = Vec::with_capacity(node_of_ix.len());
+        let mut incoming = Vec::with_capacity(3);
+        sweep(radius, width, ix_origin, flux_field, |step| {
+            incoming.clear();
+            incoming.extend(step.taps().iter().map(|tap| GraphEdge {
+                source: node_of_ix[tap.ix],
+                weight: tap.weight,
+                dx: tap.dx,
+                dy: tap.dy,
+            }));
+            node_of_ix[step.ix] = graph.add_node(&incoming);
+            coords.push((step.x, step.y));
+        });
+        (graph, coords)
+    }
+
+    /// Add a node receiving flux through the given edges and return its
+    /// index.
+    ///
+    /// Panics if an edge comes from a node that hasn't been added yet.
+    ///
+    pub fn add_node(&mut self, incoming: &[GraphEdge]) -> usize {
+        let node = self.len();
+        assert!(
+            incoming.iter().all(|edge| edge.source < node),
+            "edge from a later node"
+        );
+        self.edges.extend_from_slice(incoming);
+        self.offsets.push(self.edges.len());
+        node
+    }
+
+    /// The number of nodes.
+    pub fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The edges bringing flux into the node.
+    pub fn incoming(&self, node: usize) -> &[GraphEdge] {
+        &self.edges[self.offsets[node]..self.offsets[node + 1]]
+    }
+}
+
+impl Default for FluxGraph {
+    fn default() -> Self {
+        FluxGraph::new()
+    }
+}
+
+/// A field of vision over a flux graph.
+///
+pub struct GraphFov<T, G>
+where
+    G: AsRef<FluxGraph>,
+{
+    graph: G,
+    data: Vec<T>,
+}
+
+impl<T, G> GraphFov<T, G>
+where
+    T: Clone,
+    G: AsRef<FluxGraph>,
+{
+    pub fn new(graph: G, init: T) -> Self {
+        let data = vec![init; graph.as_ref().len()];
+        GraphFov { graph, data }
+    }
+}
+
+impl<T, G> GraphFov<T, G>
+where
+    G: AsRef<FluxGraph>,
+{
+    /// The value at the given node.
+    pub fn at(&self, node: usize) -> &T {
+        &self.data[node]
+    }
+
+    /// Expose the underlying data as a slice indexed by the nodes.
+    pub fn as_slice(&self) -> &[T] {
+        self.data.as_slice()
+    }
+
+    /// Update the field with the given function.
+    ///
+    /// The function receives the index of the node and its influxes, and
+    /// returns the new value of the node.  The nodes are updated in the
+    /// order of their indices.
+    ///
+    pub fn update<F>(&mut self, update_fn: F)
+    where
+        F: FnMut(usize, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        let graph = self.graph.as_ref();
+        for node in 0..self.data.len() {
+            let (done, rest) = self.data.split_at_mut(node);
+            let incoming = graph.incoming(node);
+            let influx = |edge: &GraphEdge| Influx {
+                weight: edge.weight,
+                dx: edge.dx,
+                dy: edge.dy,
+                value: &done[edge.source],
+            };
+            // Most nodes have only a few incoming edges and those are passed
+            // without allocating.
+            rest[0] = match incoming {
+                [] => update_fn(node, &[]),
+                [a] => update_fn(node, &[influx(a)]),
+                [a, b] => update_fn(node, &[influx(a), influx(b)]),
+                [a, b, c] => update_fn(node, &[influx(a), influx(b), influx(c)]),
+                _ => update_fn(node, &incoming.iter().map(influx).collect::<Vec<_>>()),
+            };
+        }
+    }
+}
+
+impl AsRef<FluxGraph> for FluxGraph {
+    fn as_ref(&self) -> &FluxGraph {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Fov;
+    use std::rc::Rc;
+
+    #[test]
+    fn graph_matches_grid() {
+        let flux_field = FluxField::new(6);
+        let (graph, coords) = FluxGraph::from_flux_field(&flux_field, 6);
+        assert_eq!(graph.len(), 13 * 13);
+        let kernel = |x: i32, y: i32, influxes: &[Influx<&f32>]| {
+            if x == 0 && y == 0 {
+                1.0
+            } else if x == 2 && y < 2 {
+                0.0
+            } else {
+                influxes.iter().map(|f| f.weight * *f.value).sum()
+            }
+        };
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, 0.0);
+        fov.update(kernel);
+        let mut graph_fov = GraphFov::new(graph, 0.0);
+        graph_fov.update(|node, influxes| {
+            let (x, y) = coords[node];
+            kernel(x, y, influxes)
+        });
+        for (node, &(x, y)) in coords.iter().enumerate() {
+            assert_eq!(*graph_fov.at(node), *fov.at(x, y));
+        }
+    }
+
+    #[test]
+    fn wraparound_ring() {
+        // A corridor of eight cells wrapping around to itself seen from the
+        // cell 0 in both directions.
+        let mut graph = FluxGraph::new();
+        let origin = graph.add_node(&[]);
+        let mut left = origin;
+        let mut right = origin;
+        let mut nodes = vec![(origin, 0)];
+        for k in 1..5 {
+            let edge = |source, dx| GraphEdge {
+                source,
+                weight: 1.0,
+                dx,
+                dy: 0,
+            };
+            right = graph.add_node(&[edge(right, 1)]);
+            nodes.push((right, k));
+            if k < 4 {
+                left = graph.add_node(&[edge(left, -1)]);
+                nodes.push((left, 8 - k));
+            }
+        }
+        let graph = Rc::new(graph);
+        let mut fov = GraphFov::new(graph.clone(), 0);
+        fov.update(|node, influxes| {
+            if node == 0 {
+                0
+            } else {
+                influxes.iter().map(|f| *f.value + 1).min().unwrap()
+            }
+        });
+        let distances: Vec<_> = nodes.iter().map(|&(node, _)| *fov.at(node)).collect();
+        assert_eq!(distances, [0, 1, 1, 2, 2, 3, 3, 4]);
+    }
+
+    #[test]
+    fn default_graph_is_empty() {
+        let mut graph = FluxGraph::default();
+        assert!(graph.is_empty());
+        assert_eq!(graph.add_node(&[]), 0);
+        assert_eq!(graph.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "edge from a later node")]
+    fn edges_must_come_from_earlier_nodes() {
+        let mut graph = FluxGraph::new();
+        graph.add_node(&[GraphEdge {
+            source: 0,
+            weight: 1.0,
+            dx: 0,
+            dy: 0,
+        }]);
+    }
+}
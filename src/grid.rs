@@ -0,0 +1,165 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::io;
+
+/// A map-sized grid of per-tile opacity for the kernels.
+///
+/// The opacity ranges from 0.0 (transparent) to 1.0 (opaque) as in
+/// `kernel::threshold`.  The tiles outside the map are opaque.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpacityGrid {
+    width: usize,
+    height: usize,
+    opacity: Vec<f32>,
+}
+
+impl OpacityGrid {
+    /// The mapping of `from_ascii` for the usual tiles: walls `#`, floors
+    /// `.`, closed doors `+`, and translucent `~` such as foliage or fog.
+    ///
+    pub const ASCII_TILES: &'static [(char, f32)] =
+        &[('#', 1.0), ('.', 0.0), ('+', 1.0), ('~', 0.5)];
+
+    /// Build the grid from ASCII art with `tiles` mapping the characters
+    /// to opacities, e.g., `OpacityGrid::ASCII_TILES`.
+    ///
+    /// The leading and trailing whitespace of each line is ignored as are
+    /// the empty lines so the map can be written as an indented string
+    /// literal.  All the rows must be equally long.
+    ///
+    /// ```
+    /// # use flux_fov::OpacityGrid;
+    /// let map = "
+    ///     .....
+    ///     .#.~.
+    ///     ..+..
+    /// ";
+    /// let grid = OpacityGrid::from_ascii(map, OpacityGrid::ASCII_TILES).unwrap();
+    /// assert_eq!((grid.width(), grid.height()), (5, 3));
+    /// assert_eq!(grid.at(3, 1), 0.5);
+    /// ```
+    ///
+    pub fn from_ascii(ascii: &str, tiles: &[(char, f32)]) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let rows: Vec<&str> = ascii
+            .lines()
+            .map(str::trim)
+            .filter(|row| !row.is_empty())
+            .collect();
+        let width = rows.first().map_or(0, |row| row.chars().count());
+        let mut grid = OpacityGrid::new(width, rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != width {
+                return Err(invalid(format!("row {} is not {} tiles wide", y, width)));
+            }
+            for (x, c) in row.chars().enumerate() {
+                let opacity = tiles
+                    .iter()
+                    .find(|&&(t, _)| t == c)
+                    .map(|&(_, opacity)| opacity)
+                    .ok_or_else(|| invalid(format!("unknown tile {:?}", c)))?;
+                grid.set(x, y, opacity);
+            }
+        }
+        Ok(grid)
+    }
+
+    /// Constructs a `width` by `height` grid of transparent tiles.
+    ///
+    pub fn new(width: usize, height: usize) -> Self {
+        OpacityGrid {
+            width,
+            height,
+            opacity: vec![0.0; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The opacity of the given tile, 1.0 if it is outside the map.
+    pub fn at(&self, x: i32, y: i32) -> f32 {
+        if 0 <= x && (x as usize) < self.width && 0 <= y && (y as usize) < self.height {
+            self.opacity[y as usize * self.width + x as usize]
+        } else {
+            1.0
+        }
+    }
+
+    /// Set the opacity of the given tile.
+    pub fn set(&mut self, x: usize, y: usize, opacity: f32) {
+        assert!(x < self.width && y < self.height, "tile out of range");
+        self.opacity[y * self.width + x] = opacity;
+    }
+
+    /// Expose the opacity of the tiles as a slice in row-major order.
+    pub fn as_slice(&self) -> &[f32] {
+        self.opacity.as_slice()
+    }
+
+    /// The opacity as seen from the map tile `origin` for use with the
+    /// kernels that are called with coordinates relative to the origin.
+    ///
+    pub fn relative_to(&self, origin: (i32, i32)) -> impl Fn(i32, i32) -> f32 + '_ {
+        let (origin_x, origin_y) = origin;
+        move |x, y| self.at(origin_x + x, origin_y + y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn opacity_lookup() {
+        let mut grid = OpacityGrid::new(3, 2);
+        grid.set(2, 1, 0.5);
+        assert_eq!(grid.at(2, 1), 0.5);
+        assert_eq!(grid.at(0, 0), 0.0);
+        assert_eq!(grid.at(3, 0), 1.0);
+        assert_eq!(grid.at(0, -1), 1.0);
+        let opacity = grid.relative_to((1, 1));
+        assert_eq!(opacity(1, 0), 0.5);
+        assert_eq!(opacity(-2, 0), 1.0);
+    }
+
+    #[test]
+    fn ascii_maps() {
+        let grid = OpacityGrid::from_ascii("\n  #.\n  +~\n\n", OpacityGrid::ASCII_TILES).unwrap();
+        assert_eq!(grid.as_slice(), &[1.0, 0.0, 1.0, 0.5]);
+        let tiles = [('x', 0.25), ('.', 0.0)];
+        let grid = OpacityGrid::from_ascii("x..\n.x.", &tiles).unwrap();
+        assert_eq!(grid.at(1, 1), 0.25);
+        assert!(OpacityGrid::from_ascii("##\n#", OpacityGrid::ASCII_TILES).is_err());
+        assert!(OpacityGrid::from_ascii("#?", OpacityGrid::ASCII_TILES).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "tile out of range")]
+    fn set_out_of_range() {
+        // Within the grid but past the right edge of the first row.
+        let mut grid = OpacityGrid::new(4, 3);
+        grid.set(4, 0, 1.0);
+    }
+}
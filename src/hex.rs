@@ -0,0 +1,364 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// Hexagonal grids.  The cells are addressed with the axial coordinates
+// (q, r) whose Cartesian centers are (q + r / 2, r * sqrt(3) / 2), i.e., the
+// unit q-axis points right and the unit r-axis 60 degrees counterclockwise
+// from it.  The six directions split the field into six sextants.  The cell
+// (n, j) of the sextant i, with 0 <= j < n, is at
+//
+//     (n - j) * DIRECTIONS[i] + j * DIRECTIONS[i + 1]
+//
+// and at the distance n from the origin.  The cells j = 0 form the edge of
+// the sextant and receive flux straight from the cell before them.  The
+// other cells receive flux from the two cells (n - 1, j) and (n - 1, j - 1)
+// of the previous ring, the former along DIRECTIONS[i] and the latter along
+// DIRECTIONS[i + 1].  For j = n - 1 the former is the edge cell of the next
+// sextant.  The look-up table holds the weight of the latter for the cells
+// with n >= 2 and 0 < j < n in the same layout as the square flux fields.
+
+use crate::{lut_len, Influx};
+
+/// The six axial directions in counterclockwise order.
+///
+pub const HEX_DIRECTIONS: [(i32, i32); 6] = [(1, 0), (0, 1), (-1, 1), (-1, 0), (0, -1), (1, -1)];
+
+/// The distance between the hexagon (`q`, `r`) and the origin in steps.
+///
+pub fn hex_distance(q: i32, r: i32) -> i32 {
+    (q.abs() + r.abs() + (q + r).abs()) / 2
+}
+
+/// A pre-computed flux field for hexagonal grids.
+///
+/// The hexagonal counterpart of `FluxField`; see `HexFov`.
+///
+pub struct HexFluxField {
+    radius: usize,
+    flux_lut: Vec<f32>,
+}
+
+impl HexFluxField {
+    /// Constructs a hexagonal flux field covering the hexagons within
+    /// `radius` steps.
+    ///
+    pub fn new(radius: usize) -> Self {
+        HexFluxField {
+            radius,
+            flux_lut: calc_hex_flux_lut(radius),
+        }
+    }
+
+    /// The radius of the area covered by the flux field.
+    pub fn radius(&self) -> usize {
+        self.radius
+    }
+}
+
+impl AsRef<HexFluxField> for HexFluxField {
+    fn as_ref(&self) -> &HexFluxField {
+        self
+    }
+}
+
+// Cast rays evenly across a sextant and count the cells of the previous ring
+// they come from into each cell.  The rays are marched in small steps and a
+// ray crossing into a cell from its neighbor in the same ring counts as
+// coming from the source shared by the two.
+fn calc_hex_flux_lut(radius: usize) -> Vec<f32> {
+    if radius < 2 {
+        return Vec::new();
+    }
+    let sqrt_3 = 3.0_f64.sqrt();
+    let ray_count = 64 * radius;
+    let steps_per_cell = 64;
+    let mut diagonal = vec![0.0; lut_len(radius)];
+    let mut total = vec![0.0; lut_len(radius)];
+    for ray_ix in 0..ray_count {
+        // Offset the rays by half a step to keep clear of the sextant edges.
+        let angle = (ray_ix as f64 + 0.5) / ray_count as f64 * std::f64::consts::FRAC_PI_3;
+        let (dir_x, dir_y) = (angle.cos(), angle.sin());
+        let mut prev = (0, 0);
+        for step in 1..(radius + 1) * steps_per_cell {
+            let t = step as f64 / steps_per_cell as f64;
+            let (x, y) = (t * dir_x, t * dir_y);
+            let r = y * 2.0 / sqrt_3;
+            let cell = hex_round(x - r / 2.0, r);
+            if cell == prev {
+                continue;
+            }
+            // The rays stay within the sextant 0 where (n, j) = (q + r, r).
+            let (n, j) = (cell.0 + cell.1, cell.1);
+            if n as usize > radius {
+                break;
+            }
+            if n >= 2 && 0 < j && j < n {
+                let ix = lut_len(n as usize - 1) + j as usize - 1;
+                let from = (cell.0 - prev.0, cell.1 - prev.1);
+                // Straight along (1, 0) or laterally from (n, j + 1) count as
+                // coming along the q-axis; diagonally along (0, 1) or
+                // laterally from (n, j - 1) as coming along the r-axis.
+                match from {
+                    (1, 0) | (1, -1) => total[ix] += 1.0,
+                    (0, 1) | (-1, 1) => {
+                        diagonal[ix] += 1.0;
+                        total[ix] += 1.0;
+                    }
+                    _ => (),
+                }
+            }
+            prev = cell;
+        }
+    }
+    diagonal
+        .iter()
+        .zip(total.iter())
+        .map(|(&d, &t)| if t > 0.0 { (d / t) as f32 } else { 0.5 })
+        .collect()
+}
+
+// The hexagon containing the point given in fractional axial coordinates.
+fn hex_round(q: f64, r: f64) -> (i32, i32) {
+    let s = -q - r;
+    let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+    let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+    if dq > dr && dq > ds {
+        rq = -rr - rs;
+    } else if dr > ds {
+        rr = -rq - rs;
+    }
+    (rq as i32, rr as i32)
+}
+
+/// A field of vision on a hexagonal grid.
+///
+/// Works like `Fov` but the cells are hexagons addressed with the axial
+/// coordinates (q, r); see `HEX_DIRECTIONS` for the orientation.  The update
+/// function receives the axial coordinates of the cell and the influxes
+/// whose (`dx`, `dy`) are the axial directions the flux travels:
+///
+/// ```
+/// # use flux_fov::{HexFluxField, HexFov, Influx};
+/// let mut fov = HexFov::new(Box::new(HexFluxField::new(6)), 6, 0.0_f32);
+/// let wall = (2, 0);
+/// fov.update(|q, r, influxes: &[Influx<&f32>]| {
+///     if (q, r) == (0, 0) {
+///         1.0
+///     } else if (q, r) == wall {
+///         0.0
+///     } else {
+///         influxes.iter().map(|f| f.weight * f.value).sum()
+///     }
+/// });
+/// assert_eq!(*fov.at(0, 5), 1.0);
+/// assert_eq!(*fov.at(5, 0), 0.0);
+/// ```
+///
+pub struct HexFov<T, X> {
+    flux_field: X,
+    radius: usize,
+    // The cells of the enclosing (2R + 1) by (2R + 1) rhombus of the axial
+    // coordinates, row by row along the q-axis.  The corners outside the
+    // radius are unused.
+    data: Vec<T>,
+}
+
+impl<T, X> HexFov<T, X>
+where
+    T: Clone,
+    X: AsRef<HexFluxField>,
+{
+    pub fn new(flux_field: X, radius: usize, init: T) -> Self {
+        assert!(radius <= flux_field.as_ref().radius);
+        let width = 2 * radius + 1;
+        HexFov {
+            flux_field,
+            radius,
+            data: vec![init; width * width],
+        }
+    }
+}
+
+impl<T, X> HexFov<T, X>
+where
+    X: AsRef<HexFluxField>,
+{
+    pub fn radius(&self) -> usize {
+        self.radius
+    }
+
+    /// The number of hexagons in the field, 3R(R + 1) + 1.
+    pub fn cell_count(&self) -> usize {
+        3 * self.radius * (self.radius + 1) + 1
+    }
+
+    /// The value of the hexagon (`q`, `r`).
+    ///
+    /// Panics if the hexagon is farther than the radius from the origin.
+    ///
+    pub fn at(&self, q: i32, r: i32) -> &T {
+        assert!(
+            hex_distance(q, r) <= self.radius as i32,
+            "hexagon out of range"
+        );
+        &self.data[self.index(q, r)]
+    }
+
+    /// Iterate over the hexagons of the field ring by ring yielding their
+    /// axial coordinates and values.
+    ///
+    pub fn iter(&self) -> impl Iterator<Item = (i32, i32, &T)> + '_ {
+        let radius = self.radius as i32;
+        (0..radius + 1)
+            .flat_map(ring)
+            .map(move |(q, r)| (q, r, self.at(q, r)))
+    }
+
+    fn index(&self, q: i32, r: i32) -> usize {
+        let radius = self.radius as i32;
+        ((r + radius) * (2 * radius + 1) + q + radius) as usize
+    }
+
+    /// Update the field of vision.
+    ///
+    /// The hexagons are updated ring by ring outwards from the origin which
+    /// receives no influxes.
+    ///
+    pub fn update<F>(&mut self, update_fn: F)
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        let ix = self.index(0, 0);
+        self.data[ix] = update_fn(0, 0, &[]);
+        let flux_lut = &self.flux_field.as_ref().flux_lut;
+        for n in 1..self.radius as i32 + 1 {
+            for i in 0..6 {
+                let (aq, ar) = HEX_DIRECTIONS[i];
+                let (bq, br) = HEX_DIRECTIONS[(i + 1) % 6];
+                for j in 0..n {
+                    let (q, r) = ((n - j) * aq + j * bq, (n - j) * ar + j * br);
+                    let value = {
+                        let straight = Influx {
+                            weight: 1.0,
+                            dx: aq,
+                            dy: ar,
+                            value: &self.data[self.index(q - aq, r - ar)],
+                        };
+                        if j == 0 {
+                            update_fn(q, r, &[straight])
+                        } else {
+                            let w = flux_lut[lut_len(n as usize - 1) + j as usize - 1];
+                            let diagonal = Influx {
+                                weight: w,
+                                dx: bq,
+                                dy: br,
+                                value: &self.data[self.index(q - bq, r - br)],
+                            };
+                            let straight = Influx {
+                                weight: 1.0 - w,
+                                ..straight
+                            };
+                            update_fn(q, r, &[straight, diagonal])
+                        }
+                    };
+                    let ix = self.index(q, r);
+                    self.data[ix] = value;
+                }
+            }
+        }
+    }
+}
+
+// The hexagons of the ring `n` in the order of the update.
+fn ring(n: i32) -> impl Iterator<Item = (i32, i32)> {
+    let sextants = if n == 0 { 1 } else { 6 };
+    (0..sextants).flat_map(move |i| {
+        let (aq, ar) = HEX_DIRECTIONS[i];
+        let (bq, br) = HEX_DIRECTIONS[(i + 1) % 6];
+        (0..n.max(1)).map(move |j| ((n - j) * aq + j * bq, (n - j) * ar + j * br))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn shadow<W>(is_wall: W) -> impl FnMut(i32, i32, &[Influx<&f32>]) -> f32
+    where
+        W: Fn(i32, i32) -> bool,
+    {
+        move |q, r, influxes| {
+            if (q, r) == (0, 0) {
+                1.0
+            } else if is_wall(q, r) {
+                0.0
+            } else {
+                influxes.iter().map(|f| f.weight * f.value).sum()
+            }
+        }
+    }
+
+    #[test]
+    fn hex_flux_field_is_symmetric() {
+        let flux_field = HexFluxField::new(12);
+        for n in 2..13 {
+            for j in 1..n {
+                let w = flux_field.flux_lut[lut_len(n - 1) + j - 1];
+                let mirror = flux_field.flux_lut[lut_len(n - 1) + n - j - 1];
+                assert!((0.0..=1.0).contains(&w));
+                assert!((w + mirror - 1.0).abs() < 0.05, "{} {}: {}", n, j, w);
+            }
+        }
+    }
+
+    #[test]
+    fn hex_fov_covers_every_hexagon_once() {
+        let mut fov = HexFov::new(HexFluxField::new(5), 5, 0);
+        let mut visits = std::collections::HashSet::new();
+        fov.update(|q, r, influxes| {
+            assert!(visits.insert((q, r)));
+            for f in influxes {
+                assert_eq!(hex_distance(f.dx, f.dy), 1);
+                assert_eq!(hex_distance(q - f.dx, r - f.dy), hex_distance(q, r) - 1);
+            }
+            1
+        });
+        assert_eq!(visits.len(), fov.cell_count());
+        assert!(visits.iter().all(|&(q, r)| hex_distance(q, r) <= 5));
+        assert_eq!(fov.iter().count(), fov.cell_count());
+        assert!(fov.iter().all(|(_, _, &v)| v == 1));
+    }
+
+    #[test]
+    fn hex_walls_cast_shadows() {
+        // A wall across the sextant 0 three steps away.
+        let is_wall = |q, r| hex_distance(q, r) == 3 && q >= 0 && r >= 0;
+        let mut fov = HexFov::new(HexFluxField::new(10), 10, 0.0_f32);
+        fov.update(shadow(is_wall));
+        assert!(fov.iter().all(|(_, _, &v)| v <= 1.0 + 1e-6));
+        for &(q, r) in &[(6, 0), (4, 2), (3, 3), (2, 4), (0, 6), (5, 5)] {
+            assert!(*fov.at(q, r) < 0.05, "({}, {})", q, r);
+        }
+        // Elsewhere in the open.
+        for &(q, r) in &[(-10, 10), (-10, 0), (5, -10), (4, -10), (-3, -4)] {
+            assert!((fov.at(q, r) - 1.0).abs() < 1e-5, "({}, {})", q, r);
+        }
+        // The penumbra is in between.
+        assert!(0.1 < *fov.at(-1, 5) && *fov.at(-1, 5) < 0.9);
+    }
+}
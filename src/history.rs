@@ -0,0 +1,156 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::VecDeque;
+
+use crate::{FluxField, Fov, Influx};
+
+/// A field of vision that remembers its values from the last few updates.
+///
+/// The past frames are kept in a ring buffer.  Once the buffer is full the
+/// oldest frame is recycled so that a steady stream of updates doesn't
+/// allocate.
+///
+pub struct FovHistory<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    fov: Fov<T, X>,
+    frames: VecDeque<Vec<T>>,
+    depth: usize,
+}
+
+impl<T, X> FovHistory<T, X>
+where
+    T: Clone,
+    X: AsRef<FluxField>,
+{
+    /// Wraps `fov` into a history remembering at most `depth` past frames.
+    ///
+    pub fn new(fov: Fov<T, X>, depth: usize) -> Self {
+        FovHistory {
+            fov,
+            frames: VecDeque::with_capacity(depth),
+            depth,
+        }
+    }
+
+    /// Save the current frame into the history and update the field of
+    /// vision with the given function.
+    ///
+    pub fn update<F>(&mut self, update_fn: F)
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        if self.depth > 0 {
+            let frame = if self.frames.len() == self.depth {
+                let mut frame = self.frames.pop_back().unwrap();
+                frame.clear();
+                frame.extend_from_slice(&self.fov.data);
+                frame
+            } else {
+                self.fov.data.to_vec()
+            };
+            self.frames.push_front(frame);
+        }
+        self.fov.update(update_fn);
+    }
+}
+
+impl<T, X> FovHistory<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// The current field of vision.
+    pub fn fov(&self) -> &Fov<T, X> {
+        &self.fov
+    }
+
+    /// The maximum number of past frames remembered.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The number of past frames currently remembered.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Whether there are no past frames yet.
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Forget all the past frames.
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// The value of the given grid cell `age` updates ago.
+    ///
+    /// The age 0 refers to the current frame, 1 to the frame before the last
+    /// update, and so on.  Returns `None` if the frame is not remembered or
+    /// the cell is outside the field of vision.
+    ///
+    pub fn at(&self, age: usize, x: i32, y: i32) -> Option<&T> {
+        self.fov.get(x, y)?;
+        self.frame(age).map(|frame| &frame[self.fov.index(x, y)])
+    }
+
+    /// Expose the data of the frame `age` updates ago as a slice.
+    ///
+    pub fn frame(&self, age: usize) -> Option<&[T]> {
+        if age == 0 {
+            Some(self.fov.as_slice())
+        } else {
+            self.frames.get(age - 1).map(|frame| frame.as_slice())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn history_keeps_last_frames() {
+        let fov = Fov::new(Box::new(FluxField::new(2)), 2, 0);
+        let mut history = FovHistory::new(fov, 2);
+        assert!(history.is_empty());
+        for turn in 1..5 {
+            history.update(|x, y, _| turn * 10 + x + y);
+        }
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.at(0, 1, 1), Some(&42));
+        assert_eq!(history.at(1, 1, 1), Some(&32));
+        assert_eq!(history.at(2, 1, 1), Some(&22));
+        assert_eq!(history.at(3, 1, 1), None);
+        assert_eq!(history.at(1, 3, 0), None, "outside the radius");
+        assert_eq!(history.at(1, 0, -3), None, "outside the radius");
+        assert_eq!(*history.fov().at(-1, 0), 39);
+    }
+
+    #[test]
+    fn zero_depth_history() {
+        let fov = Fov::new(Box::new(FluxField::new(1)), 1, 0);
+        let mut history = FovHistory::new(fov, 0);
+        history.update(|_, _, _| 1);
+        assert!(history.is_empty());
+        assert_eq!(history.at(0, 0, 0), Some(&1));
+        assert_eq!(history.at(1, 0, 0), None);
+    }
+}
@@ -0,0 +1,166 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::task::Poll;
+
+use crate::{FluxField, Fov, Influx, UpdateState};
+
+/// An update of a field of vision packaged as a job for an external task
+/// system.
+///
+/// The job owns the field and the update function and advances the update
+/// a bounded number of cells per step, so an engine can schedule the fields
+/// of its non-player characters on its own worker threads and spread them
+/// over several frames.  The crate never spawns threads itself.  The job is
+/// `Send` whenever the field and the update function are.
+///
+/// ```
+/// # use flux_fov::{kernel, FluxField, Fov, FovJob};
+/// # use std::sync::Arc;
+/// let flux_field = Arc::new(FluxField::new(16));
+/// let fov = Fov::new(flux_field, 16, Default::default());
+/// let mut job = FovJob::new(fov, kernel::threshold(0.5, |_, _| 0.0));
+/// let worker = std::thread::spawn(move || {
+///     while job.run_step(100).is_pending() {
+///         // Yield to the other jobs of the frame here.
+///     }
+///     job
+/// });
+/// let job = worker.join().unwrap();
+/// assert!(job.fov().at(16, 16).is_visible);
+/// ```
+///
+pub struct FovJob<T, X, F>
+where
+    X: AsRef<FluxField>,
+{
+    fov: Fov<T, X>,
+    update_fn: F,
+    state: UpdateState,
+}
+
+impl<T, X, F> FovJob<T, X, F>
+where
+    X: AsRef<FluxField>,
+    F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+{
+    /// Constructs a job updating `fov` with `update_fn`.
+    ///
+    pub fn new(fov: Fov<T, X>, update_fn: F) -> Self {
+        let state = UpdateState::new(fov.radius());
+        FovJob {
+            fov,
+            update_fn,
+            state,
+        }
+    }
+
+    /// Advance the update by at most `max_cells` cells.
+    ///
+    /// Returns `Poll::Ready` once the update has completed; further steps
+    /// do nothing until the job is restarted.
+    ///
+    pub fn run_step(&mut self, max_cells: usize) -> Poll<()> {
+        if !self.state.is_complete() {
+            self.fov
+                .resume_update(&mut self.state, max_cells, &mut self.update_fn);
+        }
+        if self.state.is_complete() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Run the rest of the update to completion.
+    ///
+    pub fn run(&mut self) {
+        let _ = self.run_step(usize::MAX);
+    }
+
+    /// Start the update over, e.g., after the observer has moved.
+    ///
+    pub fn restart(&mut self) {
+        self.state.reset();
+    }
+
+    /// Whether the update has completed.
+    pub fn is_done(&self) -> bool {
+        self.state.is_complete()
+    }
+
+    /// The number of cells updated so far.
+    pub fn cells_done(&self) -> usize {
+        self.state.cells_done()
+    }
+
+    /// The field of vision; only partially updated until the job is done.
+    pub fn fov(&self) -> &Fov<T, X> {
+        &self.fov
+    }
+
+    /// Take the field and the update function back.
+    pub fn into_inner(self) -> (Fov<T, X>, F) {
+        (self.fov, self.update_fn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel::{self, Visibility};
+    use std::sync::Arc;
+
+    #[test]
+    fn job_matches_full_update() {
+        let flux_field = Arc::new(FluxField::new(10));
+        let opacity = |x: i32, y: i32| if (x - y) % 4 == 1 { 0.7 } else { 0.0 };
+        let mut full = Fov::new(flux_field.clone(), 10, Visibility::default());
+        full.update(kernel::threshold(0.5, opacity));
+        let fov = Fov::new(flux_field, 10, Visibility::default());
+        let mut job = FovJob::new(fov, kernel::threshold(0.5, opacity));
+        assert_eq!(job.cells_done(), 0);
+        let mut steps = 0;
+        while job.run_step(50).is_pending() {
+            steps += 1;
+            assert_eq!(job.cells_done(), 50 * steps);
+        }
+        assert_eq!(steps, 441 / 50);
+        assert!(job.is_done());
+        assert_eq!(job.run_step(50), Poll::Ready(()));
+        assert_eq!(job.fov().as_slice(), full.as_slice());
+        job.restart();
+        assert!(!job.is_done());
+        job.run();
+        let (fov, _) = job.into_inner();
+        assert_eq!(fov.as_slice(), full.as_slice());
+    }
+
+    #[test]
+    fn run_after_partial_step() {
+        let flux_field = Arc::new(FluxField::new(4));
+        let mut full = Fov::new(flux_field.clone(), 4, (0, 0));
+        full.update(|x, y, _| (x, y));
+        let fov = Fov::new(flux_field, 4, (0, 0));
+        let mut job = FovJob::new(fov, |x, y, _: &[Influx<&(i32, i32)>]| (x, y));
+        assert!(job.run_step(5).is_pending());
+        job.run();
+        assert!(job.is_done());
+        assert_eq!(job.cells_done(), 81);
+        assert_eq!(job.fov().as_slice(), full.as_slice());
+    }
+}
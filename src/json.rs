@@ -0,0 +1,81 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use serde_json::{json, Value};
+
+use crate::{FluxField, Fov};
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Dump the field as JSON for debugging tools and bug reports.
+    ///
+    /// The dump is an object with the `radius`, the `width` and the
+    /// `height` of the grid, the `origin` as the column and the row of the
+    /// origin cell within the grid, and the `cells` as an array of rows from
+    /// y = -radius to y = radius, each an array of the cells from x = -radius
+    /// to x = radius converted with `f`.
+    ///
+    /// ```
+    /// # use flux_fov::{FluxField, Fov};
+    /// let fov = Fov::new(Box::new(FluxField::new(1)), 1, 0.5);
+    /// let dump = fov.to_json(|v| (*v).into());
+    /// assert_eq!(dump["origin"], serde_json::json!([1, 1]));
+    /// assert_eq!(dump["cells"][1][2], 0.5);
+    /// ```
+    ///
+    pub fn to_json<F>(&self, f: F) -> Value
+    where
+        F: FnMut(&T) -> Value,
+    {
+        let mut f = f;
+        let width = self.width as usize;
+        let cells: Vec<Value> = self
+            .data
+            .chunks(width)
+            .map(|row| Value::Array(row.iter().map(&mut f).collect()))
+            .collect();
+        json!({
+            "radius": self.radius,
+            "width": width,
+            "height": width,
+            "origin": [self.radius, self.radius],
+            "cells": cells,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn json_dump() {
+        let mut fov = Fov::new(Box::new(FluxField::new(2)), 2, (0, 0));
+        fov.update(|x, y, _| (x, y));
+        let dump = fov.to_json(|&(x, y)| json!(format!("{},{}", x, y)));
+        assert_eq!(dump["radius"], 2);
+        assert_eq!(dump["width"], 5);
+        assert_eq!(dump["height"], 5);
+        assert_eq!(dump["origin"], json!([2, 2]));
+        assert_eq!(dump["cells"].as_array().unwrap().len(), 5);
+        assert_eq!(dump["cells"][0][0], "-2,-2");
+        assert_eq!(dump["cells"][2][2], "0,0");
+        assert_eq!(dump["cells"][3][4], "2,1");
+    }
+}
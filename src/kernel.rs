@@ -0,0 +1,1265 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Standard update functions, or kernels, for `Fov::update`.
+//!
+//! The map callbacks taken by the kernels receive the coordinates relative to
+//! the origin of the field of vision.
+
+use crate::{FixedInflux, Influx, Weight, FIXED_ONE};
+
+/// The visibility of a grid cell as computed by the standard kernels.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Visibility {
+    /// Whether the cell is visible.
+    pub is_visible: bool,
+    /// The amount of rays passing through the cell onwards.
+    pub ray_output: f32,
+}
+
+impl Visibility {
+    /// The visibility of the origin.
+    pub const ORIGIN: Visibility = Visibility {
+        is_visible: true,
+        ray_output: 1.0,
+    };
+
+    /// The visibility of an origin emitting rays of the given `intensity`
+    /// instead of 1.0; see `Fov::update_seeded`.
+    ///
+    pub fn origin(intensity: f32) -> Self {
+        Visibility {
+            is_visible: true,
+            ray_output: intensity,
+        }
+    }
+}
+
+/// The illumination of a grid cell as computed by the soft-shadow kernel.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Illumination {
+    /// The fraction of the light reaching the cell, from 0.0 to 1.0.
+    pub brightness: f32,
+    /// The amount of light passing through the cell onwards.
+    pub ray_output: f32,
+}
+
+impl Illumination {
+    /// The illumination of the origin.
+    pub const ORIGIN: Illumination = Illumination {
+        brightness: 1.0,
+        ray_output: 1.0,
+    };
+
+    /// The illumination of a light of the given `intensity` instead of 1.0;
+    /// see `Fov::update_seeded`.
+    ///
+    pub fn origin(intensity: f32) -> Self {
+        Illumination {
+            brightness: intensity,
+            ray_output: intensity,
+        }
+    }
+}
+
+/// A kernel where the occluders cast soft shadows.
+///
+/// Rather than being either lit or not, a cell receives the fraction of the
+/// rays that reach it.  Since the flux weights tell how large a part of the
+/// angular span of a cell is covered by each influx, an occluder covering
+/// only a part of the span blocks only that part of the light.  The result
+/// looks like the light of an area light with penumbras rather than the
+/// razor-edged shadows of a point source.  The `opacity` of a cell is as in
+/// `threshold`.
+///
+pub fn soft_shadow<F>(opacity: F) -> impl FnMut(i32, i32, &[Influx<&Illumination>]) -> Illumination
+where
+    F: Fn(i32, i32) -> f32,
+{
+    move |x, y, influxes| {
+        if x == 0 && y == 0 {
+            Illumination::ORIGIN
+        } else {
+            let brightness: f32 = influxes.iter().map(|f| f.weight * f.value.ray_output).sum();
+            Illumination {
+                brightness,
+                ray_output: brightness * (1.0 - opacity(x, y)),
+            }
+        }
+    }
+}
+
+/// A kernel that lets the rays through cells in proportion to their
+/// transparency.
+///
+/// A cell is visible if the weighted sum of the rays reaching it exceeds
+/// `threshold`.  The `opacity` of a cell ranges from 0.0 (transparent) to 1.0
+/// (opaque) and the cell passes on the fraction `1.0 - opacity` of the rays
+/// reaching it.
+///
+pub fn threshold<F>(
+    threshold: f32,
+    opacity: F,
+) -> impl FnMut(i32, i32, &[Influx<&Visibility>]) -> Visibility
+where
+    F: Fn(i32, i32) -> f32,
+{
+    move |x, y, influxes| {
+        if x == 0 && y == 0 {
+            Visibility::ORIGIN
+        } else {
+            let ray_input: f32 = influxes.iter().map(|f| f.weight * f.value.ray_output).sum();
+            Visibility {
+                is_visible: ray_input > threshold,
+                ray_output: ray_input * (1.0 - opacity(x, y)),
+            }
+        }
+    }
+}
+
+/// The visibility of a grid cell as computed by the fixed-point kernels.
+///
+/// The amount of rays is a fixed-point number with 16 fractional bits; see
+/// `FIXED_ONE`.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FixedVisibility {
+    /// Whether the cell is visible.
+    pub is_visible: bool,
+    /// The amount of rays passing through the cell onwards.
+    pub ray_output: u32,
+}
+
+impl FixedVisibility {
+    /// The visibility of the origin.
+    pub const ORIGIN: FixedVisibility = FixedVisibility {
+        is_visible: true,
+        ray_output: FIXED_ONE,
+    };
+}
+
+// The product of two fixed-point numbers.
+#[inline]
+fn fixed_mul(a: u32, b: u32) -> u32 {
+    a.scale(b)
+}
+
+/// The fixed-point version of `threshold` for `Fov::update_fixed`.
+///
+/// The `threshold` and the `opacity` are fixed-point numbers with
+/// `FIXED_ONE` standing for 1.0.  No floating point arithmetic is involved.
+///
+pub fn fixed_threshold<F>(
+    threshold: u32,
+    opacity: F,
+) -> impl FnMut(i32, i32, &[FixedInflux<&FixedVisibility>]) -> FixedVisibility
+where
+    F: Fn(i32, i32) -> u32,
+{
+    move |x, y, influxes| {
+        if x == 0 && y == 0 {
+            FixedVisibility::ORIGIN
+        } else {
+            let ray_input: u32 = influxes
+                .iter()
+                .map(|f| fixed_mul(f.weight, f.value.ray_output))
+                .sum();
+            FixedVisibility {
+                is_visible: ray_input > threshold,
+                ray_output: fixed_mul(ray_input, FIXED_ONE - opacity(x, y).min(FIXED_ONE)),
+            }
+        }
+    }
+}
+
+/// The illumination of a grid cell as computed by `fixed_soft_shadow`.
+///
+/// The fractions are fixed-point numbers as in `FixedVisibility`.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FixedIllumination {
+    /// The fraction of the light reaching the cell.
+    pub brightness: u32,
+    /// The amount of light passing through the cell onwards.
+    pub ray_output: u32,
+}
+
+impl FixedIllumination {
+    /// The illumination of the origin.
+    pub const ORIGIN: FixedIllumination = FixedIllumination {
+        brightness: FIXED_ONE,
+        ray_output: FIXED_ONE,
+    };
+}
+
+/// The fixed-point version of `soft_shadow` for `Fov::update_fixed`.
+///
+pub fn fixed_soft_shadow<F>(
+    opacity: F,
+) -> impl FnMut(i32, i32, &[FixedInflux<&FixedIllumination>]) -> FixedIllumination
+where
+    F: Fn(i32, i32) -> u32,
+{
+    move |x, y, influxes| {
+        if x == 0 && y == 0 {
+            FixedIllumination::ORIGIN
+        } else {
+            let brightness: u32 = influxes
+                .iter()
+                .map(|f| fixed_mul(f.weight, f.value.ray_output))
+                .sum();
+            FixedIllumination {
+                brightness,
+                ray_output: fixed_mul(brightness, FIXED_ONE - opacity(x, y).min(FIXED_ONE)),
+            }
+        }
+    }
+}
+
+/// A set of directions of travel of the flux.
+///
+/// The directions are those of the eight neighbors with the y-axis pointing
+/// down, so that south is (0, 1).
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Directions(pub u8);
+
+impl Directions {
+    pub const NONE: Directions = Directions(0);
+    pub const EAST: Directions = Directions(1 << 0);
+    pub const SOUTH_EAST: Directions = Directions(1 << 1);
+    pub const SOUTH: Directions = Directions(1 << 2);
+    pub const SOUTH_WEST: Directions = Directions(1 << 3);
+    pub const WEST: Directions = Directions(1 << 4);
+    pub const NORTH_WEST: Directions = Directions(1 << 5);
+    pub const NORTH: Directions = Directions(1 << 6);
+    pub const NORTH_EAST: Directions = Directions(1 << 7);
+    pub const ALL: Directions = Directions(0xff);
+
+    /// The direction (`dx`, `dy`) where both components are -1, 0, or 1.
+    pub fn of(dx: i32, dy: i32) -> Directions {
+        match (dx.signum(), dy.signum()) {
+            (1, 0) => Directions::EAST,
+            (1, 1) => Directions::SOUTH_EAST,
+            (0, 1) => Directions::SOUTH,
+            (-1, 1) => Directions::SOUTH_WEST,
+            (-1, 0) => Directions::WEST,
+            (-1, -1) => Directions::NORTH_WEST,
+            (0, -1) => Directions::NORTH,
+            (1, -1) => Directions::NORTH_EAST,
+            _ => Directions::NONE,
+        }
+    }
+
+    /// Whether all the directions of `other` are in the set.
+    pub fn contains(self, other: Directions) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// The union of the sets.
+    pub fn union(self, other: Directions) -> Directions {
+        Directions(self.0 | other.0)
+    }
+
+    /// The directions not in the set.
+    pub fn complement(self) -> Directions {
+        Directions(!self.0)
+    }
+}
+
+/// A kernel for cells that block the flux traveling in some directions but
+/// not in others, such as one-way glass or arrow slits.
+///
+/// The `blocked` callback gives the directions of travel blocked by a cell.
+/// A cell is visible if the weighted sum of all the rays reaching it exceeds
+/// `threshold`, so the occluder itself can be seen from any side, but it
+/// passes on only the rays traveling in the directions it doesn't block.
+///
+pub fn directional<F>(
+    threshold: f32,
+    blocked: F,
+) -> impl FnMut(i32, i32, &[Influx<&Visibility>]) -> Visibility
+where
+    F: Fn(i32, i32) -> Directions,
+{
+    move |x, y, influxes| {
+        if x == 0 && y == 0 {
+            Visibility::ORIGIN
+        } else {
+            let blocked = blocked(x, y);
+            let mut ray_input = 0.0;
+            let mut ray_output = 0.0;
+            for f in influxes {
+                let rays = f.weight * f.value.ray_output;
+                ray_input += rays;
+                if !blocked.contains(Directions::of(f.dx, f.dy)) {
+                    ray_output += rays;
+                }
+            }
+            Visibility {
+                is_visible: ray_input > threshold,
+                ray_output,
+            }
+        }
+    }
+}
+
+/// The state of a door.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Door {
+    /// The door blocks all the rays.
+    Closed,
+    /// The door lets all the rays through.
+    Open,
+    /// The door lets through the fraction `transmittance` of the rays
+    /// traveling in the directions `through` and blocks the rest.
+    Ajar {
+        through: Directions,
+        transmittance: f32,
+    },
+}
+
+/// A kernel for maps with doors.
+///
+/// The `door` callback gives the state of the door in a cell, or `None` if
+/// there is no door, in which case the cell is treated as in `threshold`
+/// with the given `opacity`.  The door itself is visible whenever the rays
+/// reaching it exceed `threshold` regardless of its state.
+///
+pub fn doors<F, D>(
+    threshold: f32,
+    opacity: F,
+    door: D,
+) -> impl FnMut(i32, i32, &[Influx<&Visibility>]) -> Visibility
+where
+    F: Fn(i32, i32) -> f32,
+    D: Fn(i32, i32) -> Option<Door>,
+{
+    move |x, y, influxes| {
+        if x == 0 && y == 0 {
+            return Visibility::ORIGIN;
+        }
+        let ray_input: f32 = influxes.iter().map(|f| f.weight * f.value.ray_output).sum();
+        let ray_output = match door(x, y) {
+            None => ray_input * (1.0 - opacity(x, y)),
+            Some(Door::Closed) => 0.0,
+            Some(Door::Open) => ray_input,
+            Some(Door::Ajar {
+                through,
+                transmittance,
+            }) => {
+                let passing: f32 = influxes
+                    .iter()
+                    .filter(|f| through.contains(Directions::of(f.dx, f.dy)))
+                    .map(|f| f.weight * f.value.ray_output)
+                    .sum();
+                passing * transmittance
+            }
+        };
+        Visibility {
+            is_visible: ray_input > threshold,
+            ray_output,
+        }
+    }
+}
+
+/// The visibility of a grid cell as computed by the x-ray kernel.
+///
+/// `N` is the number of occluders the rays may pass through.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct XRay<const N: usize> {
+    /// Whether the cell is visible, possibly through occluders.
+    pub is_visible: bool,
+    /// Whether the cell is visible without looking through any occluders.
+    pub is_directly_visible: bool,
+    /// The amount of rays passing through the cell onwards without having
+    /// passed through any occluders.
+    pub ray_output: f32,
+    /// The amounts of rays passing through the cell onwards having passed
+    /// through one, two, and so on up to `N` occluders.
+    pub penetrating: [f32; N],
+}
+
+impl<const N: usize> XRay<N> {
+    /// The visibility of the origin.
+    pub const ORIGIN: XRay<N> = XRay {
+        is_visible: true,
+        is_directly_visible: true,
+        ray_output: 1.0,
+        penetrating: [0.0; N],
+    };
+}
+
+impl<const N: usize> Default for XRay<N> {
+    fn default() -> Self {
+        XRay {
+            is_visible: false,
+            is_directly_visible: false,
+            ray_output: 0.0,
+            penetrating: [0.0; N],
+        }
+    }
+}
+
+/// A kernel where the rays pass through up to `N` occluders.
+///
+/// The rays pass through the cells that are not opaque as is.  An opaque cell
+/// passes on the fraction `attenuation` of the rays that have passed through
+/// fewer than `N` occluders so far and stops the rest.  A cell is visible if
+/// the rays reaching it, including the ones that passed through occluders,
+/// exceed `threshold`, so with `N` of 1 one can see the cells right behind a
+/// wall, e.g., for x-ray vision or for sensing through thin walls.  The
+/// occluders themselves are visible like in `threshold`.
+///
+/// ```
+/// # use flux_fov::{FluxField, Fov, kernel};
+/// let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, kernel::XRay::<1>::default());
+/// fov.update(kernel::xray(0.5, 1.0, |x, _| x == 2 || x == 4));
+/// assert!(!fov.at(3, 0).is_directly_visible);
+/// assert!(fov.at(3, 0).is_visible);
+/// assert!(!fov.at(5, 0).is_visible);
+/// ```
+///
+pub fn xray<const N: usize, F>(
+    threshold: f32,
+    attenuation: f32,
+    is_opaque: F,
+) -> impl FnMut(i32, i32, &[Influx<&XRay<N>>]) -> XRay<N>
+where
+    F: Fn(i32, i32) -> bool,
+{
+    move |x, y, influxes| {
+        if x == 0 && y == 0 {
+            XRay::ORIGIN
+        } else {
+            let mut direct = 0.0;
+            let mut penetrating = [0.0; N];
+            for f in influxes {
+                direct += f.weight * f.value.ray_output;
+                for (p, q) in penetrating.iter_mut().zip(f.value.penetrating.iter()) {
+                    *p += f.weight * q;
+                }
+            }
+            let ray_input = direct + penetrating.iter().sum::<f32>();
+            let mut cell = XRay {
+                is_visible: ray_input > threshold,
+                is_directly_visible: direct > threshold,
+                ray_output: direct,
+                penetrating,
+            };
+            if is_opaque(x, y) {
+                // Every tier moves one occluder deeper and the deepest one
+                // stops here.
+                for i in (1..N).rev() {
+                    cell.penetrating[i] = attenuation * cell.penetrating[i - 1];
+                }
+                if N > 0 {
+                    cell.penetrating[0] = attenuation * direct;
+                }
+                cell.ray_output = 0.0;
+            }
+            cell
+        }
+    }
+}
+
+/// The visibility of a grid cell and the fog in front of it as computed by
+/// the fog kernel.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Fog {
+    /// Whether the cell is visible.
+    pub is_visible: bool,
+    /// The amount of rays passing through the cell onwards.
+    pub ray_output: f32,
+    /// The fog density integrated along the flux from the origin up to and
+    /// including the cell.
+    pub optical_depth: f32,
+}
+
+impl Fog {
+    /// The visibility of the origin.
+    pub const ORIGIN: Fog = Fog {
+        is_visible: true,
+        ray_output: 1.0,
+        optical_depth: 0.0,
+    };
+
+    /// The fraction of the light from the cell absorbed or scattered by the
+    /// fog before it reaches the origin, from 0.0 (clear) to 1.0 (lost in
+    /// the fog), e.g., for the alpha of a haze overlay.
+    ///
+    pub fn extinction(&self) -> f32 {
+        1.0 - (-self.optical_depth).exp()
+    }
+}
+
+/// A kernel that decides the visibility like `threshold` and integrates a
+/// fog density along the flux on the side.
+///
+/// The optical depth of a cell is its own `density` plus the optical depth
+/// of the influxes averaged over the rays they bring in, so the haze of a
+/// cell reflects the fog along the routes that actually made it visible
+/// rather than the fog along a straight line.  Where no rays arrive the
+/// average falls back to the flux weights.  The `density` is per cell, so
+/// that a cell with the density `d` alone extinguishes `1 - exp(-d)` of the
+/// light.
+///
+/// ```
+/// # use flux_fov::{FluxField, Fov, kernel};
+/// let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, kernel::Fog::default());
+/// fov.update(kernel::fog(0.5, |_, _| 0.0, |x, _| if x > 2 { 0.5 } else { 0.0 }));
+/// assert_eq!(fov.at(2, 0).extinction(), 0.0);
+/// assert!(fov.at(5, 0).is_visible);
+/// assert!((fov.at(5, 0).optical_depth - 1.5).abs() < 1e-4);
+/// ```
+///
+pub fn fog<F, D>(
+    threshold: f32,
+    opacity: F,
+    density: D,
+) -> impl FnMut(i32, i32, &[Influx<&Fog>]) -> Fog
+where
+    F: Fn(i32, i32) -> f32,
+    D: Fn(i32, i32) -> f32,
+{
+    move |x, y, influxes| {
+        if x == 0 && y == 0 {
+            Fog::ORIGIN
+        } else {
+            let mut ray_input = 0.0;
+            let mut depth = 0.0;
+            for f in influxes {
+                let rays = f.weight * f.value.ray_output;
+                ray_input += rays;
+                depth += rays * f.value.optical_depth;
+            }
+            let depth = if ray_input > 0.0 {
+                depth / ray_input
+            } else {
+                let weight: f32 = influxes.iter().map(|f| f.weight).sum();
+                let depth: f32 = influxes
+                    .iter()
+                    .map(|f| f.weight * f.value.optical_depth)
+                    .sum();
+                if weight > 0.0 {
+                    depth / weight
+                } else {
+                    0.0
+                }
+            };
+            Fog {
+                is_visible: ray_input > threshold,
+                ray_output: ray_input * (1.0 - opacity(x, y)),
+                optical_depth: depth + density(x, y),
+            }
+        }
+    }
+}
+
+/// A kernel giving symmetric visibility on maps of walls and floors.
+///
+/// A cell is visible if it can be reached from the origin by a chain of
+/// straight and diagonal flux steps that passes only through cells that are
+/// not opaque.  The flux weights are ignored.
+///
+/// The visibility is symmetric: if A sees B then B sees A.  To see why,
+/// consider B in the octant 0 of A so that the chain consists of steps
+/// (1, 0) and (1, 1).  Reversed, the chain consists of the steps (-1, 0) and
+/// (-1, -1) which are exactly the straight and diagonal flux steps in the
+/// octant 4 of B, where A lies.  As the steps never move sideways faster than
+/// forwards, every cell of the chain stays within the octant for both ends.
+/// The same holds for the other octants and the edges by symmetry.
+///
+/// The price of the symmetry is permissiveness: any chain will do, so the
+/// kernel sees further around the corners than the flux weights would
+/// suggest.
+///
+pub fn symmetric<F>(is_opaque: F) -> impl FnMut(i32, i32, &[Influx<&Visibility>]) -> Visibility
+where
+    F: Fn(i32, i32) -> bool,
+{
+    move |x, y, influxes| {
+        if x == 0 && y == 0 {
+            Visibility::ORIGIN
+        } else {
+            let is_visible = influxes.iter().any(|f| f.value.ray_output > 0.0);
+            Visibility {
+                is_visible,
+                ray_output: if is_visible && !is_opaque(x, y) {
+                    1.0
+                } else {
+                    0.0
+                },
+            }
+        }
+    }
+}
+
+/// A kernel accumulating opacity cost along the flux.
+///
+/// The value of a cell is the weighted sum of the costs reaching it plus the
+/// `cost` of the cell itself, the origin costing nothing.  A cell is
+/// considered visible if its value stays within the viewer's budget so that,
+/// with a budget of 1.0, a cost of 1/3 per cell lets the viewer see three
+/// cells into fog and a cost of 1.0 one cell into dense smoke.  Walls can be
+/// given an infinite cost.
+///
+pub fn cost<F>(cost: F) -> impl FnMut(i32, i32, &[Influx<&f32>]) -> f32
+where
+    F: Fn(i32, i32) -> f32,
+{
+    move |x, y, influxes| {
+        if x == 0 && y == 0 {
+            0.0
+        } else {
+            // Skip the unweighted influxes so that an infinite cost does not
+            // turn into a NaN.
+            let cost_input: f32 = influxes
+                .iter()
+                .filter(|f| f.weight > 0.0)
+                .map(|f| f.weight * *f.value)
+                .sum();
+            cost_input + cost(x, y)
+        }
+    }
+}
+
+/// The visibility and cover of a grid cell as computed by the cover kernel.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Cover {
+    /// Whether the cell is visible.
+    pub is_visible: bool,
+    /// The fraction of the rays reaching the cell that passed through low
+    /// obstacles on the way, from 0.0 (fully exposed) to 1.0 (fully
+    /// covered).
+    pub cover: f32,
+    /// The amount of rays passing through the cell onwards.
+    pub ray_output: f32,
+    /// The part of `ray_output` that hasn't passed through low obstacles.
+    pub exposed_output: f32,
+}
+
+impl Cover {
+    /// The cover of the origin.
+    pub const ORIGIN: Cover = Cover {
+        is_visible: true,
+        cover: 0.0,
+        ray_output: 1.0,
+        exposed_output: 1.0,
+    };
+}
+
+/// A kernel for soft cover mechanics.
+///
+/// The visibility is as in `threshold` with the given `opacity`.  In
+/// addition the `low` callback gives how much of the rays a low obstacle in
+/// a cell, such as a crate or a fence, covers from 0.0 (none) to 1.0 (all).
+/// Low obstacles don't block the rays but the cells behind them get cover in
+/// proportion to the part of the rays that passed through them.  The
+/// obstacle doesn't cover its own cell.
+///
+pub fn cover<F, L>(
+    threshold: f32,
+    opacity: F,
+    low: L,
+) -> impl FnMut(i32, i32, &[Influx<&Cover>]) -> Cover
+where
+    F: Fn(i32, i32) -> f32,
+    L: Fn(i32, i32) -> f32,
+{
+    move |x, y, influxes| {
+        if x == 0 && y == 0 {
+            Cover::ORIGIN
+        } else {
+            let ray_input: f32 = influxes.iter().map(|f| f.weight * f.value.ray_output).sum();
+            let exposed_input: f32 = influxes
+                .iter()
+                .map(|f| f.weight * f.value.exposed_output)
+                .sum();
+            let transmittance = 1.0 - opacity(x, y);
+            Cover {
+                is_visible: ray_input > threshold,
+                cover: if ray_input > 0.0 {
+                    (1.0 - exposed_input / ray_input).max(0.0)
+                } else {
+                    0.0
+                },
+                ray_output: ray_input * transmittance,
+                exposed_output: exposed_input * transmittance * (1.0 - low(x, y)),
+            }
+        }
+    }
+}
+
+/// The stance of an observer on terrain of varying height; see `terrain`.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stance {
+    /// The eyes at 1.5 above the ground.
+    Standing,
+    /// The eyes at 0.75 above the ground.
+    Crouching,
+    /// The eyes at 5.0 above the ground.
+    Flying,
+    /// The eyes at the given height above the ground.
+    Eyes(f32),
+}
+
+impl Stance {
+    /// The height of the eyes above the ground under the observer.
+    pub fn eye_height(self) -> f32 {
+        match self {
+            Stance::Standing => 1.5,
+            Stance::Crouching => 0.75,
+            Stance::Flying => 5.0,
+            Stance::Eyes(height) => height,
+        }
+    }
+}
+
+/// A kernel for terrain of varying height where what occludes depends on
+/// the stance of the observer, e.g., for peeking over low walls.
+///
+/// The `height` callback gives the height of the top of each cell, the
+/// ground plus whatever stands on it, in the units of `Stance::eye_height`.
+/// A cell reaching up to the eyes of the observer, that is, at least
+/// `stance.eye_height()` above the cell of the observer, blocks the rays;
+/// the lower ones let them through.  The visibility is otherwise as in
+/// `threshold`, so the occluders themselves are visible.
+///
+/// ```
+/// # use flux_fov::{FluxField, Fov, kernel::{self, Stance}};
+/// let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, kernel::Visibility::default());
+/// let low_wall = |x: i32, _| if x == 2 { 1.0 } else { 0.0 };
+/// fov.update(kernel::terrain(0.5, Stance::Standing, low_wall));
+/// assert!(fov.at(4, 0).is_visible);
+/// fov.update(kernel::terrain(0.5, Stance::Crouching, low_wall));
+/// assert!(!fov.at(4, 0).is_visible);
+/// ```
+///
+pub fn terrain<H>(
+    threshold: f32,
+    stance: Stance,
+    height: H,
+) -> impl FnMut(i32, i32, &[Influx<&Visibility>]) -> Visibility
+where
+    H: Fn(i32, i32) -> f32,
+{
+    let eyes = height(0, 0) + stance.eye_height();
+    self::threshold(
+        threshold,
+        move |x, y| {
+            if height(x, y) >= eyes {
+                1.0
+            } else {
+                0.0
+            }
+        },
+    )
+}
+
+/// The acuity of vision as a function of the angle off the facing
+/// direction.
+///
+/// The acuity is given as breakpoints (angle, acuity) in the order of
+/// increasing angle, the angles in radians from 0.0 (straight ahead) to π
+/// (straight behind).  The acuity is interpolated linearly between the
+/// breakpoints and held constant before the first and after the last one.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Acuity {
+    breakpoints: Vec<(f32, f32)>,
+}
+
+impl Acuity {
+    pub fn new(breakpoints: Vec<(f32, f32)>) -> Self {
+        assert!(!breakpoints.is_empty(), "no breakpoints");
+        assert!(
+            breakpoints.windows(2).all(|w| w[0].0 <= w[1].0),
+            "breakpoints out of order"
+        );
+        Acuity { breakpoints }
+    }
+
+    /// The acuity at the given angle off the facing direction.
+    pub fn at(&self, angle: f32) -> f32 {
+        let bps = &self.breakpoints;
+        match bps.iter().position(|&(a, _)| a > angle) {
+            Some(0) => bps[0].1,
+            Some(i) => {
+                let (a0, v0) = bps[i - 1];
+                let (a1, v1) = bps[i];
+                v0 + (v1 - v0) * (angle - a0) / (a1 - a0)
+            }
+            None => bps[bps.len() - 1].1,
+        }
+    }
+}
+
+impl Default for Acuity {
+    /// Full acuity within 50° of the facing direction falling off to a
+    /// third at 100° and to nothing at 110°.
+    fn default() -> Self {
+        let deg = |d: f32| d.to_radians();
+        Acuity::new(vec![
+            (deg(50.0), 1.0),
+            (deg(100.0), 1.0 / 3.0),
+            (deg(110.0), 0.0),
+        ])
+    }
+}
+
+/// A kernel modifier scaling the values of `kernel` by the acuity of vision
+/// at the angle between the cell and the `facing` direction.
+///
+/// The `facing` direction is in radians from the positive x-axis towards the
+/// positive y-axis.  The `scale` callback applies the acuity to a value and
+/// should leave the part of the value passed on to the next cells as it is
+/// so that the acuity doesn't cast shadows, e.g.:
+///
+/// ```
+/// # use flux_fov::kernel::{self, Acuity, Illumination};
+/// # use flux_fov::{FluxField, Fov};
+/// let mut fov = Fov::new(Box::new(FluxField::new(4)), 4, Illumination::default());
+/// fov.update(kernel::facing(
+///     0.0,
+///     Acuity::default(),
+///     kernel::soft_shadow(|_, _| 0.0),
+///     |v, acuity| Illumination {
+///         brightness: v.brightness * acuity,
+///         ..v
+///     },
+/// ));
+/// assert_eq!(fov.at(3, 0).brightness, 1.0);
+/// assert_eq!(fov.at(-3, 0).brightness, 0.0);
+/// assert_eq!(fov.at(-4, 0).ray_output, 1.0);
+/// ```
+///
+pub fn facing<T, K, S>(
+    facing: f32,
+    acuity: Acuity,
+    kernel: K,
+    scale: S,
+) -> impl FnMut(i32, i32, &[Influx<&T>]) -> T
+where
+    K: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    S: Fn(T, f32) -> T,
+{
+    let mut kernel = kernel;
+    move |x, y, influxes| {
+        let value = kernel(x, y, influxes);
+        if x == 0 && y == 0 {
+            value
+        } else {
+            let off = ((y as f32).atan2(x as f32) - facing).rem_euclid(2.0 * std::f32::consts::PI);
+            let angle = off.min(2.0 * std::f32::consts::PI - off);
+            scale(value, acuity.at(angle))
+        }
+    }
+}
+
+/// Combine two kernels into one computing both of their channels in a
+/// single pass, e.g., line of sight and line of fire with different
+/// blocking rules:
+///
+/// ```
+/// # use flux_fov::kernel::{self, Visibility};
+/// # use flux_fov::{FluxField, Fov};
+/// // A window at (2, 0) blocks projectiles but not sight.
+/// let window = |x, y| x == 2 && y == 0;
+/// let mut fov = Fov::new(
+///     Box::new(FluxField::new(4)),
+///     4,
+///     (Visibility::default(), Visibility::default()),
+/// );
+/// fov.update(kernel::pair(
+///     kernel::threshold(0.5, |_, _| 0.0),
+///     kernel::threshold(0.5, move |x, y| if window(x, y) { 1.0 } else { 0.0 }),
+/// ));
+/// let (sight, fire) = fov.at(4, 0);
+/// assert!(sight.is_visible && !fire.is_visible);
+/// ```
+///
+#[allow(clippy::type_complexity)]
+pub fn pair<A, B, KA, KB>(
+    first: KA,
+    second: KB,
+) -> impl FnMut(i32, i32, &[Influx<&(A, B)>]) -> (A, B)
+where
+    KA: FnMut(i32, i32, &[Influx<&A>]) -> A,
+    KB: FnMut(i32, i32, &[Influx<&B>]) -> B,
+{
+    let mut first = first;
+    let mut second = second;
+    move |x, y, influxes| {
+        if influxes.is_empty() {
+            return (first(x, y, &[]), second(x, y, &[]));
+        }
+        let n = influxes.len();
+        let a = first(x, y, &project(influxes, |v| &v.0)[..n]);
+        let b = second(x, y, &project(influxes, |v| &v.1)[..n]);
+        (a, b)
+    }
+}
+
+// Project the values of one to three influxes.  The unused slots of the
+// array repeat the last influx.
+fn project<'a, T, U, P>(influxes: &[Influx<&'a T>], p: P) -> [Influx<&'a U>; 3]
+where
+    P: Fn(&'a T) -> &'a U,
+{
+    let f = |i: usize| {
+        let f = &influxes[i.min(influxes.len() - 1)];
+        Influx {
+            weight: f.weight,
+            dx: f.dx,
+            dy: f.dy,
+            value: p(f.value),
+        }
+    };
+    [f(0), f(1), f(2)]
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::three_tap;
+    use crate::{FluxField, Fov};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::rc::Rc;
+
+    const SIZE: i32 = 24;
+    const RADIUS: i32 = 8;
+
+    fn random_map(seed: u64, density: f64) -> Vec<bool> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..SIZE * SIZE).map(|_| rng.gen_bool(density)).collect()
+    }
+
+    fn views(map: &[bool]) -> Vec<Fov<Visibility, Rc<FluxField>>> {
+        let flux_field = Rc::new(FluxField::new(RADIUS as usize));
+        let mut views = Vec::new();
+        for oy in 0..SIZE {
+            for ox in 0..SIZE {
+                let mut fov = Fov::new(flux_field.clone(), RADIUS as usize, Visibility::default());
+                fov.update(symmetric(|x, y| {
+                    let (mx, my) = (ox + x, oy + y);
+                    !(0..SIZE).contains(&mx)
+                        || !(0..SIZE).contains(&my)
+                        || map[(my * SIZE + mx) as usize]
+                }));
+                views.push(fov);
+            }
+        }
+        views
+    }
+
+    #[test]
+    fn symmetric_kernel_is_symmetric() {
+        for seed in 0..4 {
+            let map = random_map(seed, 0.1 + 0.1 * seed as f64);
+            let views = views(&map);
+            let mut visible = 0;
+            for ay in 0..SIZE {
+                for ax in 0..SIZE {
+                    let a = &views[(ay * SIZE + ax) as usize];
+                    for by in (ay - RADIUS).max(0)..(ay + RADIUS + 1).min(SIZE) {
+                        for bx in (ax - RADIUS).max(0)..(ax + RADIUS + 1).min(SIZE) {
+                            let b = &views[(by * SIZE + bx) as usize];
+                            let ab = a.at(bx - ax, by - ay).is_visible;
+                            let ba = b.at(ax - bx, ay - by).is_visible;
+                            assert_eq!(
+                                ab, ba,
+                                "seed {}: ({}, {}) vs ({}, {})",
+                                seed, ax, ay, bx, by
+                            );
+                            visible += ab as usize;
+                        }
+                    }
+                }
+            }
+            assert!(visible > 0);
+        }
+    }
+
+    #[test]
+    fn paired_kernels_match_separate_updates() {
+        let flux_field = Rc::new(FluxField::with_params(8, three_tap()));
+        let sight = |x: i32, y: i32| if (x + y) % 5 == 0 { 0.7 } else { 0.0 };
+        let fire = |x: i32, _: i32| if x == 3 { 1.0 } else { 0.1 };
+        let mut both = Fov::new(
+            flux_field.clone(),
+            8,
+            (Visibility::default(), Illumination::default()),
+        );
+        both.update(pair(threshold(0.3, sight), soft_shadow(fire)));
+        let mut los = Fov::new(flux_field.clone(), 8, Visibility::default());
+        los.update(threshold(0.3, sight));
+        let mut lof = Fov::new(flux_field, 8, Illumination::default());
+        lof.update(soft_shadow(fire));
+        for (i, (a, b)) in both.as_slice().iter().enumerate() {
+            assert_eq!(*a, los.as_slice()[i]);
+            assert_eq!(*b, lof.as_slice()[i]);
+        }
+    }
+
+    #[test]
+    fn cover_behind_low_obstacles() {
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, Cover::default());
+        fov.update(cover(
+            0.5,
+            |_, _| 0.0,
+            |x, y| if x == 2 && y == 0 { 1.0 } else { 0.0 },
+        ));
+        assert!(fov.at(2, 0).is_visible);
+        assert_eq!(fov.at(2, 0).cover, 0.0);
+        assert_eq!(fov.at(3, 0).cover, 1.0);
+        assert!(fov.at(3, 0).is_visible);
+        let partial = fov.at(6, 1).cover;
+        assert!(0.0 < partial && partial < 1.0);
+        assert_eq!(fov.at(-6, 0).cover, 0.0);
+    }
+
+    #[test]
+    fn threshold_kernel_blocks_behind_walls() {
+        let mut fov = Fov::new(Box::new(FluxField::new(4)), 4, Visibility::default());
+        fov.update(threshold(0.5, |x, _| if x == 2 { 1.0 } else { 0.0 }));
+        assert!(fov.at(2, 0).is_visible);
+        assert!(!fov.at(3, 0).is_visible);
+        assert!(fov.at(-4, 1).is_visible);
+    }
+
+    #[test]
+    fn soft_shadow_kernel_has_penumbra() {
+        let mut fov = Fov::new(Box::new(FluxField::new(8)), 8, Illumination::default());
+        fov.update(soft_shadow(|x, y| if x == 2 && y <= 0 { 1.0 } else { 0.0 }));
+        assert_eq!(fov.at(2, 0).brightness, 1.0);
+        assert_eq!(fov.at(3, 0).brightness, 0.0);
+        assert_eq!(fov.at(-8, 0).brightness, 1.0);
+        let penumbra = (1..8)
+            .map(|y| fov.at(8, y).brightness)
+            .filter(|&b| 0.05 < b && b < 0.95)
+            .count();
+        assert!(penumbra > 0);
+    }
+
+    #[test]
+    fn directional_kernel_is_one_way() {
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, Visibility::default());
+        // A wall of one-way glass letting the flux through only westwards.
+        let westwards = Directions::WEST.union(Directions::NORTH_WEST);
+        fov.update(directional(0.5, |x, _| {
+            if x.abs() == 2 {
+                westwards.complement()
+            } else {
+                Directions::NONE
+            }
+        }));
+        assert!(fov.at(2, 0).is_visible);
+        assert!(!fov.at(3, 0).is_visible);
+        assert!(fov.at(-2, 0).is_visible);
+        assert!(fov.at(-3, 0).is_visible);
+        assert!(fov.at(-5, -1).is_visible);
+        assert_eq!(Directions::of(-1, 1), Directions::SOUTH_WEST);
+    }
+
+    #[test]
+    fn door_states() {
+        // Doors in a wall at x = 2 (closed), x = -2 (open), and y = 2
+        // (ajar letting through only the rays going straight south).
+        let door = |x: i32, y: i32| match (x, y) {
+            (2, _) => Some(Door::Closed),
+            (-2, _) => Some(Door::Open),
+            (_, 2) => Some(Door::Ajar {
+                through: Directions::SOUTH,
+                transmittance: 0.75,
+            }),
+            _ => None,
+        };
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, Visibility::default());
+        fov.update(doors(0.5, |_, _| 0.0, door));
+        assert!(fov.at(2, 0).is_visible);
+        assert!(!fov.at(3, 0).is_visible);
+        assert!(fov.at(-3, 0).is_visible);
+        assert_eq!(fov.at(0, 2).ray_output, 0.75);
+        assert!(fov.at(0, 3).is_visible);
+        assert!(fov.at(-4, 2).is_visible);
+        assert_eq!(fov.at(-4, 2).ray_output, 0.0);
+        assert!(!fov.at(5, 5).is_visible);
+    }
+
+    #[test]
+    fn cost_kernel_sees_into_fog() {
+        let mut fov = Fov::new(Box::new(FluxField::new(8)), 8, 0.0);
+        fov.update(cost(|x, _| {
+            if x == 4 {
+                f32::INFINITY
+            } else if x > 0 {
+                1.0 / 3.0
+            } else if x < 0 {
+                1.0
+            } else {
+                0.0
+            }
+        }));
+        let visible = |x, y| *fov.at(x, y) <= 1.0 + 1e-6;
+        assert!(visible(3, 0));
+        assert!(!visible(4, 0));
+        assert_eq!(*fov.at(5, 1), f32::INFINITY);
+        assert!(visible(-1, 0));
+        assert!(!visible(-2, 0));
+        assert!(visible(0, 8));
+    }
+
+    #[test]
+    fn xray_passes_through_occluders() {
+        let walls = |x: i32, _| x == 2 || x == 4 || x == -2;
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, XRay::<2>::default());
+        fov.update(xray(0.1, 0.5, walls));
+        assert!(fov.at(2, 0).is_directly_visible);
+        assert!(!fov.at(3, 0).is_directly_visible);
+        assert!(fov.at(3, 0).is_visible);
+        assert_eq!(fov.at(3, 0).penetrating, [0.5, 0.0]);
+        assert_eq!(fov.at(5, 0).penetrating, [0.0, 0.25]);
+        assert!(fov.at(5, 0).is_visible);
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, XRay::<0>::default());
+        fov.update(xray(0.1, 0.5, walls));
+        let mut plain = Fov::new(Box::new(FluxField::new(6)), 6, Visibility::default());
+        plain.update(threshold(0.1, |x, y| walls(x, y) as u8 as f32));
+        for (a, b) in fov.as_slice().iter().zip(plain.as_slice()) {
+            assert_eq!(a.is_visible, b.is_visible);
+            assert_eq!(a.ray_output, b.ray_output);
+        }
+    }
+
+    #[test]
+    fn acuity_breakpoints() {
+        let acuity = Acuity::new(vec![(1.0, 1.0), (2.0, 0.0)]);
+        assert_eq!(acuity.at(0.0), 1.0);
+        assert_eq!(acuity.at(1.5), 0.5);
+        assert_eq!(acuity.at(3.0), 0.0);
+    }
+
+    #[test]
+    fn facing_kernel_falls_off() {
+        let mut fov = Fov::new(Box::new(FluxField::new(4)), 4, 0.0f32);
+        let acuity = Acuity::new(vec![(0.0, 1.0), (std::f32::consts::PI, 0.0)]);
+        let kernel = |_: i32, _: i32, _: &[Influx<&f32>]| 1.0;
+        fov.update(facing(
+            std::f32::consts::FRAC_PI_2,
+            acuity,
+            kernel,
+            |v, a| v * a,
+        ));
+        assert_eq!(*fov.at(0, 0), 1.0);
+        assert_eq!(*fov.at(0, 3), 1.0);
+        assert!((*fov.at(3, 0) - 0.5).abs() < 1e-6);
+        assert!((*fov.at(-3, 0) - 0.5).abs() < 1e-6);
+        assert!(*fov.at(0, -3) < 1e-6);
+    }
+
+    #[test]
+    fn fixed_threshold_matches_float() {
+        let flux_field = Rc::new(FluxField::new(8));
+        let opacity = |x: i32, y: i32| if x == 3 && y < 2 || y == -4 { 1.0 } else { 0.2 };
+        let mut float = Fov::new(flux_field.clone(), 8, Visibility::default());
+        float.update(threshold(0.25, opacity));
+        let mut fixed = Fov::new(flux_field, 8, FixedVisibility::default());
+        fixed.update_fixed(fixed_threshold(FIXED_ONE / 4, |x, y| {
+            (opacity(x, y) * FIXED_ONE as f32) as u32
+        }));
+        let mut differences = 0;
+        for (a, b) in float.as_slice().iter().zip(fixed.as_slice()) {
+            let output = b.ray_output as f32 / FIXED_ONE as f32;
+            assert!((a.ray_output - output).abs() < 1e-3);
+            differences += (a.is_visible != b.is_visible) as usize;
+        }
+        assert!(differences <= 2);
+        assert!(!fixed.at(5, 0).is_visible);
+        assert!(fixed.at(2, 2).is_visible);
+        let mut lit = Fov::new(Rc::new(FluxField::new(8)), 8, FixedIllumination::default());
+        lit.update_fixed(fixed_soft_shadow(|x, _| if x == 2 { FIXED_ONE } else { 0 }));
+        assert_eq!(lit.at(2, 0).brightness, FIXED_ONE);
+        assert_eq!(lit.at(3, 0).brightness, 0);
+        assert_eq!(lit.at(-8, 3).brightness, FIXED_ONE);
+    }
+
+    #[test]
+    fn fog_follows_the_visible_rays() {
+        let radius = 6;
+        let mut fov = Fov::new(Box::new(FluxField::new(radius)), radius, Fog::default());
+        // A fog bank at x = 2 and a wall that blocks the view through it
+        // straight ahead but not around it.
+        let density = |x: i32, _| if x == 2 { 1.0 } else { 0.0 };
+        let opacity = |x: i32, y: i32| if x == 3 && y == 0 { 1.0 } else { 0.0 };
+        fov.update(fog(0.25, opacity, density));
+        assert_eq!(fov.at(1, 0).optical_depth, 0.0);
+        assert_eq!(fov.at(2, 0).optical_depth, 1.0);
+        assert!((fov.at(2, 0).extinction() - (1.0 - (-1.0_f32).exp())).abs() < 1e-6);
+        // The rays in the shallow octants cross the bank exactly once.
+        for x in 3..=6 {
+            for y in -x..=x {
+                let cell = fov.at(x, y);
+                if cell.ray_output > 0.0 {
+                    assert!((cell.optical_depth - 1.0).abs() < 1e-4, "({}, {})", x, y);
+                }
+            }
+        }
+        // Matches the plain threshold kernel on the visibility.
+        let mut plain = Fov::new(
+            Box::new(FluxField::new(radius)),
+            radius,
+            Visibility::default(),
+        );
+        plain.update(threshold(0.25, opacity));
+        for y in -6..=6 {
+            for x in -6..=6 {
+                assert_eq!(fov.at(x, y).is_visible, plain.at(x, y).is_visible);
+            }
+        }
+    }
+
+    #[test]
+    fn stance_changes_the_occluders() {
+        let mut fov = Fov::new(Box::new(FluxField::new(8)), 8, Visibility::default());
+        // Standing on a ledge at 1.0, a low wall at x = 2 and a house at
+        // x = 5.
+        let height = |x: i32, y: i32| match (x, y) {
+            (0, 0) => 1.0,
+            (2, _) => 2.0,
+            (5, _) => 5.5,
+            _ => 0.0,
+        };
+        fov.update(terrain(0.5, Stance::Crouching, height));
+        assert!(fov.at(2, 0).is_visible);
+        assert!(!fov.at(3, 0).is_visible);
+        fov.update(terrain(0.5, Stance::Standing, height));
+        assert!(fov.at(3, 0).is_visible);
+        assert!(fov.at(5, 0).is_visible);
+        assert!(!fov.at(6, 0).is_visible);
+        fov.update(terrain(0.5, Stance::Flying, height));
+        assert!(fov.at(6, 0).is_visible);
+        fov.update(terrain(0.5, Stance::Eyes(0.5), height));
+        assert!(!fov.at(3, 0).is_visible);
+    }
+}
@@ -0,0 +1,223 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{FluxField, Fov, Influx};
+
+/// A field of vision over a stack of 2D layers, such as the z-levels of a
+/// dungeon, where designated cells like stairs, holes, and gratings pass
+/// flux between adjacent layers.
+///
+/// Every layer has a field of its own centered on the column of the viewer.
+/// The layer of the viewer is updated like an ordinary field.  The flux then
+/// spreads outward from it a layer at a time, up and down: a cell linked to
+/// the layer below (or above) receives, in addition to its ordinary
+/// influxes, a vertical influx with the `dx` and `dy` of zero carrying the
+/// value of the same cell in that layer, weighted by the transmission of
+/// the link.  So looking down a shaft or up through a grate shows the cells
+/// past the opening without a full 3D solver.  The flux doesn't come back
+/// to the layers closer to the viewer.
+///
+/// Like with `PortalFov` the update function receives the layer of the
+/// cell.  The origins of the other layers are not the viewer: they receive
+/// only the vertical influx if linked and no influxes at all otherwise.
+///
+/// ```
+/// # use flux_fov::{FluxField, Influx, LayeredFov};
+/// # use std::rc::Rc;
+/// let mut fov = LayeredFov::new(Rc::new(FluxField::new(6)), 6, 2, 0.0_f32);
+/// // A hole at (2, 0) in the floor of the layer 0 lets half of the light
+/// // down to the layer 1.
+/// let link = |from: usize, to: usize, x: i32, y: i32| {
+///     if (from, to, x, y) == (0, 1, 2, 0) { 0.5 } else { 0.0 }
+/// };
+/// fov.update(0, link, |z, x, y, influxes: &[Influx<&f32>]| {
+///     if z == 0 && x == 0 && y == 0 {
+///         1.0
+///     } else {
+///         influxes.iter().map(|f| f.weight * f.value).sum()
+///     }
+/// });
+/// assert_eq!(*fov.at(1, 2, 0), 0.5);
+/// assert!(*fov.at(1, 4, 0) > 0.0);
+/// assert_eq!(*fov.at(1, -4, 0), 0.0);
+/// ```
+///
+pub struct LayeredFov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    init: T,
+    viewer_layer: usize,
+    layers: Vec<Fov<T, X>>,
+}
+
+impl<T, X> LayeredFov<T, X>
+where
+    T: Clone,
+    X: AsRef<FluxField> + Clone,
+{
+    /// Constructs a field of the given `radius` over `layer_count` layers.
+    ///
+    pub fn new(flux_field: X, radius: usize, layer_count: usize, init: T) -> Self {
+        assert!(layer_count > 0, "no layers");
+        let layers = (0..layer_count)
+            .map(|_| Fov::new(flux_field.clone(), radius, init.clone()))
+            .collect();
+        LayeredFov {
+            init,
+            viewer_layer: 0,
+            layers,
+        }
+    }
+}
+
+impl<T, X> LayeredFov<T, X>
+where
+    T: Clone,
+    X: AsRef<FluxField>,
+{
+    /// Update the field for a viewer on the layer `viewer_layer`.
+    ///
+    /// The `link` callback gives the transmission of the flux from the
+    /// layer `from` to the adjacent layer `to` at the cell (`x`, `y`)
+    /// relative to the viewer, 0.0 for no link.  The update function
+    /// receives the layer in addition to the arguments of the function of
+    /// `Fov::update`.
+    ///
+    pub fn update<L, F>(&mut self, viewer_layer: usize, link: L, update_fn: F)
+    where
+        L: Fn(usize, usize, i32, i32) -> f32,
+        F: FnMut(usize, i32, i32, &[Influx<&T>]) -> T,
+    {
+        assert!(viewer_layer < self.layers.len(), "no such layer");
+        let mut update_fn = update_fn;
+        self.viewer_layer = viewer_layer;
+        self.layers[viewer_layer].update(|x, y, influxes| update_fn(viewer_layer, x, y, influxes));
+        let down = (viewer_layer + 1..self.layers.len()).map(|z| (z - 1, z));
+        let up = (0..viewer_layer).rev().map(|z| (z + 1, z));
+        for (from, to) in down.chain(up) {
+            let (source, target) = if from < to {
+                let (a, b) = self.layers.split_at_mut(to);
+                (&a[from], &mut b[0])
+            } else {
+                let (a, b) = self.layers.split_at_mut(from);
+                (&b[0], &mut a[to])
+            };
+            let init = &self.init;
+            target.update_cells(|x, y, _, cell, influxes| {
+                let transmission = link(from, to, x, y);
+                if transmission > 0.0 {
+                    let vertical = Influx {
+                        weight: transmission,
+                        dx: 0,
+                        dy: 0,
+                        value: source.at(x, y),
+                    };
+                    // At most three planar influxes and the vertical one.
+                    let mut all = [copy(&vertical), copy(&vertical), copy(&vertical), vertical];
+                    for (dst, src) in all.iter_mut().zip(influxes.iter()) {
+                        *dst = copy(src);
+                    }
+                    let len = influxes.len();
+                    all.swap(len, 3);
+                    *cell = update_fn(to, x, y, &all[..len + 1]);
+                } else if x == 0 && y == 0 {
+                    // Nothing reaches the origin of the layer.
+                    *cell = init.clone();
+                } else {
+                    *cell = update_fn(to, x, y, influxes);
+                }
+            });
+        }
+    }
+}
+
+impl<T, X> LayeredFov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// The number of layers.
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// The layer of the viewer in the last update.
+    pub fn viewer_layer(&self) -> usize {
+        self.viewer_layer
+    }
+
+    /// The field of the given layer.
+    pub fn layer(&self, layer: usize) -> &Fov<T, X> {
+        &self.layers[layer]
+    }
+
+    /// The value at the given cell of the layer.
+    pub fn at(&self, layer: usize, x: i32, y: i32) -> &T {
+        self.layers[layer].at(x, y)
+    }
+}
+
+// A copy of the influx referring to the same value.
+fn copy<'a, T>(f: &Influx<&'a T>) -> Influx<&'a T> {
+    Influx {
+        weight: f.weight,
+        dx: f.dx,
+        dy: f.dy,
+        value: f.value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn flux_passes_between_layers() {
+        let flux_field = Rc::new(FluxField::new(6));
+        let mut fov = LayeredFov::new(flux_field, 6, 4, 0.0);
+        // A shaft at (3, 0) from the layer 1 down to the layer 3 and a grate
+        // at (0, -2) up to the layer 0.
+        let link = |from: usize, to: usize, x: i32, y: i32| match (from, to, x, y) {
+            (1, 2, 3, 0) | (2, 3, 3, 0) => 1.0,
+            (1, 0, 0, -2) => 0.25,
+            _ => 0.0,
+        };
+        let mut origins = Vec::new();
+        fov.update(1, link, |z, x, y, influxes| {
+            if z == 1 && x == 0 && y == 0 {
+                1.0
+            } else {
+                if x == 0 && y == 0 {
+                    origins.push((z, influxes.len()));
+                }
+                influxes.iter().map(|f| f.weight * f.value).sum()
+            }
+        });
+        assert!(origins.is_empty());
+        assert_eq!(fov.viewer_layer(), 1);
+        assert_eq!(*fov.at(2, 3, 0), 1.0);
+        assert_eq!(*fov.at(3, 3, 0), 1.0);
+        assert_eq!(*fov.at(3, 5, 0), 1.0);
+        assert_eq!(*fov.at(3, 2, 0), 0.0);
+        assert_eq!(*fov.at(0, 0, -2), 0.25);
+        assert_eq!(*fov.at(0, 0, -5), 0.25);
+        assert_eq!(*fov.at(0, 3, 0), 0.0);
+        // The flux doesn't come back.
+        assert_eq!(*fov.at(1, 0, -3), 1.0);
+    }
+}
@@ -0,0 +1,146 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::sweep::sweep;
+use crate::{FluxField, Fov, Influx, FIXED_ONE};
+
+/// An influx into a grid cell with a fixed-point weight.
+///
+/// The weight has 16 fractional bits so that `FIXED_ONE` stands for 1.0.
+/// The weights of the influxes into a grid cell sum to exactly `FIXED_ONE`.
+/// Otherwise as `Influx`.
+///
+pub type FixedInflux<T> = Influx<T, u32>;
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Update the field of vision with fixed-point influx weights.
+    ///
+    /// Works like `update` but the weights are passed as fixed-point
+    /// numbers and the update itself performs no floating point arithmetic.
+    /// Together with the fixed-point kernels, such as
+    /// `kernel::fixed_threshold`, this makes it possible to run the updates
+    /// on targets without an FPU.  Building the flux field still takes
+    /// floating point arithmetic but it can be done once up front.  The
+    /// same as `update_weighted` with `u32` weights.
+    ///
+    pub fn update_fixed<F>(&mut self, update_fn: F)
+    where
+        F: FnMut(i32, i32, &[FixedInflux<&T>]) -> T,
+    {
+        self.update_weighted(update_fn);
+    }
+}
+
+impl<X> Fov<u8, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Update a field of light levels using fixed-point arithmetic.
+    ///
+    /// The origin is set to `origin` and every other cell to
+    /// `update_fn(x, y, input)` where `input` is the weighted sum of the
+    /// influxes rounded to the nearest level.  As the weights sum up to one
+    /// the input never overflows.
+    ///
+    pub fn update_levels<F>(&mut self, origin: u8, update_fn: F)
+    where
+        F: FnMut(i32, i32, u8) -> u8,
+    {
+        let mut update_fn = update_fn;
+        let data = &mut self.data;
+        let flux_field = self.flux_field.as_ref();
+        sweep(
+            self.radius,
+            self.width,
+            self.ix_origin,
+            flux_field,
+            |step| {
+                let taps = step.taps();
+                data[step.ix] = if taps.is_empty() {
+                    origin
+                } else {
+                    let sum: u32 = taps.iter().map(|t| t.fixed * data[t.ix] as u32).sum();
+                    let input = (sum + FIXED_ONE / 2) >> 16;
+                    update_fn(step.x, step.y, input as u8)
+                };
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::all_params;
+    use std::rc::Rc;
+
+    #[test]
+    fn levels_track_float_field() {
+        let flux_field = Rc::new(FluxField::new(8));
+        let walls = [(3, 1), (-2, -2), (0, 4)];
+        let mut levels = Fov::new(flux_field.clone(), 8, 0_u8);
+        levels.update_levels(
+            255,
+            |x, y, input| {
+                if walls.contains(&(x, y)) {
+                    0
+                } else {
+                    input
+                }
+            },
+        );
+        let mut light = Fov::new(flux_field, 8, 0.0_f32);
+        light.update(|x, y, influxes| {
+            if x == 0 && y == 0 {
+                255.0
+            } else if walls.contains(&(x, y)) {
+                0.0
+            } else {
+                influxes.iter().map(|f| f.weight * f.value).sum()
+            }
+        });
+        for (&level, &value) in levels.as_slice().iter().zip(light.as_slice().iter()) {
+            assert!(
+                (level as f32 - value).abs() <= 8.0,
+                "{} vs {}",
+                level,
+                value
+            );
+        }
+        assert_eq!(*levels.at(0, 0), 255);
+        assert_eq!(*levels.at(8, 0), 255);
+        assert_eq!(*levels.at(0, 8), 0);
+    }
+
+    #[test]
+    fn fixed_weights_sum_to_one() {
+        for params in all_params() {
+            let mut fov = Fov::new(Box::new(FluxField::with_params(6, params)), 6, 0);
+            fov.update_fixed(|x, y, influxes| {
+                if x != 0 || y != 0 {
+                    assert_eq!(influxes.iter().map(|f| f.weight).sum::<u32>(), FIXED_ONE);
+                }
+                influxes.len()
+            });
+            assert_eq!(*fov.at(0, 0), 0);
+            assert_eq!(*fov.at(3, 0), 1);
+        }
+    }
+}
@@ -14,7 +14,177 @@
 // You should have received a copy of the GNU General Public License along
 // with this program.  If not, see <http://www.gnu.org/licenses/>.
 
+#![cfg_attr(feature = "nightly", feature(allocator_api))]
+
+use std::borrow::Cow;
 use std::f32;
+use std::mem;
+use std::ops::{Deref, Range};
+use std::sync::OnceLock;
+
+use crate::allocator::{Allocator, Buffer, Global};
+
+mod alertness;
+pub mod allocator;
+pub mod analysis;
+mod any;
+mod arity;
+mod asset;
+mod auxiliary;
+mod bits;
+mod blend;
+mod builder;
+#[cfg(feature = "debug-checks")]
+mod checks;
+mod clip;
+mod compose;
+mod cone;
+mod debug;
+mod delta;
+#[cfg(feature = "deterministic")]
+mod deterministic;
+mod dirty;
+mod downsample;
+mod ellipse;
+mod entities;
+mod error;
+mod explore;
+mod falloff;
+mod flicker;
+#[cfg(feature = "half")]
+mod float16;
+mod flow;
+mod fogsave;
+#[cfg(feature = "godot")]
+mod godot;
+mod graph;
+mod grid;
+mod hex;
+mod history;
+mod job;
+#[cfg(feature = "json")]
+mod json;
+pub mod kernel;
+mod layers;
+mod levels;
+mod lighting;
+mod linear;
+mod los;
+#[cfg(feature = "mlua")]
+mod lua;
+mod mask;
+mod material;
+mod memory;
+mod metric;
+mod metrics;
+mod mirror;
+mod modifier;
+mod multires;
+mod notice;
+mod observed;
+mod octant;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod path;
+mod portal;
+mod pvs;
+mod query;
+mod radius;
+mod raw;
+mod record;
+mod rect;
+#[cfg(feature = "reference")]
+mod reference;
+mod region;
+mod regions;
+mod reverse;
+mod rle;
+mod seed;
+mod senses;
+#[cfg(feature = "serde")]
+mod serialize;
+mod shadow;
+mod shift;
+mod sliced;
+mod smoke;
+mod snapshot;
+mod stats;
+mod subcell;
+mod sweep;
+mod symmetric;
+mod table;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod text;
+mod texture;
+mod threat;
+#[cfg(feature = "tiled")]
+mod tiled;
+mod validate;
+mod visibility;
+mod weather;
+mod weight;
+mod world;
+
+pub use crate::alertness::{AlertnessMap, Spread};
+pub use crate::any::{AnyCell, AnyFov};
+pub use crate::arity::Influxes;
+pub use crate::auxiliary::incoming_direction;
+pub use crate::bits::FovBits;
+pub use crate::blend::BlendMode;
+pub use crate::builder::{BuildError, FluxBuildError, FluxFieldBuilder, FovBuilder};
+pub use crate::compose::{Bounce, BrightnessGrid, NightVision};
+pub use crate::dirty::DirtyUpdate;
+pub use crate::downsample::Downsampled;
+pub use crate::ellipse::EllipticalFov;
+pub use crate::entities::{EntityTracker, VisibilityEvents};
+pub use crate::error::{Error, MAX_RADIUS};
+pub use crate::explore::{ExploredMap, FrontierSegment};
+pub use crate::falloff::{Attenuation, Falloff, FalloffTable};
+pub use crate::flicker::LightAnimation;
+pub use crate::flow::Outflux;
+pub use crate::graph::{FluxGraph, GraphEdge, GraphFov};
+pub use crate::grid::OpacityGrid;
+pub use crate::hex::{hex_distance, HexFluxField, HexFov, HEX_DIRECTIONS};
+pub use crate::history::FovHistory;
+pub use crate::job::FovJob;
+pub use crate::layers::LayeredFov;
+pub use crate::levels::FixedInflux;
+pub use crate::lighting::{LightMap, LightSource};
+#[cfg(feature = "mlua")]
+pub use crate::lua::LuaKernel;
+pub use crate::mask::CellMask;
+pub use crate::material::{Material, Materials};
+pub use crate::metric::Metric;
+pub use crate::metrics::UpdateMetrics;
+pub use crate::mirror::{Mirror, MirrorAxis, MirrorFov};
+pub use crate::modifier::{ModifierId, VisionModifier, VisionModifiers, VisionParams};
+pub use crate::multires::MultiResFov;
+pub use crate::notice::NoticeParams;
+pub use crate::observed::ObservedByMap;
+pub use crate::portal::PortalFov;
+pub use crate::pvs::Pvs;
+pub use crate::radius::RadiusMask;
+pub use crate::raw::CellWeights;
+pub use crate::record::{RecordedCell, RecordedInflux, Recording};
+pub use crate::rect::Rect;
+pub use crate::regions::Regions;
+pub use crate::reverse::reverse_fov;
+pub use crate::seed::OriginSeed;
+pub use crate::senses::Senses;
+pub use crate::sliced::{Budget, SlicedUpdate, UpdateState};
+pub use crate::smoke::{Smoke, SmokeCloud};
+pub use crate::snapshot::FovSnapshot;
+pub use crate::stats::Stats;
+pub use crate::subcell::SubcellFov;
+pub use crate::threat::{ObserverId, ThreatMap};
+#[cfg(feature = "tiled")]
+pub use crate::tiled::TileOpacity;
+pub use crate::validate::{ValidationIssue, ValidationReport};
+pub use crate::visibility::VisibilityMap;
+pub use crate::weather::Weather;
+pub use crate::weight::Weight;
+pub use crate::world::WorldVisibility;
 
 ////////////////////////////////////////////////////////////////////////////////
 
@@ -26,22 +196,425 @@ use std::f32;
 ///
 pub struct FluxField {
     radius: usize,
-    flux_lut: Vec<f32>,
+    params: FluxParams,
+    flux_lut: Lut,
+    lateral_lut: Vec<f32>,
+    fixed_lut: OnceLock<Vec<[u32; 2]>>,
+    outfluxes: OnceLock<flow::Outfluxes>,
+    // The rays cast when building and growing the look-up table; see
+    // `FluxFieldBuilder`.
+    rays: Rays,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Rays {
+    pub count: usize,
+    /// The ray radius as a multiple of the flux field radius.
+    pub radius_scale: usize,
+}
+
+impl Default for Rays {
+    // Just the first numbers I came up with.
+    fn default() -> Self {
+        Rays {
+            count: 10_000,
+            radius_scale: 100,
+        }
+    }
+}
+
+/// The fixed-point representation of the weight 1.0 in the flux field.
+///
+/// The fixed-point weights have 16 fractional bits; see `Fov::update_fixed`.
+pub const FIXED_ONE: u32 = 1 << 16;
+
+/// The parameters controlling the construction of a flux field.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FluxParams {
+    /// The weighting of the rays cast when building the look-up table.
+    pub distribution: RayDistribution,
+    /// The number of sub-rays cast for each ray, 1 by default.
+    ///
+    /// The sub-rays start from points spread evenly across the origin cell
+    /// instead of its center.  This makes the transitions between the
+    /// weights smoother and the edges of the shadows softer at the cost of
+    /// a proportionally longer build time.
+    pub supersampling: usize,
+    /// Whether the interior cells receive a third, lateral influx.
+    ///
+    /// Normally an interior cell receives flux straight and diagonally from
+    /// the previous column.  On smooth gradients this causes slight
+    /// directional streaking.  In the three-tap mode the cell also receives
+    /// flux from its lateral neighbor in the same column, the one closer to
+    /// the axis, accounting for the rays that cross into the cell through its
+    /// side rather than near its corner.  The fields are smoother but the
+    /// updates slower.  Ignored by four-connected flux fields.
+    pub three_tap: bool,
+    /// The neighborhood through which the flux flows.
+    pub connectivity: Connectivity,
+    /// Whether the rays are classic Bresenham lines.
+    ///
+    /// Normally the rays are cast at uniformly spaced angles and the cell
+    /// selection of a ray rounds the exact halfway points up.  In the
+    /// Bresenham mode the rays are instead the Bresenham lines from the
+    /// origin to every cell of the field, so that the weights of a cell are
+    /// the shares of the Bresenham lines passing through it coming in
+    /// straight and diagonally.  Use it together with `FluxField::path_to`
+    /// when the game traces its projectiles with Bresenham lines.  The
+    /// supersampling is ignored in this mode and, unlike otherwise, the
+    /// weights depend on the radius of the field.
+    pub bresenham: bool,
+}
+
+/// The neighborhood through which the flux flows from cell to cell.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Connectivity {
+    /// The flux flows to all eight neighbors (the Moore neighborhood).
+    #[default]
+    Eight,
+    /// The flux flows only to the four orthogonal neighbors (the von Neumann
+    /// neighborhood).
+    ///
+    /// A ray jumping diagonally is decomposed into two orthogonal steps so
+    /// that an interior cell receives flux straight from the previous column
+    /// and laterally from its neighbor in the same column.  The cells on the
+    /// diagonal edges receive half of their flux from each of the two
+    /// interior cells flanking them.  This way the visibility matches the
+    /// movement topology of games without diagonal adjacency.  The cells are
+    /// updated ring by ring in the order of their Chebyshev distance from the
+    /// origin rather than edge by edge and octant by octant.
+    Four,
+}
+
+impl Default for FluxParams {
+    fn default() -> Self {
+        FluxParams {
+            distribution: RayDistribution::default(),
+            supersampling: 1,
+            three_tap: false,
+            connectivity: Connectivity::Eight,
+            bresenham: false,
+        }
+    }
+}
+
+/// The weighting of the rays cast when building the look-up table of a flux
+/// field.
+///
+/// The rays are cast at uniformly spaced angles.  On square tiles this
+/// over-weights the diagonal directions relative to the perceived brightness
+/// and the other distributions compensate for that by weighting the ray at
+/// the angle θ from the nearest axis by a factor.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum RayDistribution {
+    /// All rays have the same weight.
+    #[default]
+    Uniform,
+    /// The rays are weighted by cos θ.
+    Cosine,
+    /// The rays are weighted by cos² θ, the ray density per tile when the
+    /// rays are spaced uniformly along the side of a square rather than
+    /// along an arc.
+    AreaCorrected,
+}
+
+impl RayDistribution {
+    // The weight of the ray towards the point (`x`, `y`).  Needs only
+    // the square root so it is bit-identical on all platforms as is.
+    fn weight_towards(self, x: f32, y: f32) -> f32 {
+        let cos_sq = x * x / (x * x + y * y);
+        match self {
+            RayDistribution::Uniform => 1.0,
+            RayDistribution::Cosine => cos_sq.sqrt(),
+            RayDistribution::AreaCorrected => cos_sq,
+        }
+    }
 }
 
 impl FluxField {
     /// Constructs a flux field covering the area within `radius`.
     ///
     pub fn new(radius: usize) -> Self {
+        FluxField::with_params(radius, FluxParams::default())
+    }
+
+    /// Constructs a flux field covering the area within `radius` or fails
+    /// if the radius exceeds `MAX_RADIUS`.
+    ///
+    /// Any radius up to `MAX_RADIUS` is fine including 0 and 1; such small
+    /// fields have no interior cells and hence no look-up table.
+    ///
+    pub fn try_new(radius: usize) -> Result<Self, Error> {
+        if radius > MAX_RADIUS {
+            return Err(Error::RadiusOutOfRange { radius });
+        }
+        Ok(FluxField::new(radius))
+    }
+
+    /// Constructs a flux field covering the area within `radius` using the
+    /// given parameters.
+    ///
+    pub fn with_params(radius: usize, params: FluxParams) -> Self {
+        FluxField::with_rays(radius, params, Rays::default())
+    }
+
+    pub(crate) fn with_rays(radius: usize, params: FluxParams, rays: Rays) -> Self {
+        assert!(radius <= MAX_RADIUS, "radius out of range");
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("flux_field_build", radius).entered();
+        // Fields of radius 0 or 1 have no interior cells and hence need no
+        // look-up table.
+        let (flux_lut, lateral_lut) = if radius > 1 {
+            calc_flux_lut(radius, rays.radius_scale * radius, rays.count, &params)
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        let mut flux_field = FluxField::from_lut(radius, Lut::Owned(flux_lut));
+        flux_field.params = params;
+        flux_field.lateral_lut = lateral_lut;
+        flux_field.rays = rays;
+        flux_field
+    }
+
+    /// Constructs a flux field covering the area within `radius` from a
+    /// ready made look-up table.
+    ///
+    /// A borrowed table, e.g., one embedded in the binary, is used in place
+    /// without copying it.  The weights are laid out as in the tables written
+    /// with `write_table`.  Fails if the table doesn't pass `validate`.
+    ///
+    pub fn from_weights(
+        radius: usize,
+        weights: Cow<'static, [f32]>,
+    ) -> Result<Self, ValidationReport> {
+        let lut = match weights {
+            Cow::Owned(weights) => Lut::Owned(weights),
+            Cow::Borrowed(weights) => Lut::Borrowed(weights),
+        };
+        let flux_field = FluxField {
+            radius,
+            params: FluxParams::default(),
+            flux_lut: lut,
+            lateral_lut: Vec::new(),
+            fixed_lut: OnceLock::new(),
+            outfluxes: OnceLock::new(),
+            rays: Rays::default(),
+        };
+        flux_field.validate().map(|_| flux_field)
+    }
+
+    /// The parameters the flux field was constructed with.
+    pub fn params(&self) -> &FluxParams {
+        &self.params
+    }
+
+    /// The radius of the area covered by the flux field.
+    pub fn radius(&self) -> usize {
+        self.radius
+    }
+
+    /// Extend the flux field to cover the area within `radius`.
+    ///
+    /// Only the look-up table entries for the new outer columns are
+    /// computed; the existing ones are kept as they are.  Does nothing if the
+    /// flux field already covers `radius`.  In the Bresenham mode the lines
+    /// to the new cells pass through the old ones, too, so the whole table is
+    /// rebuilt.  Panics if `radius` exceeds `MAX_RADIUS`.
+    ///
+    pub fn grow_to(&mut self, radius: usize) {
+        assert!(radius <= MAX_RADIUS, "radius out of range");
+        if radius <= self.radius {
+            return;
+        }
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("flux_field_grow", from = self.radius, radius).entered();
+        let mut flux_lut = std::mem::replace(&mut self.flux_lut, Lut::Owned(Vec::new())).into_vec();
+        if self.params.bresenham {
+            flux_lut.clear();
+            self.lateral_lut.clear();
+        }
+        if radius > 1 {
+            let first_x = if self.params.bresenham {
+                2
+            } else {
+                (self.radius + 1).max(2)
+            };
+            let rays = self.rays;
+            let (jump, lateral) = calc_flux_lut_columns(
+                first_x,
+                radius,
+                rays.radius_scale * radius,
+                rays.count,
+                &self.params,
+            );
+            flux_lut.extend(jump);
+            self.lateral_lut.extend(lateral);
+        }
+        self.radius = radius;
+        self.flux_lut = Lut::Owned(flux_lut);
+        self.fixed_lut = OnceLock::new();
+        self.outfluxes = OnceLock::new();
+    }
+
+    /// Whether the flux field was built in the three-tap mode.
+    pub fn is_three_tap(&self) -> bool {
+        !self.lateral_lut.is_empty() && !self.is_four_connected()
+    }
+
+    /// Whether the flux flows only through the four orthogonal neighbors.
+    pub fn is_four_connected(&self) -> bool {
+        self.params.connectivity == Connectivity::Four
+    }
+
+    /// Check that the influx weights of every grid cell sum to 1.0.
+    ///
+    /// The interior cells receive the diagonal influx with the weight w from
+    /// the look-up table and the straight influx with the weight 1.0 - w.
+    /// Their sum is 1.0 as long as w is within [0.0, 1.0], which this
+    /// checks.  In the three-tap mode the lateral weight l is taken out of
+    /// the straight one and both w and l must be non-negative with w + l at
+    /// most 1.0.  The edges and the origin are normalized by definition.
+    ///
+    pub fn verify_normalization(&self) -> bool {
+        (0..self.flux_lut.len()).all(|ix| is_normalized(self.flux_lut[ix], self.lateral(ix)))
+    }
+
+    /// Force the influx weights of every grid cell to sum to 1.0.
+    ///
+    /// Clamps the weights of the look-up table into [0.0, 1.0] and replaces
+    /// any NaNs with 0.5.  This is the strict mode for kernels that integrate
+    /// energy and cannot tolerate a table that has been damaged, e.g., by
+    /// lossy storage.  Returns the number of weights that were fixed.
+    ///
+    pub fn normalize(&mut self) -> usize {
+        let fixed = (0..self.flux_lut.len())
+            .filter(|&ix| !is_normalized(self.flux_lut[ix], self.lateral(ix)))
+            .count();
+        if fixed > 0 {
+            let flux_lut: Vec<f32> = std::mem::replace(&mut self.flux_lut, Lut::Owned(Vec::new()))
+                .into_vec()
+                .into_iter()
+                .map(|w| if w.is_nan() { 0.5 } else { w.clamp(0.0, 1.0) })
+                .collect();
+            for (l, w) in self.lateral_lut.iter_mut().zip(flux_lut.iter()) {
+                *l = if l.is_nan() {
+                    0.0
+                } else {
+                    l.clamp(0.0, 1.0 - w)
+                };
+            }
+            self.flux_lut = Lut::Owned(flux_lut);
+            self.fixed_lut = OnceLock::new();
+            self.outfluxes = OnceLock::new();
+        }
+        fixed
+    }
+
+    // The lateral weight of the interior cell with the given look-up table
+    // index; zero unless in the three-tap mode.
+    #[inline]
+    pub(crate) fn lateral(&self, lut_ix: usize) -> f32 {
+        self.lateral_lut.get(lut_ix).copied().unwrap_or(0.0)
+    }
+
+    // Constructs a flux field from a ready made look-up table.
+    fn from_lut(radius: usize, flux_lut: Lut) -> Self {
+        debug_assert_eq!(flux_lut.len(), lut_len(radius));
         FluxField {
             radius,
-            // The ray radius and count are just the first numbers I came
-            // up with.
-            flux_lut: calc_flux_lut(radius, 100 * radius, 10_000),
+            params: FluxParams::default(),
+            flux_lut,
+            lateral_lut: Vec::new(),
+            fixed_lut: OnceLock::new(),
+            outfluxes: OnceLock::new(),
+            rays: Rays::default(),
+        }
+    }
+
+    // The diagonal and the lateral weights of the look-up table in
+    // fixed-point.  Computed on first use.  The lateral weight is derived
+    // from the rounded sum of the two so that they never exceed one.
+    pub(crate) fn fixed_lut(&self) -> &[[u32; 2]] {
+        self.fixed_lut.get_or_init(|| {
+            let fixed = |w: f32| (w * FIXED_ONE as f32).round() as u32;
+            self.flux_lut
+                .iter()
+                .enumerate()
+                .map(|(ix, &w)| [fixed(w), fixed(w + self.lateral(ix)) - fixed(w)])
+                .collect()
+        })
+    }
+}
+
+// The storage of the look-up table.
+enum Lut {
+    Owned(Vec<f32>),
+    Borrowed(&'static [f32]),
+    #[cfg(feature = "mmap")]
+    Mapped {
+        map: memmap2::Mmap,
+        offset: usize,
+        len: usize,
+    },
+}
+
+impl Lut {
+    fn into_vec(self) -> Vec<f32> {
+        match self {
+            Lut::Owned(lut) => lut,
+            Lut::Borrowed(lut) => lut.to_vec(),
+            #[cfg(feature = "mmap")]
+            lut @ Lut::Mapped { .. } => lut.to_vec(),
+        }
+    }
+}
+
+impl Deref for Lut {
+    type Target = [f32];
+
+    fn deref(&self) -> &[f32] {
+        match self {
+            Lut::Owned(lut) => lut.as_slice(),
+            Lut::Borrowed(lut) => lut,
+            #[cfg(feature = "mmap")]
+            Lut::Mapped { map, offset, len } => unsafe {
+                // The alignment and the size have been checked when mapping.
+                std::slice::from_raw_parts(map.as_ptr().add(*offset) as *const f32, *len)
+            },
         }
     }
 }
 
+// Whether the interior influx weights w, l, and 1.0 - w - l are all within
+// [0.0, 1.0] making them sum to 1.0.
+fn is_normalized(w: f32, l: f32) -> bool {
+    (0.0..=1.0).contains(&w) && (0.0..=1.0 - w).contains(&l)
+}
+
+// The sine and the cosine used for spreading the rays when building the
+// look-up tables.  With the `deterministic` feature they give bit-identical
+// results on all platforms.
+
+#[cfg(not(feature = "deterministic"))]
+fn sin_cos(theta: f64) -> (f64, f64) {
+    theta.sin_cos()
+}
+
+#[cfg(feature = "deterministic")]
+use crate::deterministic::sin_cos;
+
+// The length of the look-up table of a flux field with the given radius.
+fn lut_len(radius: usize) -> usize {
+    if radius > 1 {
+        (radius - 1) * radius / 2
+    } else {
+        0
+    }
+}
+
 // The construction of the look-up table for the flux field is somewhat
 // tricky.  However when trying to understand it, keep the following diagram
 // in you mind.
@@ -69,75 +642,399 @@ impl FluxField {
 
 #[derive(Clone, Default)]
 struct RayCount {
-    jump: i32,
-    total: i32,
+    jump: f64,
+    lateral: f64,
+    total: f64,
 }
 
-fn calc_flux_lut(flux_field_radius: usize, ray_radius: usize, ray_count: usize) -> Vec<f32> {
+impl RayCount {
+    // Count a ray that changed rows when entering the cell.
+    fn add_row_change(&mut self, params: &FluxParams, crossing: f32, ray_weight: f64) {
+        if params.connectivity == Connectivity::Four {
+            if crossing > 0.5 {
+                self.lateral += ray_weight;
+            } else if crossing == 0.5 {
+                self.lateral += ray_weight / 2.0;
+            }
+        } else if !params.three_tap || (0.25..=0.75).contains(&crossing) {
+            self.jump += ray_weight;
+        } else if crossing > 0.75 {
+            self.lateral += ray_weight;
+        }
+    }
+}
+
+fn calc_flux_lut(
+    flux_field_radius: usize,
+    ray_radius: usize,
+    ray_count: usize,
+    params: &FluxParams,
+) -> (Vec<f32>, Vec<f32>) {
+    calc_flux_lut_columns(2, flux_field_radius, ray_radius, ray_count, params)
+}
+
+// Calculate the part of the look-up table covering the columns from `first_x`
+// up to `flux_field_radius`.  Returns the diagonal weights and, in the
+// three-tap mode, the lateral weights.
+//
+// In the three-tap mode a ray changing rows is considered to jump diagonally
+// only if it crosses into the new row near the corner of the cell.  If it
+// crosses early it has entered the new row already in the previous column
+// and it comes in straight.  If it crosses late it comes in laterally.  The
+// four-connected flux fields are like three-tap ones except that no ray
+// jumps; it always comes in either straight or laterally.
+fn calc_flux_lut_columns(
+    first_x: usize,
+    flux_field_radius: usize,
+    ray_radius: usize,
+    ray_count: usize,
+    params: &FluxParams,
+) -> (Vec<f32>, Vec<f32>) {
     assert!(ray_count > 1);
+    assert!(params.supersampling > 0);
+    assert!(first_x > 1);
     assert!(flux_field_radius > 0);
     assert!(ray_radius as f32 / flux_field_radius as f32 >= f32::consts::SQRT_2);
-    let ray_radius = ray_radius as f32;
+    let counts = if params.bresenham {
+        count_bresenham_lines(first_x, flux_field_radius, params)
+    } else {
+        count_ray_fan(first_x, flux_field_radius, ray_radius, ray_count, params)
+    };
+    let has_lateral = params.three_tap || params.connectivity == Connectivity::Four;
+    let lut = counts.iter().map(|c| (c.jump / c.total) as f32).collect();
+    let lateral_lut = if has_lateral {
+        counts
+            .iter()
+            .map(|c| ((c.lateral / c.total) as f32).min(1.0 - (c.jump / c.total) as f32))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    (lut, lateral_lut)
+}
+
+// Count the Bresenham lines to the cells of the columns from `first_x` up to
+// `flux_field_radius` passing through the interior cells of those columns.
+// The counts are in the order of the look-up table.
+fn count_bresenham_lines(
+    first_x: usize,
+    flux_field_radius: usize,
+    params: &FluxParams,
+) -> Vec<RayCount> {
     let counts_wd = flux_field_radius - 1;
-    let counts_size = counts_wd * counts_wd;
-    let mut counts: Vec<RayCount> = vec![Default::default(); counts_size];
-    for ray_ix in 0..ray_count {
-        let ray_angle = ray_ix as f32 / (ray_count - 1) as f32 * f32::consts::FRAC_PI_4;
-        let target_x = (ray_angle.cos() * ray_radius).round() as usize;
-        let target_y = (ray_angle.sin() * ray_radius).round() as usize;
-        let mut last_y = 0;
-        march_ray(flux_field_radius, target_x, target_y, |x, y| {
-            if 1 < x && 0 < y && y < x {
-                let ix = (y - 1) * counts_wd + x - 2;
+    let mut counts: Vec<RayCount> = vec![Default::default(); counts_wd * counts_wd];
+    // The lines to the cells of the earlier columns never reach the new
+    // ones.  The lines to the later ones do reach the earlier columns which
+    // is why `grow_to` rebuilds the whole table in this mode.
+    for target_x in first_x..flux_field_radius + 1 {
+        for target_y in 0..target_x + 1 {
+            let ray_weight = params
+                .distribution
+                .weight_towards(target_x as f32, target_y as f32)
+                as f64;
+            let mut last_y = 0;
+            // The column before the first one tells whether the row changes.
+            march_ray(
+                first_x - 1,
+                target_x,
+                target_x,
+                target_y,
+                bresenham_phase(target_x),
+                |x, y, crossing| {
+                    if first_x <= x && 0 < y && y < x {
+                        let ray_count = &mut counts[(y - 1) * counts_wd + x - 2];
+                        ray_count.total += ray_weight;
+                        if last_y != y {
+                            ray_count.add_row_change(params, crossing, ray_weight);
+                        }
+                    }
+                    last_y = y;
+                },
+            );
+        }
+    }
+    let mut ordered = Vec::with_capacity(lut_len(flux_field_radius) - lut_len(first_x - 1));
+    for x in (first_x - 2)..counts_wd {
+        for y in 0..(x + 1) {
+            ordered.push(counts[y * counts_wd + x].clone());
+        }
+    }
+    ordered
+}
+
+// Count the rays of the fan of `ray_count` rays spread evenly over the octant
+// passing through the interior cells of the columns from `first_x` up to
+// `flux_field_radius`.  The counts are in the order of the look-up table.
+//
+// Each ray is the digital line of `march_ray` towards its target on the arc
+// of the radius `ray_radius`; with `supersampling` the fan is cast from
+// several phases within the origin cell.  Rather than marching the rays
+// the counts are taken column by column from the fan as a whole.  Within a
+// fan the rays are ordered by their angle and so, for any row y > 0 and any
+// point along the march, the rays that have reached the row form a suffix
+// of the fan: a ray towards (tx, ty) with the phase p has reached the row y
+// at the x-coordinate u iff
+//
+//     p + u * ty >= y * tx
+//
+// and the left-hand side minus the right-hand side only grows from one ray
+// to the next as tx never grows, ty never shrinks, and p is a fixed fraction
+// of tx rounded down.  The first ray of the suffix is found by a search
+// near a guess from the previous columns, so the rays passing through a
+// cell, those that changed rows when entering it, and those crossing into
+// the row at a given point between the columns are all ranges of the fan.
+// The weights of the ranges come from the running sums of the ray weights.
+// Counting all the cells of a column takes a few searches per cell instead
+// of a step of every ray.
+fn count_ray_fan(
+    first_x: usize,
+    flux_field_radius: usize,
+    ray_radius: usize,
+    ray_count: usize,
+    params: &FluxParams,
+) -> Vec<RayCount> {
+    // The targets and four times the phases fit in an `i32`.
+    assert!(ray_radius <= i32::MAX as usize / 4);
+    // The targets of the rays by rotating a unit vector step by step.  The
+    // steps are exact IEEE arithmetic so with a portable `sin_cos` the fan
+    // is the same everywhere.
+    let (step_sin, step_cos) = sin_cos(std::f64::consts::FRAC_PI_4 / (ray_count - 1) as f64);
+    let samples = params.supersampling;
+    // Each ray is kept as four times its phase and its target so that the
+    // searches touch a single compact array.
+    let mut rays = Vec::with_capacity(ray_count);
+    let mut weight_sums = Vec::with_capacity(ray_count + 1);
+    let mut weight_sum = 0.0;
+    weight_sums.push(weight_sum);
+    let (mut c, mut s) = (1.0_f64, 0.0_f64);
+    for _ in 0..ray_count {
+        // Both coordinates are non-negative so adding a half and truncating
+        // rounds them to the nearest integer.
+        let target_x = (c * ray_radius as f64 + 0.5) as i32;
+        let target_y = (s * ray_radius as f64 + 0.5) as i32;
+        rays.push((0, target_x, target_y));
+        weight_sum += match params.distribution {
+            RayDistribution::Uniform => 1.0,
+            distribution => distribution.weight_towards(c as f32, s as f32) as f64,
+        };
+        weight_sums.push(weight_sum / samples as f64);
+        let next_c = c * step_cos - s * step_sin;
+        s = s * step_cos + c * step_sin;
+        c = next_c;
+    }
+    let weight = |from: usize, to: usize| {
+        if from < to {
+            weight_sums[to] - weight_sums[from]
+        } else {
+            0.0
+        }
+    };
+    let mut counts: Vec<RayCount> =
+        vec![Default::default(); lut_len(flux_field_radius) - lut_len(first_x - 1)];
+    let mut prev_starts = Vec::with_capacity(flux_field_radius + 1);
+    let mut starts = Vec::with_capacity(flux_field_radius + 1);
+    let mut prev2_starts = Vec::with_capacity(flux_field_radius + 1);
+    for sample in 0..samples {
+        // Four times the phase of each ray.  Without supersampling it is
+        // half the target x-coordinate; otherwise neighbouring rays mostly
+        // share their target x-coordinate and so the phase.
+        if samples == 1 {
+            for ray in rays.iter_mut() {
+                ray.0 = 4 * (ray.1 / 2);
+            }
+        } else {
+            let mut last = (-1, 0);
+            for ray in rays.iter_mut() {
+                let tx = ray.1;
+                if last.0 != tx {
+                    let phase = (2 * sample as i64 + 1) * i64::from(tx) / (2 * samples as i64);
+                    last = (tx, 4 * phase as i32);
+                }
+                ray.0 = last.1;
+            }
+        }
+        let reached = |ray: usize, quarters: i64, y: i64, strictly: bool| {
+            let (phase, tx, ty) = rays[ray];
+            let margin = i64::from(phase) + quarters * i64::from(ty) - 4 * y * i64::from(tx);
+            margin > 0 || (margin == 0 && !strictly)
+        };
+        // The first ray within `from..to` that has reached the row `y` at
+        // the x-coordinate `quarters / 4`, or strictly passed it.
+        let first_reaching = |quarters: i64, y: i64, strictly: bool, from: usize, to: usize| {
+            let (mut lo, mut hi) = (from, to);
+            while lo < hi {
+                let mid = (lo + hi) / 2;
+                if reached(mid, quarters, y, strictly) {
+                    hi = mid;
+                } else {
+                    lo = mid + 1;
+                }
+            }
+            lo
+        };
+        // The same starting from a guess.  The guesses are mostly within a
+        // ray or two of the answer so walking from one beats the binary
+        // search, which is left as the fallback.
+        let first_reaching_near =
+            |guess: usize, quarters: i64, y: i64, strictly: bool, from: usize, to: usize| {
+                let mut at = guess.max(from).min(to);
+                if at == to || reached(at, quarters, y, strictly) {
+                    for _ in 0..6 {
+                        if at == from || !reached(at - 1, quarters, y, strictly) {
+                            return at;
+                        }
+                        at -= 1;
+                    }
+                    first_reaching(quarters, y, strictly, from, at)
+                } else {
+                    for _ in 0..6 {
+                        at += 1;
+                        if at == to || reached(at, quarters, y, strictly) {
+                            return at;
+                        }
+                    }
+                    first_reaching(quarters, y, strictly, at + 1, to)
+                }
+            };
+        // The first ray in each row of the column before the first one;
+        // the one past the last row is the end of the fan.
+        prev_starts.clear();
+        starts.clear();
+        for y in 1..first_x as i64 {
+            let from = starts.last().copied().unwrap_or(0);
+            starts.push(first_reaching(
+                4 * (first_x as i64 - 1),
+                y,
+                false,
+                from,
+                ray_count,
+            ));
+        }
+        starts.push(ray_count);
+        let mut ix = 0;
+        for x in first_x..flux_field_radius + 1 {
+            // A ray climbs at most one row per column, so the first ray in
+            // the row y lies between the first ones in the rows y - 1 and y
+            // of the previous column.  The step between the two previous
+            // columns is a good guess of the next one.
+            mem::swap(&mut prev2_starts, &mut prev_starts);
+            mem::swap(&mut prev_starts, &mut starts);
+            starts.clear();
+            let q = 4 * x as i64;
+            for y in 1..x + 1 {
+                let below = if y > 1 { prev_starts[y - 2] } else { 0 };
+                let from = starts.last().copied().unwrap_or(0).max(below);
+                let to = prev_starts[y - 1];
+                starts.push(if y + 1 < prev2_starts.len() {
+                    let guess = (2 * to).saturating_sub(prev2_starts[y - 1]);
+                    first_reaching_near(guess, q, y as i64, false, from, to)
+                } else {
+                    first_reaching(q, y as i64, false, from, to)
+                });
+            }
+            starts.push(ray_count);
+            for y in 1..x {
                 let ray_count = &mut counts[ix];
-                ray_count.total += 1;
-                if last_y != y {
-                    ray_count.jump += 1;
+                ix += 1;
+                let (first, end) = (starts[y - 1], starts[y]);
+                ray_count.total += weight(first, end);
+                // The rays that were still on the row below in the previous
+                // column.
+                let changed = end.min(prev_starts[y - 1]);
+                if changed <= first {
+                    continue;
+                }
+                let y = y as i64;
+                // The later in the fan the earlier the ray crosses into the
+                // row, roughly evenly over the step.
+                let crossed_by = |quarter: i64, strictly: bool, from: usize| {
+                    let guess = first + (changed - first) * (4 - quarter as usize) / 4;
+                    first_reaching_near(guess, q - 4 + quarter, y, strictly, from, changed)
+                };
+                if params.connectivity == Connectivity::Four {
+                    // Crossing after the midpoint counts as lateral and
+                    // exactly at it as half lateral.
+                    let at_midpoint = crossed_by(2, false, first);
+                    let past_midpoint =
+                        first_reaching_near(at_midpoint, q - 2, y, true, at_midpoint, changed);
+                    ray_count.lateral +=
+                        weight(first, at_midpoint) + weight(at_midpoint, past_midpoint) / 2.0;
+                } else if params.three_tap {
+                    // The rays before `late` cross after three quarters
+                    // of the step and those from `early` on before one.
+                    let late = crossed_by(3, false, first);
+                    let early = crossed_by(1, true, late);
+                    ray_count.jump += weight(late, early);
+                    ray_count.lateral += weight(first, late);
+                } else {
+                    ray_count.jump += weight(first, changed);
                 }
             }
-            last_y = y;
-        });
-    }
-    let lut_size = (flux_field_radius - 1) * flux_field_radius / 2;
-    let mut lut = Vec::with_capacity(lut_size);
-    for x in 0..(flux_field_radius - 1) {
-        for y in 0..(x + 1) {
-            let ray_count = &counts[y * counts_wd + x];
-            lut.push(ray_count.jump as f32 / ray_count.total as f32);
         }
     }
-    lut
+    counts
+}
+
+// The phase of `march_ray` that makes the march follow the classic Bresenham
+// line to the cell (`target_x`, `target_y`): the error term starts at
+// `target_x / 2` and the row changes only once the error goes negative, so
+// the exact halfway points round down rather than up.
+pub(crate) fn bresenham_phase(target_x: usize) -> usize {
+    target_x - 1 - target_x / 2
 }
 
 // March a ray from the origin to the direction of the point (`target_x`,
-// `target_y`) calling the function `f` at every point along the march.  The
-// march is stopped once the x-coordinate has reached `limit_x`.
+// `target_y`) calling the function `f` at every point along the march from
+// the x-coordinate `start_x` up to `limit_x`.  The ray starts from the height
+// `phase / target_x` within the origin cell, so `target_x / 2` starts it from
+// the center.  The function also receives the point where the ray crossed
+// into the current row as a fraction of the step from the center of the
+// previous column to the center of the current one.  It is meaningful only
+// when the row has just changed.
 //
-fn march_ray<F>(limit_x: usize, target_x: usize, target_y: usize, f: F)
-where
-    F: FnMut(usize, usize),
+// The march is a digital line so the row at any column follows directly
+// from the phase and the slope.  Growing a flux field jumps right to the new
+// columns instead of re-marching the old ones.
+//
+fn march_ray<F>(
+    start_x: usize,
+    limit_x: usize,
+    target_x: usize,
+    target_y: usize,
+    phase: usize,
+    f: F,
+) where
+    F: FnMut(usize, usize, f32),
 {
     assert!(target_y <= target_x, "illegal arguments");
     let mut f = f;
     if target_y == 0 {
-        for step_x in 0..limit_x + 1 {
-            f(step_x, 0);
+        for step_x in start_x..limit_x + 1 {
+            f(step_x, 0, 0.5);
         }
     } else if target_y == target_x {
-        for step_x in 0..limit_x + 1 {
-            f(step_x, step_x);
+        for step_x in start_x..limit_x + 1 {
+            f(step_x, step_x, 0.5);
         }
     } else {
-        f(0, 0);
-        let mut r = target_x / 2;
-        let mut step_y = 0;
-        for step_x in 1..limit_x + 1 {
-            r += target_y;
-            if r >= target_x {
+        if start_x == 0 {
+            f(0, 0, 0.5);
+        }
+        // The state of the march after the column before the first one
+        // visited below.
+        let skipped = start_x.max(1) - 1;
+        let mut r = (phase + skipped * target_y) % target_x;
+        let mut step_y = (phase + skipped * target_y) / target_x;
+        for step_x in start_x.max(1)..limit_x + 1 {
+            let mut crossing = 0.5;
+            if r + target_y >= target_x {
+                crossing = (target_x - r) as f32 / target_y as f32;
                 step_y += 1;
-                r -= target_x;
+                r = r + target_y - target_x;
+            } else {
+                r += target_y;
             }
-            f(step_x, step_y);
+            f(step_x, step_y, crossing);
         }
     }
 }
@@ -146,15 +1043,21 @@ where
 
 /// A field of vision.
 ///
-pub struct Fov<T, X>
+/// The buffer of the field is allocated with the allocator `A`, by default
+/// the global one; see `Fov::new_in`.  Most of the methods beyond the
+/// updates are available only for the default allocator.  The flux field
+/// tables are built once and shared, so they stay on the global heap.
+///
+pub struct Fov<T, X, A = Global>
 where
     X: AsRef<FluxField>,
+    A: Allocator,
 {
     flux_field: X,
     radius: isize,
     width: isize,
     ix_origin: isize,
-    data: Vec<T>,
+    data: Buffer<T, A>,
 }
 
 impl<T, X> Fov<T, X>
@@ -163,12 +1066,47 @@ where
     X: AsRef<FluxField>,
 {
     pub fn new(flux_field: X, radius: usize, init: T) -> Self {
-        assert!(radius <= flux_field.as_ref().radius);
+        Fov::new_in(flux_field, radius, init, Global)
+    }
+
+    /// Constructs a field of vision or fails if `radius` exceeds that of
+    /// the flux field.
+    ///
+    pub fn try_new(flux_field: X, radius: usize, init: T) -> Result<Self, Error> {
+        let max = flux_field.as_ref().radius;
+        if radius > max {
+            return Err(Error::RadiusTooLarge { radius, max });
+        }
+        Ok(Fov::new(flux_field, radius, init))
+    }
+}
+
+impl<T, X, A> Fov<T, X, A>
+where
+    T: Clone,
+    X: AsRef<FluxField>,
+    A: Allocator,
+{
+    /// Constructs a field of vision whose buffer is allocated with `alloc`,
+    /// e.g., in a frame arena.  Allocators other than `Global` require the
+    /// `allocator-api` feature; see the `allocator` module.
+    ///
+    pub fn new_in(flux_field: X, radius: usize, init: T, alloc: A) -> Self {
+        assert!(
+            radius <= flux_field.as_ref().radius,
+            "radius exceeds that of the flux field"
+        );
         let radius = radius as isize;
         let width = radius * 2 + 1;
         let ix_origin = radius * (width + 1);
         let size = (width * width) as usize;
-        let data = vec![init; size];
+        let mut data = Buffer::with_capacity_in(size, alloc);
+        data.resize(size, init);
+        // Build the lazily computed tables here so that the updates never
+        // have to allocate.
+        if flux_field.as_ref().is_four_connected() {
+            flux_field.as_ref().fixed_lut();
+        }
         Fov {
             flux_field,
             radius,
@@ -179,35 +1117,469 @@ where
     }
 }
 
+impl<T, X> Fov<T, X>
+where
+    T: Clone + Default,
+    X: AsRef<FluxField>,
+{
+    /// Constructs a field of vision with every cell set to `T::default()`.
+    ///
+    pub fn with_default(flux_field: X, radius: usize) -> Self {
+        Fov::new(flux_field, radius, T::default())
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    T: Clone,
+    X: AsRef<FluxField>,
+{
+    /// Set every cell to `init` without reallocating, e.g., to reuse one
+    /// field for several observers.
+    ///
+    pub fn reset(&mut self, init: T) {
+        for cell in self.data.iter_mut() {
+            *cell = init.clone();
+        }
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    T: Default,
+    X: AsRef<FluxField>,
+{
+    /// Set every cell back to `T::default()` without reallocating.
+    ///
+    pub fn reset_to_default(&mut self) {
+        for cell in self.data.iter_mut() {
+            *cell = T::default();
+        }
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Constructs a field of vision from existing values, e.g., from those
+    /// returned by `into_inner`, without copying them.
+    ///
+    /// The values are laid out row by row as in `as_slice` so there must be
+    /// exactly `(2 * radius + 1)²` of them.
+    ///
+    pub fn from_parts(flux_field: X, radius: usize, data: Vec<T>) -> Self {
+        let data = buffer_from_vec(data);
+        assert!(radius <= flux_field.as_ref().radius);
+        let width = radius * 2 + 1;
+        assert_eq!(data.len(), width * width, "data length mismatch");
+        if flux_field.as_ref().is_four_connected() {
+            flux_field.as_ref().fixed_lut();
+        }
+        let radius = radius as isize;
+        let width = width as isize;
+        Fov {
+            flux_field,
+            radius,
+            width,
+            ix_origin: radius * (width + 1),
+            data,
+        }
+    }
+
+    /// Take the values out of the field of vision without copying them.
+    ///
+    /// The values are laid out row by row as in `as_slice`.
+    ///
+    pub fn into_inner(self) -> Vec<T> {
+        vec_from_buffer(self.data)
+    }
+}
+
+// Move the values of a standard vector into a buffer without copying them.
+fn buffer_from_vec<T>(vec: Vec<T>) -> Buffer<T, Global> {
+    let mut vec = std::mem::ManuallyDrop::new(vec);
+    // Both are allocated by the global allocator with the same layout.
+    unsafe { Buffer::from_raw_parts(vec.as_mut_ptr(), vec.len(), vec.capacity()) }
+}
+
+// Move the values of a buffer into a standard vector without copying them.
+fn vec_from_buffer<T>(buffer: Buffer<T, Global>) -> Vec<T> {
+    let mut buffer = std::mem::ManuallyDrop::new(buffer);
+    unsafe { Vec::from_raw_parts(buffer.as_mut_ptr(), buffer.len(), buffer.capacity()) }
+}
+
+/// A dynamically dispatched update function; see `Fov::update_dyn`.
+pub type DynUpdateFn<'a, T> = dyn FnMut(i32, i32, &[Influx<&T>]) -> T + 'a;
+
 /// An influx into a grid cell.
 ///
-pub struct Influx<T> {
-    pub weight: f32,
+/// The direction (`dx`, `dy`) is the direction the flux travels, i.e. the
+/// offset from the cell the flux comes from to the cell receiving it.  This
+/// holds for all the cells; the interior cells of the octants used to have
+/// their directions negated with the straight and diagonal labels swapped,
+/// see the changelog.
+///
+/// The weights of the influxes into a grid cell always sum to 1.0; see
+/// `FluxField::verify_normalization`.  The weights are `f32` except for the
+/// updates with another type of weights; see `Fov::update_weighted`.
+///
+pub struct Influx<T, W = f32> {
+    pub weight: W,
     pub dx: i32,
     pub dy: i32,
     pub value: T,
 }
 
-impl<T, X> Fov<T, X>
-where
-    X: AsRef<FluxField>,
-{
-    /// The value of at the given grid cell.
-    pub fn at(&self, x: i32, y: i32) -> &T {
-        let ix = self.ix_origin + (self.width * y as isize) + x as isize;
-        &self.data[ix as usize]
+/// The part of the field of vision a grid cell belongs to.
+///
+/// The field is divided into the origin, eight edges radiating from the
+/// origin, and the interiors of the eight octants between the edges:
+///
+/// ```text
+///     \5555|6666/            -y
+///     4\555|666/7
+///     44\55|66/77             |
+///     444\5|6/777             |
+///     4444\|/7777
+///     -----@-----        -x --@-- +x
+///     3333/|\0000
+///     333/2|1\000             |
+///     33/22|11\00             |
+///     3/222|111\0
+///     /2222|1111\            +y
+/// ```
+///
+/// The edge `i` runs along the direction (1, 0), (1, 1), (0, 1), (-1, 1),
+/// (-1, 0), (-1, -1), (0, -1), or (1, -1) respectively and lies between the
+/// octants `i - 1` and `i` (modulo 8).  Both edges and octants are updated in
+/// the order of their indices except in four-connected fields; see
+/// `Connectivity::Four`.  The column is the Chebyshev distance of
+/// the cell from the origin, i.e. the step of the sweep at which the cell is
+/// visited.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sector {
+    Origin,
+    Edge { index: u8, column: u32 },
+    Interior { octant: u8, column: u32 },
+}
+
+impl<T, X, A> Fov<T, X, A>
+where
+    X: AsRef<FluxField>,
+    A: Allocator,
+{
+    /// The radius of the field of vision.
+    pub fn radius(&self) -> usize {
+        self.radius as usize
+    }
+
+    /// The value of at the given grid cell.
+    ///
+    /// Panics if the cell is outside the field of vision.
+    ///
+    pub fn at(&self, x: i32, y: i32) -> &T {
+        self.get(x, y).expect("cell out of range")
+    }
+
+    /// The value at the given grid cell or `None` if the cell is outside the
+    /// field of vision.
+    ///
+    pub fn get(&self, x: i32, y: i32) -> Option<&T> {
+        let radius = self.radius as u32;
+        if x.unsigned_abs() <= radius && y.unsigned_abs() <= radius {
+            Some(&self.data[self.index(x, y)])
+        } else {
+            None
+        }
+    }
+
+    /// The mutable value at the given grid cell.
+    ///
+    /// Panics if the cell is outside the field of vision.
+    ///
+    pub fn at_mut(&mut self, x: i32, y: i32) -> &mut T {
+        self.get_mut(x, y).expect("cell out of range")
+    }
+
+    /// The mutable value at the given grid cell or `None` if the cell is
+    /// outside the field of vision.
+    ///
+    pub fn get_mut(&mut self, x: i32, y: i32) -> Option<&mut T> {
+        let radius = self.radius as u32;
+        if x.unsigned_abs() <= radius && y.unsigned_abs() <= radius {
+            let ix = self.index(x, y);
+            Some(&mut self.data[ix])
+        } else {
+            None
+        }
+    }
+
+    /// Iterate over the cells yielding the coordinates and the value of each.
+    ///
+    /// The cells are yielded row by row from the corner (-R, -R) to the
+    /// corner (R, R), x increasing within a row, i.e., in the order of
+    /// `as_slice`.
+    ///
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (i32, i32, &T)> + '_ {
+        let (radius, width) = (self.radius as i32, self.width as usize);
+        self.data.iter().enumerate().map(move |(ix, value)| {
+            let x = (ix % width) as i32 - radius;
+            let y = (ix / width) as i32 - radius;
+            (x, y, value)
+        })
+    }
+
+    /// Iterate over the cells like `iter` but yielding mutable values.
+    ///
+    pub fn iter_mut(&mut self) -> impl ExactSizeIterator<Item = (i32, i32, &mut T)> + '_ {
+        let (radius, width) = (self.radius as i32, self.width as usize);
+        self.data.iter_mut().enumerate().map(move |(ix, value)| {
+            let x = (ix % width) as i32 - radius;
+            let y = (ix / width) as i32 - radius;
+            (x, y, value)
+        })
+    }
+
+    /// Iterate over the cells at the Chebyshev distance `r` from the origin,
+    /// i.e., on the perimeter of the square of the radius `r`, in the order
+    /// of `iter`.  The ring 0 is the origin alone.
+    ///
+    /// Panics if `r` exceeds the radius of the field of vision.
+    ///
+    pub fn enumerate_ring(&self, r: usize) -> impl Iterator<Item = (i32, i32, &T)> + '_ {
+        assert!(r <= self.radius as usize, "ring out of range");
+        let r = r as i32;
+        (-r..r + 1).flat_map(move |y| {
+            let step = if y.abs() == r {
+                1
+            } else {
+                (2 * r).max(1) as usize
+            };
+            (-r..r + 1)
+                .step_by(step)
+                .map(move |x| (x, y, &self.data[self.index(x, y)]))
+        })
+    }
+
+    // The index of the given grid cell in the underlying data.
+    #[inline]
+    pub(crate) fn index(&self, x: i32, y: i32) -> usize {
+        (self.ix_origin + (self.width * y as isize) + x as isize) as usize
+    }
+
+    /// Expose the underlying data as a slice.
+    pub fn as_slice(&self) -> &[T] {
+        self.data.as_slice()
+    }
+
+    /// Update the field of vision with the given function.
+    ///
+    /// The update performs no heap allocations of its own; the influxes are
+    /// passed in stack allocated arrays and all the tables are built when
+    /// the flux field and the field of vision are created.  Only the update
+    /// function may allocate.  The same holds for `update_with_sector` and
+    /// `update_notify`.
+    ///
+    pub fn update<F>(&mut self, update_fn: F)
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        self.update_cells(|x, y, _, cell, influxes| *cell = update_fn(x, y, influxes));
+    }
+
+    /// Update the field of vision with a dynamically dispatched function.
+    ///
+    /// Works like `update` but the update core is instantiated only once per
+    /// cell type instead of once per closure type which keeps the compile
+    /// times and the code size down when there are many different update
+    /// functions.  The price is an indirect call per cell which also prevents
+    /// the update function from being inlined into the walk; for cheap
+    /// update functions this can make the update noticeably slower.
+    ///
+    pub fn update_dyn(&mut self, update_fn: &mut DynUpdateFn<T>) {
+        self.update_cells(|x, y, _, cell, influxes| *cell = update_fn(x, y, influxes));
+    }
+
+    /// Update the field of vision with a function that also receives the
+    /// sector of the field each cell belongs to.
+    ///
+    pub fn update_with_sector<F>(&mut self, update_fn: F)
+    where
+        F: FnMut(i32, i32, Sector, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        self.update_cells(|x, y, sector, cell, influxes| *cell = update_fn(x, y, sector, influxes));
+    }
+
+    /// Update the field of vision with a function that also receives the
+    /// value the cell had before the update.
+    ///
+    /// Handy for values that depend on their history, e.g., for remembering
+    /// the explored cells, for fading, or for hysteresis.
+    ///
+    pub fn update_with_previous<F>(&mut self, update_fn: F)
+    where
+        F: FnMut(i32, i32, &T, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        self.update_cells(|x, y, _, cell, influxes| *cell = update_fn(x, y, cell, influxes));
+    }
+
+    /// Update the field of vision with a function that modifies each cell
+    /// in place.
+    ///
+    /// Like `update_with_previous` but the cell is handed over by a mutable
+    /// reference instead of being replaced with a new value, so cells that
+    /// own memory, e.g., lists of the entities seen, can be updated without
+    /// moving or reallocating them.  The influxes come from the cells closer
+    /// to the origin which have already been updated.
+    ///
+    /// ```
+    /// # use flux_fov::{FluxField, Fov};
+    /// let mut fov = Fov::new(Box::new(FluxField::new(4)), 4, Vec::new());
+    /// for frame in 0..3 {
+    ///     fov.update_in_place(|x, y, seen: &mut Vec<u32>, _| {
+    ///         if x.abs() + y.abs() <= 2 {
+    ///             seen.push(frame);
+    ///         }
+    ///     });
+    /// }
+    /// assert_eq!(fov.at(1, 1), &[0, 1, 2]);
+    /// assert!(fov.at(3, 0).is_empty());
+    /// ```
+    ///
+    pub fn update_in_place<F>(&mut self, update_fn: F)
+    where
+        F: FnMut(i32, i32, &mut T, &[Influx<&T>]),
+    {
+        let mut update_fn = update_fn;
+        self.update_cells(|x, y, _, cell, influxes| update_fn(x, y, cell, influxes));
+    }
+
+    /// Update the field of vision with a function that may leave cells as
+    /// they are.
+    ///
+    /// The cells for which `update_fn` returns `None` keep their current
+    /// value, e.g., the remembered contents of the cells outside the map,
+    /// without having to construct a placeholder value for them.
+    ///
+    pub fn update_option<F>(&mut self, update_fn: F)
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> Option<T>,
+    {
+        let mut update_fn = update_fn;
+        self.update_cells(|x, y, _, cell, influxes| {
+            if let Some(value) = update_fn(x, y, influxes) {
+                *cell = value;
+            }
+        });
+    }
+
+    /// Update the field of vision with the given function and report which
+    /// cells changed their value.
+    ///
+    /// The coordinates of the changed cells are returned in the order the
+    /// cells were updated.
+    ///
+    pub fn update_diff<F>(&mut self, update_fn: F) -> Vec<(i32, i32)>
+    where
+        T: PartialEq,
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        let mut changed = Vec::new();
+        self.update_cells(|x, y, _, cell, influxes| {
+            let value = update_fn(x, y, influxes);
+            if *cell != value {
+                changed.push((x, y));
+                *cell = value;
+            }
+        });
+        changed
+    }
+
+    /// Update the field of vision with the given function and call
+    /// `on_change` for every cell whose visibility changed.
+    ///
+    /// The visibility of a cell is determined with `is_visible` both before
+    /// and after the cell is updated.  The hook receives the coordinates of
+    /// the cell and whether it became visible (`true`) or invisible
+    /// (`false`).  The hook is called during the update as soon as the cell
+    /// has been updated.
+    ///
+    pub fn update_notify<P, H, F>(&mut self, is_visible: P, on_change: H, update_fn: F)
+    where
+        P: Fn(&T) -> bool,
+        H: FnMut(i32, i32, bool),
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut on_change = on_change;
+        let mut update_fn = update_fn;
+        self.update_cells(|x, y, _, cell, influxes| {
+            let was_visible = is_visible(cell);
+            *cell = update_fn(x, y, influxes);
+            let now_visible = is_visible(cell);
+            if was_visible != now_visible {
+                on_change(x, y, now_visible);
+            }
+        });
+    }
+
+    // Visit the cells in the update order calling `kernel` with the
+    // coordinates, the sector, a mutable reference to the cell, and the
+    // influxes into the cell.  The kernel is responsible for writing the new
+    // value.
+    pub(crate) fn update_cells<K>(&mut self, kernel: K)
+    where
+        K: FnMut(i32, i32, Sector, &mut T, &[Influx<&T>]),
+    {
+        self.update_cells_within(|_, _| true, kernel);
+    }
+
+    // Like `update_cells` but only within a footprint.  The walk moves on to
+    // the next edge or column of an octant at the first cell outside, so the
+    // footprint must contain the origin and every edge and column of the
+    // octant interiors up to some cell.  The cells beyond are never visited.
+    // The cells within must receive their flux only from cells within.
+    pub(crate) fn update_cells_within<P, K>(&mut self, within: P, kernel: K)
+    where
+        P: Fn(i32, i32) -> bool,
+        K: FnMut(i32, i32, Sector, &mut T, &[Influx<&T>]),
+    {
+        let mut kernel = kernel;
+        self.update_cells_within_arity(within, |x, y, sector, cell, influxes| {
+            kernel(x, y, sector, cell, influxes.as_slice())
+        });
     }
 
-    /// Expose the underlying data as a slice.
-    pub fn as_slice(&self) -> &[T] {
-        self.data.as_slice()
+    // Like `update_cells_within` but the kernel receives the influxes as
+    // arrays of a fixed size; see `Influxes`.
+    pub(crate) fn update_cells_within_arity<P, K>(&mut self, within: P, kernel: K)
+    where
+        P: Fn(i32, i32) -> bool,
+        K: FnMut(i32, i32, Sector, &mut T, Influxes<&T>),
+    {
+        #[cfg(feature = "debug-checks")]
+        {
+            let mut kernel = kernel;
+            let mut check = checks::SweepCheck::new(self, &within);
+            self.walk_cells(within, |x, y, sector, cell, influxes: Influxes<&T>| {
+                check.visit(x, y, sector, cell, influxes.as_slice());
+                kernel(x, y, sector, cell, influxes);
+            });
+            check.finish();
+        }
+        #[cfg(not(feature = "debug-checks"))]
+        self.walk_cells(within, kernel);
     }
 
-    /// Update the field of vision with the given function.
-    ///
-    pub fn update<F>(&mut self, update_fn: F)
+    fn walk_cells<P, K>(&mut self, within: P, kernel: K)
     where
-        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+        P: Fn(i32, i32) -> bool,
+        K: FnMut(i32, i32, Sector, &mut T, Influxes<&T>),
     {
         // The field of view is laid out in the memory in the following
         // manner:
@@ -230,92 +1602,192 @@ where
         // Here R is the "radius" of the field of view and W is the width
         // of a single row (W = 2 * R + 1).
 
+        #[cfg(feature = "tracing")]
+        let _span =
+            tracing::debug_span!("fov_update", radius = self.radius, cells = self.data.len())
+                .entered();
+        if self.flux_field.as_ref().is_four_connected() {
+            self.update_cells_in_rings(within, kernel);
+            return;
+        }
+
         unsafe {
-            let mut h = Helper::new(self, update_fn);
+            let mut h = Helper::new(self, within, kernel);
             let w = h.width;
             h.calc_origin();
             if h.radius > 0 {
-                h.calc_edge(1, 0, 1);
-                h.calc_edge(1, 1, w + 1);
-                h.calc_edge(0, 1, w);
-                h.calc_edge(-1, 1, w - 1);
-                h.calc_edge(-1, 0, -1);
-                h.calc_edge(-1, -1, -w - 1);
-                h.calc_edge(0, -1, -w);
-                h.calc_edge(1, -1, -w + 1);
+                h.calc_edge(0, 1, 0, 1);
+                h.calc_edge(1, 1, 1, w + 1);
+                h.calc_edge(2, 0, 1, w);
+                h.calc_edge(3, -1, 1, w - 1);
+                h.calc_edge(4, -1, 0, -1);
+                h.calc_edge(5, -1, -1, -w - 1);
+                h.calc_edge(6, 0, -1, -w);
+                h.calc_edge(7, 1, -1, -w + 1);
                 if h.radius > 1 {
-                    h.calc_interior(1, 0, 0, 1, 1, w);
-                    h.calc_interior(0, 1, 1, 0, w, 1);
-                    h.calc_interior(0, -1, 1, 0, w, -1);
-                    h.calc_interior(-1, 0, 0, 1, -1, w);
-                    h.calc_interior(-1, 0, 0, -1, -1, -w);
-                    h.calc_interior(0, -1, -1, 0, -w, -1);
-                    h.calc_interior(0, 1, -1, 0, -w, 1);
-                    h.calc_interior(1, 0, 0, -1, 1, -w);
+                    h.calc_interior(0, 1, 0, 0, 1, 1, w);
+                    h.calc_interior(1, 0, 1, 1, 0, w, 1);
+                    h.calc_interior(2, 0, -1, 1, 0, w, -1);
+                    h.calc_interior(3, -1, 0, 0, 1, -1, w);
+                    h.calc_interior(4, -1, 0, 0, -1, -1, -w);
+                    h.calc_interior(5, 0, -1, -1, 0, -w, -1);
+                    h.calc_interior(6, 0, 1, -1, 0, -w, 1);
+                    h.calc_interior(7, 1, 0, 0, -1, 1, -w);
                 }
             }
         }
     }
 }
 
-struct Helper<'a, T, F> {
+impl<T, X, A> Fov<T, X, A>
+where
+    X: AsRef<FluxField>,
+    A: Allocator,
+{
+    // The pointer walk covers the field edge by edge and octant by octant.
+    // The four-connected fields are updated ring by ring instead and for
+    // them we fall back to the index based sweep.
+    // The rings cut across the columns so the footprint is checked cell by
+    // cell.
+    fn update_cells_in_rings<P, K>(&mut self, within: P, kernel: K)
+    where
+        P: Fn(i32, i32) -> bool,
+        K: FnMut(i32, i32, Sector, &mut T, Influxes<&T>),
+    {
+        let mut kernel = kernel;
+        for k in 0..self.radius as i32 + 1 {
+            self.walk_ring_part(k, 0..usize::MAX, |x, y, sector, cell, influxes| {
+                if within(x, y) {
+                    kernel(x, y, sector, cell, influxes);
+                }
+            });
+        }
+    }
+
+    // Update the cells of the ring `k` with the index based sweep.  The
+    // rings inside it must be up to date.
+    pub(crate) fn update_cells_of_ring<K>(&mut self, k: i32, kernel: K)
+    where
+        K: FnMut(i32, i32, Sector, &mut T, &[Influx<&T>]),
+    {
+        self.update_cells_of_ring_part(k, 0..usize::MAX, kernel);
+    }
+
+    // Update the given range of the cells of the ring `k` in the order of
+    // the sweep.  The cells of the ring before the range must be up to date
+    // as well.
+    pub(crate) fn update_cells_of_ring_part<K>(&mut self, k: i32, cells: Range<usize>, kernel: K)
+    where
+        K: FnMut(i32, i32, Sector, &mut T, &[Influx<&T>]),
+    {
+        let mut kernel = kernel;
+        self.walk_ring_part(k, cells, |x, y, sector, cell, influxes| {
+            kernel(x, y, sector, cell, influxes.as_slice())
+        });
+    }
+
+    // The walk behind `update_cells_of_ring_part` passing the influxes as
+    // arrays of a fixed size.
+    fn walk_ring_part<K>(&mut self, k: i32, cells: Range<usize>, kernel: K)
+    where
+        K: FnMut(i32, i32, Sector, &mut T, Influxes<&T>),
+    {
+        let mut kernel = kernel;
+        let mut n = 0;
+        let data = self.data.as_mut_ptr();
+        sweep::sweep_ring(
+            k,
+            self.width,
+            self.ix_origin,
+            self.flux_field.as_ref(),
+            |step| unsafe {
+                n += 1;
+                if !cells.contains(&(n - 1)) {
+                    return;
+                }
+                // The cell is never one of its own influxes so the mutable
+                // reference doesn't alias the shared ones.
+                let cell = &mut *data.add(step.ix);
+                step.with_influxes(
+                    |ix| &*data.add(ix),
+                    |influxes| kernel(step.x, step.y, step.sector, cell, influxes),
+                );
+            },
+        );
+    }
+}
+
+struct Helper<'a, T, P, F> {
+    within: P,
     update_fn: F,
     origin: *mut T,
     radius: isize,
     width: isize,
     flux_lut: &'a [f32],
+    lateral_lut: &'a [f32],
 }
 
-impl<'a, T, F> Helper<'a, T, F>
+impl<'a, T, P, F> Helper<'a, T, P, F>
 where
     T: Sized,
-    F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    P: Fn(i32, i32) -> bool,
+    F: FnMut(i32, i32, Sector, &mut T, Influxes<&T>),
 {
     #[inline]
-    unsafe fn new<X>(fov: &'a mut Fov<T, X>, update_fn: F) -> Self
+    unsafe fn new<X, A>(fov: &'a mut Fov<T, X, A>, within: P, update_fn: F) -> Self
     where
         X: AsRef<FluxField>,
+        A: Allocator,
     {
         Helper {
+            within,
             update_fn,
             origin: fov.data.as_mut_ptr().offset(fov.ix_origin),
             radius: fov.radius,
             width: fov.width,
             flux_lut: &fov.flux_field.as_ref().flux_lut,
+            lateral_lut: &fov.flux_field.as_ref().lateral_lut,
         }
     }
 
     #[inline]
     unsafe fn calc_origin(&mut self) {
-        *self.origin = (self.update_fn)(0, 0, &[]);
+        (self.update_fn)(0, 0, Sector::Origin, &mut *self.origin, Influxes::Origin);
     }
 
     #[inline]
-    unsafe fn calc_edge(&mut self, dx: i32, dy: i32, stride: isize) {
+    unsafe fn calc_edge(&mut self, index: u8, dx: i32, dy: i32, stride: isize) {
         let mut x = 0;
         let mut y = 0;
         let mut curr = self.origin;
-        for _ in 0..self.radius {
+        for column in 1..self.radius as u32 + 1 {
             x += dx;
             y += dy;
+            if !(self.within)(x, y) {
+                break;
+            }
             let prev = &*curr;
             curr = curr.offset(stride);
-            *curr = (self.update_fn)(
+            (self.update_fn)(
                 x,
                 y,
-                &[Influx {
+                Sector::Edge { index, column },
+                &mut *curr,
+                Influxes::One(&[Influx {
                     dx,
                     dy,
                     weight: 1.0,
                     value: prev,
-                }],
+                }]),
             );
         }
     }
 
     #[inline]
+    #[allow(clippy::too_many_arguments)]
     unsafe fn calc_interior(
         &mut self,
+        octant: u8,
         m_xu: i32,
         m_xv: i32,
         m_yu: i32,
@@ -324,48 +1796,68 @@ where
         v_stride: isize,
     ) {
         assert!(self.radius > 1);
-        let dx_stay = -m_xu;
-        let dy_stay = -m_yu;
-        let dx_jump = -(m_xu + m_xv);
-        let dy_jump = -(m_yu + m_yv);
+        // The cell (u, v) receives flux straight from (u - 1, v) and
+        // diagonally from (u - 1, v - 1).  The weight in the look-up table
+        // is the fraction of the rays that jumped, i.e. entered diagonally.
+        // In the three-tap mode it also receives flux laterally from
+        // (u, v - 1).
+        let three_tap = !self.lateral_lut.is_empty();
+        let dx_stay = m_xu;
+        let dy_stay = m_yu;
+        let dx_jump = m_xu + m_xv;
+        let dy_jump = m_yu + m_yv;
         let mut col_ptr = self.origin.offset(u_stride);
-        let mut lut_ix = 0;
         for u in 2..self.radius as i32 + 1 {
             let mut influx_ptr = col_ptr;
-            let mut influx_stay = &*influx_ptr;
+            let mut influx_jump = &*influx_ptr;
             col_ptr = col_ptr.offset(u_stride);
             let mut curr = col_ptr;
+            let column_ix = lut_len(u as usize - 1);
             for v in 1..u {
-                curr = curr.offset(v_stride);
-                influx_ptr = influx_ptr.offset(v_stride);
-                let influx_jump = &*influx_ptr;
                 let x = m_xu * u + m_xv * v;
                 let y = m_yu * u + m_yv * v;
+                if !(self.within)(x, y) {
+                    break;
+                }
+                curr = curr.offset(v_stride);
+                influx_ptr = influx_ptr.offset(v_stride);
+                let influx_stay = &*influx_ptr;
+                let lut_ix = column_ix + v as usize - 1;
                 let w = self.flux_lut[lut_ix];
-                *curr = (self.update_fn)(
-                    x,
-                    y,
-                    &[
-                        Influx {
-                            dx: dx_stay,
-                            dy: dy_stay,
-                            // XXX(soija) Hmm, IMO this should be 1.0 - w
-                            // and the other one w ... but this the way
-                            // they give the correct result.  Need to
-                            // figure this out.
-                            weight: w,
-                            value: influx_stay,
-                        },
-                        Influx {
-                            dx: dx_jump,
-                            dy: dy_jump,
-                            weight: 1.0 - w,
-                            value: influx_jump,
-                        },
-                    ],
-                );
-                influx_stay = influx_jump;
-                lut_ix += 1;
+                let l = if three_tap {
+                    self.lateral_lut[lut_ix]
+                } else {
+                    0.0
+                };
+                let jump = Influx {
+                    dx: dx_jump,
+                    dy: dy_jump,
+                    weight: w,
+                    value: influx_jump,
+                };
+                let stay = Influx {
+                    dx: dx_stay,
+                    dy: dy_stay,
+                    weight: 1.0 - w - l,
+                    value: influx_stay,
+                };
+                let sector = Sector::Interior {
+                    octant,
+                    column: u as u32,
+                };
+                if three_tap {
+                    let lateral = Influx {
+                        dx: m_xv,
+                        dy: m_yv,
+                        weight: l,
+                        value: &*curr.offset(-v_stride),
+                    };
+                    let influxes = [jump, stay, lateral];
+                    (self.update_fn)(x, y, sector, &mut *curr, Influxes::Three(&influxes));
+                } else {
+                    (self.update_fn)(x, y, sector, &mut *curr, Influxes::Two(&[jump, stay]));
+                }
+                influx_jump = influx_stay;
             }
         }
     }
@@ -375,39 +1867,349 @@ where
 mod tests {
 
     use super::*;
-    use std::io::{self, Write};
+    use crate::testing::{all_params, four_connected, three_tap};
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::rc::Rc;
 
-    fn coordinate_flag(sz: i32) -> String {
-        let flux_field = Box::new(FluxField::new(sz as usize));
-        let mut fov = Fov::new(flux_field, sz as usize, (-1, -1));
-        fov.update(|x, y, _| (x, y));
-        let mut w = io::Cursor::new(Vec::new());
-        let mut ix = 0;
-        for y in -sz..sz + 1 {
-            if y > -sz {
-                write!(w, "; ").unwrap();
+    // An allocator that counts the allocations made by the current thread.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn count_allocations<F: FnOnce()>(f: F) -> usize {
+        let before = ALLOCATIONS.with(Cell::get);
+        f();
+        ALLOCATIONS.with(Cell::get) - before
+    }
+
+    #[test]
+    fn update_diff_reports_changed_cells() {
+        let flux_field = Box::new(FluxField::new(2));
+        let mut fov = Fov::new(flux_field, 2, 0);
+        let changed = fov.update_diff(|x, y, _| if x == 1 && y >= 0 { 1 } else { 0 });
+        assert_eq!(changed, vec![(1, 0), (1, 1), (1, 2)]);
+        assert!(fov
+            .update_diff(|x, y, _| if x == 1 && y >= 0 { 1 } else { 0 })
+            .is_empty());
+        let changed = fov.update_diff(|x, y, _| if x == 1 && y > 0 { 1 } else { 0 });
+        assert_eq!(changed, vec![(1, 0)]);
+    }
+
+    #[test]
+    fn update_notify_reports_visibility_changes() {
+        let flux_field = Box::new(FluxField::new(3));
+        let mut fov = Fov::new(flux_field, 3, 0.0);
+        let mut events = Vec::new();
+        fov.update_notify(
+            |&v| v > 0.5,
+            |x, y, visible| events.push((x, y, visible)),
+            |x, _, _| if x == 2 { 1.0 } else { 0.25 },
+        );
+        assert_eq!(events.len(), 7);
+        assert!(events.iter().all(|&(x, _, visible)| x == 2 && visible));
+        events.clear();
+        fov.update_notify(
+            |&v| v > 0.5,
+            |x, y, visible| events.push((x, y, visible)),
+            |x, y, _| if x == 2 && y != 0 { 0.75 } else { 0.0 },
+        );
+        assert_eq!(events, vec![(2, 0, false)]);
+    }
+
+    #[test]
+    fn influx_directions_point_from_source() {
+        let flux_field = Box::new(FluxField::new(4));
+        let mut fov = Fov::new(flux_field, 4, (0, 0));
+        fov.update(|x, y, influxes| {
+            for f in influxes {
+                assert_eq!(*f.value, (x - f.dx, y - f.dy));
             }
-            for x in -sz..sz + 1 {
-                if x > -sz {
-                    write!(w, "  ").unwrap();
+            (x, y)
+        });
+    }
+
+    #[test]
+    fn grow_flux_field() {
+        let mut flux_field = FluxField::new(1);
+        flux_field.grow_to(5);
+        assert_eq!(&*flux_field.flux_lut, &*FluxField::new(5).flux_lut);
+        flux_field.grow_to(9);
+        assert_eq!(flux_field.radius(), 9);
+        assert_eq!(flux_field.flux_lut.len(), lut_len(9));
+        assert_eq!(&flux_field.flux_lut[..10], &FluxField::new(5).flux_lut[..]);
+        assert_eq!(
+            &flux_field.flux_lut[10..],
+            &FluxField::new(9).flux_lut[10..]
+        );
+        flux_field.grow_to(3);
+        assert_eq!(flux_field.radius(), 9);
+    }
+
+    #[test]
+    fn march_ray_jumps_ahead() {
+        for &(target_x, target_y, phase) in
+            &[(700, 0, 350), (700, 700, 350), (700, 213, 350), (97, 41, 5)]
+        {
+            let mut full = Vec::new();
+            march_ray(0, 30, target_x, target_y, phase, |x, y, c| {
+                full.push((x, y, c))
+            });
+            assert_eq!(full.len(), 31);
+            for start_x in 0..31 {
+                let mut part = Vec::new();
+                march_ray(start_x, 30, target_x, target_y, phase, |x, y, c| {
+                    part.push((x, y, c))
+                });
+                assert_eq!(&part[..], &full[start_x..]);
+            }
+        }
+    }
+
+    #[test]
+    fn ray_fan_counts_match_marching() {
+        let (radius, ray_count) = (12, 300);
+        let ray_radius = 100 * radius;
+        let d = FluxParams::default();
+        let params = [
+            d,
+            FluxParams {
+                three_tap: true,
+                ..d
+            },
+            FluxParams {
+                connectivity: Connectivity::Four,
+                ..d
+            },
+            FluxParams {
+                supersampling: 3,
+                three_tap: true,
+                distribution: RayDistribution::Cosine,
+                ..d
+            },
+        ];
+        for params in &params {
+            for first_x in 2..6 {
+                // Every ray of the same fan marched one by one.
+                let counts_wd = radius - 1;
+                let mut marched = vec![RayCount::default(); counts_wd * counts_wd];
+                let samples = params.supersampling;
+                let (step_sin, step_cos) =
+                    sin_cos(std::f64::consts::FRAC_PI_4 / (ray_count - 1) as f64);
+                let (mut c, mut s) = (1.0_f64, 0.0_f64);
+                for _ in 0..ray_count {
+                    let target_x = (c * ray_radius as f64 + 0.5) as usize;
+                    let target_y = (s * ray_radius as f64 + 0.5) as usize;
+                    let ray_weight = params.distribution.weight_towards(c as f32, s as f32) as f64
+                        / samples as f64;
+                    for sample in 0..samples {
+                        let phase = (2 * sample + 1) * target_x / (2 * samples);
+                        let mut last_y = 0;
+                        march_ray(
+                            first_x - 1,
+                            radius,
+                            target_x,
+                            target_y,
+                            phase,
+                            |x, y, crossing| {
+                                if first_x <= x && 0 < y && y < x {
+                                    let count = &mut marched[(y - 1) * counts_wd + x - 2];
+                                    count.total += ray_weight;
+                                    if last_y != y {
+                                        count.add_row_change(params, crossing, ray_weight);
+                                    }
+                                }
+                                last_y = y;
+                            },
+                        );
+                    }
+                    let next_c = c * step_cos - s * step_sin;
+                    s = s * step_cos + c * step_sin;
+                    c = next_c;
                 }
-                let p = fov.as_slice()[ix];
-                write!(w, "{:+}{:+}", p.0, p.1).unwrap();
-                ix += 1;
+                let counted = count_ray_fan(first_x, radius, ray_radius, ray_count, params);
+                let mut ix = 0;
+                for x in first_x..radius + 1 {
+                    for y in 1..x {
+                        let (a, b) = (&counted[ix], &marched[(y - 1) * counts_wd + x - 2]);
+                        for &(a, b) in
+                            &[(a.total, b.total), (a.jump, b.jump), (a.lateral, b.lateral)]
+                        {
+                            assert!(
+                                (a - b).abs() <= 1e-9 * b.max(1.0),
+                                "{:?} from {} at ({}, {}): {} != {}",
+                                params,
+                                first_x,
+                                x,
+                                y,
+                                a,
+                                b
+                            );
+                        }
+                        ix += 1;
+                    }
+                }
+                assert_eq!(ix, counted.len(), "{:?} from {}", params, first_x);
             }
         }
-        String::from_utf8(w.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn normalization() {
+        assert!(FluxField::new(9).verify_normalization());
+        let lut = vec![1.5, -0.25, f32::NAN];
+        let mut flux_field = FluxField::from_lut(3, Lut::Owned(lut));
+        assert!(!flux_field.verify_normalization());
+        assert_eq!(flux_field.normalize(), 3);
+        assert!(flux_field.verify_normalization());
+        assert_eq!(&*flux_field.flux_lut, &[1.0, 0.0, 0.5]);
+        assert_eq!(flux_field.normalize(), 0);
+    }
+
+    #[test]
+    fn ray_distributions() {
+        let uniform = FluxField::new(8);
+        let params = FluxParams {
+            distribution: RayDistribution::AreaCorrected,
+            ..Default::default()
+        };
+        let corrected = FluxField::with_params(8, params);
+        assert_eq!(*corrected.params(), params);
+        assert!(corrected.verify_normalization());
+        assert_ne!(&*uniform.flux_lut, &*corrected.flux_lut);
+        let mut grown = FluxField::with_params(1, params);
+        grown.grow_to(8);
+        assert_eq!(&*grown.flux_lut, &*corrected.flux_lut);
+    }
+
+    #[test]
+    fn supersampling() {
+        let params = FluxParams {
+            supersampling: 4,
+            ..Default::default()
+        };
+        let plain = FluxField::new(8);
+        let soft = FluxField::with_params(8, params);
+        assert!(soft.verify_normalization());
+        assert_ne!(&*plain.flux_lut, &*soft.flux_lut);
+        let single = FluxParams {
+            supersampling: 1,
+            ..params
+        };
+        assert_eq!(
+            &*FluxField::with_params(8, single).flux_lut,
+            &*plain.flux_lut
+        );
+    }
+
+    #[test]
+    fn three_tap_influxes() {
+        let params = three_tap();
+        let flux_field = FluxField::with_params(8, params);
+        assert!(flux_field.is_three_tap());
+        assert!(!FluxField::new(8).is_three_tap());
+        assert!(flux_field.verify_normalization());
+        assert!(flux_field.lateral_lut.iter().any(|&l| l > 0.0));
+        let mut fov = Fov::new(Box::new(flux_field), 8, (0, 0));
+        fov.update(|x, y, influxes| {
+            if x.abs() > 1 && y.abs() > 0 && x.abs() != y.abs() && x.abs() > y.abs() {
+                assert_eq!(influxes.len(), 3);
+                let sum: f32 = influxes.iter().map(|f| f.weight).sum();
+                assert!((sum - 1.0).abs() < 1e-6);
+            }
+            for f in influxes {
+                assert_eq!(*f.value, (x - f.dx, y - f.dy));
+            }
+            (x, y)
+        });
+    }
+
+    #[test]
+    fn four_connected_influxes() {
+        let params = four_connected();
+        let flux_field = Rc::new(FluxField::with_params(8, params));
+        assert!(flux_field.is_four_connected());
+        assert!(!flux_field.is_three_tap());
+        assert!(flux_field.verify_normalization());
+        let mut fov = Fov::new(flux_field.clone(), 8, (0, 0));
+        let mut count = 0;
+        fov.update(|x, y, influxes| {
+            count += 1;
+            let sum: f32 = influxes.iter().map(|f| f.weight).sum();
+            assert!(influxes.is_empty() || (sum - 1.0).abs() < 1e-6);
+            for f in influxes {
+                assert_eq!(f.dx.abs() + f.dy.abs(), 1);
+                assert_eq!(*f.value, (x - f.dx, y - f.dy));
+            }
+            (x, y)
+        });
+        assert_eq!(count, 17 * 17);
+        // Two orthogonal walls block the diagonal between them.
+        let walls = [(1, 0), (0, 1)];
+        let mut fov = Fov::new(flux_field, 8, kernel::Visibility::default());
+        fov.update(kernel::symmetric(|x, y| walls.contains(&(x, y))));
+        assert!(!fov.at(1, 1).is_visible);
+        assert!(!fov.at(3, 2).is_visible);
+        assert!(fov.at(1, -1).is_visible);
+    }
+
+    #[test]
+    fn sectors_match_coordinates() {
+        let flux_field = Box::new(FluxField::new(4));
+        let mut fov = Fov::new(flux_field, 4, None);
+        fov.update_with_sector(|x, y, sector, _| {
+            match sector {
+                Sector::Origin => assert_eq!((x, y), (0, 0)),
+                Sector::Edge { index, column } => {
+                    let (dx, dy) = crate::sweep::EDGES[index as usize];
+                    assert_eq!((x, y), (dx * column as i32, dy * column as i32));
+                }
+                Sector::Interior { octant, column } => {
+                    assert_eq!(x.abs().max(y.abs()), column as i32);
+                    assert!(x != 0 && y != 0 && x.abs() != y.abs());
+                    let angle = (y as f32).atan2(x as f32).rem_euclid(2.0 * f32::consts::PI);
+                    assert_eq!((angle / f32::consts::FRAC_PI_4) as u8, octant);
+                }
+            }
+            Some(sector)
+        });
+        assert_eq!(
+            *fov.at(-3, -2),
+            Some(Sector::Interior {
+                octant: 4,
+                column: 3
+            })
+        );
     }
 
     #[test]
     fn test_1() {
-        assert_eq!(coordinate_flag(0), "+0+0");
+        assert_eq!(
+            testing::coordinate_flag(0, &testing::FlagFormat::INLINE),
+            "+0+0"
+        );
     }
 
     #[test]
     fn small_coordinate_flag() {
         assert_eq!(
-            coordinate_flag(1),
+            testing::coordinate_flag(1, &testing::FlagFormat::INLINE),
             "-1-1  +0-1  +1-1; \
              -1+0  +0+0  +1+0; \
              -1+1  +0+1  +1+1"
@@ -417,7 +2219,7 @@ mod tests {
     #[test]
     fn big_coordinate_flag() {
         assert_eq!(
-            coordinate_flag(5),
+            testing::coordinate_flag(5, &testing::FlagFormat::INLINE),
             "-5-5  -4-5  -3-5  -2-5  -1-5  +0-5  +1-5  +2-5  +3-5  +4-5  +5-5; \
              -5-4  -4-4  -3-4  -2-4  -1-4  +0-4  +1-4  +2-4  +3-4  +4-4  +5-4; \
              -5-3  -4-3  -3-3  -2-3  -1-3  +0-3  +1-3  +2-3  +3-3  +4-3  +5-3; \
@@ -432,41 +2234,30 @@ mod tests {
         );
     }
 
-    fn flux_connection_flag(sz: i32) -> String {
-        let flux_field = Box::new(FluxField::new(sz as usize));
-        let mut fov = Fov::new(flux_field, sz as usize, -1);
-        fov.update(|x, y, influxes| {
-            if x == 0 && y == 0 {
-                1_i32
-            } else {
-                influxes.iter().map(|f| *f.value).sum()
-            }
-        });
-        let mut w = io::Cursor::new(Vec::new());
-        let mut ix = 0;
-        for _ in -sz..sz + 1 {
-            write!(w, "[  ").unwrap();
-            for x in -sz..sz + 1 {
-                if x > -sz {
-                    write!(w, "  ").unwrap();
-                }
-                write!(w, "{:2}", fov.as_slice()[ix]).unwrap();
-                ix += 1;
-            }
-            write!(w, "  ] ").unwrap();
-        }
-        String::from_utf8(w.into_inner()).unwrap()
-    }
-
     #[test]
     fn null_connection_flag() {
-        assert_eq!(flux_connection_flag(1), "[   1  ] ");
+        assert_eq!(
+            testing::flux_connection_flag(
+                0,
+                &testing::FlagFormat {
+                    width: 2,
+                    ..Default::default()
+                }
+            ),
+            "[   1  ] "
+        );
     }
 
     #[test]
     fn small_connection_flag() {
         assert_eq!(
-            flux_connection_flag(1),
+            testing::flux_connection_flag(
+                1,
+                &testing::FlagFormat {
+                    width: 2,
+                    ..Default::default()
+                }
+            ),
             "[   1   1   1  ] \
              [   1   1   1  ] \
              [   1   1   1  ] "
@@ -476,7 +2267,13 @@ mod tests {
     #[test]
     fn big_connection_flag() {
         assert_eq!(
-            flux_connection_flag(5),
+            testing::flux_connection_flag(
+                5,
+                &testing::FlagFormat {
+                    width: 2,
+                    ..Default::default()
+                }
+            ),
             "[   1   5  10  10   5   1   5  10  10   5   1  ] \
              [   5   1   4   6   4   1   4   6   4   1   5  ] \
              [  10   4   1   3   3   1   3   3   1   4  10  ] \
@@ -491,30 +2288,10 @@ mod tests {
         );
     }
 
-    fn weight_flag(sz: i32) -> String {
-        let flux_field = Box::new(FluxField::new(sz as usize));
-        let mut fov = Fov::new(flux_field, sz as usize, -1.0);
-        fov.update(|_, _, influxes| influxes.iter().map(|f| f.weight).sum());
-        let mut w = io::Cursor::new(Vec::new());
-        let mut ix = 0;
-        for _ in -sz..sz + 1 {
-            write!(w, "[  ").unwrap();
-            for x in -sz..sz + 1 {
-                if x > -sz {
-                    write!(w, "  ").unwrap();
-                }
-                write!(w, "{:.3}", fov.as_slice()[ix]).unwrap();
-                ix += 1;
-            }
-            write!(w, "  ] ").unwrap();
-        }
-        String::from_utf8(w.into_inner()).unwrap()
-    }
-
     #[test]
     fn small_weight_flag() {
         assert_eq!(
-            weight_flag(1),
+            testing::weight_flag(1, &Default::default()),
             "[  1.000  1.000  1.000  ] \
              [  1.000  0.000  1.000  ] \
              [  1.000  1.000  1.000  ] "
@@ -524,7 +2301,7 @@ mod tests {
     #[test]
     fn big_weight_flag() {
         assert_eq!(
-            weight_flag(5),
+            testing::weight_flag(5, &Default::default()),
             "[  1.000  1.000  1.000  1.000  1.000  1.000  1.000  1.000  1.000  1.000  1.000  ] \
              [  1.000  1.000  1.000  1.000  1.000  1.000  1.000  1.000  1.000  1.000  1.000  ] \
              [  1.000  1.000  1.000  1.000  1.000  1.000  1.000  1.000  1.000  1.000  1.000  ] \
@@ -538,4 +2315,349 @@ mod tests {
              [  1.000  1.000  1.000  1.000  1.000  1.000  1.000  1.000  1.000  1.000  1.000  ] "
         );
     }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn tracing_spans() {
+        use std::sync::{Arc, Mutex};
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Metadata, Subscriber};
+
+        // A subscriber recording the names and the radii of the new spans.
+        #[derive(Clone, Default)]
+        struct Spans(Arc<Mutex<Vec<(&'static str, u64)>>>);
+
+        struct Radius(u64);
+
+        impl Visit for Radius {
+            fn record_u64(&mut self, field: &Field, value: u64) {
+                if field.name() == "radius" {
+                    self.0 = value;
+                }
+            }
+
+            fn record_i64(&mut self, field: &Field, value: i64) {
+                self.record_u64(field, value as u64);
+            }
+
+            fn record_debug(&mut self, _: &Field, _: &dyn std::fmt::Debug) {}
+        }
+
+        impl Subscriber for Spans {
+            fn enabled(&self, _: &Metadata) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &Attributes) -> Id {
+                let mut radius = Radius(0);
+                span.record(&mut radius);
+                let mut spans = self.0.lock().unwrap();
+                spans.push((span.metadata().name(), radius.0));
+                Id::from_u64(spans.len() as u64)
+            }
+
+            fn record(&self, _: &Id, _: &Record) {}
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, _: &Event) {}
+            fn enter(&self, _: &Id) {}
+            fn exit(&self, _: &Id) {}
+        }
+
+        let spans = Spans::default();
+        tracing::subscriber::with_default(spans.clone(), || {
+            let mut flux_field = FluxField::new(3);
+            flux_field.grow_to(5);
+            let mut fov = Fov::new(Box::new(flux_field), 4, 0);
+            fov.update(|_, _, _| 1);
+        });
+        assert_eq!(
+            *spans.0.lock().unwrap(),
+            vec![
+                ("flux_field_build", 3),
+                ("flux_field_grow", 5),
+                ("fov_update", 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn default_construction_and_reset() {
+        let mut fov = Fov::<kernel::Visibility, _>::with_default(Box::new(FluxField::new(3)), 3);
+        assert_eq!(*fov.at(2, 1), kernel::Visibility::default());
+        fov.update(kernel::threshold(0.5, |_, _| 0.0));
+        assert!(fov.at(3, 1).is_visible);
+        fov.reset_to_default();
+        assert!(fov.as_slice().iter().all(|v| *v == Default::default()));
+    }
+
+    // An allocator that counts its live allocations.
+    #[cfg(feature = "allocator-api")]
+    #[derive(Default)]
+    struct Arena {
+        live: Cell<usize>,
+    }
+
+    #[cfg(feature = "allocator-api")]
+    unsafe impl Allocator for &Arena {
+        fn allocate(
+            &self,
+            layout: Layout,
+        ) -> Result<std::ptr::NonNull<[u8]>, allocator_api2::alloc::AllocError> {
+            self.live.set(self.live.get() + 1);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: std::ptr::NonNull<u8>, layout: Layout) {
+            self.live.set(self.live.get() - 1);
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "allocator-api")]
+    fn new_in_allocates_with_the_given_allocator() {
+        let arena = Arena::default();
+        let mut fov = Fov::new_in(Box::new(FluxField::new(3)), 3, 0.0f32, &arena);
+        assert_eq!(arena.live.get(), 1);
+        fov.update(|_, _, influxes| {
+            if influxes.is_empty() {
+                1.0
+            } else {
+                influxes.iter().map(|i| i.weight * *i.value).sum()
+            }
+        });
+        let mut reference = Fov::new(Box::new(FluxField::new(3)), 3, 0.0f32);
+        reference.update(|_, _, influxes| {
+            if influxes.is_empty() {
+                1.0
+            } else {
+                influxes.iter().map(|i| i.weight * *i.value).sum()
+            }
+        });
+        assert_eq!(fov.as_slice(), reference.as_slice());
+        drop(fov);
+        assert_eq!(arena.live.get(), 0);
+    }
+
+    #[test]
+    #[cfg_attr(feature = "debug-checks", ignore = "the debug checks allocate")]
+    fn update_does_not_allocate() {
+        for params in all_params() {
+            let mut fov = Fov::new(Box::new(FluxField::with_params(8, params)), 8, 0.0f32);
+            let allocations = count_allocations(|| {
+                fov.update(|x, y, influxes| {
+                    if x == 0 && y == 0 {
+                        1.0
+                    } else {
+                        influxes.iter().map(|f| f.weight * f.value).sum()
+                    }
+                });
+                fov.update_with_sector(|_, _, _, influxes| influxes.iter().map(|f| *f.value).sum());
+            });
+            assert_eq!(allocations, 0);
+        }
+    }
+
+    #[test]
+    fn update_dyn_matches_update() {
+        let flux_field = Rc::new(FluxField::new(6));
+        let mut expected = Fov::new(flux_field.clone(), 6, 0.0f32);
+        let mut actual = Fov::new(flux_field, 6, 0.0f32);
+        let mut f = |x: i32, y: i32, influxes: &[Influx<&f32>]| {
+            if x == 0 && y == 0 {
+                1.0
+            } else if x == 2 && y > 0 {
+                0.0
+            } else {
+                influxes.iter().map(|f| f.weight * f.value).sum()
+            }
+        };
+        expected.update(&mut f);
+        actual.update_dyn(&mut f);
+        assert_eq!(actual.as_slice(), expected.as_slice());
+    }
+
+    #[test]
+    fn update_with_previous_value() {
+        let mut fov = Fov::new(Box::new(FluxField::new(3)), 3, 0);
+        for _ in 0..3 {
+            fov.update_with_previous(|x, y, previous, influxes| {
+                if x == 0 && y == 0 {
+                    previous + 1
+                } else {
+                    previous + influxes.len() as i32
+                }
+            });
+        }
+        assert_eq!(*fov.at(0, 0), 3);
+        assert_eq!(*fov.at(3, 0), 3);
+        assert_eq!(*fov.at(3, 1), 6);
+    }
+
+    #[test]
+    fn update_in_place_matches_update_with_previous() {
+        let flux_field = Rc::new(FluxField::new(5));
+        let mut expected = Fov::new(flux_field.clone(), 5, 0.0f32);
+        let mut actual = Fov::new(flux_field, 5, 0.0f32);
+        for _ in 0..3 {
+            expected.update_with_previous(|x, y, previous, influxes| {
+                let light = if x == 0 && y == 0 {
+                    1.0
+                } else {
+                    influxes.iter().map(|f| f.weight * f.value).sum()
+                };
+                0.5 * previous + light
+            });
+            actual.update_in_place(|x, y, cell, influxes| {
+                let light = if x == 0 && y == 0 {
+                    1.0
+                } else {
+                    influxes.iter().map(|f| f.weight * f.value).sum()
+                };
+                *cell = 0.5 * *cell + light;
+            });
+        }
+        assert_eq!(actual.as_slice(), expected.as_slice());
+    }
+
+    #[test]
+    fn into_inner_and_from_parts() {
+        let flux_field = Rc::new(FluxField::new(2));
+        let mut fov = Fov::new(flux_field.clone(), 2, 0);
+        fov.update(|x, y, _| x + 10 * y);
+        let data = fov.into_inner();
+        let ptr = data.as_ptr();
+        let fov = Fov::from_parts(flux_field, 2, data);
+        assert_eq!(fov.as_slice().as_ptr(), ptr);
+        assert_eq!(*fov.at(1, -2), -19);
+    }
+
+    #[test]
+    #[should_panic(expected = "data length mismatch")]
+    fn from_parts_length_mismatch() {
+        Fov::from_parts(Box::new(FluxField::new(2)), 2, vec![0; 24]);
+    }
+
+    #[test]
+    fn borrowed_weights() {
+        static WEIGHTS: [f32; 3] = [0.5, 0.25, 0.75];
+        let flux_field = FluxField::from_weights(3, Cow::Borrowed(&WEIGHTS)).unwrap();
+        assert_eq!(flux_field.flux_lut.as_ptr(), WEIGHTS.as_ptr());
+        let copy = FluxField::from_weights(3, Cow::Owned(WEIGHTS.to_vec())).unwrap();
+        assert_eq!(&*copy.flux_lut, &WEIGHTS[..]);
+        assert!(FluxField::from_weights(4, Cow::Borrowed(&WEIGHTS)).is_err());
+        assert!(FluxField::from_weights(3, Cow::Owned(vec![0.5, 1.5, 0.5])).is_err());
+    }
+
+    #[test]
+    fn update_option_keeps_cells() {
+        let mut fov = Fov::new(Box::new(FluxField::new(2)), 2, String::from("old"));
+        fov.update_option(|x, _, _| if x < 0 { None } else { Some(x.to_string()) });
+        assert_eq!(fov.at(-1, 2), "old");
+        assert_eq!(fov.at(2, -1), "2");
+    }
+
+    #[test]
+    fn fallible_construction_and_access() {
+        assert_eq!(
+            FluxField::try_new(MAX_RADIUS + 1).err(),
+            Some(Error::RadiusOutOfRange {
+                radius: MAX_RADIUS + 1
+            })
+        );
+        let flux_field = std::rc::Rc::new(FluxField::try_new(3).unwrap());
+        assert_eq!(
+            Fov::try_new(flux_field.clone(), 4, 0).err(),
+            Some(Error::RadiusTooLarge { radius: 4, max: 3 })
+        );
+        let mut fov = Fov::try_new(flux_field, 2, 0).unwrap();
+        fov.update(|x, y, _| 10 * x + y);
+        assert_eq!(fov.get(-2, 1), Some(&-19));
+        assert_eq!(fov.get(3, 0), None);
+        assert_eq!(fov.get(0, -3), None);
+        assert_eq!(fov.get(i32::MIN, 0), None);
+    }
+
+    #[test]
+    fn fallible_construction_of_small_fields() {
+        for radius in 0..2 {
+            let flux_field = FluxField::try_new(radius).unwrap();
+            assert!(flux_field.flux_lut.is_empty());
+            let mut fov = Fov::try_new(Box::new(flux_field), radius, 0).unwrap();
+            fov.update(|_, _, influxes| influxes.len());
+            assert_eq!(fov.get(0, 0), Some(&0));
+            assert_eq!(fov.get(radius as i32, 0), Some(&radius));
+            assert_eq!(fov.get(radius as i32 + 1, 0), None);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "cell out of range")]
+    fn at_out_of_range() {
+        let fov = Fov::new(Box::new(FluxField::new(2)), 2, 0);
+        fov.at(0, 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "radius out of range")]
+    fn grow_to_out_of_range() {
+        FluxField::new(2).grow_to(MAX_RADIUS + 1);
+    }
+
+    #[test]
+    fn iterators_follow_as_slice() {
+        let mut fov = Fov::new(Box::new(FluxField::new(3)), 3, (0, 0));
+        fov.update(|x, y, _| (x, y));
+        assert_eq!(fov.iter().len(), 49);
+        assert!(fov.iter().all(|(x, y, &value)| (x, y) == value));
+        assert!(fov.iter().map(|(_, _, v)| v).eq(fov.as_slice().iter()));
+        for (x, y, value) in fov.iter_mut() {
+            *value = (y, x);
+        }
+        *fov.at_mut(1, -2) = (7, 7);
+        assert_eq!(*fov.at(1, -2), (7, 7));
+        assert_eq!(*fov.at(2, -1), (-1, 2));
+        assert!(fov.get_mut(4, 0).is_none());
+    }
+
+    #[test]
+    fn rings() {
+        let mut fov = Fov::new(Box::new(FluxField::new(3)), 3, (0, 0));
+        fov.update(|x, y, _| (x, y));
+        let ring = |r| {
+            fov.enumerate_ring(r)
+                .map(|(x, y, &value)| {
+                    assert_eq!((x, y), value);
+                    value
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ring(0), [(0, 0)]);
+        assert_eq!(
+            ring(1),
+            [
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1)
+            ]
+        );
+        for r in 1..4 {
+            let cells = ring(r);
+            assert_eq!(cells.len(), 8 * r);
+            assert!(cells.iter().all(|&(x, y)| x.abs().max(y.abs()) == r as i32));
+            // In the order of `iter`.
+            let ordered: Vec<_> = fov
+                .iter()
+                .filter(|&(x, y, _)| x.abs().max(y.abs()) == r as i32)
+                .map(|(x, y, _)| (x, y))
+                .collect();
+            assert_eq!(cells, ordered);
+        }
+    }
 }
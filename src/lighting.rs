@@ -0,0 +1,229 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::collections::HashMap;
+
+use crate::kernel::{self, Illumination};
+use crate::{Attenuation, Falloff, FluxField, Fov};
+
+/// A colored point light; see `LightMap::update`.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightSource {
+    /// The tile of the source on the map.
+    pub x: i32,
+    pub y: i32,
+    /// The radius of the light in cells.
+    pub radius: usize,
+    /// The brightness at the source.
+    pub intensity: f32,
+    pub falloff: Falloff,
+    /// The color of the light as linear RGB.
+    pub color: [f32; 3],
+}
+
+/// A map-sized grid of colored light composited from any number of light
+/// sources.
+///
+/// Each source is propagated with the soft shadows of
+/// `kernel::soft_shadow` so the light bends a little around the corners and
+/// casts penumbras.  The lights add up on top of the ambient light:
+///
+/// ```
+/// # use flux_fov::{Falloff, LightMap, LightSource};
+/// let torch = LightSource {
+///     x: 3,
+///     y: 4,
+///     radius: 6,
+///     intensity: 1.0,
+///     falloff: Falloff::Linear,
+///     color: [1.0, 0.8, 0.5],
+/// };
+/// let mut lights = LightMap::new(20, 10, [0.05; 3]);
+/// lights.update(&[torch], |x, _| (x == 6) as u8 as f32);
+/// assert_eq!(lights.light_at(3, 4), [1.05, 0.85, 0.55]);
+/// assert_eq!(lights.light_at(8, 4), [0.05; 3]);
+/// ```
+///
+pub struct LightMap {
+    width: usize,
+    height: usize,
+    ambient: [f32; 3],
+    light: Vec<[f32; 3]>,
+    // A scratch field per light radius.
+    fields: HashMap<usize, Fov<Illumination, Box<FluxField>>>,
+}
+
+impl LightMap {
+    /// Constructs a `width` by `height` map lit uniformly by `ambient`.
+    ///
+    pub fn new(width: usize, height: usize, ambient: [f32; 3]) -> Self {
+        LightMap {
+            width,
+            height,
+            ambient,
+            light: vec![ambient; width * height],
+            fields: HashMap::new(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn ambient(&self) -> [f32; 3] {
+        self.ambient
+    }
+
+    pub fn set_ambient(&mut self, ambient: [f32; 3]) {
+        self.ambient = ambient;
+    }
+
+    /// The light at the given tile.
+    pub fn light_at(&self, x: usize, y: usize) -> [f32; 3] {
+        assert!(x < self.width && y < self.height, "tile out of range");
+        self.light[y * self.width + x]
+    }
+
+    /// Expose the light of the tiles as a slice in row-major order.
+    pub fn as_slice(&self) -> &[[f32; 3]] {
+        self.light.as_slice()
+    }
+
+    /// Relight the map with the given sources.
+    ///
+    /// The `opacity` of the tiles is given in map coordinates and ranges from
+    /// 0.0 (transparent) to 1.0 (opaque) as for `kernel::soft_shadow`; the
+    /// tiles off the map are opaque.  A source contributes to a tile its
+    /// color times its intensity, the brightness of the soft-shadowed flux
+    /// reaching the tile, and the falloff at the Euclidean distance of the
+    /// tile.
+    ///
+    pub fn update<O>(&mut self, sources: &[LightSource], opacity: O)
+    where
+        O: Fn(i32, i32) -> f32,
+    {
+        let (width, height) = (self.width as i32, self.height as i32);
+        for value in self.light.iter_mut() {
+            *value = self.ambient;
+        }
+        for source in sources {
+            let field = self.fields.entry(source.radius).or_insert_with(|| {
+                Fov::new(
+                    Box::new(FluxField::new(source.radius)),
+                    source.radius,
+                    Illumination::default(),
+                )
+            });
+            field.update(kernel::soft_shadow(|dx, dy| {
+                let (x, y) = (source.x + dx, source.y + dy);
+                if x < 0 || y < 0 || x >= width || y >= height {
+                    1.0
+                } else {
+                    opacity(x, y)
+                }
+            }));
+            let radius = source.radius as i32;
+            for dy in -radius..radius + 1 {
+                let y = source.y + dy;
+                if y < 0 || y >= height {
+                    continue;
+                }
+                for dx in -radius..radius + 1 {
+                    let x = source.x + dx;
+                    if x < 0 || x >= width {
+                        continue;
+                    }
+                    let brightness = source.intensity
+                        * field.at(dx, dy).brightness
+                        * source.falloff.at_cell(dx, dy, source.radius as f32);
+                    let value = &mut self.light[(y * width + x) as usize];
+                    for (channel, &color) in value.iter_mut().zip(source.color.iter()) {
+                        *channel += brightness * color;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn light(x: i32, y: i32, falloff: Falloff, color: [f32; 3]) -> LightSource {
+        LightSource {
+            x,
+            y,
+            radius: 8,
+            intensity: 1.0,
+            falloff,
+            color,
+        }
+    }
+
+    #[test]
+    fn lights_composite_and_respect_walls() {
+        // A wall splits the room at x = 10 save for a door at y = 0.
+        let wall = |x: i32, y: i32| (x == 10 && y != 0) as u8 as f32;
+        let mut lights = LightMap::new(21, 9, [0.0; 3]);
+        let red = light(5, 4, Falloff::Constant, [1.0, 0.0, 0.0]);
+        let blue = light(15, 4, Falloff::Linear, [0.0, 0.0, 1.0]);
+        lights.update(&[red, blue], wall);
+        assert_eq!(lights.light_at(5, 4), [1.0, 0.0, 0.0]);
+        assert_eq!(lights.light_at(15, 4), [0.0, 0.0, 1.0]);
+        assert!(lights.light_at(7, 4)[0] > 0.95);
+        assert_eq!(lights.light_at(7, 4)[2], 0.0);
+        // The blue light dims with the distance.
+        let blue_at = |x| lights.light_at(x, 4)[2];
+        assert!(blue_at(14) > blue_at(13) && blue_at(13) > blue_at(12));
+        // Some light leaks through the door.
+        assert!(lights.light_at(9, 0)[2] > 0.0);
+        // Relighting starts from the ambient light.
+        lights.set_ambient([0.1; 3]);
+        lights.update(&[blue], wall);
+        assert_eq!(lights.light_at(5, 4), [0.1; 3]);
+    }
+
+    #[test]
+    fn falloff_curves() {
+        for falloff in [
+            Falloff::Constant,
+            Falloff::Linear,
+            Falloff::InverseSquare,
+            Falloff::Smooth,
+        ] {
+            assert_eq!(falloff.at(0.0, 5.0), 1.0);
+            assert!(falloff.at(3.0, 5.0) >= falloff.at(4.0, 5.0));
+        }
+        assert_eq!(Falloff::Linear.at(6.0, 5.0), 0.0);
+        assert_eq!(Falloff::Smooth.at(3.0, 5.0), 0.5);
+        assert_eq!(Falloff::InverseSquare.at(2.0, 5.0), 0.2);
+    }
+
+    #[test]
+    #[should_panic(expected = "tile out of range")]
+    fn light_at_out_of_range() {
+        // Within the map but past the right edge of the first row.
+        let lights = LightMap::new(4, 3, [0.0; 3]);
+        lights.light_at(4, 0);
+    }
+}
@@ -0,0 +1,89 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{FluxField, Fov, Influx};
+
+impl<X> Fov<f32, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Update the field with the linear kernel specialized for `f32`.
+    ///
+    /// The origin is set to 1.0 and every other cell to `transmittance(x,
+    /// y)` times the weighted sum of its influxes.  This is the kind of
+    /// field `subtract_occluder` expects.
+    ///
+    /// With the `fast-math` feature the weighted sums are computed with
+    /// fused multiply-adds.  The results then differ slightly from those of
+    /// the plain update and, depending on whether the target has hardware
+    /// FMA, from one target to another.  Enable FMA for the target, e.g.,
+    /// with `-C target-cpu=native`, or the fused multiply-adds are emulated
+    /// in software and the update gets slower rather than faster.  The
+    /// `deterministic` feature takes precedence and turns them off.
+    ///
+    pub fn update_linear<F>(&mut self, transmittance: F)
+    where
+        F: FnMut(i32, i32) -> f32,
+    {
+        let mut transmittance = transmittance;
+        self.update_cells(|x, y, _, cell, influxes| {
+            *cell = if influxes.is_empty() {
+                1.0
+            } else {
+                weighted_sum(influxes) * transmittance(x, y)
+            };
+        });
+    }
+}
+
+#[cfg(any(not(feature = "fast-math"), feature = "deterministic"))]
+#[inline]
+fn weighted_sum(influxes: &[Influx<&f32>]) -> f32 {
+    influxes.iter().map(|f| f.weight * f.value).sum()
+}
+
+#[cfg(all(feature = "fast-math", not(feature = "deterministic")))]
+#[inline]
+fn weighted_sum(influxes: &[Influx<&f32>]) -> f32 {
+    influxes
+        .iter()
+        .fold(0.0, |sum, f| f.weight.mul_add(*f.value, sum))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn linear_update_matches_generic() {
+        let flux_field = std::rc::Rc::new(FluxField::new(10));
+        let transmittance = |x: i32, y: i32| if (x * y) % 4 == 1 { 0.25 } else { 0.9 };
+        let mut generic = Fov::new(flux_field.clone(), 10, 0.0);
+        generic.update(|x, y, influxes| {
+            if x == 0 && y == 0 {
+                1.0
+            } else {
+                influxes.iter().map(|f| f.weight * f.value).sum::<f32>() * transmittance(x, y)
+            }
+        });
+        let mut linear = Fov::new(flux_field, 10, 0.0);
+        linear.update_linear(transmittance);
+        for (a, b) in linear.as_slice().iter().zip(generic.as_slice()) {
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+}
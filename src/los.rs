@@ -0,0 +1,176 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{lut_len, FluxField};
+
+impl FluxField {
+    /// The amount of rays from `origin` reaching `target` through the cells
+    /// of the given `opacity`, from 0.0 (blocked) to 1.0 (clear).
+    ///
+    /// The result is the brightness `kernel::soft_shadow` would give the
+    /// target in a field of vision centered at `origin`, bit for bit, and it
+    /// is compared against a threshold like in `kernel::threshold`.  Only the
+    /// cells within the rectangle spanned by the origin and the target are
+    /// evaluated, as the flux into the target comes from those alone, so a
+    /// single query costs a fraction of a full update.  The `opacity` of the
+    /// cells is in map coordinates and ranges from 0.0 (transparent) to 1.0
+    /// (opaque); that of the origin and the target doesn't matter.  The
+    /// targets beyond the radius of the flux field get 0.0.
+    ///
+    /// ```
+    /// # use flux_fov::FluxField;
+    /// let flux_field = FluxField::new(10);
+    /// let pillar = |x, y| ((x, y) == (7, 5)) as u8 as f32;
+    /// assert_eq!(flux_field.line_of_sight((5, 5), (5, 9), pillar), 1.0);
+    /// assert_eq!(flux_field.line_of_sight((5, 5), (9, 5), pillar), 0.0);
+    /// ```
+    ///
+    pub fn line_of_sight<O>(&self, origin: (i32, i32), target: (i32, i32), opacity: O) -> f32
+    where
+        O: Fn(i32, i32) -> f32,
+    {
+        self.line_of_sight_in(origin, target, opacity, &mut Vec::new())
+    }
+
+    // As `line_of_sight` but with the scratch space for the ray outputs of
+    // the cells passed in so that repeated queries don't allocate.
+    pub(crate) fn line_of_sight_in<O>(
+        &self,
+        origin: (i32, i32),
+        target: (i32, i32),
+        opacity: O,
+        scratch: &mut Vec<f32>,
+    ) -> f32
+    where
+        O: Fn(i32, i32) -> f32,
+    {
+        let (tx, ty) = (target.0 - origin.0, target.1 - origin.1);
+        if tx.abs().max(ty.abs()) as usize > self.radius {
+            return 0.0;
+        }
+        if (tx, ty) == (0, 0) {
+            return 1.0;
+        }
+        // Every source of a cell is at most as far from the origin along
+        // either axis as the cell itself, so the cells feeding the target
+        // are within the rectangle between the origin and the target.  We
+        // propagate the rays through the whole rectangle row by row outwards
+        // from the origin which puts the sources of each cell before it.
+        let (sx, sy) = (tx.signum(), ty.signum());
+        let (wd, ht) = (
+            tx.unsigned_abs() as usize + 1,
+            ty.unsigned_abs() as usize + 1,
+        );
+        scratch.clear();
+        scratch.resize(wd * ht, 0.0);
+        let index = |x: i32, y: i32| (y * sy) as usize * wd + (x * sx) as usize;
+        let mut ray_input = 1.0;
+        for j in 0..ht as i32 {
+            for i in 0..wd as i32 {
+                let (x, y) = (i * sx, j * sy);
+                scratch[index(x, y)] = if (x, y) == (0, 0) {
+                    1.0
+                } else {
+                    ray_input = self
+                        .taps(x, y)
+                        .iter()
+                        .flatten()
+                        .map(|&(ax, ay, weight)| weight * scratch[index(ax, ay)])
+                        .sum();
+                    ray_input * (1.0 - opacity(origin.0 + x, origin.1 + y))
+                };
+            }
+        }
+        // The target is the last cell of the rectangle.
+        ray_input
+    }
+
+    // The cells the cell (`x`, `y`) receives flux from and the weights of
+    // the influxes as in `Fov::update`.
+    fn taps(&self, x: i32, y: i32) -> [Option<(i32, i32, f32)>; 3] {
+        let (sx, sy) = (x.signum(), y.signum());
+        let (major, minor) = (x.abs().max(y.abs()), x.abs().min(y.abs()));
+        if major == 0 {
+            [None; 3]
+        } else if minor == 0 {
+            [Some((x - sx, y - sy, 1.0)), None, None]
+        } else if minor == major {
+            if self.is_four_connected() {
+                [Some((x - sx, y, 0.5)), Some((x, y - sy, 0.5)), None]
+            } else {
+                [Some((x - sx, y - sy, 1.0)), None, None]
+            }
+        } else {
+            let lut_ix = lut_len(major as usize - 1) + minor as usize - 1;
+            let l = self.lateral(lut_ix);
+            let (straight, lateral) = if x.abs() > y.abs() {
+                ((x - sx, y), (x, y - sy))
+            } else {
+                ((x, y - sy), (x - sx, y))
+            };
+            if self.is_four_connected() {
+                [
+                    Some((straight.0, straight.1, 1.0 - l)),
+                    Some((lateral.0, lateral.1, l)),
+                    None,
+                ]
+            } else {
+                let w = self.flux_lut[lut_ix];
+                [
+                    Some((x - sx, y - sy, w)),
+                    Some((straight.0, straight.1, 1.0 - w - l)),
+                    Some((lateral.0, lateral.1, l)).filter(|_| self.is_three_tap()),
+                ]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel::{self, Illumination};
+    use crate::testing::all_params;
+    use crate::Fov;
+
+    #[test]
+    fn line_of_sight_matches_update() {
+        let opacity = |x: i32, y: i32| match (x * 7 + y * 3).rem_euclid(11) {
+            0 => 1.0,
+            1 => 0.5,
+            _ => 0.0,
+        };
+        let origin = (20, 30);
+        for params in all_params() {
+            let flux_field = std::rc::Rc::new(FluxField::with_params(9, params));
+            let mut fov = Fov::new(flux_field.clone(), 9, Illumination::default());
+            fov.update(kernel::soft_shadow(|x, y| {
+                opacity(origin.0 + x, origin.1 + y)
+            }));
+            for y in -9..10 {
+                for x in -9..10 {
+                    let target = (origin.0 + x, origin.1 + y);
+                    let los = flux_field.line_of_sight(origin, target, opacity);
+                    // Exactly the same so that `Fov::update_symmetric` is.
+                    assert_eq!(los, fov.at(x, y).brightness, "({}, {})", x, y);
+                }
+            }
+            let beyond = (origin.0 + 10, origin.1);
+            assert_eq!(flux_field.line_of_sight(origin, beyond, |_, _| 0.0), 0.0);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use mlua::{Function, Lua, RegistryKey};
+
+use crate::allocator::Allocator;
+use crate::{FluxField, Fov, Influx};
+
+/// An update function written in Lua for prototyping kernels at runtime.
+///
+/// The script evaluates to the update function.  The function receives the
+/// coordinates of the cell followed by the weights and the values of its
+/// influxes as plain arguments, `x, y, w1, v1, w2, v2, w3, v3`, with `nil`
+/// for the missing ones, e.g., all of them at the origin.  The arguments are
+/// passed on the Lua stack, so no tables are built per cell.  The function
+/// returns the new value of the cell as a number.
+///
+/// ```
+/// # use flux_fov::{FluxField, Fov, LuaKernel};
+/// let kernel = LuaKernel::new(
+///     r#"
+///     return function(x, y, w1, v1, w2, v2, w3, v3)
+///         if w1 == nil then return 1.0 end
+///         local light = w1 * v1 + (w2 and w2 * v2 or 0) + (w3 and w3 * v3 or 0)
+///         if x == 2 then return 0.0 end
+///         return light * 0.9
+///     end
+///     "#,
+/// )
+/// .unwrap();
+/// let mut fov = Fov::new(Box::new(FluxField::new(4)), 4, 0.0_f32);
+/// fov.update_lua(&kernel).unwrap();
+/// assert_eq!(*fov.at(1, 0), 0.9);
+/// assert_eq!(*fov.at(3, 0), 0.0);
+/// ```
+///
+pub struct LuaKernel {
+    lua: Lua,
+    // The function lives in the registry of the state so that the kernel
+    // doesn't borrow the state.
+    function: RegistryKey,
+}
+
+impl LuaKernel {
+    /// Load the update function from the Lua `source`.
+    ///
+    pub fn new(source: &str) -> mlua::Result<Self> {
+        let lua = Lua::new();
+        let function = lua.load(source).eval::<Function>()?;
+        let function = lua.create_registry_value(function)?;
+        Ok(LuaKernel { lua, function })
+    }
+
+    /// The Lua state, e.g., for exposing the map to the script as globals.
+    pub fn lua(&self) -> &Lua {
+        &self.lua
+    }
+}
+
+// Call the update function for the given cell.
+fn call(function: &Function, x: i32, y: i32, influxes: &[Influx<&f32>]) -> mlua::Result<f32> {
+    let arg = |i: usize| influxes.get(i).map(|f| (f.weight, *f.value)).unzip();
+    let (w1, v1) = arg(0);
+    let (w2, v2) = arg(1);
+    let (w3, v3) = arg(2);
+    function.call((x, y, w1, v1, w2, v2, w3, v3))
+}
+
+impl<X, A> Fov<f32, X, A>
+where
+    X: AsRef<FluxField>,
+    A: Allocator,
+{
+    /// Update the field of vision with a function written in Lua.
+    ///
+    /// Once the function fails the rest of the cells keep their values and
+    /// the first error is returned.
+    ///
+    pub fn update_lua(&mut self, kernel: &LuaKernel) -> mlua::Result<()> {
+        let function: Function = kernel.lua.registry_value(&kernel.function)?;
+        let mut error = None;
+        self.update_cells(|x, y, _, cell, influxes| {
+            if error.is_none() {
+                match call(&function, x, y, influxes) {
+                    Ok(value) => *cell = value,
+                    Err(err) => error = Some(err),
+                }
+            }
+        });
+        error.map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn lua_kernel_matches_rust() {
+        let kernel = LuaKernel::new(
+            r#"
+            return function(x, y, w1, v1, w2, v2, w3, v3)
+                if w1 == nil then return 1.0 end
+                local sum = w1 * v1 + (w2 and w2 * v2 or 0) + (w3 and w3 * v3 or 0)
+                return sum * (1.0 - opacity(x, y))
+            end
+            "#,
+        )
+        .unwrap();
+        let opacity = |x: i32, y: i32| if (x + 2 * y) % 3 == 0 { 0.5 } else { 0.0 };
+        let f = kernel
+            .lua()
+            .create_function(move |_, (x, y): (i32, i32)| Ok(opacity(x, y)))
+            .unwrap();
+        kernel.lua().globals().set("opacity", f).unwrap();
+        let mut lua = Fov::new(Box::new(FluxField::new(6)), 6, 0.0_f32);
+        lua.update_lua(&kernel).unwrap();
+        let mut rust = Fov::new(Box::new(FluxField::new(6)), 6, 0.0_f32);
+        rust.update(|x, y, influxes| {
+            if influxes.is_empty() {
+                1.0
+            } else {
+                let sum: f32 = influxes.iter().map(|f| f.weight * f.value).sum();
+                sum * (1.0 - opacity(x, y))
+            }
+        });
+        for (a, b) in lua.as_slice().iter().zip(rust.as_slice()) {
+            assert!((a - b).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn lua_errors() {
+        assert!(LuaKernel::new("return (").is_err());
+        let kernel =
+            LuaKernel::new("return function(x, y) if x > 1 then error('boom') end return 0.5 end")
+                .unwrap();
+        let mut fov = Fov::new(Box::new(FluxField::new(3)), 3, 0.0_f32);
+        assert!(fov.update_lua(&kernel).is_err());
+        assert_eq!(*fov.at(1, 0), 0.5);
+        assert_eq!(*fov.at(3, 0), 0.0);
+    }
+}
@@ -0,0 +1,129 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::bits::{get, set, WORD_BITS};
+use crate::{FluxField, Fov, Influx};
+
+/// A bit-packed set of grid cells around the origin of a field of vision.
+///
+/// The mask covers the same cells as a field of vision with the same radius
+/// and is laid out the same way but doesn't refer to a flux field.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CellMask {
+    radius: i32,
+    words: Vec<u64>,
+}
+
+impl CellMask {
+    /// Constructs a mask covering the area within `radius` with every cell
+    /// set to `init`.
+    ///
+    pub fn new(radius: usize, init: bool) -> Self {
+        let width = 2 * radius + 1;
+        let mut mask = CellMask {
+            radius: radius as i32,
+            words: vec![0; (width * width).div_ceil(WORD_BITS)],
+        };
+        if init {
+            for ix in 0..width * width {
+                set(&mut mask.words, ix, true);
+            }
+        }
+        mask
+    }
+
+    pub fn radius(&self) -> usize {
+        self.radius as usize
+    }
+
+    /// Whether the given cell is in the mask.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        get(&self.words, self.index(x, y))
+    }
+
+    /// Add the given cell to the mask or remove it from the mask.
+    pub fn set(&mut self, x: i32, y: i32, value: bool) {
+        let ix = self.index(x, y);
+        set(&mut self.words, ix, value);
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        let r = self.radius;
+        assert!(x.abs() <= r && y.abs() <= r, "cell out of range");
+        ((y + r) * (2 * r + 1) + x + r) as usize
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Update the field of vision skipping the cells in the mask.
+    ///
+    /// The cells in `skip`, e.g., the cells outside the map or hidden behind
+    /// the user interface, keep their current value and `update_fn` is not
+    /// called for them.  The mask must have the same radius as the field.
+    ///
+    pub fn update_masked<F>(&mut self, skip: &CellMask, update_fn: F)
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        assert_eq!(self.radius, skip.radius as isize, "radius mismatch");
+        let mut update_fn = update_fn;
+        let (width, ix_origin) = (self.width, self.ix_origin);
+        self.update_cells(|x, y, _, cell, influxes| {
+            let ix = (ix_origin + width * y as isize + x as isize) as usize;
+            if !get(&skip.words, ix) {
+                *cell = update_fn(x, y, influxes);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn masked_cells_are_skipped() {
+        let mut skip = CellMask::new(3, false);
+        for y in -3..4 {
+            skip.set(-3, y, true);
+        }
+        skip.set(1, 1, true);
+        skip.set(1, 1, false);
+        assert!(skip.contains(-3, 2));
+        assert!(!skip.contains(1, 1));
+        let mut fov = Fov::new(Box::new(FluxField::new(3)), 3, -1);
+        let mut calls = 0;
+        fov.update_masked(&skip, |x, _, _| {
+            calls += 1;
+            x
+        });
+        assert_eq!(calls, 49 - 7);
+        assert_eq!(*fov.at(-3, 1), -1);
+        assert_eq!(*fov.at(-2, 1), -2);
+    }
+
+    #[test]
+    #[should_panic(expected = "radius mismatch")]
+    fn mask_radius_mismatch() {
+        let mut fov = Fov::new(Box::new(FluxField::new(3)), 3, 0);
+        fov.update_masked(&CellMask::new(2, false), |_, _, _| 0);
+    }
+}
@@ -0,0 +1,265 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+/// A class of grid cells sharing the same transmission, e.g., walls or
+/// glass.
+///
+/// Materials are registered in `Materials`; the constants are the standard
+/// ones every `Materials` starts with.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Material(u16);
+
+impl Material {
+    /// Open floor letting all the rays through.
+    pub const FLOOR: Material = Material(0);
+    /// A wall blocking all the rays.
+    pub const WALL: Material = Material(1);
+    /// Glass letting all the rays through.
+    pub const GLASS: Material = Material(2);
+    /// Foliage letting half of the rays through.
+    pub const FOLIAGE: Material = Material(3);
+    /// Smoke letting 70% of the rays through.
+    pub const SMOKE: Material = Material(4);
+
+    /// The index of the material in the order of registration.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// The table of materials and their transmission coefficients.
+///
+/// The transmission of a material is the fraction of the rays it passes on,
+/// from 0.0 (opaque) to 1.0 (transparent), i.e., one minus the opacity the
+/// standard kernels take.  Senses may override the transmission of some
+/// materials, e.g., infravision may see through foliage, and the opacities
+/// are then looked up for a sense by its name:
+///
+/// ```
+/// # use flux_fov::{kernel, FluxField, Fov, Material, Materials};
+/// let mut materials = Materials::new();
+/// materials.set_override("infravision", Material::FOLIAGE, 1.0);
+/// let map = |x: i32, _: i32| if x == 2 { Material::FOLIAGE } else { Material::FLOOR };
+/// let mut sight = Fov::new(Box::new(FluxField::new(4)), 4, Default::default());
+/// sight.update(kernel::soft_shadow(materials.opacity(map)));
+/// let mut infravision = Fov::new(Box::new(FluxField::new(4)), 4, Default::default());
+/// infravision.update(kernel::soft_shadow(materials.opacity_for("infravision", map)));
+/// assert_eq!(sight.at(4, 0).brightness, 0.5);
+/// assert_eq!(infravision.at(4, 0).brightness, 1.0);
+/// ```
+///
+#[derive(Clone, Debug)]
+pub struct Materials {
+    names: Vec<String>,
+    transmission: Vec<f32>,
+    overrides: Vec<(String, Material, f32)>,
+}
+
+impl Materials {
+    /// Constructs a table with the standard materials.
+    ///
+    pub fn new() -> Self {
+        let mut materials = Materials {
+            names: Vec::new(),
+            transmission: Vec::new(),
+            overrides: Vec::new(),
+        };
+        materials.add("floor", 1.0);
+        materials.add("wall", 0.0);
+        materials.add("glass", 1.0);
+        materials.add("foliage", 0.5);
+        materials.add("smoke", 0.7);
+        materials
+    }
+
+    /// Register a new material with the given transmission.
+    ///
+    pub fn add(&mut self, name: &str, transmission: f32) -> Material {
+        check(transmission);
+        assert!(self.names.len() <= u16::MAX as usize, "too many materials");
+        self.names.push(name.to_string());
+        self.transmission.push(transmission);
+        Material(self.names.len() as u16 - 1)
+    }
+
+    /// The number of materials.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// The material with the given name.
+    pub fn find(&self, name: &str) -> Option<Material> {
+        self.names
+            .iter()
+            .position(|n| n == name)
+            .map(|ix| Material(ix as u16))
+    }
+
+    /// The name of the material.
+    pub fn name(&self, material: Material) -> &str {
+        &self.names[material.index()]
+    }
+
+    /// The transmission of the material.
+    pub fn transmission(&self, material: Material) -> f32 {
+        self.transmission[material.index()]
+    }
+
+    /// Change the transmission of the material.
+    pub fn set_transmission(&mut self, material: Material, transmission: f32) {
+        check(transmission);
+        self.transmission[material.index()] = transmission;
+    }
+
+    /// Override the transmission of the material for the named sense.
+    ///
+    pub fn set_override(&mut self, sense: &str, material: Material, transmission: f32) {
+        check(transmission);
+        assert!(material.index() < self.names.len(), "unknown material");
+        match self
+            .overrides
+            .iter_mut()
+            .find(|(s, m, _)| s == sense && *m == material)
+        {
+            Some(entry) => entry.2 = transmission,
+            None => self
+                .overrides
+                .push((sense.to_string(), material, transmission)),
+        }
+    }
+
+    /// The transmission of the material for the named sense.
+    ///
+    pub fn transmission_for(&self, sense: &str, material: Material) -> f32 {
+        self.overrides
+            .iter()
+            .find(|(s, m, _)| s == sense && *m == material)
+            .map_or(self.transmission(material), |entry| entry.2)
+    }
+
+    /// The opacity function for the standard kernels given the material of
+    /// each cell relative to the origin.
+    ///
+    /// The opacities are looked up from a copy of the table so later changes
+    /// to the table don't affect the returned function.
+    ///
+    pub fn opacity<M>(&self, map: M) -> impl Fn(i32, i32) -> f32
+    where
+        M: Fn(i32, i32) -> Material,
+    {
+        let opacity: Vec<f32> = self.transmission.iter().map(|t| 1.0 - t).collect();
+        move |x, y| opacity[map(x, y).index()]
+    }
+
+    /// The opacity function for the named sense; see `opacity`.
+    ///
+    pub fn opacity_for<M>(&self, sense: &str, map: M) -> impl Fn(i32, i32) -> f32
+    where
+        M: Fn(i32, i32) -> Material,
+    {
+        let opacity: Vec<f32> = (0..self.names.len())
+            .map(|ix| 1.0 - self.transmission_for(sense, Material(ix as u16)))
+            .collect();
+        move |x, y| opacity[map(x, y).index()]
+    }
+}
+
+impl Default for Materials {
+    fn default() -> Self {
+        Materials::new()
+    }
+}
+
+fn check(transmission: f32) {
+    assert!(
+        (0.0..=1.0).contains(&transmission),
+        "transmission out of range"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel::{self, Visibility};
+    use crate::{FluxField, Fov, Influx, Senses};
+    use std::rc::Rc;
+
+    #[test]
+    fn standard_materials() {
+        let mut materials = Materials::new();
+        assert_eq!(materials.len(), 5);
+        assert_eq!(materials.find("glass"), Some(Material::GLASS));
+        assert_eq!(materials.name(Material::SMOKE), "smoke");
+        assert_eq!(materials.transmission(Material::WALL), 0.0);
+        let metal = materials.add("metal", 0.0);
+        assert_eq!(metal.index(), 5);
+        materials.set_override("tremorsense", Material::WALL, 0.25);
+        materials.set_override("tremorsense", Material::WALL, 0.5);
+        assert_eq!(
+            materials.transmission_for("tremorsense", Material::WALL),
+            0.5
+        );
+        assert_eq!(materials.transmission_for("sight", Material::WALL), 0.0);
+        assert_eq!(materials.transmission_for("tremorsense", metal), 0.0);
+    }
+
+    #[test]
+    fn materials_in_senses() {
+        let mut materials = Materials::new();
+        materials.set_override("infravision", Material::FOLIAGE, 1.0);
+        materials.set_override("infravision", Material::GLASS, 0.0);
+        let map = |x: i32, y: i32| match (x, y) {
+            (2, _) => Material::FOLIAGE,
+            (_, 2) => Material::GLASS,
+            (_, -2) => Material::WALL,
+            _ => Material::FLOOR,
+        };
+        let names = ["sight", "infravision"];
+        let opacities = [
+            materials.opacity_for(names[0], map),
+            materials.opacity_for(names[1], map),
+        ];
+        let mut kernels = [
+            kernel::threshold(0.75, &opacities[0]),
+            kernel::threshold(0.75, &opacities[1]),
+        ];
+        let mut senses = Senses::new(Rc::new(FluxField::new(4)), 4, &names, Visibility::default());
+        senses.update(|channel, x, y, influxes: &[Influx<&Visibility>]| {
+            kernels[channel](x, y, influxes)
+        });
+        assert!(!senses.at(0, 4, 0).is_visible);
+        assert!(senses.at(1, 4, 0).is_visible);
+        assert!(senses.at(0, 0, 4).is_visible);
+        assert!(!senses.at(1, 0, 4).is_visible);
+        assert!(!senses.at(0, 0, -4).is_visible);
+        assert!(!senses.at(1, 0, -4).is_visible);
+        let mut plain = Fov::new(Rc::new(FluxField::new(4)), 4, Visibility::default());
+        plain.update(kernel::threshold(0.75, materials.opacity(map)));
+        assert_eq!(plain.as_slice(), senses.as_slice(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "transmission out of range")]
+    fn transmission_is_checked() {
+        Materials::new().add("antimatter", -1.0);
+    }
+}
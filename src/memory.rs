@@ -0,0 +1,74 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::mem::size_of;
+
+use crate::{FluxField, Fov, Lut};
+
+impl FluxField {
+    /// The number of bytes the flux field has allocated on the heap.
+    ///
+    /// Borrowed and memory-mapped look-up tables don't count as they don't
+    /// live on the heap of the flux field.  The lazily built fixed-point
+    /// and outflux tables count once they have been built.
+    ///
+    pub fn heap_bytes(&self) -> usize {
+        let flux_lut = match &self.flux_lut {
+            Lut::Owned(lut) => lut.capacity() * size_of::<f32>(),
+            _ => 0,
+        };
+        let fixed_lut = self
+            .fixed_lut
+            .get()
+            .map_or(0, |lut| lut.len() * size_of::<[u32; 2]>());
+        let outfluxes = self.outfluxes.get().map_or(0, |table| table.heap_bytes());
+        flux_lut + self.lateral_lut.capacity() * size_of::<f32>() + fixed_lut + outfluxes
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// The number of bytes the buffer of the field of vision takes on the
+    /// heap.
+    ///
+    /// Neither the flux field, which may be shared, nor any heap memory
+    /// owned by the values themselves is included.
+    ///
+    pub fn heap_bytes(&self) -> usize {
+        self.data.capacity() * size_of::<T>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::four_connected;
+    use std::rc::Rc;
+
+    #[test]
+    fn heap_usage() {
+        let flux_field = Rc::new(FluxField::new(10));
+        assert_eq!(flux_field.heap_bytes(), 45 * 4);
+        let fov = Fov::new(flux_field, 10, 0_u16);
+        assert_eq!(fov.heap_bytes(), 21 * 21 * 2);
+        let four = Rc::new(FluxField::with_params(10, four_connected()));
+        let _fov = Fov::new(four.clone(), 10, ());
+        assert_eq!(four.heap_bytes(), 45 * 4 + 45 * 4 + 45 * 8);
+    }
+}
@@ -0,0 +1,151 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{FluxField, Fov, Influx};
+
+/// The distance metric shaping the outer boundary of a field of vision.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Metric {
+    /// The square of the cells within the radius along both axes; the shape
+    /// of the field itself.
+    #[default]
+    Chebyshev,
+    /// The disk of the cells whose centers are within the radius plus half
+    /// a cell from the origin.
+    Euclidean,
+    /// The diamond of the cells reachable in `radius` orthogonal steps.
+    Manhattan,
+}
+
+impl Metric {
+    /// Whether the cell is within `radius` of the origin.
+    pub fn contains(self, radius: i32, x: i32, y: i32) -> bool {
+        match self {
+            Metric::Chebyshev => x.abs().max(y.abs()) <= radius,
+            Metric::Euclidean => x * x + y * y <= radius * radius + radius,
+            Metric::Manhattan => x.abs() + y.abs() <= radius,
+        }
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    T: Clone,
+    X: AsRef<FluxField>,
+{
+    /// Update the field of vision with its boundary shaped by the given
+    /// metric.
+    ///
+    /// The cells beyond the radius of the field in the metric are set to
+    /// `outside` without calling `update_fn`.
+    ///
+    pub fn update_with_metric<F>(&mut self, metric: Metric, outside: T, update_fn: F)
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        if metric == Metric::Chebyshev {
+            self.update(update_fn);
+            return;
+        }
+        let radius = self.radius as i32;
+        let mut update_fn = update_fn;
+        self.update_cells(|x, y, _, cell, influxes| {
+            *cell = if metric.contains(radius, x, y) {
+                update_fn(x, y, influxes)
+            } else {
+                outside.clone()
+            };
+        });
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    T: Clone,
+    X: AsRef<FluxField>,
+{
+    /// Update the field of vision within a fractional Euclidean radius.
+    ///
+    /// The cells along the boundary are only partially covered and
+    /// `update_fn` receives the coverage of the cell, from 0.0 to 1.0, after
+    /// the coordinates.  The coverage falls linearly from 1.0 to 0.0 as the
+    /// distance of the center of the cell from the origin grows from
+    /// `radius - 0.5` to `radius + 0.5`.  The cells that are not covered at
+    /// all are set to `outside` without calling `update_fn`.  The origin is
+    /// always fully covered.  This lets the range of vision grow smoothly,
+    /// e.g., with the light level.
+    ///
+    pub fn update_fractional<F>(&mut self, radius: f32, outside: T, update_fn: F)
+    where
+        F: FnMut(i32, i32, f32, &[Influx<&T>]) -> T,
+    {
+        assert!(radius <= self.radius as f32 + 0.5, "radius out of range");
+        let mut update_fn = update_fn;
+        self.update_cells(|x, y, _, cell, influxes| {
+            let coverage = if x == 0 && y == 0 {
+                1.0
+            } else {
+                let distance = ((x * x + y * y) as f32).sqrt();
+                (radius + 0.5 - distance).clamp(0.0, 1.0)
+            };
+            *cell = if coverage > 0.0 {
+                update_fn(x, y, coverage, influxes)
+            } else {
+                outside.clone()
+            };
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn boundary_shapes() {
+        let mut fov = Fov::new(Box::new(FluxField::new(4)), 4, 0);
+        let count = |fov: &Fov<i32, _>| fov.as_slice().iter().sum::<i32>();
+        fov.update_with_metric(Metric::Chebyshev, 0, |_, _, _| 1);
+        assert_eq!(count(&fov), 81);
+        fov.update_with_metric(Metric::Manhattan, 0, |_, _, _| 1);
+        assert_eq!(count(&fov), 41);
+        assert_eq!(*fov.at(2, 2), 1);
+        assert_eq!(*fov.at(3, 2), 0);
+        let mut calls = 0;
+        fov.update_with_metric(Metric::Euclidean, 0, |_, _, _| {
+            calls += 1;
+            1
+        });
+        assert_eq!(count(&fov), calls);
+        assert_eq!(*fov.at(4, 2), 1);
+        assert_eq!(*fov.at(3, 3), 1);
+        assert_eq!(*fov.at(4, 3), 0);
+    }
+
+    #[test]
+    fn fractional_coverage() {
+        let mut fov = Fov::new(Box::new(FluxField::new(4)), 4, -1.0);
+        fov.update_fractional(2.3, -1.0, |_, _, coverage, _| coverage);
+        assert_eq!(*fov.at(0, 0), 1.0);
+        assert_eq!(*fov.at(1, 1), 1.0);
+        assert!((*fov.at(2, 0) - 0.8).abs() < 1e-6);
+        assert!((*fov.at(0, -2) - 0.8).abs() < 1e-6);
+        assert_eq!(*fov.at(2, 2), -1.0);
+        assert_eq!(*fov.at(3, 0), -1.0);
+    }
+}
@@ -0,0 +1,100 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::time::{Duration, Instant};
+
+use crate::{FluxField, Fov, Influx};
+
+/// The cost of an update; see `Fov::update_metered`.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UpdateMetrics {
+    /// The radius of the updated field.
+    pub radius: usize,
+    /// The number of cells visited by the update, the origin included.
+    pub cells: usize,
+    /// The number of calls to the update function.
+    pub kernel_calls: usize,
+    /// The wall clock time of the update, the update function included.
+    pub elapsed: Duration,
+}
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Update the field of vision like `update` and report the cost of the
+    /// update to `on_metrics`, e.g., for an in-game profiler.
+    ///
+    /// The measurement adds only a clock read before and after the update
+    /// and a counter increment per cell.
+    ///
+    /// ```
+    /// # use flux_fov::{FluxField, Fov};
+    /// let mut fov = Fov::new(Box::new(FluxField::new(4)), 4, 0);
+    /// fov.update_metered(
+    ///     |_, _, _| 1,
+    ///     |metrics| println!("fov: {} cells in {:?}", metrics.cells, metrics.elapsed),
+    /// );
+    /// ```
+    ///
+    pub fn update_metered<F, M>(&mut self, update_fn: F, on_metrics: M)
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+        M: FnOnce(&UpdateMetrics),
+    {
+        let mut update_fn = update_fn;
+        let mut cells = 0;
+        let mut kernel_calls = 0;
+        let start = Instant::now();
+        self.update_cells(|x, y, _, cell, influxes| {
+            cells += 1;
+            kernel_calls += 1;
+            *cell = update_fn(x, y, influxes);
+        });
+        let elapsed = start.elapsed();
+        on_metrics(&UpdateMetrics {
+            radius: self.radius as usize,
+            cells,
+            kernel_calls,
+            elapsed,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel::{self, Visibility};
+
+    #[test]
+    fn metered_update() {
+        let flux_field = std::rc::Rc::new(FluxField::new(6));
+        let mut fov = Fov::new(flux_field.clone(), 5, Visibility::default());
+        let mut reported = None;
+        fov.update_metered(kernel::threshold(0.5, |x, _| (x == 2) as u8 as f32), |m| {
+            reported = Some(*m)
+        });
+        let metrics = reported.unwrap();
+        assert_eq!(metrics.radius, 5);
+        assert_eq!(metrics.cells, 121);
+        assert_eq!(metrics.kernel_calls, 121);
+        let mut plain = Fov::new(flux_field, 5, Visibility::default());
+        plain.update(kernel::threshold(0.5, |x, _| (x == 2) as u8 as f32));
+        assert_eq!(fov.as_slice(), plain.as_slice());
+    }
+}
@@ -0,0 +1,214 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{FluxField, Fov, Influx, Sector};
+
+/// The orientation of a mirror.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MirrorAxis {
+    /// A mirror along the y-axis flipping the x-direction of the flux.
+    Vertical,
+    /// A mirror along the x-axis flipping the y-direction of the flux.
+    Horizontal,
+}
+
+/// A reflective cell.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mirror {
+    /// The position of the mirror relative to the viewer.
+    pub x: i32,
+    pub y: i32,
+    pub axis: MirrorAxis,
+    /// The fraction of the flux reflected, used as the weight of the influx
+    /// into the mirror cell.
+    pub reflectance: f32,
+}
+
+/// A field of vision with flux reflected by mirrors.
+///
+/// The flux reaching a mirror is redirected along the mirrored direction
+/// into a reflection of its own.  The reflection is a field centered on the
+/// mirror with the radius that remains after reaching it.  Its origin
+/// receives a single influx carrying the value of the mirror cell in the
+/// main field with the weight given by the reflectance and the direction of
+/// the reflected flux.  Only the side of the mirror facing the viewer is
+/// lit; the cells on the mirror line and behind the mirror receive no
+/// influxes.  The flux is reflected only once.
+///
+pub struct MirrorFov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    init: T,
+    radius: usize,
+    main: Fov<T, X>,
+    mirrors: Vec<Mirror>,
+    reflections: Vec<Fov<T, X>>,
+}
+
+impl<T, X> MirrorFov<T, X>
+where
+    T: Clone,
+    X: AsRef<FluxField> + Clone,
+{
+    pub fn new(flux_field: X, radius: usize, init: T) -> Self {
+        MirrorFov {
+            init: init.clone(),
+            radius,
+            main: Fov::new(flux_field, radius, init),
+            mirrors: Vec::new(),
+            reflections: Vec::new(),
+        }
+    }
+
+    /// Update the field with the given mirrors.
+    ///
+    /// The update function receives the index of the field being updated, 0
+    /// for the main field and `i + 1` for the reflection of the mirror `i`,
+    /// and the coordinates of the cell relative to the viewer, so that the
+    /// same map can be used for all of them.
+    ///
+    pub fn update<F>(&mut self, mirrors: &[Mirror], update_fn: F)
+    where
+        F: FnMut(usize, i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        self.main
+            .update(|x, y, influxes| update_fn(0, x, y, influxes));
+        self.mirrors.clear();
+        self.mirrors.extend_from_slice(mirrors);
+        self.reflections.truncate(mirrors.len());
+        let radius = self.radius as i32;
+        for (i, mirror) in mirrors.iter().enumerate() {
+            let (mx, my) = (mirror.x, mirror.y);
+            let distance = mx.abs().max(my.abs());
+            let remaining = (radius - distance).max(0) as usize;
+            if self.reflections.len() == i {
+                let flux_field = self.main.flux_field.clone();
+                self.reflections
+                    .push(Fov::new(flux_field, remaining, self.init.clone()));
+            } else if self.reflections[i].radius() != remaining {
+                let flux_field = self.reflections[i].flux_field.clone();
+                self.reflections[i] = Fov::new(flux_field, remaining, self.init.clone());
+            }
+            if distance > radius || distance == 0 {
+                continue;
+            }
+            // The direction of the incoming flux snapped to one of the eight
+            // neighbors and then mirrored.
+            let mut dx = mx.signum() * (2 * mx.abs() >= distance) as i32;
+            let mut dy = my.signum() * (2 * my.abs() >= distance) as i32;
+            match mirror.axis {
+                MirrorAxis::Vertical => dx = -dx,
+                MirrorAxis::Horizontal => dy = -dy,
+            }
+            let entry = [Influx {
+                weight: mirror.reflectance,
+                dx,
+                dy,
+                value: self.main.at(mx, my),
+            }];
+            let axis = mirror.axis;
+            self.reflections[i].update_cells(|x, y, sector, cell, influxes| {
+                let facing = match axis {
+                    MirrorAxis::Vertical => x * mx < 0,
+                    MirrorAxis::Horizontal => y * my < 0,
+                };
+                let influxes = match sector {
+                    Sector::Origin => &entry[..],
+                    _ if facing => influxes,
+                    _ => &[],
+                };
+                *cell = update_fn(i + 1, mx + x, my + y, influxes);
+            });
+        }
+    }
+}
+
+impl<T, X> MirrorFov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// The main field around the viewer.
+    pub fn main(&self) -> &Fov<T, X> {
+        &self.main
+    }
+
+    /// The reflection of the mirror `i`.
+    ///
+    /// The coordinates of the reflection are relative to the mirror.
+    ///
+    pub fn reflection(&self, i: usize) -> &Fov<T, X> {
+        &self.reflections[i]
+    }
+
+    /// The mirror `i`.
+    pub fn mirror(&self, i: usize) -> &Mirror {
+        &self.mirrors[i]
+    }
+
+    /// The value at the given cell, relative to the viewer, in the
+    /// reflection of the mirror `i`.
+    ///
+    /// Returns `None` if the cell is outside the reflection.
+    ///
+    pub fn reflected_at(&self, i: usize, x: i32, y: i32) -> Option<&T> {
+        let mirror = &self.mirrors[i];
+        let (rx, ry) = (x - mirror.x, y - mirror.y);
+        let radius = self.reflections[i].radius() as i32;
+        if rx.abs().max(ry.abs()) <= radius {
+            Some(self.reflections[i].at(rx, ry))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn mirror_reflects_towards_viewer() {
+        let flux_field = Rc::new(FluxField::new(10));
+        let mut fov = MirrorFov::new(flux_field, 10, 0.0);
+        let mirror = Mirror {
+            x: 4,
+            y: 0,
+            axis: MirrorAxis::Vertical,
+            reflectance: 0.5,
+        };
+        fov.update(&[mirror], |i, x, y, influxes| {
+            if i == 0 && x == 0 && y == 0 {
+                1.0
+            } else if i == 0 && x > 4 {
+                0.0
+            } else {
+                influxes.iter().map(|f| f.weight * *f.value).sum()
+            }
+        });
+        assert_eq!(fov.reflection(0).radius(), 6);
+        assert_eq!(*fov.reflected_at(0, 4, 0).unwrap(), 0.5);
+        assert_eq!(*fov.reflected_at(0, 1, 0).unwrap(), 0.5);
+        assert_eq!(*fov.reflected_at(0, 6, 0).unwrap(), 0.0);
+        assert_eq!(fov.reflected_at(0, -3, 0), None);
+        assert_eq!(*fov.main().at(6, 0), 0.0);
+    }
+}
@@ -0,0 +1,264 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::kernel::{self, Visibility};
+use crate::{Influx, NightVision};
+
+/// A temporary change to the vision of an observer; see `VisionModifiers`.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VisionModifier {
+    /// Sees nothing but its own cell.
+    Blind,
+    /// Add to the radius, e.g., `RadiusBonus(-3)` for darkness or a hood.
+    RadiusBonus(i32),
+    /// Multiply the radius, e.g., `RadiusScale(2.0)` for a telescope.
+    RadiusScale(f32),
+    /// Multiply the threshold of the rays a cell needs to be visible; below
+    /// 1.0 sees further through smoke and foliage.
+    ThresholdScale(f32),
+    /// Multiply the opacity of the cells, e.g., `OpacityScale(0.5)` for
+    /// seeing through fog.
+    OpacityScale(f32),
+    /// Add to the dark vision distance.
+    DarkVisionBonus(f32),
+}
+
+/// The vision of an observer with the modifiers applied.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VisionParams {
+    /// The Euclidean distance the observer sees to.
+    pub radius: f32,
+    /// The threshold of the `threshold` kernel.
+    pub threshold: f32,
+    /// The factor applied to the opacity of the cells.
+    pub opacity_scale: f32,
+    /// The brightness a tile needs for the observer to see it.
+    pub min_brightness: f32,
+    /// The distance within which the observer sees in the dark.
+    pub dark_vision: f32,
+    /// Whether the observer is blind.
+    pub blind: bool,
+}
+
+impl VisionParams {
+    /// Unmodified vision to `radius` with the given threshold and no dark
+    /// vision.
+    ///
+    pub fn new(radius: f32, threshold: f32) -> Self {
+        VisionParams {
+            radius,
+            threshold,
+            opacity_scale: 1.0,
+            min_brightness: 0.0,
+            dark_vision: 0.0,
+            blind: false,
+        }
+    }
+
+    /// The night vision for `BrightnessGrid::perceive`.
+    pub fn night_vision(&self) -> NightVision {
+        NightVision {
+            min_brightness: self.min_brightness,
+            dark_vision: self.dark_vision,
+        }
+    }
+
+    /// The `threshold` kernel with these parameters.
+    ///
+    /// The cells beyond the radius and, if blind, every cell but the origin
+    /// are not visible and pass on no rays.  The `opacity` is as in
+    /// `kernel::threshold`.
+    ///
+    pub fn kernel<F>(
+        &self,
+        opacity: F,
+    ) -> impl FnMut(i32, i32, &[Influx<&Visibility>]) -> Visibility
+    where
+        F: Fn(i32, i32) -> f32,
+    {
+        let params = *self;
+        let radius_sq = if params.blind {
+            0.0
+        } else {
+            params.radius * params.radius
+        };
+        let scaled = move |x, y| (params.opacity_scale * opacity(x, y)).min(1.0);
+        let mut inner = kernel::threshold(params.threshold, scaled);
+        move |x, y, influxes| {
+            if (x * x + y * y) as f32 > radius_sq {
+                Visibility::default()
+            } else {
+                inner(x, y, influxes)
+            }
+        }
+    }
+}
+
+/// The handle of a modifier pushed on `VisionModifiers`.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ModifierId(u64);
+
+/// A stack of temporary vision modifiers over the base vision of an
+/// observer.
+///
+/// The modifiers apply in the order they were pushed, so a radius bonus
+/// pushed before a radius scale gets scaled too.  Each turn call `tick` to
+/// expire the modifiers that have run out and then build the kernel of the
+/// turn from `effective`:
+///
+/// ```
+/// # use flux_fov::{FluxField, Fov, VisionModifier, VisionModifiers, VisionParams};
+/// # use flux_fov::kernel::Visibility;
+/// let mut fov = Fov::new(Box::new(FluxField::new(8)), 8, Visibility::default());
+/// let mut vision = VisionModifiers::new(VisionParams::new(4.0, 0.5));
+/// vision.push(VisionModifier::RadiusScale(2.0), Some(1));
+/// fov.update(vision.effective().kernel(|_, _| 0.0));
+/// assert!(fov.at(7, 0).is_visible);
+/// vision.tick();
+/// fov.update(vision.effective().kernel(|_, _| 0.0));
+/// assert!(!fov.at(7, 0).is_visible);
+/// ```
+///
+pub struct VisionModifiers {
+    base: VisionParams,
+    stack: Vec<(ModifierId, VisionModifier, Option<u32>)>,
+    next_id: u64,
+}
+
+impl VisionModifiers {
+    /// Constructs an empty stack over the `base` vision.
+    ///
+    pub fn new(base: VisionParams) -> Self {
+        VisionModifiers {
+            base,
+            stack: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// The base vision.
+    pub fn base(&self) -> &VisionParams {
+        &self.base
+    }
+
+    /// Replace the base vision, e.g., when the observer levels up.
+    pub fn set_base(&mut self, base: VisionParams) {
+        self.base = base;
+    }
+
+    /// The modifiers in effect in the order they apply.
+    pub fn modifiers(&self) -> impl Iterator<Item = &VisionModifier> + '_ {
+        self.stack.iter().map(|(_, modifier, _)| modifier)
+    }
+
+    /// Push a modifier lasting `turns` turns, or until removed if `None`.
+    ///
+    pub fn push(&mut self, modifier: VisionModifier, turns: Option<u32>) -> ModifierId {
+        let id = ModifierId(self.next_id);
+        self.next_id += 1;
+        self.stack.push((id, modifier, turns));
+        id
+    }
+
+    /// Remove the modifier; returns `false` if it has already expired.
+    ///
+    pub fn remove(&mut self, id: ModifierId) -> bool {
+        let len = self.stack.len();
+        self.stack.retain(|&(other, _, _)| other != id);
+        self.stack.len() < len
+    }
+
+    /// Advance the stack by one turn, expiring the modifiers that have run
+    /// out.
+    ///
+    pub fn tick(&mut self) {
+        for (_, _, turns) in self.stack.iter_mut() {
+            if let Some(turns) = turns {
+                *turns = turns.saturating_sub(1);
+            }
+        }
+        self.stack.retain(|&(_, _, turns)| turns != Some(0));
+    }
+
+    /// The base vision with the modifiers applied.
+    ///
+    pub fn effective(&self) -> VisionParams {
+        let mut params = self.base;
+        for &(_, modifier, _) in self.stack.iter() {
+            match modifier {
+                VisionModifier::Blind => params.blind = true,
+                VisionModifier::RadiusBonus(bonus) => {
+                    params.radius = (params.radius + bonus as f32).max(0.0)
+                }
+                VisionModifier::RadiusScale(scale) => params.radius *= scale,
+                VisionModifier::ThresholdScale(scale) => params.threshold *= scale,
+                VisionModifier::OpacityScale(scale) => params.opacity_scale *= scale,
+                VisionModifier::DarkVisionBonus(bonus) => {
+                    params.dark_vision = (params.dark_vision + bonus).max(0.0)
+                }
+            }
+        }
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{FluxField, Fov};
+
+    #[test]
+    fn modifiers_stack_and_expire() {
+        let mut vision = VisionModifiers::new(VisionParams::new(4.0, 0.5));
+        let hood = vision.push(VisionModifier::RadiusBonus(-2), None);
+        let scope = vision.push(VisionModifier::RadiusScale(3.0), Some(2));
+        vision.push(VisionModifier::DarkVisionBonus(1.5), Some(1));
+        let params = vision.effective();
+        assert_eq!(params.radius, 6.0);
+        assert_eq!(params.dark_vision, 1.5);
+        assert_eq!(params.night_vision().dark_vision, 1.5);
+        vision.tick();
+        assert_eq!(vision.effective().dark_vision, 0.0);
+        assert_eq!(vision.effective().radius, 6.0);
+        assert!(vision.remove(hood));
+        assert!(!vision.remove(hood));
+        assert_eq!(vision.effective().radius, 12.0);
+        vision.tick();
+        assert_eq!(vision.effective().radius, 4.0);
+        assert!(!vision.remove(scope));
+        assert_eq!(vision.modifiers().count(), 0);
+    }
+
+    #[test]
+    fn modified_kernel() {
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, Visibility::default());
+        let smoke = |x: i32, _| if x == 2 { 0.6 } else { 0.0 };
+        let mut vision = VisionModifiers::new(VisionParams::new(6.0, 0.5));
+        fov.update(vision.effective().kernel(smoke));
+        assert!(!fov.at(4, 0).is_visible);
+        vision.push(VisionModifier::OpacityScale(0.5), None);
+        fov.update(vision.effective().kernel(smoke));
+        assert!(fov.at(4, 0).is_visible);
+        vision.push(VisionModifier::Blind, None);
+        fov.update(vision.effective().kernel(smoke));
+        assert!(fov.at(0, 0).is_visible);
+        assert!(!fov.at(1, 0).is_visible);
+    }
+}
@@ -0,0 +1,150 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{FluxField, Fov, Influx};
+
+/// A field of vision with full resolution near the origin and half
+/// resolution further away.
+///
+/// The field consists of two layers: a fine layer covering the cells within
+/// the inner radius and a coarse layer covering the whole radius in blocks of
+/// 2x2 cells.  The coarse block (bx, by) covers the cells from (2 * bx,
+/// 2 * by) to (2 * bx + 1, 2 * by + 1).  An update costs roughly as much as
+/// updating a field with the inner radius plus a quarter of the full one.
+///
+pub struct MultiResFov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    fine: Fov<T, X>,
+    coarse: Fov<T, X>,
+    inner_radius: i32,
+    radius: i32,
+}
+
+impl<T, X> MultiResFov<T, X>
+where
+    T: Clone,
+    X: AsRef<FluxField>,
+{
+    /// Constructs a field covering `radius` with full resolution within
+    /// `inner_radius`.
+    ///
+    /// The flux field `fine_flux` must cover `inner_radius` and the flux
+    /// field `coarse_flux` half of `radius` rounded up.
+    ///
+    pub fn new(fine_flux: X, coarse_flux: X, inner_radius: usize, radius: usize, init: T) -> Self {
+        assert!(inner_radius <= radius);
+        MultiResFov {
+            fine: Fov::new(fine_flux, inner_radius, init.clone()),
+            coarse: Fov::new(coarse_flux, radius.div_ceil(2), init),
+            inner_radius: inner_radius as i32,
+            radius: radius as i32,
+        }
+    }
+}
+
+impl<T, X> MultiResFov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    pub fn inner_radius(&self) -> usize {
+        self.inner_radius as usize
+    }
+
+    pub fn radius(&self) -> usize {
+        self.radius as usize
+    }
+
+    /// The value at the given grid cell.
+    ///
+    /// Within the inner radius the value comes from the fine layer and
+    /// beyond it from the block of the coarse layer covering the cell.
+    ///
+    pub fn at(&self, x: i32, y: i32) -> &T {
+        if x.abs().max(y.abs()) <= self.inner_radius {
+            self.fine.at(x, y)
+        } else {
+            assert!(x.abs().max(y.abs()) <= self.radius, "out of range");
+            self.coarse.at(x.div_euclid(2), y.div_euclid(2))
+        }
+    }
+
+    /// The fine layer.
+    pub fn fine(&self) -> &Fov<T, X> {
+        &self.fine
+    }
+
+    /// The coarse layer.
+    pub fn coarse(&self) -> &Fov<T, X> {
+        &self.coarse
+    }
+
+    /// Update both layers with the given function.
+    ///
+    /// The function receives the coordinates of the cell, or of the top-left
+    /// cell of the block, and the size of the cell: 1 for the fine layer and
+    /// 2 for the coarse one.
+    ///
+    pub fn update<F>(&mut self, update_fn: F)
+    where
+        F: FnMut(i32, i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        self.fine
+            .update(|x, y, influxes| update_fn(x, y, 1, influxes));
+        self.coarse
+            .update(|x, y, influxes| update_fn(2 * x, 2 * y, 2, influxes));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn query_picks_layer() {
+        let fine = Rc::new(FluxField::new(3));
+        let coarse = Rc::new(FluxField::new(5));
+        let mut fov = MultiResFov::new(fine, coarse, 3, 9, (0, 0, 0));
+        fov.update(|x, y, size, _| (x, y, size));
+        assert_eq!(*fov.at(3, -2), (3, -2, 1));
+        assert_eq!(*fov.at(4, -2), (4, -2, 2));
+        assert_eq!(*fov.at(5, -1), (4, -2, 2));
+        assert_eq!(*fov.at(-9, 9), (-10, 8, 2));
+    }
+
+    #[test]
+    fn coarse_layer_casts_shadows() {
+        let fine = Rc::new(FluxField::new(4));
+        let coarse = Rc::new(FluxField::new(10));
+        let mut fov = MultiResFov::new(fine, coarse, 4, 20, 0.0);
+        fov.update(|x, y, size, influxes| {
+            if x == 0 && y == 0 {
+                1.0
+            } else if x <= 6 && 6 < x + size && y.abs() <= 2 {
+                0.0
+            } else {
+                influxes.iter().map(|f| f.weight * f.value).sum()
+            }
+        });
+        assert_eq!(*fov.at(3, 0), 1.0);
+        assert_eq!(*fov.at(-18, 0), 1.0);
+        assert!(*fov.at(18, 0) < 1e-6);
+    }
+}
@@ -0,0 +1,134 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::allocator::Buffer;
+use crate::{FluxField, Fov};
+
+/// The parameters of the noticeability model of `Fov::noticeability`.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoticeParams {
+    /// The size of the target in cells.
+    pub size: f32,
+    /// The angular size in radians at and above which the target is fully
+    /// noticeable.  Smaller targets are noticed in proportion to their
+    /// angular size.
+    pub full_angle: f32,
+    /// The light level at and below which the target cannot be noticed.
+    pub dark: f32,
+    /// The light level at and above which the target is fully noticeable.
+    pub bright: f32,
+}
+
+impl Default for NoticeParams {
+    fn default() -> Self {
+        // A human sized target is fully noticeable up to four cells away.
+        NoticeParams {
+            size: 1.0,
+            full_angle: 2.0 * (1.0f32 / 8.0).atan(),
+            dark: 0.0,
+            bright: 1.0,
+        }
+    }
+}
+
+impl NoticeParams {
+    /// The noticeability of a target at `distance` cells lit by `light` and
+    /// seen through the fraction `visibility` of the rays.
+    ///
+    pub fn score(&self, distance: f32, light: f32, visibility: f32) -> f32 {
+        let angle = 2.0 * (self.size / (2.0 * distance)).atan();
+        let size_factor = (angle / self.full_angle).min(1.0);
+        let light_factor = if self.bright > self.dark {
+            ((light - self.dark) / (self.bright - self.dark)).clamp(0.0, 1.0)
+        } else {
+            (light >= self.bright) as u8 as f32
+        };
+        visibility.clamp(0.0, 1.0) * light_factor * size_factor
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField> + Clone,
+{
+    /// Compute how noticeable a target standing in each cell would be to the
+    /// viewer at the origin.
+    ///
+    /// Combines the `visibility` of the cells, from 0.0 to 1.0 such as the
+    /// brightness of a soft-shadow field, with the `light` level of the
+    /// cells relative to the origin and the angular size of the target at
+    /// the distance of the cell.  The scores range from 0.0 (unnoticeable)
+    /// to 1.0 (impossible to miss); see `NoticeParams::score`.  The score of
+    /// the origin is 1.0.
+    ///
+    pub fn noticeability<V, L>(&self, params: &NoticeParams, visibility: V, light: L) -> Fov<f32, X>
+    where
+        V: Fn(&T) -> f32,
+        L: Fn(i32, i32) -> f32,
+    {
+        let r = self.radius as i32;
+        let mut data = Buffer::with_capacity(self.data.len());
+        for (ix, value) in self.data.iter().enumerate() {
+            let x = (ix as isize % self.width) as i32 - r;
+            let y = (ix as isize / self.width) as i32 - r;
+            data.push(if x == 0 && y == 0 {
+                1.0
+            } else {
+                let distance = ((x * x + y * y) as f32).sqrt();
+                params.score(distance, light(x, y), visibility(value))
+            });
+        }
+        Fov {
+            flux_field: self.flux_field.clone(),
+            radius: self.radius,
+            width: self.width,
+            ix_origin: self.ix_origin,
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel::{self, Illumination};
+    use std::rc::Rc;
+
+    #[test]
+    fn score_components() {
+        let params = NoticeParams::default();
+        assert_eq!(params.score(2.0, 1.0, 1.0), 1.0);
+        assert!((params.score(8.0, 1.0, 1.0) - 0.5).abs() < 0.01);
+        assert_eq!(params.score(2.0, 0.5, 1.0), 0.5);
+        assert_eq!(params.score(2.0, 1.0, 0.25), 0.25);
+        assert_eq!(params.score(2.0, 0.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn noticeability_map() {
+        let mut fov = Fov::new(Rc::new(FluxField::new(8)), 8, Illumination::default());
+        fov.update(kernel::soft_shadow(|x, _| if x == -2 { 1.0 } else { 0.0 }));
+        let light = |x: i32, _: i32| if x > 4 { 0.2 } else { 1.0 };
+        let notice = fov.noticeability(&NoticeParams::default(), |v| v.brightness, light);
+        assert_eq!(*notice.at(0, 0), 1.0);
+        assert_eq!(*notice.at(2, 1), 1.0);
+        assert_eq!(*notice.at(-6, 0), 0.0);
+        assert!(*notice.at(6, 0) < 0.2);
+        assert!(*notice.at(0, 8) < *notice.at(0, 4));
+    }
+}
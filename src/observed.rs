@@ -0,0 +1,198 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::compose::for_each_tile;
+use crate::{FluxField, Fov, ObserverId};
+
+/// A map-sized grid counting the observers that currently see each tile,
+/// e.g., for surveillance mechanics or for a heatmap of the awareness of
+/// the AI.
+///
+/// Like in `ThreatMap` the tiles seen by each observer are remembered so
+/// that moving or removing one of them updates the counts without touching
+/// the others.
+///
+/// ```
+/// # use flux_fov::{FluxField, Fov, ObservedByMap};
+/// let sight = Fov::new(Box::new(FluxField::new(2)), 2, true);
+/// let mut map = ObservedByMap::new(10, 5);
+/// let guard = map.add_observer(2, 2, &sight, |&visible| visible);
+/// map.add_observer(4, 2, &sight, |&visible| visible);
+/// assert_eq!(map.at(3, 2), 2);
+/// map.move_observer(guard, 8, 2, &sight, |&visible| visible);
+/// assert_eq!(map.at(3, 2), 1);
+/// assert_eq!(map.at(7, 2), 1);
+/// ```
+///
+pub struct ObservedByMap {
+    width: usize,
+    height: usize,
+    counts: Vec<u32>,
+    observers: Vec<Option<Vec<usize>>>,
+}
+
+impl ObservedByMap {
+    /// Constructs a `width` by `height` map with no observers.
+    ///
+    pub fn new(width: usize, height: usize) -> Self {
+        ObservedByMap {
+            width,
+            height,
+            counts: vec![0; width * height],
+            observers: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The number of observers seeing the given tile.
+    pub fn at(&self, x: usize, y: usize) -> u32 {
+        assert!(x < self.width && y < self.height, "tile out of range");
+        self.counts[y * self.width + x]
+    }
+
+    /// Expose the counts of the tiles as a slice in row-major order.
+    pub fn as_slice(&self) -> &[u32] {
+        self.counts.as_slice()
+    }
+
+    /// The number of observers on the map.
+    pub fn observer_count(&self) -> usize {
+        self.observers.iter().filter(|o| o.is_some()).count()
+    }
+
+    /// Add an observer at the tile (`x`, `y`) seeing the cells of `sight`
+    /// for which `is_visible` holds.
+    ///
+    pub fn add_observer<T, X, P>(
+        &mut self,
+        x: i32,
+        y: i32,
+        sight: &Fov<T, X>,
+        is_visible: P,
+    ) -> ObserverId
+    where
+        X: AsRef<FluxField>,
+        P: Fn(&T) -> bool,
+    {
+        let seen = self.see(x, y, sight, is_visible);
+        match self.observers.iter().position(Option::is_none) {
+            Some(id) => {
+                self.observers[id] = Some(seen);
+                ObserverId(id)
+            }
+            None => {
+                self.observers.push(Some(seen));
+                ObserverId(self.observers.len() - 1)
+            }
+        }
+    }
+
+    /// Replace the tiles seen by the observer, e.g., after it has moved or
+    /// turned.
+    ///
+    pub fn move_observer<T, X, P>(
+        &mut self,
+        id: ObserverId,
+        x: i32,
+        y: i32,
+        sight: &Fov<T, X>,
+        is_visible: P,
+    ) where
+        X: AsRef<FluxField>,
+        P: Fn(&T) -> bool,
+    {
+        self.unsee(id);
+        let seen = self.see(x, y, sight, is_visible);
+        self.observers[id.0] = Some(seen);
+    }
+
+    /// Remove the observer and the tiles it sees from the map.
+    ///
+    pub fn remove_observer(&mut self, id: ObserverId) {
+        self.unsee(id);
+        self.observers[id.0] = None;
+    }
+
+    fn see<T, X, P>(&mut self, x: i32, y: i32, sight: &Fov<T, X>, is_visible: P) -> Vec<usize>
+    where
+        X: AsRef<FluxField>,
+        P: Fn(&T) -> bool,
+    {
+        let mut seen = Vec::new();
+        let counts = &mut self.counts;
+        for_each_tile(self.width, self.height, x, y, sight, |ix, value| {
+            if is_visible(value) {
+                counts[ix] += 1;
+                seen.push(ix);
+            }
+        });
+        seen
+    }
+
+    fn unsee(&mut self, id: ObserverId) {
+        let seen = self.observers[id.0].as_ref().expect("observer removed");
+        for &ix in seen {
+            self.counts[ix] -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn observers_count_and_move() {
+        let flux_field = Rc::new(FluxField::new(2));
+        let mut sight = Fov::new(flux_field, 2, false);
+        sight.update(|x, _, _| x >= 0);
+        let mut map = ObservedByMap::new(8, 5);
+        let guard = map.add_observer(2, 2, &sight, |&v| v);
+        let camera = map.add_observer(3, 2, &sight, |&v| v);
+        assert_eq!(map.observer_count(), 2);
+        assert_eq!(map.at(1, 2), 0);
+        assert_eq!(map.at(2, 2), 1);
+        assert_eq!(map.at(4, 0), 2);
+        assert_eq!(map.at(5, 4), 1);
+        map.move_observer(guard, 6, 2, &sight, |&v| v);
+        assert_eq!(map.at(2, 2), 0);
+        assert_eq!(map.at(4, 0), 1);
+        assert_eq!(map.at(7, 4), 1);
+        assert_eq!(map.at(6, 1), 1);
+        map.remove_observer(camera);
+        assert_eq!(map.observer_count(), 1);
+        assert_eq!(map.at(4, 0), 0);
+        assert_eq!(map.as_slice().iter().sum::<u32>(), 2 * 5);
+        assert_eq!(map.add_observer(0, 0, &sight, |&v| v), camera);
+    }
+
+    #[test]
+    #[should_panic(expected = "tile out of range")]
+    fn at_out_of_range() {
+        // Within the map but past the right edge of the first row.
+        let map = ObservedByMap::new(4, 3);
+        map.at(4, 0);
+    }
+}
@@ -0,0 +1,82 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::sweep::{EDGES, INTERIORS};
+use crate::{FluxField, Fov};
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Iterate over the cells of the edge `edge` outwards from the origin,
+    /// excluding the origin, yielding the coordinates and the value of each.
+    ///
+    /// The edges are numbered as in `Sector`.
+    ///
+    pub fn edge_cells(&self, edge: u8) -> impl Iterator<Item = (i32, i32, &T)> + '_ {
+        let (dx, dy) = EDGES[edge as usize];
+        (1..self.radius as i32 + 1).map(move |k| (dx * k, dy * k, self.at(dx * k, dy * k)))
+    }
+
+    /// Iterate over the interior cells of the octant `octant` in the order
+    /// the update visits them in fields that aren't four-connected, yielding
+    /// the coordinates and the value of each.
+    ///
+    /// The cells are visited column by column outwards from the origin and
+    /// within a column from the edge `octant` towards the edge `octant + 1`.
+    /// The octants are numbered as in `Sector`.
+    ///
+    pub fn octant_cells(&self, octant: u8) -> impl Iterator<Item = (i32, i32, &T)> + '_ {
+        let (m_xu, m_xv, m_yu, m_yv) = INTERIORS[octant as usize];
+        (2..self.radius as i32 + 1)
+            .flat_map(|u| (1..u).map(move |v| (u, v)))
+            .map(move |(u, v)| {
+                let (x, y) = (m_xu * u + m_xv * v, m_yu * u + m_yv * v);
+                (x, y, self.at(x, y))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Sector;
+
+    #[test]
+    fn octants_in_sweep_order() {
+        let mut fov = Fov::new(Box::new(FluxField::new(5)), 5, Sector::Origin);
+        let mut order = Vec::new();
+        fov.update_with_sector(|x, y, sector, _| {
+            order.push((x, y));
+            sector
+        });
+        let mut walked = vec![(0, 0)];
+        for edge in 0..8 {
+            for (x, y, &sector) in fov.edge_cells(edge) {
+                assert!(matches!(sector, Sector::Edge { index, .. } if index == edge));
+                walked.push((x, y));
+            }
+        }
+        for octant in 0..8 {
+            for (x, y, &sector) in fov.octant_cells(octant) {
+                assert!(matches!(sector, Sector::Interior { octant: o, .. } if o == octant));
+                walked.push((x, y));
+            }
+        }
+        assert_eq!(walked, order);
+    }
+}
@@ -0,0 +1,189 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use rayon::prelude::*;
+
+use crate::sweep::{EDGES, INTERIORS};
+use crate::{FluxField, Fov, Influx};
+
+impl<T, X> Fov<T, X>
+where
+    T: Sync,
+    X: AsRef<FluxField>,
+{
+    /// A parallel iterator over the cells of the field of vision yielding
+    /// the coordinates and the value of each cell.
+    ///
+    /// Meant for post-processing the field after an update, e.g., mapping
+    /// the values to colors or collecting statistics.  The cells are yielded
+    /// row by row from the bottom-left corner like in `as_slice`.
+    ///
+    pub fn par_iter(&self) -> impl IndexedParallelIterator<Item = (i32, i32, &T)> + '_ {
+        let (radius, width) = (self.radius as i32, self.width as usize);
+        self.data.par_iter().enumerate().map(move |(ix, value)| {
+            let x = (ix % width) as i32 - radius;
+            let y = (ix / width) as i32 - radius;
+            (x, y, value)
+        })
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    T: Send + Sync,
+    X: AsRef<FluxField>,
+{
+    /// Update the field of vision evaluating the octants in parallel.
+    ///
+    /// The result is the same as with `update` but `update_fn` is called
+    /// from several threads and in a different order.  The origin and the
+    /// edges between the octants are updated first and then the interiors
+    /// of the octants concurrently, each into a buffer of its own that is
+    /// moved into the field in the end.  The four-connected fields are
+    /// updated ring by ring and hence sequentially.
+    ///
+    pub fn par_update<F>(&mut self, update_fn: F)
+    where
+        F: Fn(i32, i32, &[Influx<&T>]) -> T + Sync,
+    {
+        if self.flux_field.as_ref().is_four_connected() {
+            self.update(&update_fn);
+            return;
+        }
+        let radius = self.radius as i32;
+        let ix = self.index(0, 0);
+        self.data[ix] = update_fn(0, 0, &[]);
+        for &(dx, dy) in EDGES.iter() {
+            for k in 1..radius + 1 {
+                let source = self.index(dx * (k - 1), dy * (k - 1));
+                let value = update_fn(
+                    dx * k,
+                    dy * k,
+                    &[Influx {
+                        weight: 1.0,
+                        dx,
+                        dy,
+                        value: &self.data[source],
+                    }],
+                );
+                let ix = self.index(dx * k, dy * k);
+                self.data[ix] = value;
+            }
+        }
+        let (width, ix_origin) = (self.width, self.ix_origin);
+        let index = |x: i32, y: i32| (ix_origin + width * y as isize + x as isize) as usize;
+        let data = &self.data[..];
+        let flux_field = self.flux_field.as_ref();
+        let tap_count = if flux_field.is_three_tap() { 3 } else { 2 };
+        let interiors: Vec<Vec<T>> = INTERIORS
+            .par_iter()
+            .map(|&(m_xu, m_xv, m_yu, m_yv)| {
+                // The cells of the interior are pushed in the order of the
+                // look-up table so the cell (u, v) is found at its index.
+                let mut cells: Vec<T> = Vec::with_capacity(flux_field.flux_lut.len());
+                let at = |u: i32, v: i32| (m_xu * u + m_xv * v, m_yu * u + m_yv * v);
+                for u in 2..radius + 1 {
+                    for v in 1..u {
+                        let value = {
+                            let cell = |u: i32, v: i32| {
+                                if v == 0 || v == u {
+                                    let (x, y) = at(u, v);
+                                    &data[index(x, y)]
+                                } else {
+                                    &cells[crate::lut_len(u as usize - 1) + v as usize - 1]
+                                }
+                            };
+                            let lut_ix = cells.len();
+                            let w = flux_field.flux_lut[lut_ix];
+                            let l = flux_field.lateral(lut_ix);
+                            let influxes = [
+                                Influx {
+                                    weight: w,
+                                    dx: m_xu + m_xv,
+                                    dy: m_yu + m_yv,
+                                    value: cell(u - 1, v - 1),
+                                },
+                                Influx {
+                                    weight: 1.0 - w - l,
+                                    dx: m_xu,
+                                    dy: m_yu,
+                                    value: cell(u - 1, v),
+                                },
+                                Influx {
+                                    weight: l,
+                                    dx: m_xv,
+                                    dy: m_yv,
+                                    value: cell(u, v - 1),
+                                },
+                            ];
+                            let (x, y) = at(u, v);
+                            update_fn(x, y, &influxes[..tap_count])
+                        };
+                        cells.push(value);
+                    }
+                }
+                cells
+            })
+            .collect();
+        for (cells, &(m_xu, m_xv, m_yu, m_yv)) in interiors.into_iter().zip(INTERIORS.iter()) {
+            let mut cells = cells.into_iter();
+            for u in 2..radius + 1 {
+                for v in 1..u {
+                    let ix = self.index(m_xu * u + m_xv * v, m_yu * u + m_yv * v);
+                    self.data[ix] = cells.next().unwrap();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::all_params;
+
+    #[test]
+    fn par_iter_yields_coordinates() {
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, (0, 0));
+        fov.update(|x, y, _| (x, y));
+        assert_eq!(fov.par_iter().count(), 13 * 13);
+        assert!(fov.par_iter().all(|(x, y, &value)| (x, y) == value));
+        let sum: i32 = fov.par_iter().map(|(x, _, _)| x.abs()).sum();
+        assert_eq!(sum, 13 * 2 * 21);
+    }
+
+    #[test]
+    fn par_update_matches_update() {
+        let kernel = |x: i32, y: i32, influxes: &[Influx<&f32>]| {
+            if (x, y) == (0, 0) {
+                1.0
+            } else if (x + 2 * y) % 7 == 3 {
+                0.0
+            } else {
+                influxes.iter().map(|f| f.weight * f.value).sum()
+            }
+        };
+        for params in all_params() {
+            let flux_field = std::rc::Rc::new(FluxField::with_params(30, params));
+            let mut fov = Fov::new(flux_field.clone(), 30, 0.0);
+            fov.update(kernel);
+            let mut par = Fov::new(flux_field, 30, 0.5);
+            par.par_update(kernel);
+            assert_eq!(par.as_slice(), fov.as_slice());
+        }
+    }
+}
@@ -0,0 +1,142 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{bresenham_phase, march_ray, FluxField};
+
+impl FluxField {
+    /// The cells a ray from the origin to the cell (`x`, `y`) passes
+    /// through, from the origin to the cell itself.
+    ///
+    /// The ray is the same eight-connected digital line the look-up table
+    /// is built from, so a projectile following the path passes through the
+    /// cells that feed the target.  In the Bresenham mode (see
+    /// `FluxParams::bresenham`) the path is the classic Bresenham line,
+    /// stepping along the major axis with the error term starting at half
+    /// the major distance, mirrored to the other octants; otherwise the
+    /// exact halfway points round away from the major axis instead of
+    /// towards it.
+    ///
+    /// ```
+    /// # use flux_fov::{FluxField, FluxParams};
+    /// let bresenham = FluxField::with_params(
+    ///     4,
+    ///     FluxParams {
+    ///         bresenham: true,
+    ///         ..FluxParams::default()
+    ///     },
+    /// );
+    /// assert_eq!(bresenham.path_to(4, -1), [(0, 0), (1, 0), (2, 0), (3, -1), (4, -1)]);
+    /// assert_eq!(FluxField::new(4).path_to(4, -1)[2], (2, -1));
+    /// ```
+    ///
+    pub fn path_to(&self, x: i32, y: i32) -> Vec<(i32, i32)> {
+        let (major, minor) = (x.abs().max(y.abs()), x.abs().min(y.abs()));
+        let (sx, sy) = (x.signum(), y.signum());
+        let is_steep = y.abs() > x.abs();
+        let (major, minor) = (major as usize, minor as usize);
+        let phase = if self.params.bresenham {
+            bresenham_phase(major.max(1))
+        } else {
+            major / 2
+        };
+        let mut path = Vec::with_capacity(major + 1);
+        if major == 0 {
+            path.push((0, 0));
+            return path;
+        }
+        march_ray(0, major, major, minor, phase, |i, j, _| {
+            let (i, j) = (i as i32, j as i32);
+            path.push(if is_steep {
+                (sx * j, sy * i)
+            } else {
+                (sx * i, sy * j)
+            });
+        });
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::FluxParams;
+
+    // The classic integer Bresenham line in the first octant.
+    fn bresenham(x: i32, y: i32) -> Vec<(i32, i32)> {
+        let mut err = x / 2;
+        let mut j = 0;
+        let mut line = vec![(0, 0)];
+        for i in 1..x + 1 {
+            err -= y;
+            if err < 0 {
+                j += 1;
+                err += x;
+            }
+            line.push((i, j));
+        }
+        line
+    }
+
+    #[test]
+    fn bresenham_paths_match_classic_bresenham() {
+        let flux_field = FluxField::with_params(
+            2,
+            FluxParams {
+                bresenham: true,
+                ..FluxParams::default()
+            },
+        );
+        for x in 0..13 {
+            for y in 0..x + 1 {
+                let line = bresenham(x, y);
+                assert_eq!(flux_field.path_to(x, y), line);
+                let mirrored: Vec<_> = line.iter().map(|&(i, j)| (-j, i)).collect();
+                assert_eq!(flux_field.path_to(-y, x), mirrored);
+            }
+        }
+    }
+
+    #[test]
+    fn paths_are_connected() {
+        let flux_field = FluxField::new(2);
+        for x in -9..10 {
+            for y in -9..10 {
+                let path = flux_field.path_to(x, y);
+                assert_eq!(path[0], (0, 0));
+                assert_eq!(*path.last().unwrap(), (x, y));
+                for step in path.windows(2) {
+                    let (dx, dy) = (step[1].0 - step[0].0, step[1].1 - step[0].1);
+                    assert!(dx.abs() <= 1 && dy.abs() <= 1 && (dx, dy) != (0, 0));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bresenham_lut() {
+        let params = FluxParams {
+            bresenham: true,
+            ..FluxParams::default()
+        };
+        let flux_field = FluxField::with_params(10, params);
+        assert!(flux_field.verify_normalization());
+        assert_ne!(flux_field.flux_lut[..], FluxField::new(10).flux_lut[..]);
+        let mut grown = FluxField::with_params(6, params);
+        grown.grow_to(10);
+        assert_eq!(grown.flux_lut[..], flux_field.flux_lut[..]);
+    }
+}
@@ -0,0 +1,180 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{FluxField, Fov, Influx, Sector};
+
+/// A field of vision that continues through portals.
+///
+/// The field consists of segments.  The segment 0 is the ordinary field of
+/// vision around the viewer.  When the flux reaches a portal cell it
+/// continues from the linked cell in a segment of its own, so that looking
+/// through a magic mirror or down a stairwell shows the far side without
+/// stitching together separate runs.
+///
+/// The segment of a portal is centered on the linked cell and its radius is
+/// what remains of the radius of the field after reaching the portal.  The
+/// origin of the segment receives a single influx carrying the value of the
+/// portal cell.  The direction of the influx is the direction from the
+/// viewer to the portal snapped to one of the eight neighbors, which
+/// the update function can use to limit the view to the far side.
+///
+pub struct PortalFov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    init: T,
+    radius: usize,
+    main: Fov<T, X>,
+    portals: Vec<(i32, i32)>,
+    segments: Vec<Fov<T, X>>,
+}
+
+impl<T, X> PortalFov<T, X>
+where
+    T: Clone,
+    X: AsRef<FluxField> + Clone,
+{
+    pub fn new(flux_field: X, radius: usize, init: T) -> Self {
+        PortalFov {
+            init: init.clone(),
+            radius,
+            main: Fov::new(flux_field, radius, init),
+            portals: Vec::new(),
+            segments: Vec::new(),
+        }
+    }
+
+    /// Update the field with the given portals.
+    ///
+    /// The portals are given as the coordinates of the portal cells relative
+    /// to the viewer.  The segment of the portal `i` has the index `i + 1`.
+    /// The update function receives the index of the segment in addition to
+    /// the arguments of the function of `Fov::update`; the coordinates are
+    /// relative to the origin of the segment, i.e. the viewer or the cell
+    /// linked to the portal.  The portals outside the field get empty
+    /// segments.
+    ///
+    pub fn update<F>(&mut self, portals: &[(i32, i32)], update_fn: F)
+    where
+        F: FnMut(usize, i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        self.main
+            .update(|x, y, influxes| update_fn(0, x, y, influxes));
+        self.portals.clear();
+        self.portals.extend_from_slice(portals);
+        self.segments.truncate(portals.len());
+        let radius = self.radius as i32;
+        for (i, &(px, py)) in portals.iter().enumerate() {
+            let distance = px.abs().max(py.abs());
+            let remaining = if distance <= radius {
+                (radius - distance) as usize
+            } else {
+                0
+            };
+            if self.segments.len() == i {
+                let flux_field = self.main.flux_field.clone();
+                self.segments
+                    .push(Fov::new(flux_field, remaining, self.init.clone()));
+            } else if self.segments[i].radius as usize != remaining {
+                let flux_field = self.segments[i].flux_field.clone();
+                self.segments[i] = Fov::new(flux_field, remaining, self.init.clone());
+            }
+            if distance > radius {
+                continue;
+            }
+            let entry = Influx {
+                weight: 1.0,
+                dx: px.signum() * (2 * px.abs() >= distance) as i32,
+                dy: py.signum() * (2 * py.abs() >= distance) as i32,
+                value: self.main.at(px, py),
+            };
+            let entry = [entry];
+            self.segments[i].update_cells(|x, y, sector, cell, influxes| {
+                let influxes = if sector == Sector::Origin {
+                    &entry[..]
+                } else {
+                    influxes
+                };
+                *cell = update_fn(i + 1, x, y, influxes);
+            });
+        }
+    }
+}
+
+impl<T, X> PortalFov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// The number of segments including the main one.
+    pub fn segment_count(&self) -> usize {
+        self.segments.len() + 1
+    }
+
+    /// The segment with the given index.
+    pub fn segment(&self, segment: usize) -> &Fov<T, X> {
+        if segment == 0 {
+            &self.main
+        } else {
+            &self.segments[segment - 1]
+        }
+    }
+
+    /// The portal cell leading to the segment with the given index.
+    pub fn portal(&self, segment: usize) -> (i32, i32) {
+        assert!(segment > 0, "the main segment has no portal");
+        self.portals[segment - 1]
+    }
+
+    /// The value at the given cell of the segment.
+    pub fn at(&self, segment: usize, x: i32, y: i32) -> &T {
+        self.segment(segment).at(x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn flux_continues_through_portal() {
+        let flux_field = Rc::new(FluxField::new(8));
+        let mut fov = PortalFov::new(flux_field, 8, 0.0);
+        let mut entries = Vec::new();
+        fov.update(&[(3, 0), (-9, 0)], |segment, x, y, influxes| {
+            if segment == 0 && x == 0 && y == 0 {
+                1.0
+            } else if segment == 0 && x > 3 {
+                // Nothing beyond the portal in the main segment.
+                0.0
+            } else if segment == 1 && x == 0 && y == 0 {
+                entries.push((influxes[0].dx, influxes[0].dy));
+                0.5 * *influxes[0].value
+            } else {
+                influxes.iter().map(|f| f.weight * *f.value).sum()
+            }
+        });
+        assert_eq!(entries, [(1, 0)]);
+        assert_eq!(fov.segment_count(), 3);
+        assert_eq!(fov.portal(1), (3, 0));
+        assert_eq!(*fov.at(0, 4, 0), 0.0);
+        assert_eq!(fov.segment(1).radius(), 5);
+        assert_eq!(*fov.at(1, 5, 0), 0.5);
+        assert_eq!(fov.segment(2).radius(), 0);
+    }
+}
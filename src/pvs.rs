@@ -0,0 +1,130 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::kernel::{self, Visibility};
+use crate::{FluxField, Fov, OpacityGrid};
+
+/// A potentially visible set telling which regions of a static map, such as
+/// the rooms of a dungeon, can possibly see each other.
+///
+/// A field of vision is computed from every tile of every region with the
+/// symmetric kernel, the tiles with opacity 1.0 being walls.  That kernel is
+/// more permissive than the others so the set errs on the side of
+/// visibility: if any tile of a region is visible from any tile of another
+/// at all, the regions are marked mutually visible.  At runtime the regions
+/// not in the set of the viewer's region can be skipped altogether.
+///
+pub struct Pvs {
+    region_count: usize,
+    visible: Vec<bool>,
+}
+
+impl Pvs {
+    /// Compute the set for the map `grid` whose tiles belong to the regions
+    /// given by `regions` in row-major order, `None` for the tiles outside
+    /// all regions.  The regions are numbered from 0 up to `region_count`.
+    ///
+    pub fn compute<X>(
+        flux_field: X,
+        radius: usize,
+        grid: &OpacityGrid,
+        regions: &[Option<usize>],
+        region_count: usize,
+    ) -> Self
+    where
+        X: AsRef<FluxField>,
+    {
+        let (width, height) = (grid.width(), grid.height());
+        assert_eq!(regions.len(), width * height, "region map size mismatch");
+        let mut visible = vec![false; region_count * region_count];
+        let mut fov = Fov::new(flux_field, radius, Visibility::default());
+        let r = radius as i32;
+        for (ix, &from) in regions.iter().enumerate() {
+            let from = match from {
+                Some(from) if grid.as_slice()[ix] < 1.0 => from,
+                _ => continue,
+            };
+            let (ox, oy) = ((ix % width) as i32, (ix / width) as i32);
+            let opacity = grid.relative_to((ox, oy));
+            fov.update(kernel::symmetric(|x, y| opacity(x, y) >= 1.0));
+            for y in (oy - r).max(0)..(oy + r + 1).min(height as i32) {
+                for x in (ox - r).max(0)..(ox + r + 1).min(width as i32) {
+                    if let Some(to) = regions[y as usize * width + x as usize] {
+                        if fov.at(x - ox, y - oy).is_visible {
+                            visible[from * region_count + to] = true;
+                            visible[to * region_count + from] = true;
+                        }
+                    }
+                }
+            }
+        }
+        Pvs {
+            region_count,
+            visible,
+        }
+    }
+
+    /// The number of regions.
+    pub fn region_count(&self) -> usize {
+        self.region_count
+    }
+
+    /// Whether any tile of the region `to` may be visible from the region
+    /// `from`.
+    ///
+    pub fn can_see(&self, from: usize, to: usize) -> bool {
+        self.visible[from * self.region_count + to]
+    }
+
+    /// The regions possibly visible from the region `from`, itself
+    /// included.
+    ///
+    pub fn visible_from(&self, from: usize) -> impl Iterator<Item = usize> + '_ {
+        (0..self.region_count).filter(move |&to| self.can_see(from, to))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn rooms_behind_walls() {
+        let map = "
+            ..#..#....
+            ..#..#....
+            .....#....
+            ..#..#....
+        ";
+        let grid = OpacityGrid::from_ascii(map, OpacityGrid::ASCII_TILES).unwrap();
+        let regions: Vec<_> = (0..grid.width() * grid.height())
+            .map(|ix| match ix % 10 {
+                0..=1 => Some(0),
+                3..=4 => Some(1),
+                6..=9 => Some(2),
+                _ => None,
+            })
+            .collect();
+        let pvs = Pvs::compute(Box::new(FluxField::new(8)), 8, &grid, &regions, 3);
+        assert_eq!(pvs.region_count(), 3);
+        assert!(pvs.can_see(0, 1) && pvs.can_see(1, 0));
+        assert!(pvs.can_see(1, 1));
+        assert!(!pvs.can_see(1, 2));
+        assert!(!pvs.can_see(0, 2));
+        assert_eq!(pvs.visible_from(2).collect::<Vec<_>>(), vec![2]);
+    }
+}
@@ -0,0 +1,194 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::iter::once;
+
+use crate::{FluxField, Fov, Influx, Rect};
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Update the field of vision only as far as needed to tell whether any
+    /// cell of `area` is visible.
+    ///
+    /// The `area` is relative to the origin.  The update stops calling
+    /// `update_fn` as soon as a cell of the area is found visible or all the
+    /// cells of the area have been updated, whichever comes first.  The cells
+    /// left over keep their previous values so the field should not be used
+    /// for anything else afterwards without a full update.
+    ///
+    pub fn update_any_visible<P, F>(&mut self, area: &Rect, is_visible: P, update_fn: F) -> bool
+    where
+        P: Fn(&T) -> bool,
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        let r = self.radius as i32;
+        let area = area.intersect(&Rect::new(-r, -r, 2 * r + 1, 2 * r + 1));
+        let mut remaining = area.width as usize * area.height as usize;
+        let mut found = false;
+        let mut update_fn = update_fn;
+        if remaining > 0 {
+            self.update_cells(|x, y, _, cell, influxes| {
+                if found || remaining == 0 {
+                    return;
+                }
+                *cell = update_fn(x, y, influxes);
+                if area.contains(x, y) {
+                    remaining -= 1;
+                    found = is_visible(cell);
+                }
+            });
+        }
+        found
+    }
+
+    /// Whether any cell of `area` is visible in the field as it stands,
+    /// e.g., for culling chunks of the tilemap that are off-screen or fully
+    /// dark.
+    ///
+    /// The `area` is relative to the origin; the cells outside the field
+    /// are not visible.  The search stops at the first visible cell.
+    ///
+    pub fn is_region_visible<P>(&self, area: &Rect, is_visible: P) -> bool
+    where
+        P: Fn(&T) -> bool,
+    {
+        let r = self.radius as i32;
+        let area = area.intersect(&Rect::new(-r, -r, 2 * r + 1, 2 * r + 1));
+        (area.y..area.y + area.height).any(|y| {
+            let start = self.index(area.x, y);
+            self.data[start..start + area.width as usize]
+                .iter()
+                .any(&is_visible)
+        })
+    }
+
+    /// The cell nearest to the origin, by Euclidean distance, whose value
+    /// satisfies `predicate`, e.g., the closest visible water tile.
+    ///
+    /// The search proceeds outwards ring by ring and stops as soon as no
+    /// farther ring can hold a nearer cell, so a match close to the origin
+    /// is found without looking at the rest of the field.  Of equally near
+    /// cells the one with the smallest y and then x is returned.
+    ///
+    pub fn nearest_where<P>(&self, predicate: P) -> Option<(i32, i32, &T)>
+    where
+        P: Fn(i32, i32, &T) -> bool,
+    {
+        let mut best: Option<(i32, i32, i32)> = None;
+        for k in 0..self.radius as i32 + 1 {
+            if let Some((d2, _, _)) = best {
+                if k * k > d2 {
+                    break;
+                }
+            }
+            for (x, y) in ring_cells(k) {
+                let d2 = x * x + y * y;
+                if best.is_none_or(|b| (d2, y, x) < b) && predicate(x, y, self.at(x, y)) {
+                    best = Some((d2, y, x));
+                }
+            }
+        }
+        best.map(|(_, y, x)| (x, y, self.at(x, y)))
+    }
+}
+
+// The cells whose Chebyshev distance from the origin is `k`.
+pub(crate) fn ring_cells(k: i32) -> impl Iterator<Item = (i32, i32)> {
+    let rows = (-k..k + 1).flat_map(move |x| {
+        let ys = if k == 0 { 1 } else { 2 };
+        (0..ys).map(move |i| (x, if i == 0 { -k } else { k }))
+    });
+    let columns = (-k + 1..k).flat_map(move |y| once((-k, y)).chain(once((k, y))));
+    rows.chain(columns)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel::{self, Visibility};
+
+    #[test]
+    fn region_visibility_over_stored_field() {
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, Visibility::default());
+        fov.update(kernel::threshold(
+            0.5,
+            |x, _| if x == 2 { 1.0 } else { 0.0 },
+        ));
+        assert!(!fov.is_region_visible(&Rect::new(4, -1, 3, 3), |v| v.is_visible));
+        assert!(fov.is_region_visible(&Rect::new(-20, -20, 40, 40), |v| v.is_visible));
+        assert!(!fov.is_region_visible(&Rect::new(7, 0, 5, 5), |_| true));
+        assert!(fov.is_region_visible(&Rect::new(6, 6, 5, 5), |_| true));
+    }
+
+    #[test]
+    fn nearest_matching_cell() {
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, 0);
+        fov.update(|x, y, _| (x + 7 * y).rem_euclid(11));
+        let nearest = fov.nearest_where(|_, _, &v| v == 10);
+        let mut expected = None;
+        for y in -6..7 {
+            for x in -6..7 {
+                if *fov.at(x, y) == 10 {
+                    let key = (x * x + y * y, y, x);
+                    if expected.is_none_or(|e| key < e) {
+                        expected = Some(key);
+                    }
+                }
+            }
+        }
+        let (_, y, x) = expected.unwrap();
+        assert_eq!(nearest, Some((x, y, &10)));
+        assert_eq!(fov.nearest_where(|_, _, &v| v == 0), Some((0, 0, &0)));
+        assert_eq!(fov.nearest_where(|_, _, &v| v > 10), None);
+        assert_eq!(ring_cells(2).count(), 16);
+    }
+
+    #[test]
+    fn stops_early() {
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, Visibility::default());
+        let wall = |x: i32, _: i32| if x == 2 { 1.0 } else { 0.0 };
+        let mut calls = 0;
+        let mut kernel = kernel::threshold(0.5, wall);
+        let visible = fov.update_any_visible(
+            &Rect::new(4, -1, 2, 3),
+            |v| v.is_visible,
+            |x, y, f| {
+                calls += 1;
+                kernel(x, y, f)
+            },
+        );
+        assert!(!visible);
+        let mut kernel = kernel::threshold(0.5, wall);
+        let visible = fov.update_any_visible(
+            &Rect::new(-5, 2, 2, 2),
+            |v| v.is_visible,
+            |x, y, f| {
+                calls += 1;
+                kernel(x, y, f)
+            },
+        );
+        assert!(visible);
+        assert!(calls < 2 * 13 * 13);
+        assert!(!fov.update_any_visible(
+            &Rect::new(7, 0, 2, 2),
+            |v| v.is_visible,
+            |_, _, _| { panic!("outside the field") }
+        ));
+    }
+}
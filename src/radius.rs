@@ -0,0 +1,135 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::f32::consts::PI;
+
+use crate::{FluxField, Fov, Influx};
+
+/// A viewing radius that depends on the direction.
+///
+/// The mask clips the field of vision to a star-shaped region: a cell is
+/// within the mask if its distance from the origin is at most the radius in
+/// its direction.  The radii are kept in a table of evenly spaced directions
+/// starting from the positive x-axis and turning towards the positive
+/// y-axis and interpolated linearly in between.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct RadiusMask {
+    radii: Vec<f32>,
+}
+
+impl RadiusMask {
+    /// Constructs a mask from a table of radii, e.g., with 360 entries one
+    /// per degree.
+    ///
+    pub fn from_table(radii: Vec<f32>) -> Self {
+        assert!(!radii.is_empty(), "empty radius table");
+        RadiusMask { radii }
+    }
+
+    /// Constructs a mask by sampling the radius function `radius` at 360
+    /// directions.  The function receives the direction in radians from 0.0
+    /// to 2π.
+    ///
+    pub fn from_fn<F>(radius: F) -> Self
+    where
+        F: Fn(f32) -> f32,
+    {
+        RadiusMask::from_table((0..360).map(|d| radius((d as f32).to_radians())).collect())
+    }
+
+    /// The radius in the given direction.
+    pub fn radius_at(&self, angle: f32) -> f32 {
+        let n = self.radii.len();
+        let t = angle.rem_euclid(2.0 * PI) / (2.0 * PI) * n as f32;
+        let i = (t as usize).min(n - 1);
+        let f = t - i as f32;
+        self.radii[i] * (1.0 - f) + self.radii[(i + 1) % n] * f
+    }
+
+    /// Whether the cell is within the mask.
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        if x == 0 && y == 0 {
+            return true;
+        }
+        let radius = self.radius_at((y as f32).atan2(x as f32));
+        (x * x + y * y) as f32 <= radius * radius
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    T: Clone,
+    X: AsRef<FluxField>,
+{
+    /// Update the field of vision clipped to the given mask.
+    ///
+    /// The cells outside the mask are set to `outside` without calling
+    /// `update_fn`.  As the flux cannot pass through them the kernels need
+    /// not know about the mask as long as `outside` is the value of a cell
+    /// passing on no flux.
+    ///
+    pub fn update_within<F>(&mut self, mask: &RadiusMask, outside: T, update_fn: F)
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        self.update_cells(|x, y, _, cell, influxes| {
+            *cell = if mask.contains(x, y) {
+                update_fn(x, y, influxes)
+            } else {
+                outside.clone()
+            };
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel::{self, Visibility};
+
+    #[test]
+    fn radius_interpolation() {
+        let mask = RadiusMask::from_table(vec![4.0, 2.0, 4.0, 2.0]);
+        assert_eq!(mask.radius_at(0.0), 4.0);
+        assert_eq!(mask.radius_at(PI / 4.0), 3.0);
+        assert_eq!(mask.radius_at(PI / 2.0), 2.0);
+        assert_eq!(mask.radius_at(-PI / 4.0), 3.0);
+        assert!(mask.contains(4, 0));
+        assert!(!mask.contains(0, 3));
+    }
+
+    #[test]
+    fn lopsided_view() {
+        // Sees six cells ahead (east) but only two behind.
+        let mask = RadiusMask::from_fn(|a| 4.0 + 2.0 * a.cos());
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, Visibility::default());
+        let mut calls = 0;
+        let mut kernel = kernel::threshold(0.5, |_, _| 0.0);
+        fov.update_within(&mask, Visibility::default(), |x, y, influxes| {
+            calls += 1;
+            kernel(x, y, influxes)
+        });
+        assert!(fov.at(6, 0).is_visible);
+        assert!(fov.at(-2, 0).is_visible);
+        assert!(!fov.at(-3, 0).is_visible);
+        assert!(fov.at(0, 4).is_visible);
+        assert!(!fov.at(0, 5).is_visible);
+        assert!(calls < 13 * 13 / 2);
+    }
+}
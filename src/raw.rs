@@ -0,0 +1,172 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::sweep::sweep;
+use crate::{lut_len, FluxField, Fov, Influx, Sector};
+
+/// The raw look-up table weights of a grid cell; see
+/// `Fov::update_with_weights`.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CellWeights {
+    /// The origin, which receives no influxes.
+    Origin,
+    /// A cell on one of the edges radiating from the origin.  The weights
+    /// of its influxes are fixed rather than looked up from the table.
+    Edge,
+    /// An interior cell of an octant with its entry in the look-up table.
+    Interior {
+        /// The index of the entry in the look-up table shared by the eight
+        /// octants; see `FluxField::write_table` for the layout.
+        lut_index: usize,
+        /// The weight of the diagonal influx as stored in the table.
+        diagonal: f32,
+        /// The weight of the lateral influx, zero unless in the three-tap
+        /// or four-connected mode.
+        lateral: f32,
+    },
+}
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Update the field of vision with a function that also receives the
+    /// raw look-up table weights of each cell.
+    ///
+    /// The influxes are the same as with `update`; the raw weights tell
+    /// where they come from, e.g., for blending schemes that treat the
+    /// diagonal and the straight flux differently or take their maximum
+    /// instead of the weighted sum.
+    ///
+    /// ```
+    /// # use flux_fov::{CellWeights, FluxField, Fov};
+    /// let mut fov = Fov::new(Box::new(FluxField::new(4)), 4, 0.0_f32);
+    /// fov.update_with_weights(|_, _, weights, influxes| match weights {
+    ///     CellWeights::Origin => 1.0,
+    ///     // The brightest influx if the diagonal one carries at least a
+    ///     // quarter of the flux and the weighted sum otherwise.
+    ///     CellWeights::Interior { diagonal, .. } if diagonal >= 0.25 => {
+    ///         influxes.iter().map(|f| *f.value).fold(0.0, f32::max)
+    ///     }
+    ///     _ => influxes.iter().map(|f| f.weight * *f.value).sum(),
+    /// });
+    /// ```
+    ///
+    pub fn update_with_weights<F>(&mut self, update_fn: F)
+    where
+        F: FnMut(i32, i32, CellWeights, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        let flux_field = self.flux_field.as_ref();
+        let data = &mut self.data;
+        sweep(
+            self.radius,
+            self.width,
+            self.ix_origin,
+            flux_field,
+            |step| {
+                let weights = match step.sector {
+                    Sector::Origin => CellWeights::Origin,
+                    Sector::Edge { .. } => CellWeights::Edge,
+                    Sector::Interior { column, .. } => {
+                        let minor = step.x.unsigned_abs().min(step.y.unsigned_abs()) as usize;
+                        let lut_index = lut_len(column as usize - 1) + minor - 1;
+                        CellWeights::Interior {
+                            lut_index,
+                            diagonal: flux_field.flux_lut[lut_index],
+                            lateral: flux_field.lateral(lut_index),
+                        }
+                    }
+                };
+                let cell = &data[step.ix];
+                let mut influxes = [
+                    Influx {
+                        weight: 0.0,
+                        dx: 0,
+                        dy: 0,
+                        value: cell,
+                    },
+                    Influx {
+                        weight: 0.0,
+                        dx: 0,
+                        dy: 0,
+                        value: cell,
+                    },
+                    Influx {
+                        weight: 0.0,
+                        dx: 0,
+                        dy: 0,
+                        value: cell,
+                    },
+                ];
+                let taps = step.taps();
+                for (influx, tap) in influxes.iter_mut().zip(taps.iter()) {
+                    influx.weight = tap.weight;
+                    influx.dx = tap.dx;
+                    influx.dy = tap.dy;
+                    influx.value = &data[tap.ix];
+                }
+                let value = update_fn(step.x, step.y, weights, &influxes[..taps.len()]);
+                data[step.ix] = value;
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::all_params;
+    use crate::Connectivity;
+
+    #[test]
+    fn raw_weights_match_influxes() {
+        for params in all_params() {
+            let mut fov = Fov::new(Box::new(FluxField::with_params(6, params)), 6, 0);
+            let mut interior = 0;
+            fov.update_with_weights(|x, y, weights, influxes| {
+                match weights {
+                    CellWeights::Origin => assert_eq!((x, y), (0, 0)),
+                    CellWeights::Edge => {
+                        assert!(x == 0 || y == 0 || x.abs() == y.abs());
+                    }
+                    CellWeights::Interior {
+                        lut_index,
+                        diagonal,
+                        lateral,
+                    } => {
+                        interior += 1;
+                        assert!(lut_index < 15);
+                        let sum: f32 = influxes.iter().map(|f| f.weight).sum();
+                        assert!((sum - 1.0).abs() < 1e-6);
+                        if params.connectivity == Connectivity::Eight {
+                            let diagonal_influx =
+                                influxes.iter().find(|f| f.dx != 0 && f.dy != 0).unwrap();
+                            assert_eq!(diagonal_influx.weight, diagonal);
+                        }
+                        if lateral > 0.0 {
+                            assert!(influxes.iter().any(|f| f.weight == lateral));
+                        }
+                    }
+                }
+                0
+            });
+            assert_eq!(interior, 8 * 15);
+        }
+    }
+}
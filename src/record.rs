@@ -0,0 +1,154 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{FluxField, Fov, Influx};
+
+/// A single cell visited by a recorded update.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedCell<T> {
+    pub x: i32,
+    pub y: i32,
+    /// The influxes the update function received.
+    pub influxes: Vec<RecordedInflux<T>>,
+    /// The value the update function produced.
+    pub value: T,
+}
+
+/// An influx received by the update function during a recorded update.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedInflux<T> {
+    pub weight: f32,
+    pub dx: i32,
+    pub dy: i32,
+    pub value: T,
+}
+
+/// The sequence of calls made to the update function during an update.
+///
+/// A recording captures everything the update function saw and produced so
+/// it can be replayed against a kernel without the map or the rest of the
+/// game.  This makes it possible to reproduce an artifact from a report and
+/// to bisect the kernel changes that introduced it.
+///
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recording<T> {
+    cells: Vec<RecordedCell<T>>,
+}
+
+impl<T> Recording<T> {
+    /// The recorded cells in the update order.
+    pub fn cells(&self) -> &[RecordedCell<T>] {
+        self.cells.as_slice()
+    }
+
+    /// Replay the recording against the given update function.
+    ///
+    /// The function is called with the recorded coordinates and influxes of
+    /// every cell in the update order.  Returns the index of the first cell
+    /// for which the function produces a value different from the recorded
+    /// one, or `None` if the function reproduces the whole recording.
+    ///
+    pub fn replay<F>(&self, update_fn: F) -> Option<usize>
+    where
+        T: PartialEq,
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        let mut influxes = Vec::with_capacity(2);
+        self.cells.iter().position(|cell| {
+            influxes.clear();
+            influxes.extend(cell.influxes.iter().map(|f| Influx {
+                weight: f.weight,
+                dx: f.dx,
+                dy: f.dy,
+                value: &f.value,
+            }));
+            update_fn(cell.x, cell.y, &influxes) != cell.value
+        })
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    T: Clone,
+    X: AsRef<FluxField>,
+{
+    /// Update the field of vision with the given function and record the
+    /// calls made to it.
+    ///
+    /// This is a debugging aid; the recording copies every influx value and
+    /// is considerably slower than a plain update.
+    ///
+    pub fn update_recorded<F>(&mut self, update_fn: F) -> Recording<T>
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        let mut cells = Vec::with_capacity(self.data.len());
+        self.update_cells(|x, y, _, cell, influxes| {
+            let value = update_fn(x, y, influxes);
+            cells.push(RecordedCell {
+                x,
+                y,
+                influxes: influxes
+                    .iter()
+                    .map(|f| RecordedInflux {
+                        weight: f.weight,
+                        dx: f.dx,
+                        dy: f.dy,
+                        value: f.value.clone(),
+                    })
+                    .collect(),
+                value: value.clone(),
+            });
+            *cell = value;
+        });
+        Recording { cells }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn kernel(wall: i32) -> impl FnMut(i32, i32, &[Influx<&f32>]) -> f32 {
+        move |x, y, influxes| {
+            if x == 0 && y == 0 {
+                1.0
+            } else if x == wall {
+                0.0
+            } else {
+                influxes.iter().map(|f| f.weight * *f.value).sum()
+            }
+        }
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let mut fov = Fov::new(Box::new(FluxField::new(4)), 4, 0.0);
+        let recording = fov.update_recorded(kernel(2));
+        assert_eq!(recording.cells().len(), 81);
+        assert_eq!(recording.cells()[0].influxes.len(), 0);
+        let last = recording.cells().last().unwrap();
+        assert_eq!(*fov.at(last.x, last.y), last.value);
+        assert_eq!(recording.replay(kernel(2)), None);
+        let diverged = recording.replay(kernel(3)).unwrap();
+        assert_eq!(recording.cells()[diverged].x, 2);
+    }
+}
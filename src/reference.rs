@@ -0,0 +1,129 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::analysis::ray_cells;
+use crate::{FluxField, Fov, Influx};
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Update the field of vision by casting an exact ray to every cell.
+    ///
+    /// This is the slow but exact reference for `update`: the value of each
+    /// cell is computed by applying `update_fn` cell by cell along the ray
+    /// from the center of the origin to the center of the cell, each cell
+    /// receiving a single influx of weight 1.0 from the previous cell on the
+    /// ray; see `analysis::transmittance` for the cells a ray crosses.  Any
+    /// kernel written for `update` works as is.  The cost grows with the cube
+    /// of the radius rather than the square so this is meant as a correctness
+    /// oracle for tests and for the occasional check that must be exact,
+    /// e.g., a puzzle, rather than for every frame.
+    ///
+    /// The values of the cells along a ray are computed anew for each ray,
+    /// so `update_fn` is called many times for the same cell and must not
+    /// depend on the order of the calls.
+    ///
+    pub fn update_reference<F>(&mut self, update_fn: F)
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        let radius = self.radius as i32;
+        let origin = update_fn(0, 0, &[]);
+        let mut ray: Vec<T> = Vec::with_capacity(2 * radius as usize);
+        for y in -radius..radius + 1 {
+            for x in -radius..radius + 1 {
+                if x == 0 && y == 0 {
+                    continue;
+                }
+                ray.clear();
+                let (mut px, mut py) = (0, 0);
+                for (cx, cy) in ray_cells(x, y) {
+                    let value = update_fn(
+                        cx,
+                        cy,
+                        &[Influx {
+                            weight: 1.0,
+                            dx: cx - px,
+                            dy: cy - py,
+                            value: ray.last().unwrap_or(&origin),
+                        }],
+                    );
+                    ray.push(value);
+                    px = cx;
+                    py = cy;
+                }
+                let ix = self.index(x, y);
+                self.data[ix] = ray.pop().unwrap();
+            }
+        }
+        let ix = self.index(0, 0);
+        self.data[ix] = origin;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::analysis::transmittance;
+    use crate::kernel;
+
+    #[test]
+    fn reference_matches_exact_rays() {
+        let smoke = |x: i32, y: i32| if x == 2 || y == -3 { 0.5 } else { 0.0 };
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, Default::default());
+        fov.update_reference(kernel::soft_shadow(smoke));
+        for y in -6..7 {
+            for x in -6..7 {
+                assert_eq!(fov.at(x, y).brightness, transmittance(x, y, smoke));
+            }
+        }
+    }
+
+    #[test]
+    fn reference_influxes() {
+        let mut fov = Fov::new(Box::new(FluxField::new(3)), 3, Vec::new());
+        fov.update_reference(|x, y, influxes| match influxes {
+            [] => vec![(x, y)],
+            [influx] => {
+                assert_eq!(influx.weight, 1.0);
+                let mut path = influx.value.clone();
+                let (px, py) = *path.last().unwrap();
+                assert_eq!((influx.dx, influx.dy), (x - px, y - py));
+                path.push((x, y));
+                path
+            }
+            _ => unreachable!(),
+        });
+        assert_eq!(fov.at(0, 0), &vec![(0, 0)]);
+        assert_eq!(fov.at(-3, 1), &vec![(0, 0), (-1, 0), (-2, 1), (-3, 1)]);
+        assert_eq!(fov.at(2, 2), &vec![(0, 0), (1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn reference_shadow_behind_pillar() {
+        let pillar = |x, y| if (x, y) == (1, 0) { 1.0 } else { 0.0 };
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, Default::default());
+        fov.update_reference(kernel::threshold(0.0, pillar));
+        assert!(fov.at(1, 0).is_visible);
+        assert!(!fov.at(6, 1).is_visible);
+        assert!(!fov.at(6, 3).is_visible);
+        assert!(fov.at(6, 6).is_visible);
+        assert!(fov.at(-6, 1).is_visible);
+    }
+}
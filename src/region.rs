@@ -0,0 +1,94 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::allocator::Allocator;
+use crate::{FluxField, Fov, Rect};
+
+impl<T, X, A> Fov<T, X, A>
+where
+    T: Clone,
+    X: AsRef<FluxField>,
+    A: Allocator,
+{
+    /// Copy the cells within `rect` into the strided buffer `out`, e.g.,
+    /// for a partial texture upload or a viewport on a big field.
+    ///
+    /// The rectangle is in the coordinates of the field and must lie within
+    /// it.  The row `j` of the rectangle is copied to `out[j * stride..]`,
+    /// so `out` must hold at least `(rect.height - 1) * stride +
+    /// rect.width` values and `stride` must be at least `rect.width`.  The
+    /// rest of `out` is left as it is.
+    ///
+    /// ```
+    /// # use flux_fov::{FluxField, Fov, Rect};
+    /// let mut fov = Fov::new(Box::new(FluxField::new(3)), 3, 0);
+    /// fov.update(|x, y, _| 10 * y + x);
+    /// let mut out = [0; 8];
+    /// fov.copy_region(&Rect::new(-1, 1, 3, 2), &mut out, 4);
+    /// assert_eq!(out, [9, 10, 11, 0, 19, 20, 21, 0]);
+    /// ```
+    ///
+    pub fn copy_region(&self, rect: &Rect, out: &mut [T], stride: usize) {
+        if rect.is_empty() {
+            return;
+        }
+        let r = self.radius as i32;
+        assert!(
+            -r <= rect.x
+                && rect.x + rect.width - 1 <= r
+                && -r <= rect.y
+                && rect.y + rect.height - 1 <= r,
+            "region out of range"
+        );
+        let (width, height) = (rect.width as usize, rect.height as usize);
+        assert!(stride >= width, "stride too small");
+        assert!(
+            out.len() >= (height - 1) * stride + width,
+            "buffer too small"
+        );
+        for (j, row) in out.chunks_mut(stride).take(height).enumerate() {
+            let start = self.index(rect.x, rect.y + j as i32);
+            row[..width].clone_from_slice(&self.data[start..start + width]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn copy_regions() {
+        let mut fov = Fov::new(Box::new(FluxField::new(4)), 4, 0);
+        fov.update(|x, y, _| 100 * y + x);
+        let mut out = vec![-1; 9 * 9];
+        fov.copy_region(&Rect::new(-4, -4, 9, 9), &mut out, 9);
+        assert_eq!(out, fov.as_slice());
+        let mut out = vec![-1; 5];
+        fov.copy_region(&Rect::new(4, -2, 1, 3), &mut out, 2);
+        assert_eq!(out, [-196, -1, -96, -1, 4]);
+        fov.copy_region(&Rect::new(0, 0, 0, 3), &mut out, 0);
+        assert_eq!(out[0], -196);
+    }
+
+    #[test]
+    #[should_panic(expected = "region out of range")]
+    fn region_out_of_range() {
+        let fov = Fov::new(Box::new(FluxField::new(2)), 2, 0);
+        fov.copy_region(&Rect::new(-2, 0, 6, 1), &mut [0; 6], 6);
+    }
+}
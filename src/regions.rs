@@ -0,0 +1,280 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{Connectivity, FluxField, Fov};
+
+const NO_REGION: u32 = u32::MAX;
+
+/// The connected regions of the visible cells of a field of vision.
+///
+/// The regions are numbered from zero in the order their first cells appear
+/// in the field, row by row.
+///
+#[derive(Clone, Debug)]
+pub struct Regions {
+    width: isize,
+    ix_origin: isize,
+    labels: Vec<u32>,
+    sizes: Vec<usize>,
+}
+
+impl Regions {
+    /// The number of regions.
+    pub fn count(&self) -> usize {
+        self.sizes.len()
+    }
+
+    /// The region of the given grid cell, or `None` if the cell isn't
+    /// visible.
+    pub fn at(&self, x: i32, y: i32) -> Option<u32> {
+        let radius = (self.width / 2) as u32;
+        assert!(
+            x.unsigned_abs() <= radius && y.unsigned_abs() <= radius,
+            "cell out of range"
+        );
+        let ix = self.ix_origin + self.width * y as isize + x as isize;
+        match self.labels[ix as usize] {
+            NO_REGION => None,
+            label => Some(label),
+        }
+    }
+
+    /// The number of cells in the region.
+    pub fn size(&self, region: u32) -> usize {
+        self.sizes[region as usize]
+    }
+
+    /// The region containing the viewer, if the origin is visible.
+    pub fn origin_region(&self) -> Option<u32> {
+        self.at(0, 0)
+    }
+
+    /// Expose the labels as a slice in the same order as `Fov::as_slice`.
+    /// The cells that aren't visible have the label `u32::MAX`.
+    pub fn as_slice(&self) -> &[u32] {
+        self.labels.as_slice()
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Label the connected regions of the visible cells.
+    ///
+    /// Two visible cells belong to the same region if they are neighbors
+    /// within the given connectivity.  With the four-connectivity, say, the
+    /// room the viewer is in and a sliver of another room seen through a
+    /// window at a corner end up in different regions.
+    ///
+    pub fn label_regions<P>(&self, connectivity: Connectivity, is_visible: P) -> Regions
+    where
+        P: Fn(&T) -> bool,
+    {
+        let width = self.width;
+        let mut labels = vec![NO_REGION; self.data.len()];
+        let mut sizes = Vec::new();
+        let mut stack = Vec::new();
+        let neighbors: &[(isize, isize)] = match connectivity {
+            Connectivity::Four => &[(1, 0), (0, 1), (-1, 0), (0, -1)],
+            Connectivity::Eight => &[
+                (1, 0),
+                (1, 1),
+                (0, 1),
+                (-1, 1),
+                (-1, 0),
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+            ],
+        };
+        for start in 0..self.data.len() {
+            if labels[start] != NO_REGION || !is_visible(&self.data[start]) {
+                continue;
+            }
+            let label = sizes.len() as u32;
+            let mut size = 0;
+            labels[start] = label;
+            stack.push(start);
+            while let Some(ix) = stack.pop() {
+                size += 1;
+                let (x, y) = (ix as isize % width, ix as isize / width);
+                for &(dx, dy) in neighbors {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx < 0 || ny < 0 || nx >= width || ny >= width {
+                        continue;
+                    }
+                    let nix = (ny * width + nx) as usize;
+                    if labels[nix] == NO_REGION && is_visible(&self.data[nix]) {
+                        labels[nix] = label;
+                        stack.push(nix);
+                    }
+                }
+            }
+            sizes.push(size);
+        }
+        Regions {
+            width,
+            ix_origin: self.ix_origin,
+            labels,
+            sizes,
+        }
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Extract the boundary of the visible region, the shadow frontier.
+    ///
+    /// The boundary consists of the visible cells that have a non-visible
+    /// orthogonal neighbor; the cells beyond the radius count as
+    /// non-visible.  The cells are returned as chains where consecutive cells
+    /// are neighbors, walking along the boundary with the visible region on
+    /// the right hand side where possible.  A simple outline yields a single
+    /// chain while a boundary with holes or one cell wide protrusions is
+    /// split into several chains.
+    ///
+    pub fn shadow_frontier<P>(&self, is_visible: P) -> Vec<Vec<(i32, i32)>>
+    where
+        P: Fn(&T) -> bool,
+    {
+        const AROUND: [(isize, isize); 8] = [
+            (1, 0),
+            (1, 1),
+            (0, 1),
+            (-1, 1),
+            (-1, 0),
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+        ];
+        let width = self.width;
+        let visible = |x: isize, y: isize| {
+            0 <= x
+                && x < width
+                && 0 <= y
+                && y < width
+                && is_visible(&self.data[(y * width + x) as usize])
+        };
+        let on_boundary: Vec<bool> = (0..self.data.len() as isize)
+            .map(|ix| {
+                let (x, y) = (ix % width, ix / width);
+                visible(x, y)
+                    && AROUND
+                        .iter()
+                        .step_by(2)
+                        .any(|&(dx, dy)| !visible(x + dx, y + dy))
+            })
+            .collect();
+        let mut taken = vec![false; self.data.len()];
+        let mut chains = Vec::new();
+        for start in 0..self.data.len() {
+            if !on_boundary[start] || taken[start] {
+                continue;
+            }
+            let mut chain = Vec::new();
+            let (mut x, mut y) = (start as isize % width, start as isize / width);
+            // The first cell of a chain is the top-left most, so the walk
+            // starts eastwards with the region below on the right.
+            let mut dir = 0;
+            loop {
+                taken[(y * width + x) as usize] = true;
+                chain.push(((x - self.radius) as i32, (y - self.radius) as i32));
+                // Try turning left first, then straight, and so on clockwise.
+                let next = (0..8).map(|turn| (dir + 6 + turn) % 8).find(|&d| {
+                    let (nx, ny) = (x + AROUND[d].0, y + AROUND[d].1);
+                    visible(nx, ny) && {
+                        let nix = (ny * width + nx) as usize;
+                        on_boundary[nix] && !taken[nix]
+                    }
+                });
+                match next {
+                    Some(d) => {
+                        dir = d;
+                        x += AROUND[d].0;
+                        y += AROUND[d].1;
+                    }
+                    None => break,
+                }
+            }
+            chains.push(chain);
+        }
+        chains
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn label_rooms() {
+        let mut fov = Fov::new(Box::new(FluxField::new(3)), 3, false);
+        // The viewer's room is the left half with a doorway at (1, -1)
+        // through which a single cell of another room is seen diagonally.
+        fov.update(|x, y, _| x <= 0 || (x, y) == (2, -2) || (x, y) == (1, -1));
+        let four = fov.label_regions(Connectivity::Four, |&v| v);
+        assert_eq!(four.count(), 2);
+        assert_eq!(four.origin_region(), Some(0));
+        assert_eq!(four.at(-3, -3), Some(0));
+        assert_eq!(four.at(1, -1), Some(0));
+        assert_eq!(four.size(0), 4 * 7 + 1);
+        assert_eq!(four.at(2, -2), Some(1));
+        assert_eq!(four.at(3, 3), None);
+        let eight = fov.label_regions(Connectivity::Eight, |&v| v);
+        assert_eq!(eight.count(), 1);
+        assert_eq!(eight.size(0), 4 * 7 + 2);
+    }
+
+    #[test]
+    fn frontier_follows_boundary() {
+        let mut fov = Fov::new(Box::new(FluxField::new(3)), 3, true);
+        fov.update(|_, _, _| true);
+        let chains = fov.shadow_frontier(|&v| v);
+        assert_eq!(chains.len(), 1);
+        assert_eq!(chains[0].len(), 24);
+        for pair in chains[0].windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            assert!((a.0 - b.0).abs().max((a.1 - b.1).abs()) == 1);
+        }
+        fov.update(|x, y, _| x.abs() <= 1 && y.abs() <= 1);
+        let chains = fov.shadow_frontier(|&v| v);
+        assert_eq!(
+            chains,
+            [vec![
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (1, 0),
+                (1, 1),
+                (0, 1),
+                (-1, 1),
+                (-1, 0)
+            ]]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "cell out of range")]
+    fn at_out_of_range() {
+        // Within the labels but past the right edge of the field.
+        let fov = Fov::new(Box::new(FluxField::new(2)), 2, true);
+        fov.label_regions(Connectivity::Four, |&v| v).at(3, 0);
+    }
+}
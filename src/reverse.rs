@@ -0,0 +1,81 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::kernel::{self, Visibility};
+use crate::{FluxField, Fov};
+
+/// Determine the cells from which the target cell is visible.
+///
+/// Returns a field centered on `target` telling for every cell within
+/// `radius` whether an observer standing there would see the target, e.g.,
+/// to find the cells where a sneaking player stays out of the guard's sight.
+/// The `is_opaque` callback receives map coordinates.
+///
+/// The visibility is that of the `kernel::symmetric` kernel.  As the kernel
+/// is symmetric the observers who see the target are exactly the cells the
+/// target sees, so this takes a single update rather than one per candidate
+/// cell.
+///
+pub fn reverse_fov<X, F>(
+    flux_field: X,
+    target: (i32, i32),
+    radius: usize,
+    is_opaque: F,
+) -> Fov<bool, X>
+where
+    X: AsRef<FluxField> + Clone,
+    F: Fn(i32, i32) -> bool,
+{
+    let (tx, ty) = target;
+    let mut flux = Fov::new(flux_field.clone(), radius, Visibility::default());
+    flux.update(kernel::symmetric(|x, y| is_opaque(tx + x, ty + y)));
+    let mut result = Fov::new(flux_field, radius, false);
+    for (seen, v) in result.data.iter_mut().zip(flux.as_slice()) {
+        *seen = v.is_visible;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::rc::Rc;
+
+    #[test]
+    fn reverse_matches_forward() {
+        let radius = 6;
+        let flux_field = Rc::new(FluxField::new(radius));
+        let mut rng = StdRng::seed_from_u64(7);
+        let walls: Vec<bool> = (0..41 * 41).map(|_| rng.gen_bool(0.25)).collect();
+        let is_opaque = |x: i32, y: i32| walls[((y + 20) * 41 + x + 20) as usize];
+        let target = (3, -2);
+        let reverse = reverse_fov(flux_field.clone(), target, radius, is_opaque);
+        let mut forward = Fov::new(flux_field, radius, Visibility::default());
+        let r = radius as i32;
+        let mut seen = 0;
+        for y in -r..r + 1 {
+            for x in -r..r + 1 {
+                let (ox, oy) = (target.0 + x, target.1 + y);
+                forward.update(kernel::symmetric(|dx, dy| is_opaque(ox + dx, oy + dy)));
+                assert_eq!(forward.at(-x, -y).is_visible, *reverse.at(x, y));
+                seen += *reverse.at(x, y) as usize;
+            }
+        }
+        assert!(seen > 1 && seen < (2 * r as usize + 1).pow(2));
+    }
+}
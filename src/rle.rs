@@ -0,0 +1,208 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// The run-length encoding of a visibility mask is:
+//
+//     radius                  varint
+//     run length 0            varint   (non-visible cells)
+//     run length 1            varint   (visible cells)
+//     run length 2            varint   (non-visible cells)
+//     ...
+//
+// The runs alternate between non-visible and visible cells starting with the
+// non-visible ones, so the first run may be empty.  They cover the cells of
+// the field row by row in the same order as `Fov::as_slice`.  A varint is an
+// unsigned LEB128 number: seven bits per byte, least significant first, with
+// the high bit set on all but the last byte.
+
+use std::io;
+
+use crate::{FluxField, Fov, FovSnapshot, MAX_RADIUS};
+
+// A sanity limit on the decoded mask so that a corrupted or hostile message
+// cannot make us allocate the moon.  A single run of a few bytes can cover
+// the whole field and the mask takes a byte per cell, so the field is kept
+// at 8191 by 8191 cells, i.e., a radius of 4095.
+const RLE_MAX_CELLS: u64 = 1 << 26;
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Encode the visibility of the cells as run-length encoded rows.
+    ///
+    /// The encoding is meant for shipping the visibility compactly over the
+    /// network and can be decoded with `FovSnapshot::decode_rle`.
+    ///
+    pub fn encode_rle<P>(&self, is_visible: P) -> Vec<u8>
+    where
+        P: Fn(&T) -> bool,
+    {
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, self.radius as u64);
+        let mut current = false;
+        let mut run = 0;
+        for value in self.data.iter() {
+            if is_visible(value) != current {
+                write_varint(&mut bytes, run);
+                current = !current;
+                run = 0;
+            }
+            run += 1;
+        }
+        write_varint(&mut bytes, run);
+        bytes
+    }
+}
+
+impl FovSnapshot<bool> {
+    /// Decode a visibility mask encoded with `Fov::encode_rle`.
+    ///
+    pub fn decode_rle(bytes: &[u8]) -> io::Result<FovSnapshot<bool>> {
+        let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut pos = 0;
+        let radius = read_varint(bytes, &mut pos)?;
+        if radius > MAX_RADIUS as u64 {
+            return Err(invalid("radius too large"));
+        }
+        let size = match (2 * radius + 1).checked_mul(2 * radius + 1) {
+            Some(size) if size <= RLE_MAX_CELLS => size as usize,
+            _ => return Err(invalid("mask too large")),
+        };
+        let radius = radius as isize;
+        let width = 2 * radius + 1;
+        // The size comes from the input so the cells are allocated only as
+        // the runs covering them are decoded.
+        let mut data = Vec::new();
+        let mut current = false;
+        while pos < bytes.len() {
+            let run = read_varint(bytes, &mut pos)?;
+            if run > (size - data.len()) as u64 {
+                return Err(invalid("run past the end of the field"));
+            }
+            data.resize(data.len() + run as usize, current);
+            current = !current;
+        }
+        if data.len() != size {
+            return Err(invalid("runs don't cover the field"));
+        }
+        Ok(FovSnapshot {
+            radius,
+            width,
+            ix_origin: radius * (width + 1),
+            data,
+        })
+    }
+}
+
+pub(crate) fn write_varint(bytes: &mut Vec<u8>, value: u64) {
+    let mut value = value;
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+pub(crate) fn read_varint(bytes: &[u8], pos: &mut usize) -> io::Result<u64> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = match bytes.get(*pos) {
+            Some(&byte) => byte,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "truncated varint",
+                ))
+            }
+        };
+        *pos += 1;
+        if shift >= 64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "varint too long",
+            ));
+        }
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn rle_round_trip() {
+        let mut fov = Fov::new(Box::new(FluxField::new(20)), 20, 0.0);
+        fov.update(|x, y, _| if x > 3 || y * y > 50 { 0.0 } else { 1.0 });
+        let bytes = fov.encode_rle(|&v| v > 0.5);
+        assert!(bytes.len() < 100);
+        let mask = FovSnapshot::decode_rle(&bytes).unwrap();
+        assert_eq!(mask.radius(), 20);
+        for y in -20..21 {
+            for x in -20..21 {
+                assert_eq!(*mask.at(x, y), *fov.at(x, y) > 0.5);
+            }
+        }
+        assert!(FovSnapshot::decode_rle(&bytes[..bytes.len() - 1]).is_err());
+        // A large radius in the header alone doesn't allocate the field.
+        for &radius in &[MAX_RADIUS as u64, MAX_RADIUS as u64 + 1] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, radius);
+            write_varint(&mut bytes, 1);
+            assert!(FovSnapshot::decode_rle(&bytes).is_err());
+        }
+    }
+
+    #[test]
+    fn rle_rejects_huge_runs() {
+        // A single run covering the whole of a huge field.
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, MAX_RADIUS as u64);
+        write_varint(&mut bytes, (2 * MAX_RADIUS as u64 + 1).pow(2));
+        assert!(FovSnapshot::decode_rle(&bytes).is_err());
+        // A run past the end of a field of acceptable size.
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, 2);
+        write_varint(&mut bytes, u64::MAX);
+        assert!(FovSnapshot::decode_rle(&bytes).is_err());
+        // A single run covering the largest field accepted.
+        let mut bytes = Vec::new();
+        write_varint(&mut bytes, 4095);
+        write_varint(&mut bytes, 0);
+        write_varint(&mut bytes, 8191 * 8191);
+        assert_eq!(FovSnapshot::decode_rle(&bytes).unwrap().radius(), 4095);
+    }
+
+    #[test]
+    fn varints() {
+        let mut bytes = Vec::new();
+        for &value in &[0, 127, 128, 300, u64::MAX] {
+            write_varint(&mut bytes, value);
+        }
+        let mut pos = 0;
+        for &value in &[0, 127, 128, 300, u64::MAX] {
+            assert_eq!(read_varint(&bytes, &mut pos).unwrap(), value);
+        }
+        assert_eq!(pos, bytes.len());
+    }
+}
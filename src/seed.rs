@@ -0,0 +1,106 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::allocator::Allocator;
+use crate::{FluxField, Fov, Influx};
+
+/// How the origin is seeded by `Fov::update_seeded`.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OriginSeed<T> {
+    /// Call the update function with the coordinates (0, 0) and no
+    /// influxes like `update` does.
+    Kernel,
+    /// Set the origin to the given value without calling the update
+    /// function, e.g., `Illumination::origin(0.5)` for a dim light.
+    Value(T),
+    /// Leave the origin as it is, e.g., to keep the value it was seeded
+    /// with in an earlier update.
+    Keep,
+}
+
+impl<T, X, A> Fov<T, X, A>
+where
+    T: Clone,
+    X: AsRef<FluxField>,
+    A: Allocator,
+{
+    /// Update the field of vision seeding the origin as told by `seed`.
+    ///
+    /// Unless seeded with `OriginSeed::Kernel`, the update function is
+    /// called only for the cells other than the origin, so it need not
+    /// special-case the origin and the rest of the field propagates from
+    /// whatever value the origin was seeded with:
+    ///
+    /// ```
+    /// # use flux_fov::{FluxField, Fov, Influx, OriginSeed};
+    /// let mut fov = Fov::new(Box::new(FluxField::new(4)), 4, 0.0_f32);
+    /// let spread = |_, _, influxes: &[Influx<&f32>]| {
+    ///     influxes.iter().map(|f| f.weight * f.value).sum::<f32>() * 0.5
+    /// };
+    /// fov.update_seeded(OriginSeed::Value(0.8), spread);
+    /// assert_eq!(*fov.at(2, 0), 0.2);
+    /// ```
+    ///
+    pub fn update_seeded<F>(&mut self, seed: OriginSeed<T>, update_fn: F)
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        let mut seed = Some(seed);
+        self.update_cells(|x, y, _, cell, influxes| {
+            if x == 0 && y == 0 {
+                match seed.take() {
+                    Some(OriginSeed::Kernel) => *cell = update_fn(x, y, influxes),
+                    Some(OriginSeed::Value(value)) => *cell = value,
+                    Some(OriginSeed::Keep) | None => (),
+                }
+            } else {
+                *cell = update_fn(x, y, influxes);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel::{self, Illumination};
+
+    #[test]
+    fn origin_seeding() {
+        let mut fov = Fov::new(Box::new(FluxField::new(5)), 5, Illumination::default());
+        let opacity = |_, _| 0.0;
+        fov.update_seeded(OriginSeed::Kernel, kernel::soft_shadow(opacity));
+        assert_eq!(*fov.at(0, 0), Illumination::ORIGIN);
+        assert!((fov.at(3, 1).brightness - 1.0).abs() < 1e-5);
+        fov.update_seeded(
+            OriginSeed::Value(Illumination::origin(0.5)),
+            kernel::soft_shadow(opacity),
+        );
+        assert_eq!(fov.at(0, 0).brightness, 0.5);
+        assert!((fov.at(3, 1).brightness - 0.5).abs() < 1e-5);
+        let mut wall = kernel::soft_shadow(|x, _| if x == 2 { 1.0 } else { 0.0 });
+        fov.update_seeded(OriginSeed::Keep, |x, y, influxes| {
+            assert!((x, y) != (0, 0));
+            wall(x, y, influxes)
+        });
+        assert_eq!(fov.at(0, 0).brightness, 0.5);
+        assert!((fov.at(-4, 5).brightness - 0.5).abs() < 1e-5);
+        assert_eq!(fov.at(4, 0).brightness, 0.0);
+    }
+}
@@ -0,0 +1,229 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::sweep::sweep;
+use crate::{FluxField, Influx};
+
+/// Several named fields of vision computed in a single sweep.
+///
+/// Each channel, say sight, infravision, or tremorsense, has its own values
+/// and its own rule for computing them but all share the geometry and the
+/// flux traversal.  Updating N channels this way is cheaper than updating N
+/// separate fields.
+///
+#[derive(Clone)]
+pub struct Senses<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    flux_field: X,
+    radius: isize,
+    width: isize,
+    ix_origin: isize,
+    names: Vec<String>,
+    data: Vec<T>,
+}
+
+impl<T, X> Senses<T, X>
+where
+    T: Clone,
+    X: AsRef<FluxField>,
+{
+    pub fn new(flux_field: X, radius: usize, names: &[&str], init: T) -> Self {
+        assert!(radius <= flux_field.as_ref().radius);
+        let radius = radius as isize;
+        let width = radius * 2 + 1;
+        let ix_origin = radius * (width + 1);
+        let size = (width * width) as usize;
+        Senses {
+            flux_field,
+            radius,
+            width,
+            ix_origin,
+            names: names.iter().map(|name| name.to_string()).collect(),
+            data: vec![init; size * names.len()],
+        }
+    }
+}
+
+impl<T, X> Senses<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// The number of channels.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// The index of the channel with the given name.
+    pub fn channel(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    /// The name of the channel with the given index.
+    pub fn name(&self, channel: usize) -> &str {
+        &self.names[channel]
+    }
+
+    /// The value of the channel at the given grid cell.
+    pub fn at(&self, channel: usize, x: i32, y: i32) -> &T {
+        assert!(channel < self.names.len());
+        &self.data[channel * self.size() + self.index(x, y)]
+    }
+
+    /// The value of the named channel at the given grid cell.
+    ///
+    /// Panics if there is no channel with the name.
+    ///
+    pub fn at_named(&self, name: &str, x: i32, y: i32) -> &T {
+        let channel = self.channel(name).expect("unknown channel");
+        self.at(channel, x, y)
+    }
+
+    /// Expose the values of the channel as a slice in the same order as
+    /// `Fov::as_slice`.
+    pub fn as_slice(&self, channel: usize) -> &[T] {
+        let size = self.size();
+        &self.data[channel * size..(channel + 1) * size]
+    }
+
+    /// Update all channels with the given function.
+    ///
+    /// The function receives the index of the channel being updated in
+    /// addition to the arguments taken by the function of `Fov::update`.
+    /// The channels of a cell are updated in order before moving on to the
+    /// next cell.
+    ///
+    pub fn update<F>(&mut self, update_fn: F)
+    where
+        F: FnMut(usize, i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        let size = self.size();
+        let channels = self.names.len();
+        let data = &mut self.data;
+        sweep(
+            self.radius,
+            self.width,
+            self.ix_origin,
+            self.flux_field.as_ref(),
+            |step| {
+                for channel in 0..channels {
+                    let base = channel * size;
+                    let cell = &data[base + step.ix];
+                    let mut influxes = [
+                        Influx {
+                            weight: 0.0,
+                            dx: 0,
+                            dy: 0,
+                            value: cell,
+                        },
+                        Influx {
+                            weight: 0.0,
+                            dx: 0,
+                            dy: 0,
+                            value: cell,
+                        },
+                        Influx {
+                            weight: 0.0,
+                            dx: 0,
+                            dy: 0,
+                            value: cell,
+                        },
+                    ];
+                    let taps = step.taps();
+                    for (influx, tap) in influxes.iter_mut().zip(taps.iter()) {
+                        influx.weight = tap.weight;
+                        influx.dx = tap.dx;
+                        influx.dy = tap.dy;
+                        influx.value = &data[base + tap.ix];
+                    }
+                    let value = update_fn(channel, step.x, step.y, &influxes[..taps.len()]);
+                    data[base + step.ix] = value;
+                }
+            },
+        );
+    }
+
+    fn size(&self) -> usize {
+        (self.width * self.width) as usize
+    }
+
+    fn index(&self, x: i32, y: i32) -> usize {
+        let radius = self.radius as u32;
+        assert!(
+            x.unsigned_abs() <= radius && y.unsigned_abs() <= radius,
+            "cell out of range"
+        );
+        (self.ix_origin + (self.width * y as isize) + x as isize) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Fov;
+    use std::rc::Rc;
+
+    #[test]
+    fn channels_match_separate_fields() {
+        let flux_field = Rc::new(FluxField::new(5));
+        let sight = |x: i32, y: i32, influxes: &[Influx<&f32>]| {
+            if x == 0 && y == 0 {
+                1.0
+            } else if x == 2 {
+                0.0
+            } else {
+                influxes.iter().map(|f| f.weight * *f.value).sum()
+            }
+        };
+        let tremor = |x: i32, y: i32, influxes: &[Influx<&f32>]| {
+            if x == 0 && y == 0 {
+                1.0
+            } else {
+                0.75 * influxes.iter().map(|f| f.weight * *f.value).sum::<f32>()
+            }
+        };
+        let mut senses = Senses::new(flux_field.clone(), 5, &["sight", "tremorsense"], 0.0);
+        senses.update(|channel, x, y, influxes| match channel {
+            0 => sight(x, y, influxes),
+            _ => tremor(x, y, influxes),
+        });
+        let mut a = Fov::new(flux_field.clone(), 5, 0.0);
+        a.update(sight);
+        let mut b = Fov::new(flux_field, 5, 0.0);
+        b.update(tremor);
+        assert_eq!(senses.len(), 2);
+        assert_eq!(senses.channel("tremorsense"), Some(1));
+        assert_eq!(senses.channel("smell"), None);
+        assert_eq!(senses.as_slice(0), a.as_slice());
+        assert_eq!(senses.as_slice(1), b.as_slice());
+        assert_eq!(*senses.at_named("sight", 3, 1), *a.at(3, 1));
+    }
+
+    #[test]
+    #[should_panic(expected = "cell out of range")]
+    fn at_out_of_range() {
+        // Within the buffer but past the right edge of the field.
+        let senses = Senses::new(Box::new(FluxField::new(2)), 2, &["sight"], 0.0);
+        senses.at(0, 3, 0);
+    }
+}
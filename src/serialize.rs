@@ -0,0 +1,98 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// Serde support for the flux fields.  A flux field is serialized as the
+// bytes of its lossless cache, see `FluxField::to_bytes`, so the self-
+// describing formats store it as a byte string and the deserialized field is
+// checked like the cache.
+
+use std::fmt;
+
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use crate::FluxField;
+
+impl Serialize for FluxField {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(&self.to_bytes())
+    }
+}
+
+impl<'de> Deserialize<'de> for FluxField {
+    fn deserialize<D>(deserializer: D) -> Result<FluxField, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_bytes(FluxFieldVisitor)
+    }
+}
+
+struct FluxFieldVisitor;
+
+impl<'de> Visitor<'de> for FluxFieldVisitor {
+    type Value = FluxField;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the bytes of a flux field cache")
+    }
+
+    fn visit_bytes<E>(self, bytes: &[u8]) -> Result<FluxField, E>
+    where
+        E: de::Error,
+    {
+        FluxField::from_bytes(bytes).map_err(E::custom)
+    }
+
+    // The formats without a byte string type, e.g., JSON, store the bytes as
+    // a sequence of numbers.
+    fn visit_seq<S>(self, seq: S) -> Result<FluxField, S::Error>
+    where
+        S: SeqAccess<'de>,
+    {
+        let mut seq = seq;
+        // The size hint comes from the input so it is capped like serde does
+        // for its own collections.
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        self.visit_bytes(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde::de::value::{BytesDeserializer, Error, SeqDeserializer};
+
+    #[test]
+    fn flux_field_deserializes_from_bytes() {
+        let flux_field = FluxField::new(9);
+        let bytes = flux_field.to_bytes();
+        let copy = FluxField::deserialize(BytesDeserializer::<Error>::new(&bytes)).unwrap();
+        assert_eq!(copy.to_bytes(), bytes);
+        let seq = SeqDeserializer::<_, Error>::new(bytes.iter().cloned());
+        let copy = FluxField::deserialize(seq).unwrap();
+        assert_eq!(copy.to_bytes(), bytes);
+        let truncated = BytesDeserializer::<Error>::new(&bytes[..20]);
+        assert!(FluxField::deserialize(truncated).is_err());
+    }
+}
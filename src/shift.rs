@@ -0,0 +1,294 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::allocator::Allocator;
+use crate::sweep::sweep;
+use crate::{DirtyUpdate, FluxField, Fov};
+
+impl<T, X, A> Fov<T, X, A>
+where
+    T: Clone,
+    X: AsRef<FluxField>,
+    A: Allocator,
+{
+    /// Recenter the field of vision on the cell (`dx`, `dy`) by translating
+    /// the values in place, e.g., when the observer takes a step.
+    ///
+    /// Afterwards each cell holds the value of the same map cell as before,
+    /// and the cells of the newly exposed rim hold `fill`.  The buffer is
+    /// neither reallocated nor cloned.
+    ///
+    /// The flux depends on where the origin is, so the shifted values are
+    /// not the field of vision of the new origin and the field still needs
+    /// an update.  What the shift buys is that the update sees the previous
+    /// values of the same map cells: `update_with_previous` can fade or
+    /// remember cells by their map position and `update_diff` reports the
+    /// cells whose value changed on the map rather than all the cells that
+    /// slid under a different offset.
+    ///
+    /// The flux reaches every map cell from a new direction after the
+    /// shift, so any cell may change and not just the exposed rim, e.g., the
+    /// shadow of a pillar swings around it as the observer passes by.  See
+    /// `shift_origin_dirty` for following up with `update_dirty` instead of
+    /// a full update.
+    ///
+    /// ```
+    /// # use flux_fov::{FluxField, Fov};
+    /// let mut fov = Fov::new(Box::new(FluxField::new(3)), 3, (0, 0));
+    /// fov.update(|x, y, _| (x, y));
+    /// fov.shift_origin(1, 0, (9, 9));
+    /// assert_eq!(*fov.at(0, 0), (1, 0));
+    /// assert_eq!(*fov.at(3, 1), (9, 9));
+    /// ```
+    ///
+    pub fn shift_origin(&mut self, dx: i32, dy: i32, fill: T) {
+        let radius = self.radius as i32;
+        let width = self.width as i32;
+        if dx.abs() >= width || dy.abs() >= width {
+            for value in self.data.iter_mut() {
+                *value = fill.clone();
+            }
+            return;
+        }
+        // Rotating the buffer by the offset of the shift moves the values
+        // into place except for the rim where they wrap around.
+        let offset = dy * width + dx;
+        if offset > 0 {
+            self.data.rotate_left(offset as usize);
+        } else {
+            self.data.rotate_right(-offset as usize);
+        }
+        for y in -radius..radius + 1 {
+            for x in -radius..radius + 1 {
+                if (x + dx).abs() > radius || (y + dy).abs() > radius {
+                    let ix = self.index(x, y);
+                    self.data[ix] = fill.clone();
+                }
+            }
+        }
+    }
+}
+
+impl<T, X, A> Fov<T, X, A>
+where
+    T: Clone + PartialEq,
+    X: AsRef<FluxField>,
+    A: Allocator,
+{
+    /// Like `shift_origin` but also mark the cells that may change in the
+    /// follow-up update in `scratch` for `update_dirty`.
+    ///
+    /// The field must be up to date for the origin before the shift.  The
+    /// marked cells are the exposed rim, the origin, and the cells whose
+    /// influxes don't all carry the same value, the same one both before and
+    /// after the shift.  The weights of the influxes into a cell sum to one,
+    /// so a cell whose influxes all carry the same value receives the same
+    /// flux whichever directions it comes from.  Hence the follow-up
+    /// `update_dirty` matches a full update, up to the rounding of the
+    /// weighted sums, as long as the update function depends on the
+    /// influxes only through the weighted sums of their values, as the
+    /// kernels of the `kernel` module do, and not on their directions.
+    ///
+    /// The cells recomputed are the rim, the penumbras, and whatever changes
+    /// spread from them.  Where the rounding leaves the values of a lit area
+    /// uneven, as it does in the three-tap fields, that may be most of the
+    /// field.
+    ///
+    /// ```
+    /// # use flux_fov::{kernel, DirtyUpdate, FluxField, Fov};
+    /// let pillar = |ox: i32, oy: i32| move |x, y| ((x + ox, y + oy) == (2, 0)) as u8 as f32;
+    /// let mut fov = Fov::new(Box::new(FluxField::new(8)), 8, Default::default());
+    /// fov.update(kernel::soft_shadow(pillar(0, 0)));
+    /// let mut scratch = DirtyUpdate::new();
+    /// fov.shift_origin_dirty(0, 1, Default::default(), &mut scratch);
+    /// fov.update_dirty(&mut scratch, &[], kernel::soft_shadow(pillar(0, 1)));
+    /// ```
+    ///
+    pub fn shift_origin_dirty(&mut self, dx: i32, dy: i32, fill: T, scratch: &mut DirtyUpdate) {
+        let radius = self.radius as i32;
+        let width = self.width as i32;
+        let inside = |x: i32, y: i32| x.abs() <= radius && y.abs() <= radius;
+        let pending = &mut scratch.pending;
+        if dx.abs() >= width || dy.abs() >= width {
+            self.shift_origin(dx, dy, fill);
+            for y in -radius..radius + 1 {
+                pending.extend((-radius..radius + 1).map(|x| (x, y)));
+            }
+            return;
+        }
+        // One plus the index of a source of the influxes into each cell
+        // before the shift if they all carry the same value and zero
+        // otherwise.
+        let sources = &mut scratch.sources;
+        sources.clear();
+        sources.resize(self.data.len(), 0);
+        let data = &self.data;
+        sweep(
+            self.radius,
+            self.width,
+            self.ix_origin,
+            self.flux_field.as_ref(),
+            |step| {
+                if let Some((first, rest)) = step.taps().split_first() {
+                    if rest.iter().all(|tap| data[tap.ix] == data[first.ix]) {
+                        sources[step.ix] = first.ix as u32 + 1;
+                    }
+                }
+            },
+        );
+        self.shift_origin(dx, dy, fill);
+        let data = &self.data;
+        let index = |x: i32, y: i32| self.index(x, y);
+        let coords = |ix: usize| (ix as i32 % width - radius, ix as i32 / width - radius);
+        sweep(
+            self.radius,
+            self.width,
+            self.ix_origin,
+            self.flux_field.as_ref(),
+            |step| {
+                // The coordinates of the cell before the shift.
+                let (x, y) = (step.x + dx, step.y + dy);
+                let unchanged = inside(x, y)
+                    && match step.taps().split_first() {
+                        Some((first, rest)) => match sources[index(x, y)] {
+                            0 => false,
+                            source => {
+                                let value = &data[first.ix];
+                                let (sx, sy) = coords(source as usize - 1);
+                                rest.iter().all(|tap| data[tap.ix] == *value)
+                                    && inside(sx - dx, sy - dy)
+                                    && data[index(sx - dx, sy - dy)] == *value
+                            }
+                        },
+                        None => false,
+                    };
+                if !unchanged {
+                    pending.push((step.x, step.y));
+                }
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel::{self, Illumination};
+    use crate::testing::all_params;
+    use std::rc::Rc;
+
+    #[test]
+    fn shift_translates_values() {
+        let radius = 4;
+        for &(dx, dy) in &[(1, 0), (-1, 1), (0, -3), (2, 2), (-8, 0), (9, -1), (0, 0)] {
+            let mut fov = Fov::new(Box::new(FluxField::new(radius)), radius, (0, 0));
+            fov.update(|x, y, _| (x, y));
+            fov.shift_origin(dx, dy, (99, 99));
+            let r = radius as i32;
+            for y in -r..r + 1 {
+                for x in -r..r + 1 {
+                    let (ox, oy) = (x + dx, y + dy);
+                    let expected = if ox.abs() <= r && oy.abs() <= r {
+                        (ox, oy)
+                    } else {
+                        (99, 99)
+                    };
+                    assert_eq!(
+                        *fov.at(x, y),
+                        expected,
+                        "({}, {}) by ({}, {})",
+                        x,
+                        y,
+                        dx,
+                        dy
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shift_changes_more_than_the_rim() {
+        // Step up past a pillar to the right.
+        let radius = 6;
+        let pillar = (2, 0);
+        let update = |fov: &mut Fov<Illumination, _>, (ox, oy): (i32, i32)| {
+            fov.update(kernel::soft_shadow(|x, y| {
+                ((x + ox, y + oy) == pillar) as u8 as f32
+            }))
+        };
+        let mut fov = Fov::new(Box::new(FluxField::new(radius)), radius, Default::default());
+        update(&mut fov, (0, 0));
+        fov.shift_origin(0, 1, Default::default());
+        let shifted = fov.as_slice().to_vec();
+        update(&mut fov, (0, 1));
+        let r = radius as i32;
+        let changed_within = (-r..r + 1)
+            .flat_map(|y| (-r..r + 1).map(move |x| (x, y)))
+            .filter(|&(x, y)| y < r && shifted[fov.index(x, y)] != *fov.at(x, y))
+            .count();
+        assert!(changed_within > 0, "the shadow should have swung");
+    }
+
+    #[test]
+    fn shifted_history_follows_the_map() {
+        // Remember the cells seen within a step while walking three steps to
+        // the right.
+        let mut fov = Fov::new(Box::new(FluxField::new(3)), 3, false);
+        let seen = |x: i32, y: i32| x.abs() <= 1 && y.abs() <= 1;
+        fov.update_with_previous(|x, y, &explored, _| explored || seen(x, y));
+        for _ in 0..3 {
+            fov.shift_origin(1, 0, false);
+            fov.update_with_previous(|x, y, &explored, _| explored || seen(x, y));
+        }
+        // The map cells from one left of the start onwards are remembered as
+        // far as the field reaches back.
+        assert!((-3..2).all(|x| *fov.at(x, 0) && *fov.at(x, -1)));
+        assert!(!*fov.at(2, 0) && !*fov.at(-1, 2));
+    }
+
+    #[test]
+    fn dirty_shift_matches_full_update() {
+        let radius = 12;
+        let width = 2 * radius as i32 + 1;
+        let walls = [(2, 0), (-3, 4), (5, -5), (6, -5), (0, 9)];
+        let opacity = |(ox, oy): (i32, i32)| {
+            move |x: i32, y: i32| walls.contains(&(x + ox, y + oy)) as u8 as f32
+        };
+        for params in all_params() {
+            let flux_field = Rc::new(FluxField::with_params(radius, params));
+            let mut fov = Fov::new(flux_field.clone(), radius, Illumination::default());
+            let mut full = Fov::new(flux_field, radius, Illumination::default());
+            fov.update(kernel::soft_shadow(opacity((0, 0))));
+            let mut scratch = DirtyUpdate::new();
+            let mut origin = (0, 0);
+            for &(dx, dy) in &[(0, 1), (1, 0), (1, 1), (-2, 1), (0, -3), (30, 0)] {
+                origin = (origin.0 + dx, origin.1 + dy);
+                fov.shift_origin_dirty(dx, dy, Illumination::default(), &mut scratch);
+                let count =
+                    fov.update_dirty(&mut scratch, &[], kernel::soft_shadow(opacity(origin)));
+                full.update(kernel::soft_shadow(opacity(origin)));
+                for (a, b) in fov.as_slice().iter().zip(full.as_slice()) {
+                    assert!((a.brightness - b.brightness).abs() < 1e-5, "{:?}", origin);
+                }
+                if dx.abs() < width {
+                    assert!(count < fov.as_slice().len(), "{:?}", origin);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,368 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::time::{Duration, Instant};
+
+use crate::{FluxField, Fov, Influx};
+
+/// The amount of work a single slice of a time-sliced update may do.
+///
+/// The budget is checked between the rings so a slice always completes at
+/// least one ring and may overrun the budget by up to one ring.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Budget {
+    /// At most the given number of cells.
+    Cells(usize),
+    /// At most the given wall clock time.
+    Time(Duration),
+}
+
+/// The progress of an update spread over several slices; see
+/// `Fov::update_sliced`.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SlicedUpdate {
+    // The next ring to update.
+    ring: i32,
+    complete: bool,
+}
+
+impl SlicedUpdate {
+    /// Constructs the progress of an update that hasn't started yet.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The number of rings updated so far, the origin included.
+    pub fn rings_done(&self) -> usize {
+        self.ring as usize
+    }
+
+    /// Whether the update has completed.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Abandon the update in progress and start over with the next slice.
+    pub fn reset(&mut self) {
+        *self = Default::default();
+    }
+}
+
+/// The state of an update interrupted at an arbitrary cell; see
+/// `Fov::resume_update`.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UpdateState {
+    radius: i32,
+    ring: i32,
+    // The number of the cells of the ring already updated.
+    cell: usize,
+}
+
+impl UpdateState {
+    /// Constructs the state of an update of a field with the given radius
+    /// that hasn't started yet.
+    ///
+    pub fn new(radius: usize) -> Self {
+        UpdateState {
+            radius: radius as i32,
+            ring: 0,
+            cell: 0,
+        }
+    }
+
+    /// The number of cells updated so far.
+    pub fn cells_done(&self) -> usize {
+        if self.ring == 0 {
+            self.cell
+        } else {
+            let inner = 2 * self.ring as usize - 1;
+            inner * inner + self.cell
+        }
+    }
+
+    /// Whether the update has completed.
+    pub fn is_complete(&self) -> bool {
+        self.ring > self.radius
+    }
+
+    /// Start the update over.
+    pub fn reset(&mut self) {
+        self.ring = 0;
+        self.cell = 0;
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Update the field of vision in slices spread over several calls,
+    /// e.g., one per frame, so that a huge field doesn't cause a frame
+    /// spike.
+    ///
+    /// Each call continues the update tracked by `progress` from the ring
+    /// where the previous call stopped and updates rings outwards until the
+    /// `budget` is spent or the field is done.  Returns whether the update
+    /// completed.  Calling it again after that starts a new update.
+    ///
+    /// While the update is in progress the rings beyond `rings_done` still
+    /// hold the values of the previous update.  The update function should
+    /// stay the same over the slices of one update.
+    ///
+    pub fn update_sliced<F>(
+        &mut self,
+        progress: &mut SlicedUpdate,
+        budget: Budget,
+        update_fn: F,
+    ) -> bool
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        if progress.complete {
+            progress.reset();
+        }
+        let start = Instant::now();
+        let mut cells = 0;
+        while progress.ring <= self.radius as i32 {
+            let k = progress.ring;
+            self.update_cells_of_ring(k, |x, y, _, cell, influxes| {
+                *cell = update_fn(x, y, influxes);
+            });
+            progress.ring += 1;
+            cells += if k == 0 { 1 } else { 8 * k as usize };
+            let spent = match budget {
+                Budget::Cells(max) => cells >= max,
+                Budget::Time(max) => start.elapsed() >= max,
+            };
+            if spent {
+                break;
+            }
+        }
+        progress.complete = progress.ring > self.radius as i32;
+        progress.complete
+    }
+
+    /// Continue the update captured by `state` updating at most `max_cells`
+    /// cells and return whether the update completed.
+    ///
+    /// Unlike `update_sliced` the update may stop at any cell so the work
+    /// of many large fields can be interleaved finely, e.g., by giving each
+    /// one a share of the cells left in the turn.  The state records where
+    /// the update stopped.  The field must not be updated otherwise until
+    /// the update completes.  Calling it again after that starts a new
+    /// update.
+    ///
+    pub fn resume_update<F>(
+        &mut self,
+        state: &mut UpdateState,
+        max_cells: usize,
+        update_fn: F,
+    ) -> bool
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        assert_eq!(state.radius, self.radius as i32, "radius mismatch");
+        let mut update_fn = update_fn;
+        if state.is_complete() {
+            state.reset();
+        }
+        let mut budget = max_cells;
+        while budget > 0 && !state.is_complete() {
+            let k = state.ring;
+            let ring_len = if k == 0 { 1 } else { 8 * k as usize };
+            let end = ring_len.min(state.cell.saturating_add(budget));
+            self.update_cells_of_ring_part(k, state.cell..end, |x, y, _, cell, influxes| {
+                *cell = update_fn(x, y, influxes);
+            });
+            budget -= end - state.cell;
+            if end == ring_len {
+                state.ring += 1;
+                state.cell = 0;
+            } else {
+                state.cell = end;
+            }
+        }
+        state.is_complete()
+    }
+
+    /// Update only the cells of the ring `ring`, i.e. those whose Chebyshev
+    /// distance from the origin is `ring`, the ring 0 being the origin.
+    ///
+    /// The cells receive flux only from their own ring and the one just
+    /// inside it so the result is the same as that of a full update as long
+    /// as the inner rings are up to date.  Growing the vision by one ring,
+    /// e.g., as dawn breaks, costs only the cells of the new ring.
+    ///
+    pub fn update_ring<F>(&mut self, ring: usize, update_fn: F)
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+    {
+        assert!(ring <= self.radius(), "ring out of range");
+        let mut update_fn = update_fn;
+        self.update_cells_of_ring(ring as i32, |x, y, _, cell, influxes| {
+            *cell = update_fn(x, y, influxes);
+        });
+    }
+
+    /// Update the field of vision ring by ring calling `on_ring` after each
+    /// completed ring.
+    ///
+    /// The hook receives the ring just completed, 0 being the origin, and
+    /// the field with the rings up to it updated, e.g., for animating the
+    /// vision expanding outwards.  Returning `false` from the hook stops the
+    /// update leaving the rest of the rings as they were.  Returns whether
+    /// all the rings were updated.
+    ///
+    pub fn update_with_ring_hook<F, H>(&mut self, update_fn: F, on_ring: H) -> bool
+    where
+        F: FnMut(i32, i32, &[Influx<&T>]) -> T,
+        H: FnMut(usize, &Self) -> bool,
+    {
+        let mut update_fn = update_fn;
+        let mut on_ring = on_ring;
+        for k in 0..self.radius as i32 + 1 {
+            self.update_cells_of_ring(k, |x, y, _, cell, influxes| {
+                *cell = update_fn(x, y, influxes);
+            });
+            if !on_ring(k as usize, self) {
+                return k == self.radius as i32;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel::{self, Visibility};
+    use crate::testing::{all_params, three_tap};
+    use crate::FluxParams;
+
+    fn check_sliced(params: FluxParams) {
+        let radius = 12;
+        let flux_field = std::rc::Rc::new(FluxField::with_params(radius, params));
+        let opacity = |x: i32, y: i32| if (x + 2 * y) % 7 == 3 { 0.8 } else { 0.1 };
+        let mut full = Fov::new(flux_field.clone(), radius, Visibility::default());
+        full.update(kernel::threshold(0.2, opacity));
+        let mut sliced = Fov::new(flux_field, radius, Visibility::default());
+        let mut progress = SlicedUpdate::new();
+        let mut slices = 1;
+        let mut kernel = kernel::threshold(0.2, opacity);
+        while !sliced.update_sliced(&mut progress, Budget::Cells(40), &mut kernel) {
+            slices += 1;
+        }
+        assert_eq!(slices, 9);
+        assert_eq!(progress.rings_done(), radius + 1);
+        assert_eq!(sliced.as_slice(), full.as_slice());
+        // The next call starts a new update.
+        sliced.update_sliced(&mut progress, Budget::Cells(1), &mut kernel);
+        assert_eq!(progress.rings_done(), 1);
+    }
+
+    #[test]
+    fn interleaved_updates_match_full() {
+        let radius = 10;
+        let flux_field = std::rc::Rc::new(FluxField::with_params(radius, three_tap()));
+        let opacity = |x: i32, y: i32| if (3 * x + y) % 5 == 1 { 0.9 } else { 0.0 };
+        let mut full = Fov::new(flux_field.clone(), radius, Visibility::default());
+        full.update(kernel::threshold(0.2, opacity));
+        let mut fovs: Vec<_> = (0..2)
+            .map(|_| Fov::new(flux_field.clone(), radius, Visibility::default()))
+            .collect();
+        let mut states = vec![UpdateState::new(radius); 2];
+        let mut kernel = kernel::threshold(0.2, opacity);
+        let mut turns = 0;
+        while !states.iter().all(UpdateState::is_complete) {
+            for (fov, state) in fovs.iter_mut().zip(states.iter_mut()) {
+                fov.resume_update(state, 13, &mut kernel);
+            }
+            turns += 1;
+        }
+        assert_eq!(turns, 34);
+        assert_eq!(states[0].cells_done(), 21 * 21);
+        for fov in &fovs {
+            assert_eq!(fov.as_slice(), full.as_slice());
+        }
+    }
+
+    #[test]
+    fn cells_done_counts_from_the_origin() {
+        let mut fov = Fov::new(Box::new(FluxField::new(3)), 3, 0);
+        let mut state = UpdateState::new(3);
+        assert_eq!(state.cells_done(), 0);
+        // A part of the ring 0 is no cells at all.
+        assert!(!fov.resume_update(&mut state, 0, |_, _, _| 1));
+        assert_eq!(state.cells_done(), 0);
+        fov.resume_update(&mut state, 1, |_, _, _| 1);
+        assert_eq!(state.cells_done(), 1);
+        fov.resume_update(&mut state, 3, |_, _, _| 1);
+        assert_eq!(state.cells_done(), 4);
+    }
+
+    #[test]
+    fn growing_ring_by_ring() {
+        let flux_field = std::rc::Rc::new(FluxField::new(8));
+        let opacity = |x: i32, y: i32| if x == 2 && y < 3 { 1.0 } else { 0.0 };
+        let mut full = Fov::new(flux_field.clone(), 8, Visibility::default());
+        full.update(kernel::threshold(0.1, opacity));
+        let mut grown = Fov::new(flux_field, 8, Visibility::default());
+        let mut kernel = kernel::threshold(0.1, opacity);
+        let mut calls = 0;
+        for ring in 0..9 {
+            grown.update_ring(ring, |x, y, influxes| {
+                calls += 1;
+                assert_eq!(x.abs().max(y.abs()), ring as i32);
+                kernel(x, y, influxes)
+            });
+        }
+        assert_eq!(calls, 17 * 17);
+        assert_eq!(grown.as_slice(), full.as_slice());
+    }
+
+    #[test]
+    fn ring_hook_sees_the_rings_grow() {
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, 0);
+        let mut rings = Vec::new();
+        let complete = fov.update_with_ring_hook(
+            |x, y, _| x.abs().max(y.abs()) + 1,
+            |k, fov| {
+                let updated = fov.as_slice().iter().filter(|&&v| v > 0).count();
+                rings.push((k, updated));
+                assert_eq!(*fov.at(k as i32, 0), k as i32 + 1);
+                k < 6
+            },
+        );
+        assert!(complete);
+        assert_eq!(rings.len(), 7);
+        assert_eq!(rings[2], (2, 25));
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, 0);
+        assert!(!fov.update_with_ring_hook(|_, _, _| 1, |k, _| k < 3));
+        assert_eq!(fov.as_slice().iter().sum::<i32>(), 49);
+    }
+
+    #[test]
+    fn sliced_update_matches_full() {
+        for params in all_params() {
+            check_sliced(params);
+        }
+    }
+}
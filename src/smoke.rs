@@ -0,0 +1,157 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+/// A cloud of smoke, e.g., from a smoke grenade, expanding and thinning out
+/// turn by turn.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SmokeCloud {
+    /// The map cell at the center of the cloud.
+    pub center: (i32, i32),
+    /// The radius of the cloud in cells.
+    pub radius: f32,
+    /// The opacity of the cloud, from 0.0 to 1.0.
+    pub density: f32,
+    /// The growth of the radius per turn.
+    pub growth: f32,
+    /// The largest radius the cloud grows to.
+    pub max_radius: f32,
+    /// The fraction of the density left after each turn.
+    pub decay: f32,
+}
+
+impl SmokeCloud {
+    /// The opacity of the cloud at the map cell (`x`, `y`).
+    pub fn opacity(&self, x: i32, y: i32) -> f32 {
+        let (dx, dy) = ((x - self.center.0) as f32, (y - self.center.1) as f32);
+        if dx * dx + dy * dy <= self.radius * self.radius {
+            self.density
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Temporary opacity layered on top of the per-cell opacity of the map.
+///
+/// The clouds are advanced once per turn with `advance` and dropped once
+/// they have thinned out below `min_density`.  Like `Weather`, the layers
+/// combine like stacked translucent sheets.
+///
+#[derive(Clone, Debug, Default)]
+pub struct Smoke {
+    clouds: Vec<SmokeCloud>,
+    min_density: f32,
+}
+
+impl Smoke {
+    /// Constructs an empty set of clouds dropping the clouds thinner than
+    /// `min_density`.
+    ///
+    pub fn new(min_density: f32) -> Self {
+        Smoke {
+            clouds: Vec::new(),
+            min_density,
+        }
+    }
+
+    /// Add a cloud.
+    pub fn add(&mut self, cloud: SmokeCloud) {
+        self.clouds.push(cloud);
+    }
+
+    /// The clouds still lingering.
+    pub fn clouds(&self) -> &[SmokeCloud] {
+        &self.clouds
+    }
+
+    /// Whether there are no clouds.
+    pub fn is_empty(&self) -> bool {
+        self.clouds.is_empty()
+    }
+
+    /// Advance the clouds by one turn: expand and thin them out and drop the
+    /// ones that have thinned out.
+    ///
+    pub fn advance(&mut self) {
+        for cloud in self.clouds.iter_mut() {
+            cloud.radius = (cloud.radius + cloud.growth).min(cloud.max_radius);
+            cloud.density *= cloud.decay;
+        }
+        let min_density = self.min_density;
+        self.clouds.retain(|cloud| cloud.density >= min_density);
+    }
+
+    /// The opacity of the smoke at the map cell (`x`, `y`).
+    ///
+    pub fn opacity(&self, x: i32, y: i32) -> f32 {
+        let transmittance = self
+            .clouds
+            .iter()
+            .fold(1.0, |t, cloud| t * (1.0 - cloud.opacity(x, y)));
+        1.0 - transmittance
+    }
+
+    /// Layer the smoke on top of the per-cell `opacity` for use with the
+    /// standard kernels; see `Weather::layer`.
+    ///
+    pub fn layer<F>(&self, origin: (i32, i32), opacity: F) -> impl Fn(i32, i32) -> f32 + '_
+    where
+        F: Fn(i32, i32) -> f32 + 'static,
+    {
+        let (origin_x, origin_y) = origin;
+        move |x, y| {
+            let smoke = self.opacity(origin_x + x, origin_y + y);
+            1.0 - (1.0 - smoke) * (1.0 - opacity(x, y))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel::{self, Visibility};
+    use crate::{FluxField, Fov};
+
+    #[test]
+    fn clouds_expand_and_dissipate() {
+        let mut smoke = Smoke::new(0.1);
+        smoke.add(SmokeCloud {
+            center: (5, 5),
+            radius: 1.0,
+            density: 0.9,
+            growth: 1.0,
+            max_radius: 2.0,
+            decay: 0.5,
+        });
+        assert_eq!(smoke.opacity(5, 6), 0.9);
+        assert_eq!(smoke.opacity(5, 7), 0.0);
+        let mut fov = Fov::new(Box::new(FluxField::new(6)), 6, Visibility::default());
+        fov.update(kernel::threshold(0.5, smoke.layer((2, 5), |_, _| 0.0)));
+        assert!(fov.at(2, 0).is_visible);
+        assert!(!fov.at(4, 0).is_visible);
+        smoke.advance();
+        assert_eq!(smoke.opacity(5, 7), 0.45);
+        smoke.advance();
+        assert_eq!(smoke.clouds()[0].radius, 2.0);
+        smoke.advance();
+        assert!(smoke.opacity(5, 5) > 0.1);
+        smoke.advance();
+        assert!(smoke.is_empty());
+        assert_eq!(smoke.opacity(5, 5), 0.0);
+    }
+}
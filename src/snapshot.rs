@@ -0,0 +1,181 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::sync::Arc;
+
+use crate::{FluxField, Fov};
+
+/// An immutable copy of the values of a field of vision.
+///
+/// A snapshot doesn't refer to the flux field so it can be freely sent to
+/// and shared between threads as long as the values can.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct FovSnapshot<T> {
+    pub(crate) radius: isize,
+    pub(crate) width: isize,
+    pub(crate) ix_origin: isize,
+    pub(crate) data: Vec<T>,
+}
+
+impl<T> FovSnapshot<T> {
+    /// The radius of the field the snapshot was taken from.
+    pub fn radius(&self) -> usize {
+        self.radius as usize
+    }
+
+    /// The value of at the given grid cell.
+    pub fn at(&self, x: i32, y: i32) -> &T {
+        let radius = self.radius as u32;
+        assert!(
+            x.unsigned_abs() <= radius && y.unsigned_abs() <= radius,
+            "cell out of range"
+        );
+        let ix = self.ix_origin + (self.width * y as isize) + x as isize;
+        &self.data[ix as usize]
+    }
+
+    /// Expose the underlying data as a slice.
+    pub fn as_slice(&self) -> &[T] {
+        self.data.as_slice()
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    T: Clone,
+    X: AsRef<FluxField>,
+{
+    /// Publish the current values as a shared snapshot.
+    ///
+    /// The snapshot is meant to be handed over to another thread, e.g. a
+    /// render thread, while this field keeps being updated.  Combine with an
+    /// atomic pointer swap (such as the `arc-swap` crate) to share the latest
+    /// snapshot without locks.
+    ///
+    pub fn publish(&self) -> Arc<FovSnapshot<T>> {
+        Arc::new(self.snapshot())
+    }
+
+    /// Take a snapshot of the current values.
+    ///
+    /// Together with `restore` this allows saving and restoring the state of
+    /// the field, e.g. for rewinding turns or for looking ahead, without
+    /// constructing new fields.
+    ///
+    pub fn snapshot(&self) -> FovSnapshot<T> {
+        FovSnapshot {
+            radius: self.radius,
+            width: self.width,
+            ix_origin: self.ix_origin,
+            data: self.data.to_vec(),
+        }
+    }
+
+    /// Restore the values from a snapshot.
+    ///
+    /// The snapshot must have been taken from a field with the same radius.
+    ///
+    pub fn restore(&mut self, snapshot: &FovSnapshot<T>) {
+        assert_eq!(self.radius, snapshot.radius, "radius mismatch");
+        self.data.clear();
+        self.data.extend_from_slice(&snapshot.data);
+    }
+
+    /// Publish the current values as a shared snapshot reusing the buffer of
+    /// a previously published snapshot.
+    ///
+    /// The buffer is reused only if `previous` is the last reference to the
+    /// old snapshot.  Otherwise this is equivalent to `publish`.
+    ///
+    pub fn republish(&self, previous: Arc<FovSnapshot<T>>) -> Arc<FovSnapshot<T>> {
+        match Arc::try_unwrap(previous) {
+            Ok(mut snapshot) => {
+                snapshot.radius = self.radius;
+                snapshot.width = self.width;
+                snapshot.ix_origin = self.ix_origin;
+                snapshot.data.clear();
+                snapshot.data.extend_from_slice(&self.data);
+                Arc::new(snapshot)
+            }
+            Err(_) => self.publish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn publish_to_another_thread() {
+        let mut fov = Fov::new(Box::new(FluxField::new(2)), 2, 0);
+        fov.update(|x, y, _| x * 10 + y);
+        let snapshot = fov.publish();
+        fov.update(|_, _, _| 0);
+        let reader = {
+            let snapshot = snapshot.clone();
+            thread::spawn(move || *snapshot.at(2, -1))
+        };
+        assert_eq!(reader.join().unwrap(), 19);
+        assert_eq!(snapshot.radius(), 2);
+        assert_eq!(*fov.at(2, -1), 0);
+    }
+
+    #[test]
+    fn republish_reuses_unshared_buffer() {
+        let mut fov = Fov::new(Box::new(FluxField::new(2)), 2, 0);
+        let snapshot = fov.publish();
+        let buffer = snapshot.as_slice().as_ptr();
+        fov.update(|x, y, _| x + y);
+        let snapshot = fov.republish(snapshot);
+        assert_eq!(snapshot.as_slice().as_ptr(), buffer);
+        assert_eq!(*snapshot.at(1, 1), 2);
+        let reader = snapshot.clone();
+        let snapshot = fov.republish(snapshot);
+        assert_ne!(snapshot.as_slice().as_ptr(), reader.as_slice().as_ptr());
+    }
+
+    #[test]
+    fn snapshot_and_restore() {
+        let mut fov = Fov::new(Box::new(FluxField::new(2)), 2, 0);
+        fov.update(|x, y, _| x - y);
+        let snapshot = fov.snapshot();
+        fov.update(|_, _, _| 7);
+        assert_eq!(*fov.at(2, -2), 7);
+        fov.restore(&snapshot);
+        assert_eq!(*fov.at(2, -2), 4);
+        assert_eq!(fov.as_slice(), snapshot.as_slice());
+    }
+
+    #[test]
+    #[should_panic(expected = "radius mismatch")]
+    fn restore_radius_mismatch() {
+        let flux_field = std::rc::Rc::new(FluxField::new(3));
+        let snapshot = Fov::new(flux_field.clone(), 3, 0).snapshot();
+        Fov::new(flux_field, 2, 0).restore(&snapshot);
+    }
+
+    #[test]
+    #[should_panic(expected = "cell out of range")]
+    fn at_out_of_range() {
+        // Within the buffer but past the right edge of the field.
+        let snapshot = Fov::new(Box::new(FluxField::new(2)), 2, 0).snapshot();
+        snapshot.at(3, 0);
+    }
+}
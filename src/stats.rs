@@ -0,0 +1,150 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::f32;
+
+use crate::{FluxField, Fov, Rect};
+
+/// Statistics of the cells matching a predicate.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stats {
+    /// The number of matching cells.
+    pub count: usize,
+    /// The mean position of the matching cells, if any.
+    pub centroid: Option<(f32, f32)>,
+    /// The smallest rectangle covering the matching cells, if any.
+    pub bounds: Option<Rect>,
+}
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Compute the statistics of the cells matching `predicate`.
+    ///
+    /// The coordinates are relative to the origin of the field.
+    ///
+    pub fn stats<P>(&self, predicate: P) -> Stats
+    where
+        P: Fn(&T) -> bool,
+    {
+        let radius = self.radius as i32;
+        let mut count = 0;
+        let mut sum_x = 0_i64;
+        let mut sum_y = 0_i64;
+        let (mut min_x, mut min_y) = (i32::MAX, i32::MAX);
+        let (mut max_x, mut max_y) = (i32::MIN, i32::MIN);
+        for y in -radius..radius + 1 {
+            for x in -radius..radius + 1 {
+                if predicate(self.at(x, y)) {
+                    count += 1;
+                    sum_x += x as i64;
+                    sum_y += y as i64;
+                    min_x = min_x.min(x);
+                    min_y = min_y.min(y);
+                    max_x = max_x.max(x);
+                    max_y = max_y.max(y);
+                }
+            }
+        }
+        if count == 0 {
+            Stats {
+                count,
+                centroid: None,
+                bounds: None,
+            }
+        } else {
+            Stats {
+                count,
+                centroid: Some((sum_x as f32 / count as f32, sum_y as f32 / count as f32)),
+                bounds: Some(Rect::new(
+                    min_x,
+                    min_y,
+                    max_x - min_x + 1,
+                    max_y - min_y + 1,
+                )),
+            }
+        }
+    }
+
+    /// Compute the farthest distance at which a cell matches `predicate` in
+    /// each of `buckets` equal angular sectors.
+    ///
+    /// The first sector starts at the positive x-axis and the sectors
+    /// proceed towards the positive y-axis.  The distances are Euclidean and
+    /// measured between the cell centers.  A sector with no matching cells
+    /// gets the distance 0.0.
+    ///
+    pub fn visibility_profile<P>(&self, buckets: usize, predicate: P) -> Vec<f32>
+    where
+        P: Fn(&T) -> bool,
+    {
+        assert!(buckets > 0);
+        let radius = self.radius as i32;
+        let mut profile = vec![0.0_f32; buckets];
+        for y in -radius..radius + 1 {
+            for x in -radius..radius + 1 {
+                if (x != 0 || y != 0) && predicate(self.at(x, y)) {
+                    let (x, y) = (x as f32, y as f32);
+                    let angle = y.atan2(x).rem_euclid(2.0 * f32::consts::PI);
+                    let bucket = ((angle / (2.0 * f32::consts::PI) * buckets as f32) as usize)
+                        .min(buckets - 1);
+                    profile[bucket] = profile[bucket].max(x.hypot(y));
+                }
+            }
+        }
+        profile
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn stats_of_matching_cells() {
+        let mut fov = Fov::new(Box::new(FluxField::new(3)), 3, false);
+        fov.update(|x, y, _| x >= 1 && (-1..=0).contains(&y));
+        let stats = fov.stats(|&v| v);
+        assert_eq!(stats.count, 6);
+        assert_eq!(stats.centroid, Some((2.0, -0.5)));
+        assert_eq!(stats.bounds, Some(Rect::new(1, -1, 3, 2)));
+    }
+
+    #[test]
+    fn stats_of_no_cells() {
+        let fov = Fov::new(Box::new(FluxField::new(1)), 1, false);
+        let stats = fov.stats(|&v| v);
+        assert_eq!(stats.count, 0);
+        assert_eq!(stats.centroid, None);
+        assert_eq!(stats.bounds, None);
+    }
+
+    #[test]
+    fn visibility_profile_by_sector() {
+        let mut fov = Fov::new(Box::new(FluxField::new(4)), 4, false);
+        fov.update(|x, y, _| x.abs() + y.abs() <= 2 || (x > 0 && y == 0));
+        let profile = fov.visibility_profile(4, |&v| v);
+        assert_eq!(profile[0], 4.0);
+        assert!((profile[1] - 2.0).abs() < 1e-6);
+        assert!((profile[2] - 2.0).abs() < 1e-6);
+        assert!((profile[3] - 2.0).abs() < 1e-6);
+        let fov = Fov::new(Box::new(FluxField::new(2)), 2, false);
+        assert_eq!(fov.visibility_profile(3, |&v| v), vec![0.0; 3]);
+    }
+}
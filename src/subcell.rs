@@ -0,0 +1,210 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{FluxField, Fov, Influx};
+
+/// A light field whose origin can sit anywhere within a cell, e.g., for a
+/// player rendered at a fractional position during smooth movement.
+///
+/// The flux fields assume the origin at the center of a cell.  Rather than
+/// bending the weights, the field is updated from the four cell centers
+/// around the origin and the results are blended bilinearly by how close
+/// the origin is to each, so the light slides smoothly instead of snapping
+/// when the logical cell changes.  The cost is up to four updates.
+///
+/// The cells are addressed relative to the base cell, the one whose center
+/// is up and to the left of the origin.
+///
+/// ```
+/// # use flux_fov::{kernel, FluxField, SubcellFov};
+/// # use std::rc::Rc;
+/// let mut light = SubcellFov::new(Rc::new(FluxField::new(6)), 6, Default::default());
+/// let wall = |x: i32, _| if x == -2 { 1.0 } else { 0.0 };
+/// let shadow = |ox: i32, oy: i32| kernel::soft_shadow(move |x, y| wall(ox + x, oy + y));
+/// light.update((0.0, 0.0), shadow, |c| c.brightness);
+/// assert_eq!(light.at(-3, 0), 0.0);
+/// light.update((0.5, 0.0), shadow, |c| c.brightness);
+/// assert_eq!(light.at(-3, 0), 0.0);
+/// assert_eq!(light.at(0, 0), 1.0);
+/// ```
+///
+pub struct SubcellFov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    radius: i32,
+    corners: Vec<Fov<T, X>>,
+    light: Vec<f32>,
+}
+
+// The offsets of the corner cells from the base cell.
+const CORNERS: [(i32, i32); 4] = [(0, 0), (1, 0), (0, 1), (1, 1)];
+
+impl<T, X> SubcellFov<T, X>
+where
+    T: Clone,
+    X: AsRef<FluxField> + Clone,
+{
+    pub fn new(flux_field: X, radius: usize, init: T) -> Self {
+        let corners = CORNERS
+            .iter()
+            .map(|_| Fov::new(flux_field.clone(), radius, init.clone()))
+            .collect();
+        let width = 2 * radius + 1;
+        SubcellFov {
+            radius: radius as i32,
+            corners,
+            light: vec![0.0; width * width],
+        }
+    }
+}
+
+impl<T, X> SubcellFov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// The radius of the field.
+    pub fn radius(&self) -> usize {
+        self.radius as usize
+    }
+
+    /// The light at the given cell relative to the base cell.
+    pub fn at(&self, x: i32, y: i32) -> f32 {
+        let radius = self.radius as u32;
+        assert!(
+            x.unsigned_abs() <= radius && y.unsigned_abs() <= radius,
+            "cell out of range"
+        );
+        let width = 2 * self.radius + 1;
+        self.light[((y + self.radius) * width + x + self.radius) as usize]
+    }
+
+    /// Expose the light as a slice in row-major order like `Fov::as_slice`.
+    pub fn as_slice(&self) -> &[f32] {
+        self.light.as_slice()
+    }
+
+    /// Update the field with the origin at `offset` from the center of the
+    /// base cell, each coordinate from 0.0 to 1.0.
+    ///
+    /// For each corner cell around the origin `make_kernel` receives the
+    /// offset of the corner from the base cell and returns the update
+    /// function for the field centered on that corner, typically a stock
+    /// kernel whose callbacks shift their coordinates by the offset.  The
+    /// light of a cell is then taken from its value with `light`.  The
+    /// corners the origin is not at all close to are skipped.
+    ///
+    pub fn update<M, K, L>(&mut self, offset: (f32, f32), make_kernel: M, light: L)
+    where
+        M: Fn(i32, i32) -> K,
+        K: FnMut(i32, i32, &[Influx<&T>]) -> T,
+        L: Fn(&T) -> f32,
+    {
+        let (fx, fy) = (offset.0.clamp(0.0, 1.0), offset.1.clamp(0.0, 1.0));
+        let weights = [
+            (1.0 - fx) * (1.0 - fy),
+            fx * (1.0 - fy),
+            (1.0 - fx) * fy,
+            fx * fy,
+        ];
+        for v in self.light.iter_mut() {
+            *v = 0.0;
+        }
+        let r = self.radius;
+        let width = 2 * r + 1;
+        for ((&(ox, oy), &weight), fov) in CORNERS
+            .iter()
+            .zip(weights.iter())
+            .zip(self.corners.iter_mut())
+        {
+            if weight == 0.0 {
+                continue;
+            }
+            fov.update(make_kernel(ox, oy));
+            for y in -r..r + 1 {
+                for x in -r..r + 1 {
+                    let (cx, cy) = (x - ox, y - oy);
+                    if cx.abs() <= r && cy.abs() <= r {
+                        self.light[((y + r) * width + x + r) as usize] +=
+                            weight * light(fov.at(cx, cy));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel;
+    use std::rc::Rc;
+
+    #[test]
+    fn light_slides_between_cells() {
+        let flux_field = Rc::new(FluxField::new(5));
+        let mut light = SubcellFov::new(flux_field.clone(), 5, 0.0_f32);
+        let spread = |_: i32, _: i32| {
+            |x: i32, y: i32, influxes: &[Influx<&f32>]| {
+                if x == 0 && y == 0 {
+                    1.0
+                } else {
+                    0.8 * influxes.iter().map(|f| f.weight * f.value).sum::<f32>()
+                }
+            }
+        };
+        let mut plain = Fov::new(flux_field, 5, 0.0_f32);
+        plain.update(spread(0, 0));
+        light.update((0.0, 0.0), spread, |&v| v);
+        assert_eq!(light.as_slice(), plain.as_slice());
+        light.update((1.0, 0.0), spread, |&v| v);
+        assert_eq!(light.at(3, 2), *plain.at(2, 2));
+        assert_eq!(light.at(-5, 0), 0.0);
+        // Moving the origin a little changes the light a little.
+        let mut last: Option<f32> = None;
+        for step in 0..11 {
+            light.update((step as f32 / 10.0, 0.3), spread, |&v| v);
+            let v = light.at(3, 1);
+            if let Some(last) = last {
+                assert!((v - last).abs() < 0.05);
+            }
+            last = Some(v);
+        }
+    }
+
+    #[test]
+    fn shifted_kernels() {
+        let flux_field = Rc::new(FluxField::new(6));
+        let mut light = SubcellFov::new(flux_field, 6, kernel::Illumination::default());
+        let wall = |x: i32, _: i32| if x == 2 { 1.0 } else { 0.0 };
+        let shadow = |ox: i32, oy: i32| kernel::soft_shadow(move |x, y| wall(ox + x, oy + y));
+        // Right next to the wall the corner behind it is dark.
+        light.update((1.0, 0.0), shadow, |c| c.brightness);
+        assert_eq!(light.at(2, 0), 1.0);
+        assert_eq!(light.at(3, 0), 0.0);
+        light.update((0.5, 0.0), shadow, |c| c.brightness);
+        assert_eq!(light.at(3, 0), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "cell out of range")]
+    fn at_out_of_range() {
+        // Within the buffer but past the right edge of the field.
+        let light = SubcellFov::new(Rc::new(FluxField::new(2)), 2, 0.0_f32);
+        light.at(3, 0);
+    }
+}
@@ -0,0 +1,548 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// A safe, index based walk over the field in the same order as
+// `Fov::update` visits the cells.  It is slower than the pointer walk of the
+// update proper but it is handy for the auxiliary passes that need to know
+// where the flux comes from without running the user's update function.
+
+use crate::{lut_len, FluxField, Influx, Influxes, Sector, FIXED_ONE};
+
+/// A single influx into a cell expressed as a buffer index.
+///
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Tap {
+    pub ix: usize,
+    pub dx: i32,
+    pub dy: i32,
+    pub weight: f32,
+    /// The weight in fixed-point.
+    pub fixed: u32,
+}
+
+/// A single cell visited by the sweep.
+///
+#[derive(Clone, Copy)]
+pub(crate) struct Step {
+    pub x: i32,
+    pub y: i32,
+    pub ix: usize,
+    pub sector: Sector,
+    taps: [Tap; 3],
+    tap_count: usize,
+}
+
+impl Step {
+    fn new(x: i32, y: i32, ix: usize, sector: Sector, taps: &[Tap]) -> Self {
+        let mut step = Step {
+            x,
+            y,
+            ix,
+            sector,
+            taps: Default::default(),
+            tap_count: taps.len(),
+        };
+        step.taps[..taps.len()].copy_from_slice(taps);
+        step
+    }
+
+    /// The influxes into the cell.
+    #[inline]
+    pub fn taps(&self) -> &[Tap] {
+        &self.taps[..self.tap_count]
+    }
+
+    /// Call `f` with the influxes into the cell reading the values of their
+    /// sources with `value`.
+    #[inline]
+    pub fn with_influxes<'a, T: 'a, V, F, R>(&self, value: V, f: F) -> R
+    where
+        V: Fn(usize) -> &'a T,
+        F: FnOnce(Influxes<&'a T>) -> R,
+    {
+        let influx = |tap: &Tap| Influx {
+            weight: tap.weight,
+            dx: tap.dx,
+            dy: tap.dy,
+            value: value(tap.ix),
+        };
+        match self.taps() {
+            [] => f(Influxes::Origin),
+            [a] => f(Influxes::One(&[influx(a)])),
+            [a, b] => f(Influxes::Two(&[influx(a), influx(b)])),
+            [a, b, c] => f(Influxes::Three(&[influx(a), influx(b), influx(c)])),
+            _ => unreachable!("more than three taps"),
+        }
+    }
+}
+
+// The edge directions and the interior octant parametrizations in the order
+// they are visited by `Fov::update`.  For the interiors the tuples are
+// (m_xu, m_xv, m_yu, m_yv) mapping the octant coordinates (u, v) to the
+// field coordinates.
+
+pub(crate) const EDGES: [(i32, i32); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
+pub(crate) const INTERIORS: [(i32, i32, i32, i32); 8] = [
+    (1, 0, 0, 1),
+    (0, 1, 1, 0),
+    (0, -1, 1, 0),
+    (-1, 0, 0, 1),
+    (-1, 0, 0, -1),
+    (0, -1, -1, 0),
+    (0, 1, -1, 0),
+    (1, 0, 0, -1),
+];
+
+/// Walk the cells of a field with the given geometry calling `f` for each.
+///
+pub(crate) fn sweep<F>(radius: isize, width: isize, ix_origin: isize, flux_field: &FluxField, f: F)
+where
+    F: FnMut(&Step),
+{
+    sweep_within(radius, width, ix_origin, flux_field, |_, _| true, f);
+}
+
+/// Walk the cells of a field within a footprint like
+/// `Fov::update_cells_within` does.
+///
+pub(crate) fn sweep_within<P, F>(
+    radius: isize,
+    width: isize,
+    ix_origin: isize,
+    flux_field: &FluxField,
+    within: P,
+    f: F,
+) where
+    P: Fn(i32, i32) -> bool,
+    F: FnMut(&Step),
+{
+    let mut f = f;
+    let index = |x: i32, y: i32| (ix_origin + width * y as isize + x as isize) as usize;
+    f(&Step::new(0, 0, index(0, 0), Sector::Origin, &[]));
+    if flux_field.is_four_connected() {
+        sweep_rings(radius as i32, &index, flux_field, &mut |step: &Step| {
+            if within(step.x, step.y) {
+                f(step);
+            }
+        });
+    } else {
+        sweep_octants(
+            radius as i32,
+            &index,
+            flux_field,
+            &|edge: usize, k| within(EDGES[edge].0 * k, EDGES[edge].1 * k),
+            &|_, u| Some((1, u - 1)),
+            &within,
+            &mut f,
+        );
+    }
+}
+
+/// Walk the cells of an eight-connected field in the same order as
+/// `Fov::update` but only the given spans of the edges and the columns of
+/// the octant interiors.  The edge `edge` is walked outwards for as long as
+/// `on_edge(edge, k)` holds and the column `u` of the octant `octant` from
+/// `a` to `b` where `span(octant, u)` is `Some((a, b))`.  The cost of the
+/// walk is that of the cells visited plus a few steps per column.
+///
+pub(crate) fn sweep_spans<E, S, F>(
+    radius: isize,
+    width: isize,
+    ix_origin: isize,
+    flux_field: &FluxField,
+    on_edge: E,
+    span: S,
+    f: F,
+) where
+    E: Fn(usize, i32) -> bool,
+    S: Fn(usize, i32) -> Option<(i32, i32)>,
+    F: FnMut(&Step),
+{
+    debug_assert!(!flux_field.is_four_connected());
+    let mut f = f;
+    let index = |x: i32, y: i32| (ix_origin + width * y as isize + x as isize) as usize;
+    f(&Step::new(0, 0, index(0, 0), Sector::Origin, &[]));
+    sweep_octants(
+        radius as i32,
+        &index,
+        flux_field,
+        &on_edge,
+        &|octant, u| span(octant, u).map(|(a, b)| (a.max(1), b.min(u - 1))),
+        &|_, _| true,
+        &mut f,
+    );
+}
+
+// Walk the edges and then the spans of the columns of the octant interiors
+// one by one like the pointer walk of `Fov::update`, moving on to the next
+// edge or column at the first cell outside the footprint.
+fn sweep_octants<I, E, S, P, F>(
+    radius: i32,
+    index: &I,
+    flux_field: &FluxField,
+    on_edge: &E,
+    span: &S,
+    within: &P,
+    f: &mut F,
+) where
+    I: Fn(i32, i32) -> usize,
+    E: Fn(usize, i32) -> bool,
+    S: Fn(usize, i32) -> Option<(i32, i32)>,
+    P: Fn(i32, i32) -> bool,
+    F: FnMut(&Step),
+{
+    let flux_lut = &flux_field.flux_lut;
+    let fixed_lut = flux_field.fixed_lut();
+    let tap_count = if flux_field.is_three_tap() { 3 } else { 2 };
+    for (edge, &(dx, dy)) in EDGES.iter().enumerate() {
+        for k in 1..radius + 1 {
+            if !on_edge(edge, k) {
+                break;
+            }
+            f(&Step::new(
+                dx * k,
+                dy * k,
+                index(dx * k, dy * k),
+                Sector::Edge {
+                    index: edge as u8,
+                    column: k as u32,
+                },
+                &[Tap {
+                    ix: index(dx * (k - 1), dy * (k - 1)),
+                    dx,
+                    dy,
+                    weight: 1.0,
+                    fixed: FIXED_ONE,
+                }],
+            ));
+        }
+    }
+    for (octant, &(m_xu, m_xv, m_yu, m_yv)) in INTERIORS.iter().enumerate() {
+        let at = |u: i32, v: i32| index(m_xu * u + m_xv * v, m_yu * u + m_yv * v);
+        for u in 2..radius + 1 {
+            let column_ix = lut_len(u as usize - 1);
+            let (a, b) = match span(octant, u) {
+                Some(span) => span,
+                None => continue,
+            };
+            for v in a..b + 1 {
+                if !within(m_xu * u + m_xv * v, m_yu * u + m_yv * v) {
+                    break;
+                }
+                let lut_ix = column_ix + v as usize - 1;
+                let w = flux_lut[lut_ix];
+                let l = flux_field.lateral(lut_ix);
+                let [fw, fl] = fixed_lut[lut_ix];
+                let taps = [
+                    Tap {
+                        ix: at(u - 1, v - 1),
+                        dx: m_xu + m_xv,
+                        dy: m_yu + m_yv,
+                        weight: w,
+                        fixed: fw,
+                    },
+                    Tap {
+                        ix: at(u - 1, v),
+                        dx: m_xu,
+                        dy: m_yu,
+                        weight: 1.0 - w - l,
+                        fixed: FIXED_ONE - fw - fl,
+                    },
+                    Tap {
+                        ix: at(u, v - 1),
+                        dx: m_xv,
+                        dy: m_yv,
+                        weight: l,
+                        fixed: fl,
+                    },
+                ];
+                f(&Step::new(
+                    m_xu * u + m_xv * v,
+                    m_yu * u + m_yv * v,
+                    at(u, v),
+                    Sector::Interior {
+                        octant: octant as u8,
+                        column: u as u32,
+                    },
+                    &taps[..tap_count],
+                ));
+            }
+        }
+    }
+}
+
+// Walk a four-connected field ring by ring.
+fn sweep_rings<I, F>(radius: i32, index: &I, flux_field: &FluxField, f: &mut F)
+where
+    I: Fn(i32, i32) -> usize,
+    F: FnMut(&Step),
+{
+    for k in 1..radius + 1 {
+        sweep_four_connected_ring(k, index, flux_field, f);
+    }
+}
+
+/// Walk the cells of a single ring `k`, i.e. those whose Chebyshev distance
+/// from the origin is `k`, calling `f` for each.  The ring 0 is the origin.
+///
+/// The influxes of the cells come from the ring itself and the ring just
+/// inside it so walking the rings in order updates the whole field.
+///
+pub(crate) fn sweep_ring<F>(k: i32, width: isize, ix_origin: isize, flux_field: &FluxField, f: F)
+where
+    F: FnMut(&Step),
+{
+    let mut f = f;
+    let index = |x: i32, y: i32| (ix_origin + width * y as isize + x as isize) as usize;
+    if k == 0 {
+        f(&Step::new(0, 0, index(0, 0), Sector::Origin, &[]));
+    } else if flux_field.is_four_connected() {
+        sweep_four_connected_ring(k, &index, flux_field, &mut f);
+    } else {
+        sweep_eight_connected_ring(k, &index, flux_field, &mut f);
+    }
+}
+
+// Walk a ring of an eight-connected field: the edges first and then the
+// columns `u = k` of the octant interiors.
+fn sweep_eight_connected_ring<I, F>(k: i32, index: &I, flux_field: &FluxField, f: &mut F)
+where
+    I: Fn(i32, i32) -> usize,
+    F: FnMut(&Step),
+{
+    let flux_lut = &flux_field.flux_lut;
+    let fixed_lut = flux_field.fixed_lut();
+    let tap_count = if flux_field.is_three_tap() { 3 } else { 2 };
+    let column = k as u32;
+    for (edge, &(dx, dy)) in EDGES.iter().enumerate() {
+        f(&Step::new(
+            dx * k,
+            dy * k,
+            index(dx * k, dy * k),
+            Sector::Edge {
+                index: edge as u8,
+                column,
+            },
+            &[Tap {
+                ix: index(dx * (k - 1), dy * (k - 1)),
+                dx,
+                dy,
+                weight: 1.0,
+                fixed: FIXED_ONE,
+            }],
+        ));
+    }
+    for (octant, &(m_xu, m_xv, m_yu, m_yv)) in INTERIORS.iter().enumerate() {
+        let at = |u: i32, v: i32| index(m_xu * u + m_xv * v, m_yu * u + m_yv * v);
+        for v in 1..k {
+            let lut_ix = lut_len(column as usize - 1) + v as usize - 1;
+            let w = flux_lut[lut_ix];
+            let l = flux_field.lateral(lut_ix);
+            let [fw, fl] = fixed_lut[lut_ix];
+            let taps = [
+                Tap {
+                    ix: at(k - 1, v - 1),
+                    dx: m_xu + m_xv,
+                    dy: m_yu + m_yv,
+                    weight: w,
+                    fixed: fw,
+                },
+                Tap {
+                    ix: at(k - 1, v),
+                    dx: m_xu,
+                    dy: m_yu,
+                    weight: 1.0 - w - l,
+                    fixed: FIXED_ONE - fw - fl,
+                },
+                Tap {
+                    ix: at(k, v - 1),
+                    dx: m_xv,
+                    dy: m_yv,
+                    weight: l,
+                    fixed: fl,
+                },
+            ];
+            f(&Step::new(
+                m_xu * k + m_xv * v,
+                m_yu * k + m_yv * v,
+                at(k, v),
+                Sector::Interior {
+                    octant: octant as u8,
+                    column,
+                },
+                &taps[..tap_count],
+            ));
+        }
+    }
+}
+
+// Walk a ring of a four-connected field.  The straight edges come first,
+// then the octant interiors from the straight edges towards the diagonals,
+// and finally the diagonal edges which receive flux from the two interior
+// cells flanking them.
+fn sweep_four_connected_ring<I, F>(k: i32, index: &I, flux_field: &FluxField, f: &mut F)
+where
+    I: Fn(i32, i32) -> usize,
+    F: FnMut(&Step),
+{
+    let fixed_lut = flux_field.fixed_lut();
+    let column = k as u32;
+    for edge in (0..8).step_by(2) {
+        let (dx, dy) = EDGES[edge];
+        f(&Step::new(
+            dx * k,
+            dy * k,
+            index(dx * k, dy * k),
+            Sector::Edge {
+                index: edge as u8,
+                column,
+            },
+            &[Tap {
+                ix: index(dx * (k - 1), dy * (k - 1)),
+                dx,
+                dy,
+                weight: 1.0,
+                fixed: FIXED_ONE,
+            }],
+        ));
+    }
+    for (octant, &(m_xu, m_xv, m_yu, m_yv)) in INTERIORS.iter().enumerate() {
+        let at = |u: i32, v: i32| index(m_xu * u + m_xv * v, m_yu * u + m_yv * v);
+        for v in 1..k {
+            let lut_ix = lut_len(column as usize - 1) + v as usize - 1;
+            let l = flux_field.lateral(lut_ix);
+            let [_, fl] = fixed_lut[lut_ix];
+            f(&Step::new(
+                m_xu * k + m_xv * v,
+                m_yu * k + m_yv * v,
+                at(k, v),
+                Sector::Interior {
+                    octant: octant as u8,
+                    column,
+                },
+                &[
+                    Tap {
+                        ix: at(k - 1, v),
+                        dx: m_xu,
+                        dy: m_yu,
+                        weight: 1.0 - l,
+                        fixed: FIXED_ONE - fl,
+                    },
+                    Tap {
+                        ix: at(k, v - 1),
+                        dx: m_xv,
+                        dy: m_yv,
+                        weight: l,
+                        fixed: fl,
+                    },
+                ],
+            ));
+        }
+    }
+    for edge in (1..8).step_by(2) {
+        let (dx, dy) = EDGES[edge];
+        f(&Step::new(
+            dx * k,
+            dy * k,
+            index(dx * k, dy * k),
+            Sector::Edge {
+                index: edge as u8,
+                column,
+            },
+            &[
+                Tap {
+                    ix: index(dx * (k - 1), dy * k),
+                    dx,
+                    dy: 0,
+                    weight: 0.5,
+                    fixed: FIXED_ONE / 2,
+                },
+                Tap {
+                    ix: index(dx * k, dy * (k - 1)),
+                    dx: 0,
+                    dy,
+                    weight: 0.5,
+                    fixed: FIXED_ONE / 2,
+                },
+            ],
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::{four_connected, three_tap};
+    use crate::Fov;
+
+    #[test]
+    fn sweep_matches_update() {
+        sweep_matches_update_with(crate::FluxParams::default());
+    }
+
+    #[test]
+    fn three_tap_sweep_matches_update() {
+        sweep_matches_update_with(three_tap());
+    }
+
+    #[test]
+    fn four_connected_sweep_matches_update() {
+        sweep_matches_update_with(four_connected());
+    }
+
+    fn sweep_matches_update_with(params: crate::FluxParams) {
+        let sz = 5;
+        let flux_field = Box::new(FluxField::with_params(sz, params));
+        let mut fov = Fov::new(flux_field, sz, (0, 0));
+        let mut expected = Vec::new();
+        fov.update(|x, y, influxes| {
+            let taps: Vec<_> = influxes
+                .iter()
+                .map(|f| (*f.value, f.dx, f.dy, f.weight))
+                .collect();
+            expected.push((x, y, taps));
+            (x, y)
+        });
+        let mut actual = Vec::new();
+        sweep(
+            fov.radius,
+            fov.width,
+            fov.ix_origin,
+            &fov.flux_field,
+            |step| {
+                let taps: Vec<_> = step
+                    .taps()
+                    .iter()
+                    .map(|t| (fov.as_slice()[t.ix], t.dx, t.dy, t.weight))
+                    .collect();
+                assert_eq!(fov.as_slice()[step.ix], (step.x, step.y));
+                actual.push((step.x, step.y, taps));
+            },
+        );
+        assert_eq!(actual, expected);
+    }
+}
@@ -0,0 +1,124 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::kernel::{self, Visibility};
+use crate::{FluxField, Fov};
+
+impl<X> Fov<Visibility, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Update the field of vision with `kernel::threshold` made symmetric:
+    /// the viewer sees a cell only if the cell would see the viewer, too.
+    ///
+    /// The flux reaching a cell from the viewer generally differs from the
+    /// flux reaching the viewer from the cell, so the plain threshold kernel
+    /// may let A see B but not B see A.  Here a cell is visible only if the
+    /// rays exceed the `threshold` in both directions, the way back computed
+    /// with `FluxField::line_of_sight`.  Hence for any two cells within the
+    /// radius of each other A sees B exactly when B sees A.
+    ///
+    /// The trade-off is that the visibility is the more conservative of the
+    /// two directions: the cells seen in one direction only, typically on
+    /// the edges of the shadows and along walls seen at grazing angles, are
+    /// hidden.  The `ray_output` of the cells is that of the plain threshold
+    /// kernel so the flux itself is unchanged.
+    ///
+    /// The symmetry relies on `FluxField::line_of_sight` giving exactly, bit
+    /// for bit, the flux an update would give; a difference in the last bit
+    /// right at the threshold would break it.
+    ///
+    /// The way back is propagated separately for every cell visible in the
+    /// forward direction through the rectangle between the cell and the
+    /// viewer.  The cost of the update therefore grows with the fourth power
+    /// of the radius rather than the square: with everything visible it
+    /// costs roughly as much as R² plain updates, e.g., four hundred at the
+    /// radius 20 and three thousand at the radius 60.  Use it for small
+    /// radii or sparingly.
+    ///
+    /// The `opacity` of a cell is as in `kernel::threshold`, relative to the
+    /// origin.
+    ///
+    pub fn update_symmetric<F>(&mut self, threshold: f32, opacity: F)
+    where
+        F: Fn(i32, i32) -> f32,
+    {
+        self.update(kernel::threshold(threshold, &opacity));
+        let radius = self.radius as i32;
+        let mut scratch = Vec::new();
+        for y in -radius..radius + 1 {
+            for x in -radius..radius + 1 {
+                let ix = self.index(x, y);
+                if self.data[ix].is_visible && (x, y) != (0, 0) {
+                    let back = self.flux_field.as_ref().line_of_sight_in(
+                        (x, y),
+                        (0, 0),
+                        &opacity,
+                        &mut scratch,
+                    );
+                    self.data[ix].is_visible = back > threshold;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn symmetric_threshold_visibility() {
+        let radius = 8;
+        let opacity = |x: i32, y: i32| match (x * 5 + y * 3).rem_euclid(13) {
+            0 => 1.0,
+            1 => 0.6,
+            _ => 0.0,
+        };
+        let flux_field = Rc::new(FluxField::new(radius));
+        let view = |(ox, oy): (i32, i32), symmetric: bool| {
+            let mut fov = Fov::new(flux_field.clone(), radius, Visibility::default());
+            let opacity = |x, y| opacity(ox + x, oy + y);
+            if symmetric {
+                fov.update_symmetric(0.25, opacity);
+            } else {
+                fov.update(kernel::threshold(0.25, opacity));
+            }
+            fov
+        };
+        let viewers: Vec<(i32, i32)> = (0..6).flat_map(|y| (0..6).map(move |x| (x, y))).collect();
+        let plain: Vec<_> = viewers.iter().map(|&a| view(a, false)).collect();
+        let sym: Vec<_> = viewers.iter().map(|&a| view(a, true)).collect();
+        let mut asymmetric = 0;
+        for (i, &a) in viewers.iter().enumerate() {
+            let (plain_a, sym_a) = (&plain[i], &sym[i]);
+            for (j, &b) in viewers.iter().enumerate() {
+                let (plain_b, sym_b) = (&plain[j], &sym[j]);
+                let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+                let ab = sym_a.at(dx, dy).is_visible;
+                assert_eq!(ab, sym_b.at(-dx, -dy).is_visible, "{:?} {:?}", a, b);
+                let plain_ab = plain_a.at(dx, dy).is_visible;
+                let plain_ba = plain_b.at(-dx, -dy).is_visible;
+                assert_eq!(ab, plain_ab && plain_ba);
+                asymmetric += (plain_ab != plain_ba) as usize;
+            }
+        }
+        // The plain kernel is asymmetric on this map.
+        assert!(asymmetric > 0);
+    }
+}
@@ -0,0 +1,300 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// The flux field table is the look-up table of a flux field stored as is so
+// that it can be used in place without parsing.  The layout is:
+//
+//     offset  size  content
+//     0       8     magic "FLUXFOV\0"
+//     8       4     format version, currently 1
+//     12      4     radius of the flux field
+//     16      4     number of weights N, (radius - 1) * radius / 2
+//     20      4N    the weights
+//
+// All the numbers are little-endian; the version, the radius, and the count
+// are `u32` and the weights `f32`.  The weights start at an offset divisible
+// by four so they are properly aligned as long as the table itself is.
+
+use std::io::{self, Read, Write};
+
+use crate::{FluxField, FluxParams, Lut};
+
+pub(crate) const TABLE_MAGIC: &[u8; 8] = b"FLUXFOV\0";
+pub(crate) const TABLE_VERSION: u32 = 1;
+pub(crate) const TABLE_HEADER_LEN: usize = 20;
+
+impl FluxField {
+    /// Write the flux field as a table that can be used in place later.
+    ///
+    /// The table doesn't record the parameters the field was built with and
+    /// the field read back always has the default ones.  So only the flux
+    /// fields built with the default `FluxParams` can be stored as tables;
+    /// use `write_to` or `to_bytes` for the others.
+    ///
+    pub fn write_table<W>(&self, writer: W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        if self.params != FluxParams::default() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only flux fields with the default parameters can be stored as tables",
+            ));
+        }
+        let mut writer = writer;
+        writer.write_all(TABLE_MAGIC)?;
+        writer.write_all(&TABLE_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.radius as u32).to_le_bytes())?;
+        writer.write_all(&(self.flux_lut.len() as u32).to_le_bytes())?;
+        for w in self.flux_lut.iter() {
+            writer.write_all(&w.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Read a flux field table written with `write_table`.
+    ///
+    pub fn read_table<R>(reader: R) -> io::Result<FluxField>
+    where
+        R: Read,
+    {
+        let mut reader = reader;
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let (radius, _) = parse_table_header(&bytes)?;
+        let lut = read_weights(&bytes[TABLE_HEADER_LEN..]);
+        verified(FluxField::from_lut(radius, Lut::Owned(lut)))
+    }
+
+    /// Use a flux field table written with `write_table` in place, e.g., one
+    /// embedded in the binary with `include_bytes!`.
+    ///
+    /// The weights are used without copying them as long as the table is
+    /// aligned to four bytes and the target is little-endian; otherwise they
+    /// are copied.  `include_bytes!` doesn't guarantee any alignment but
+    /// wrapping the bytes in a `#[repr(align(4))]` struct does:
+    ///
+    /// ```
+    /// # use flux_fov::FluxField;
+    /// #[repr(align(4))]
+    /// struct Aligned<T: ?Sized>(T);
+    ///
+    /// static TABLE: &Aligned<[u8]> = &Aligned(*b"FLUXFOV\0\x01\0\0\0\x02\0\0\0\x01\0\0\0\0\0\0\x3f");
+    /// let flux_field = FluxField::from_static(&TABLE.0).unwrap();
+    /// assert_eq!(flux_field.radius(), 2);
+    /// ```
+    ///
+    pub fn from_static(bytes: &'static [u8]) -> io::Result<FluxField> {
+        let (radius, len) = parse_table_header(bytes)?;
+        let weights = &bytes[TABLE_HEADER_LEN..];
+        let lut = if cfg!(target_endian = "little") && weights.as_ptr().align_offset(4) == 0 {
+            // The alignment and the size have been checked above.
+            Lut::Borrowed(unsafe {
+                std::slice::from_raw_parts(weights.as_ptr() as *const f32, len)
+            })
+        } else {
+            Lut::Owned(read_weights(weights))
+        };
+        verified(FluxField::from_lut(radius, lut))
+    }
+
+    /// Map a flux field table written with `write_table` into memory.
+    ///
+    /// The weights are read from the file on demand rather than loaded up
+    /// front.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified while the flux field is alive.
+    ///
+    #[cfg(feature = "mmap")]
+    pub unsafe fn map_table<P>(path: P) -> io::Result<FluxField>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let file = std::fs::File::open(path)?;
+        let map = memmap2::Mmap::map(&file)?;
+        let (radius, len) = parse_table_header(&map)?;
+        let weights = &map[TABLE_HEADER_LEN..];
+        let lut = if cfg!(target_endian = "little") && weights.as_ptr().align_offset(4) == 0 {
+            Lut::Mapped {
+                map,
+                offset: TABLE_HEADER_LEN,
+                len,
+            }
+        } else {
+            Lut::Owned(read_weights(weights))
+        };
+        verified(FluxField::from_lut(radius, lut))
+    }
+}
+
+// Reject a flux field whose look-up table is corrupted.
+fn verified(flux_field: FluxField) -> io::Result<FluxField> {
+    flux_field
+        .validate()
+        .map(|_| flux_field)
+        .map_err(|report| io::Error::new(io::ErrorKind::InvalidData, report))
+}
+
+// Parse and validate the header of a table returning the radius and the
+// number of weights.
+pub(crate) fn parse_table_header(bytes: &[u8]) -> io::Result<(usize, usize)> {
+    let invalid = |msg| Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+    if bytes.len() < TABLE_HEADER_LEN || &bytes[..8] != TABLE_MAGIC {
+        return invalid("not a flux field table");
+    }
+    let word = |offset: usize| {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_le_bytes(buf) as usize
+    };
+    if word(8) != TABLE_VERSION as usize {
+        return invalid("unsupported flux field table version");
+    }
+    let radius = word(12);
+    let len = word(16);
+    // The sizes of a corrupt header may overflow on 32-bit targets.
+    let expected_len = radius
+        .checked_mul(radius.saturating_sub(1))
+        .map(|double_len| double_len / 2);
+    if expected_len != Some(len) {
+        return invalid("flux field table size doesn't match its radius");
+    }
+    if len
+        .checked_mul(4)
+        .and_then(|n| n.checked_add(TABLE_HEADER_LEN))
+        != Some(bytes.len())
+    {
+        return invalid("flux field table has wrong length");
+    }
+    Ok((radius, len))
+}
+
+// Read the little-endian weights into a vector.
+pub(crate) fn read_weights(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks(4)
+        .map(|chunk| {
+            let mut buf = [0; 4];
+            buf.copy_from_slice(chunk);
+            f32::from_le_bytes(buf)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{Connectivity, RayDistribution};
+
+    #[test]
+    fn table_header() {
+        let mut bytes = Vec::new();
+        FluxField::new(5).write_table(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), TABLE_HEADER_LEN + 4 * 10);
+        assert_eq!(parse_table_header(&bytes).unwrap(), (5, 10));
+        assert!(parse_table_header(&bytes[..bytes.len() - 1]).is_err());
+        let copy = FluxField::read_table(&bytes[..]).unwrap();
+        assert_eq!(&*copy.flux_lut, &*FluxField::new(5).flux_lut);
+        bytes[TABLE_HEADER_LEN..TABLE_HEADER_LEN + 4].copy_from_slice(&2.0f32.to_le_bytes());
+        assert!(FluxField::read_table(&bytes[..]).is_err());
+        bytes[16] = 9;
+        assert!(parse_table_header(&bytes).is_err());
+    }
+
+    #[test]
+    fn tables_need_default_params() {
+        let d = FluxParams::default();
+        for params in [
+            FluxParams {
+                three_tap: true,
+                ..d
+            },
+            FluxParams {
+                connectivity: Connectivity::Four,
+                ..d
+            },
+            FluxParams {
+                distribution: RayDistribution::Cosine,
+                ..d
+            },
+            FluxParams {
+                supersampling: 2,
+                ..d
+            },
+            FluxParams {
+                bresenham: true,
+                ..d
+            },
+        ] {
+            let error = FluxField::with_params(4, params)
+                .write_table(Vec::new())
+                .unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{:?}", params);
+        }
+    }
+
+    #[test]
+    fn huge_table_header() {
+        let header = |radius: u32, len: u32| {
+            let mut bytes = Vec::new();
+            FluxField::new(2).write_table(&mut bytes).unwrap();
+            bytes[12..16].copy_from_slice(&radius.to_le_bytes());
+            bytes[16..20].copy_from_slice(&len.to_le_bytes());
+            bytes
+        };
+        // The sizes overflow a 32-bit usize.
+        assert!(parse_table_header(&header(u32::MAX, 7)).is_err());
+        assert!(parse_table_header(&header(1 << 16, (1 << 31) - (1 << 15))).is_err());
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn map_table_matches_original() {
+        let flux_field = FluxField::new(7);
+        let path = std::env::temp_dir().join(format!("flux-fov-{}.table", std::process::id()));
+        flux_field
+            .write_table(std::fs::File::create(&path).unwrap())
+            .unwrap();
+        let mapped = unsafe { FluxField::map_table(&path).unwrap() };
+        assert_eq!(mapped.radius(), 7);
+        assert_eq!(&*mapped.flux_lut, &*flux_field.flux_lut);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn static_table_is_borrowed() {
+        let mut table = Vec::new();
+        FluxField::new(5).write_table(&mut table).unwrap();
+        // Leak a buffer of words to get an aligned static table.
+        let words: &'static mut [u32] = Box::leak(vec![0; table.len() / 4].into_boxed_slice());
+        let bytes: &'static mut [u8] =
+            unsafe { std::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, table.len()) };
+        bytes.copy_from_slice(&table);
+        let bytes: &'static [u8] = bytes;
+        let flux_field = FluxField::from_static(bytes).unwrap();
+        assert_eq!(&*flux_field.flux_lut, &*FluxField::new(5).flux_lut);
+        if cfg!(target_endian = "little") {
+            assert_eq!(
+                flux_field.flux_lut.as_ptr() as *const u8,
+                bytes[TABLE_HEADER_LEN..].as_ptr()
+            );
+        }
+        assert!(FluxField::from_static(&bytes[..TABLE_HEADER_LEN]).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Helpers for snapshot testing update functions.
+//!
+//! The renderers turn a field of vision into a "flag", a single string
+//! showing the value of every cell row by row, that is easy to compare
+//! against an expected string in a test.  Requires the `testing` feature.
+
+use std::fmt::Write;
+
+#[cfg(test)]
+use crate::{Connectivity, FluxParams};
+use crate::{FluxField, Fov};
+
+/// The layout of a rendered flag.
+///
+/// Each row is rendered as `row_prefix`, the cells separated by
+/// `cell_separator`, and `row_suffix`, and the rows are separated by
+/// `row_separator`.  The cells are right-aligned to at least `width`
+/// characters.  The floating point values are rendered with `precision`
+/// decimals.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlagFormat {
+    pub row_prefix: &'static str,
+    pub row_suffix: &'static str,
+    pub row_separator: &'static str,
+    pub cell_separator: &'static str,
+    pub width: usize,
+    pub precision: usize,
+}
+
+impl FlagFormat {
+    /// Every row in brackets, e.g., `"[  1  2  ] [  3  4  ] "`.
+    pub const BRACKETED: FlagFormat = FlagFormat {
+        row_prefix: "[  ",
+        row_suffix: "  ] ",
+        row_separator: "",
+        cell_separator: "  ",
+        width: 0,
+        precision: 3,
+    };
+
+    /// The rows separated by semicolons, e.g., `"1  2; 3  4"`.
+    pub const INLINE: FlagFormat = FlagFormat {
+        row_prefix: "",
+        row_suffix: "",
+        row_separator: "; ",
+        cell_separator: "  ",
+        width: 0,
+        precision: 3,
+    };
+}
+
+impl Default for FlagFormat {
+    fn default() -> Self {
+        FlagFormat::BRACKETED
+    }
+}
+
+/// Render the field of vision with the given cell renderer.
+///
+pub fn render_flag<T, X, F>(fov: &Fov<T, X>, format: &FlagFormat, render_cell: F) -> String
+where
+    X: AsRef<FluxField>,
+    F: Fn(&T) -> String,
+{
+    let width = fov.width as usize;
+    let mut flag = String::new();
+    for (y, row) in fov.as_slice().chunks(width).enumerate() {
+        if y > 0 {
+            flag.push_str(format.row_separator);
+        }
+        flag.push_str(format.row_prefix);
+        for (x, cell) in row.iter().enumerate() {
+            if x > 0 {
+                flag.push_str(format.cell_separator);
+            }
+            write!(flag, "{:>1$}", render_cell(cell), format.width).unwrap();
+        }
+        flag.push_str(format.row_suffix);
+    }
+    flag
+}
+
+/// Render the coordinates each cell sees itself at.
+///
+/// Checks that the update function is called with the right coordinates for
+/// every cell.
+///
+pub fn coordinate_flag(radius: usize, format: &FlagFormat) -> String {
+    let mut fov = Fov::new(Box::new(FluxField::new(radius)), radius, (-1, -1));
+    fov.update(|x, y, _| (x, y));
+    render_flag(&fov, format, |p| format!("{:+}{:+}", p.0, p.1))
+}
+
+/// Render the number of distinct flux paths from the origin to each cell.
+///
+pub fn flux_connection_flag(radius: usize, format: &FlagFormat) -> String {
+    let mut fov = Fov::new(Box::new(FluxField::new(radius)), radius, -1);
+    fov.update(|x, y, influxes| {
+        if x == 0 && y == 0 {
+            1_i32
+        } else {
+            influxes.iter().map(|f| *f.value).sum()
+        }
+    });
+    render_flag(&fov, format, |n| n.to_string())
+}
+
+/// Render the sum of the influx weights into each cell.
+///
+pub fn weight_flag(radius: usize, format: &FlagFormat) -> String {
+    let mut fov = Fov::new(Box::new(FluxField::new(radius)), radius, -1.0);
+    fov.update(|_, _, influxes| influxes.iter().fold(0.0, |acc, f| acc + f.weight));
+    render_flag(&fov, format, |w| format!("{:.1$}", w, format.precision))
+}
+
+// The parameters of the three-tap fields.
+#[cfg(test)]
+pub(crate) fn three_tap() -> FluxParams {
+    FluxParams {
+        three_tap: true,
+        ..FluxParams::default()
+    }
+}
+
+// The parameters of the four-connected fields.
+#[cfg(test)]
+pub(crate) fn four_connected() -> FluxParams {
+    FluxParams {
+        connectivity: Connectivity::Four,
+        ..FluxParams::default()
+    }
+}
+
+// The parameters of every kind of walk for the tests to run over: the
+// default two-tap, the three-tap, and the four-connected fields.
+#[cfg(test)]
+pub(crate) fn all_params() -> [FluxParams; 3] {
+    [FluxParams::default(), three_tap(), four_connected()]
+}
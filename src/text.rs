@@ -0,0 +1,213 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// The text snapshot format is meant for golden files committed to version
+// control and is kept stable across releases.  It is:
+//
+//     flux-fov snapshot 1
+//     radius R
+//     <row -R>
+//     ...
+//     <row R>
+//
+// Each row lists the values of the cells from x = -R to x = R rendered with
+// `Display` and separated by spaces.  The values are right-aligned to the
+// width of the widest value so that the columns line up in diffs.  As the
+// values are separated by whitespace they may not contain any themselves.
+// Lines end in a single '\n'.
+
+use std::fmt::Display;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use crate::{FluxField, Fov, FovSnapshot, MAX_RADIUS};
+
+const TEXT_HEADER: &str = "flux-fov snapshot 1";
+
+impl<T, X> Fov<T, X>
+where
+    T: Display,
+    X: AsRef<FluxField>,
+{
+    /// Write the values in the stable text snapshot format.
+    ///
+    /// The snapshot can be read back with `FovSnapshot::read_text`.  Fails
+    /// if a value renders empty or contains whitespace.
+    ///
+    pub fn write_text<W>(&self, writer: W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        write_text(writer, self.radius, &self.data)
+    }
+}
+
+impl<T> FovSnapshot<T>
+where
+    T: Display,
+{
+    /// Write the values in the stable text snapshot format.
+    ///
+    pub fn write_text<W>(&self, writer: W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        write_text(writer, self.radius, &self.data)
+    }
+}
+
+impl<T> FovSnapshot<T>
+where
+    T: FromStr,
+{
+    /// Read a snapshot written in the stable text snapshot format.
+    ///
+    pub fn read_text<R>(reader: R) -> io::Result<FovSnapshot<T>>
+    where
+        R: BufRead,
+    {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut lines = reader.lines();
+        let mut next_line = |what: &str| match lines.next() {
+            Some(line) => line,
+            None => Err(invalid(format!("missing {}", what))),
+        };
+        if next_line("header")? != TEXT_HEADER {
+            return Err(invalid("not a flux-fov text snapshot".to_string()));
+        }
+        let radius_line = next_line("radius")?;
+        let radius: usize = radius_line
+            .strip_prefix("radius ")
+            .and_then(|r| r.parse().ok())
+            .ok_or_else(|| invalid(format!("invalid radius line {:?}", radius_line)))?;
+        if radius > MAX_RADIUS {
+            return Err(invalid(format!("radius {} out of range", radius)));
+        }
+        let radius = radius as isize;
+        let width = 2 * radius + 1;
+        // The rows are allocated as they are read rather than trusting the
+        // radius up front.
+        let mut data = Vec::with_capacity(width as usize);
+        for y in -radius..radius + 1 {
+            let line = next_line("row")?;
+            let row_start = data.len();
+            for cell in line.split_whitespace() {
+                let value = cell
+                    .parse()
+                    .map_err(|_| invalid(format!("invalid value {:?} on row {}", cell, y)))?;
+                data.push(value);
+            }
+            if data.len() - row_start != width as usize {
+                return Err(invalid(format!(
+                    "row {} has {} values, expected {}",
+                    y,
+                    data.len() - row_start,
+                    width
+                )));
+            }
+        }
+        // Blank lines may follow the last row but nothing else.
+        for line in lines {
+            if !line?.trim().is_empty() {
+                return Err(invalid("trailing data after the last row".to_string()));
+            }
+        }
+        Ok(FovSnapshot {
+            radius,
+            width,
+            ix_origin: radius * (width + 1),
+            data,
+        })
+    }
+}
+
+fn write_text<W, T>(writer: W, radius: isize, data: &[T]) -> io::Result<()>
+where
+    W: Write,
+    T: Display,
+{
+    let cells: Vec<String> = data.iter().map(|value| value.to_string()).collect();
+    if cells
+        .iter()
+        .any(|cell| cell.is_empty() || cell.contains(char::is_whitespace))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "values must render non-empty and without whitespace",
+        ));
+    }
+    let cell_width = cells
+        .iter()
+        .map(|cell| cell.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut writer = writer;
+    writeln!(writer, "{}", TEXT_HEADER)?;
+    writeln!(writer, "radius {}", radius)?;
+    for row in cells.chunks(2 * radius as usize + 1) {
+        for (x, cell) in row.iter().enumerate() {
+            if x > 0 {
+                write!(writer, " ")?;
+            }
+            write!(writer, "{:>1$}", cell, cell_width)?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn text_round_trip() {
+        let mut fov = Fov::new(Box::new(FluxField::new(2)), 2, 0);
+        fov.update(|x, y, _| x * 10 + y);
+        let mut text = Vec::new();
+        fov.write_text(&mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text.clone()).unwrap(),
+            "flux-fov snapshot 1\n\
+             radius 2\n\
+             -22 -12  -2   8  18\n\
+             -21 -11  -1   9  19\n\
+             -20 -10   0  10  20\n\
+             -19  -9   1  11  21\n\
+             -18  -8   2  12  22\n"
+        );
+        let snapshot = FovSnapshot::<i32>::read_text(&text[..]).unwrap();
+        assert_eq!(snapshot, fov.snapshot());
+    }
+
+    #[test]
+    fn invalid_text() {
+        let read = |text: &str| FovSnapshot::<u8>::read_text(text.as_bytes());
+        assert!(read("flux-fov snapshot 1\nradius 0\n1\n").is_ok());
+        assert!(read("flux-fov snapshot 2\nradius 0\n1\n").is_err());
+        assert!(read("flux-fov snapshot 1\nradius x\n1\n").is_err());
+        assert!(read("flux-fov snapshot 1\nradius 1\n1 2 3\n4 5 6\n").is_err());
+        assert!(read("flux-fov snapshot 1\nradius 0\n1 2\n").is_err());
+        assert!(read("flux-fov snapshot 1\nradius 0\n-1\n").is_err());
+        assert!(read("flux-fov snapshot 1\nradius 0\n1\n2\n").is_err());
+        assert!(read("flux-fov snapshot 1\nradius 0\n1\n\n \n").is_ok());
+        assert!(read("flux-fov snapshot 1\nradius 0\n1\n\n2\n").is_err());
+        assert!(read("flux-fov snapshot 1\nradius 99999999999\n1\n").is_err());
+        let fov = Fov::new(Box::new(FluxField::new(0)), 0, "a b");
+        assert!(fov.write_text(Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::{FluxField, Fov};
+
+impl<X> Fov<f32, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Render the field into an RGBA texture with `pixels_per_tile` pixels
+    /// along the side of each cell, e.g., for a light overlay.
+    ///
+    /// The texture is `(2 * radius + 1) * pixels_per_tile` pixels square,
+    /// four bytes per pixel in row-major order, with the top-left pixel on
+    /// the cell (-radius, -radius).  The value at each pixel is interpolated
+    /// bilinearly between the centers of the surrounding cells, clamped to
+    /// the border of the field, and converted to the pixel with `color`,
+    /// e.g., `|v| [0, 0, 0, ((1.0 - v.clamp(0.0, 1.0)) * 255.0) as u8]` for a
+    /// darkness overlay.
+    ///
+    pub fn to_rgba<F>(&self, pixels_per_tile: usize, color: F) -> Vec<u8>
+    where
+        F: Fn(f32) -> [u8; 4],
+    {
+        assert!(pixels_per_tile > 0, "zero pixels per tile");
+        let width = self.width as usize;
+        let size = width * pixels_per_tile;
+        let scale = pixels_per_tile as f32;
+        // The cell to the top-left of the pixel and the weight of the next
+        // cell along one axis.
+        let taps: Vec<(usize, usize, f32)> = (0..size)
+            .map(|p| {
+                let u = ((p as f32 + 0.5) / scale - 0.5).clamp(0.0, (width - 1) as f32);
+                let i = (u as usize).min(width - 1);
+                let j = (i + 1).min(width - 1);
+                (i, j, u - i as f32)
+            })
+            .collect();
+        let data = &self.data;
+        let mut pixels = Vec::with_capacity(size * size * 4);
+        for &(y0, y1, fy) in &taps {
+            for &(x0, x1, fx) in &taps {
+                let top = data[y0 * width + x0] * (1.0 - fx) + data[y0 * width + x1] * fx;
+                let bottom = data[y1 * width + x0] * (1.0 - fx) + data[y1 * width + x1] * fx;
+                pixels.extend_from_slice(&color(top * (1.0 - fy) + bottom * fy));
+            }
+        }
+        pixels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn rgba_texture() {
+        let mut fov = Fov::new(Box::new(FluxField::new(1)), 1, 0.0_f32);
+        fov.update(|x, _, _| if x == 1 { 1.0 } else { 0.0 });
+        let gray = |v: f32| {
+            let v = (v * 255.0).round() as u8;
+            [v, v, v, 255]
+        };
+        let pixels = fov.to_rgba(1, gray);
+        assert_eq!(pixels.len(), 9 * 4);
+        assert_eq!(
+            &pixels[..12],
+            &[0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255, 255]
+        );
+        let pixels = fov.to_rgba(4, gray);
+        assert_eq!(pixels.len(), 12 * 12 * 4);
+        let row: Vec<u8> = pixels[..12 * 4].chunks(4).map(|p| p[0]).collect();
+        // Flat within the first cell and the border, ramping up across the
+        // centers of the middle and the last cells.
+        assert_eq!(row, vec![0, 0, 0, 0, 0, 0, 32, 96, 159, 223, 255, 255]);
+        assert_eq!(&pixels[..12 * 4], &pixels[11 * 12 * 4..]);
+    }
+}
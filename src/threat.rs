@@ -0,0 +1,194 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::compose::for_each_tile;
+use crate::{FluxField, Fov};
+
+/// A map-sized grid of danger accumulated from the fields of vision of
+/// several observers weighted by their threat levels.
+///
+/// The contribution of each observer is remembered so that moving or
+/// removing one of them updates the grid without touching the others.
+///
+pub struct ThreatMap {
+    width: usize,
+    height: usize,
+    danger: Vec<f32>,
+    observers: Vec<Option<Vec<(usize, f32)>>>,
+}
+
+/// The handle of an observer added to a `ThreatMap`.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObserverId(pub(crate) usize);
+
+impl ThreatMap {
+    /// Constructs a `width` by `height` map with no danger.
+    ///
+    pub fn new(width: usize, height: usize) -> Self {
+        ThreatMap {
+            width,
+            height,
+            danger: vec![0.0; width * height],
+            observers: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The danger at the given tile.
+    pub fn at(&self, x: usize, y: usize) -> f32 {
+        assert!(x < self.width && y < self.height, "tile out of range");
+        self.danger[y * self.width + x]
+    }
+
+    /// Expose the danger of the tiles as a slice in row-major order.
+    pub fn as_slice(&self) -> &[f32] {
+        self.danger.as_slice()
+    }
+
+    /// Add an observer at the tile (`x`, `y`) seeing the field `sight`.
+    ///
+    /// The danger of each tile is increased by `threat` times the
+    /// visibility of the tile, between 0.0 and 1.0, as given by
+    /// `visibility`.
+    ///
+    pub fn add_observer<T, X, V>(
+        &mut self,
+        x: i32,
+        y: i32,
+        threat: f32,
+        sight: &Fov<T, X>,
+        visibility: V,
+    ) -> ObserverId
+    where
+        X: AsRef<FluxField>,
+        V: Fn(&T) -> f32,
+    {
+        let contribution = self.contribute(x, y, threat, sight, visibility);
+        match self.observers.iter().position(Option::is_none) {
+            Some(id) => {
+                self.observers[id] = Some(contribution);
+                ObserverId(id)
+            }
+            None => {
+                self.observers.push(Some(contribution));
+                ObserverId(self.observers.len() - 1)
+            }
+        }
+    }
+
+    /// Replace the contribution of the observer, e.g., after it has moved
+    /// or turned.
+    ///
+    pub fn move_observer<T, X, V>(
+        &mut self,
+        id: ObserverId,
+        x: i32,
+        y: i32,
+        threat: f32,
+        sight: &Fov<T, X>,
+        visibility: V,
+    ) where
+        X: AsRef<FluxField>,
+        V: Fn(&T) -> f32,
+    {
+        self.withdraw(id);
+        let contribution = self.contribute(x, y, threat, sight, visibility);
+        self.observers[id.0] = Some(contribution);
+    }
+
+    /// Remove the observer and its contribution from the map.
+    ///
+    pub fn remove_observer(&mut self, id: ObserverId) {
+        self.withdraw(id);
+        self.observers[id.0] = None;
+    }
+
+    fn contribute<T, X, V>(
+        &mut self,
+        x: i32,
+        y: i32,
+        threat: f32,
+        sight: &Fov<T, X>,
+        visibility: V,
+    ) -> Vec<(usize, f32)>
+    where
+        X: AsRef<FluxField>,
+        V: Fn(&T) -> f32,
+    {
+        let mut contribution = Vec::new();
+        let danger = &mut self.danger;
+        for_each_tile(self.width, self.height, x, y, sight, |ix, value| {
+            let amount = threat * visibility(value);
+            if amount != 0.0 {
+                danger[ix] += amount;
+                contribution.push((ix, amount));
+            }
+        });
+        contribution
+    }
+
+    fn withdraw(&mut self, id: ObserverId) {
+        let contribution = self.observers[id.0].as_ref().expect("observer removed");
+        for &(ix, amount) in contribution {
+            // Guard against the rounding errors making the danger negative.
+            self.danger[ix] = (self.danger[ix] - amount).max(0.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn observers_accumulate_and_move() {
+        let flux_field = Rc::new(FluxField::new(2));
+        let mut sight = Fov::new(flux_field, 2, 1.0);
+        sight.update(|x, _, _| if x >= 0 { 1.0 } else { 0.5 });
+        let mut map = ThreatMap::new(8, 5);
+        let guard = map.add_observer(2, 2, 2.0, &sight, |&v| v);
+        let dog = map.add_observer(3, 2, 1.0, &sight, |&v| v);
+        assert_eq!(map.at(0, 2), 1.0);
+        assert_eq!(map.at(2, 2), 2.5);
+        assert_eq!(map.at(5, 0), 1.0);
+        map.move_observer(guard, 5, 2, 2.0, &sight, |&v| v);
+        assert_eq!(map.at(0, 2), 0.0);
+        assert_eq!(map.at(2, 2), 0.5);
+        assert_eq!(map.at(5, 0), 3.0);
+        map.remove_observer(dog);
+        assert_eq!(map.at(2, 2), 0.0);
+        assert_eq!(map.at(7, 4), 2.0);
+        assert_eq!(map.add_observer(0, 0, 1.0, &sight, |&v| v), dog);
+    }
+
+    #[test]
+    #[should_panic(expected = "tile out of range")]
+    fn at_out_of_range() {
+        // Within the map but past the right edge of the first row.
+        let map = ThreatMap::new(4, 3);
+        map.at(4, 0);
+    }
+}
@@ -0,0 +1,196 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+// A minimal loader for the TMX maps of the Tiled map editor.  Only the tile
+// layers with CSV or XML encoded data and the tilesets embedded in the map
+// are supported; base64 encoded or compressed layers and external tilesets
+// are not.
+
+use std::io;
+
+use roxmltree::{Document, Node};
+
+use crate::OpacityGrid;
+
+/// How the opacity of the tiles of a TMX layer is decided.
+///
+#[derive(Clone, Copy, Debug)]
+pub enum TileOpacity<'a> {
+    /// The tiles with the given global tile IDs are opaque and the rest
+    /// transparent.
+    Ids(&'a [u32]),
+    /// The opacity is given by the named property of the tile in its
+    /// tileset: `true` stands for 1.0, `false` for 0.0, and numbers for
+    /// themselves.  The tiles without the property are transparent.
+    Property(&'a str),
+}
+
+// The bits of a global tile ID telling how the tile is flipped.
+const FLIP_FLAGS: u32 = 0xf000_0000;
+
+impl OpacityGrid {
+    /// Build the opacity grid from the tile layer `layer` of the TMX map
+    /// `tmx`.
+    ///
+    /// Empty tiles are transparent.
+    ///
+    pub fn from_tmx(tmx: &str, layer: &str, tiles: TileOpacity) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let doc = Document::parse(tmx).map_err(|e| invalid(e.to_string()))?;
+        let map = doc.root_element();
+        let layer_node = map
+            .children()
+            .find(|n| n.has_tag_name("layer") && n.attribute("name") == Some(layer))
+            .ok_or_else(|| invalid(format!("no layer {:?}", layer)))?;
+        let width = number(layer_node, "width")? as usize;
+        let height = number(layer_node, "height")? as usize;
+        let data = layer_node
+            .children()
+            .find(|n| n.has_tag_name("data"))
+            .ok_or_else(|| invalid("layer without data".to_string()))?;
+        let gids = match data.attribute("encoding") {
+            Some("csv") => data
+                .text()
+                .unwrap_or("")
+                .split(',')
+                .map(|s| s.trim().parse::<u32>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| invalid(e.to_string()))?,
+            None => data
+                .children()
+                .filter(|n| n.has_tag_name("tile"))
+                .map(|n| n.attribute("gid").map_or(Ok(0), parse_gid))
+                .collect::<io::Result<Vec<_>>>()?,
+            Some(encoding) => return Err(invalid(format!("unsupported encoding {:?}", encoding))),
+        };
+        if gids.len() != width * height {
+            return Err(invalid("layer size mismatch".to_string()));
+        }
+        let properties = match tiles {
+            TileOpacity::Property(name) => tile_properties(map, name)?,
+            TileOpacity::Ids(_) => Vec::new(),
+        };
+        let mut grid = OpacityGrid::new(width, height);
+        for (ix, &gid) in gids.iter().enumerate() {
+            let gid = gid & !FLIP_FLAGS;
+            let opacity = match tiles {
+                _ if gid == 0 => 0.0,
+                TileOpacity::Ids(ids) => {
+                    if ids.contains(&gid) {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                }
+                TileOpacity::Property(_) => properties
+                    .iter()
+                    .find(|&&(g, _)| g == gid)
+                    .map_or(0.0, |&(_, opacity)| opacity),
+            };
+            grid.set(ix % width, ix / width, opacity);
+        }
+        Ok(grid)
+    }
+}
+
+fn number(node: Node, name: &str) -> io::Result<u32> {
+    node.attribute(name)
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("missing or invalid {:?}", name),
+            )
+        })
+}
+
+fn parse_gid(s: &str) -> io::Result<u32> {
+    s.parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid gid"))
+}
+
+// The opacities given by the property `name` of the tiles of the embedded
+// tilesets keyed by the global tile ID.
+fn tile_properties(map: Node, name: &str) -> io::Result<Vec<(u32, f32)>> {
+    let mut result = Vec::new();
+    for tileset in map.children().filter(|n| n.has_tag_name("tileset")) {
+        let first_gid = number(tileset, "firstgid")?;
+        for tile in tileset.children().filter(|n| n.has_tag_name("tile")) {
+            let id = number(tile, "id")?;
+            let value = tile
+                .descendants()
+                .find(|n| n.has_tag_name("property") && n.attribute("name") == Some(name))
+                .and_then(|n| n.attribute("value"));
+            let opacity = match value {
+                None => continue,
+                Some("true") => 1.0,
+                Some("false") => 0.0,
+                Some(s) => s.parse().map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid opacity {:?}", s),
+                    )
+                })?,
+            };
+            result.push((first_gid + id, opacity));
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const MAP: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="4" height="2" tilewidth="16" tileheight="16">
+ <tileset firstgid="1" name="walls" tilewidth="16" tileheight="16" tilecount="4" columns="4">
+  <tile id="1">
+   <properties>
+    <property name="opaque" type="bool" value="true"/>
+   </properties>
+  </tile>
+  <tile id="2">
+   <properties>
+    <property name="opaque" type="float" value="0.5"/>
+   </properties>
+  </tile>
+ </tileset>
+ <layer id="1" name="ground" width="4" height="2">
+  <data encoding="csv">
+1,2,2,1,
+3,0,2147483650,1
+</data>
+ </layer>
+ <layer id="2" name="objects" width="4" height="2">
+  <data>
+   <tile gid="2"/><tile/><tile/><tile/>
+   <tile/><tile/><tile/><tile gid="4"/>
+  </data>
+ </layer>
+</map>"#;
+
+    #[test]
+    fn load_by_property_and_ids() {
+        let grid = OpacityGrid::from_tmx(MAP, "ground", TileOpacity::Property("opaque")).unwrap();
+        assert_eq!(grid.width(), 4);
+        assert_eq!(grid.as_slice(), &[0.0, 1.0, 1.0, 0.0, 0.5, 0.0, 1.0, 0.0]);
+        let grid = OpacityGrid::from_tmx(MAP, "objects", TileOpacity::Ids(&[4])).unwrap();
+        assert_eq!(grid.as_slice(), &[0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+        assert!(OpacityGrid::from_tmx(MAP, "sky", TileOpacity::Ids(&[])).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::{lut_len, FluxField};
+
+/// A problem found in the look-up table of a flux field.
+///
+/// The cells are identified by their coordinates in the first octant, i.e.,
+/// `x > y > 0`; the other octants share the same weights.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValidationIssue {
+    /// The look-up table has the wrong number of weights for the radius.
+    LutLength { expected: usize, actual: usize },
+    /// The table of the lateral weights has the wrong number of weights.
+    LateralLength { expected: usize, actual: usize },
+    /// The diagonal weight of the cell is not within [0.0, 1.0].
+    Diagonal { x: i32, y: i32, weight: f32 },
+    /// The lateral weight of the cell is not within [0.0, 1.0].
+    Lateral { x: i32, y: i32, weight: f32 },
+    /// The diagonal and the lateral weights of the cell sum to more than
+    /// 1.0 leaving a negative weight for the straight influx.
+    Straight { x: i32, y: i32, weight: f32 },
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationIssue::LutLength { expected, actual } => {
+                write!(
+                    f,
+                    "look-up table has {} weights, expected {}",
+                    actual, expected
+                )
+            }
+            ValidationIssue::LateralLength { expected, actual } => {
+                write!(
+                    f,
+                    "lateral table has {} weights, expected {}",
+                    actual, expected
+                )
+            }
+            ValidationIssue::Diagonal { x, y, weight } => {
+                write!(
+                    f,
+                    "diagonal weight {} at ({}, {}) outside [0, 1]",
+                    weight, x, y
+                )
+            }
+            ValidationIssue::Lateral { x, y, weight } => {
+                write!(
+                    f,
+                    "lateral weight {} at ({}, {}) outside [0, 1]",
+                    weight, x, y
+                )
+            }
+            ValidationIssue::Straight { x, y, weight } => {
+                write!(
+                    f,
+                    "straight weight {} at ({}, {}) is negative",
+                    weight, x, y
+                )
+            }
+        }
+    }
+}
+
+/// The problems found by `FluxField::validate`.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct ValidationReport {
+    issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// The problems in the order they were found.
+    pub fn issues(&self) -> &[ValidationIssue] {
+        &self.issues
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid flux field: {}", self.issues[0])?;
+        if self.issues.len() > 1 {
+            write!(f, " (and {} more issues)", self.issues.len() - 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ValidationReport {}
+
+impl FluxField {
+    /// Check the look-up table of the flux field for corruption.
+    ///
+    /// Checks that the tables have the right length for the radius, that
+    /// all the weights are within [0.0, 1.0], and that the influx weights of
+    /// every cell sum to 1.0.  Useful after loading a flux field from a
+    /// source that cannot be trusted.  Returns all the problems found.
+    ///
+    pub fn validate(&self) -> Result<(), ValidationReport> {
+        let mut issues = Vec::new();
+        let expected = lut_len(self.radius);
+        if self.flux_lut.len() != expected {
+            issues.push(ValidationIssue::LutLength {
+                expected,
+                actual: self.flux_lut.len(),
+            });
+        }
+        if !self.lateral_lut.is_empty() && self.lateral_lut.len() != self.flux_lut.len() {
+            issues.push(ValidationIssue::LateralLength {
+                expected: self.flux_lut.len(),
+                actual: self.lateral_lut.len(),
+            });
+        }
+        let (mut x, mut y) = (2, 1);
+        for (ix, &w) in self.flux_lut.iter().enumerate() {
+            let l = self.lateral(ix);
+            let in_range = |weight: f32| (0.0..=1.0).contains(&weight);
+            if !in_range(w) {
+                issues.push(ValidationIssue::Diagonal { x, y, weight: w });
+            }
+            if !in_range(l) {
+                issues.push(ValidationIssue::Lateral { x, y, weight: l });
+            }
+            if in_range(w) && in_range(l) && w + l > 1.0 {
+                issues.push(ValidationIssue::Straight {
+                    x,
+                    y,
+                    weight: 1.0 - w - l,
+                });
+            }
+            y += 1;
+            if y == x {
+                x += 1;
+                y = 1;
+            }
+        }
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationReport { issues })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::three_tap;
+
+    #[test]
+    fn valid_fields() {
+        assert_eq!(FluxField::new(0).validate(), Ok(()));
+        assert_eq!(FluxField::new(9).validate(), Ok(()));
+        let params = three_tap();
+        assert_eq!(FluxField::with_params(9, params).validate(), Ok(()));
+    }
+
+    #[test]
+    fn corrupted_fields() {
+        let mut flux_field = FluxField::with_params(3, three_tap());
+        flux_field.lateral_lut[0] = f32::NAN;
+        flux_field.lateral_lut[2] = 1.0;
+        flux_field.lateral_lut.push(0.0);
+        let report = flux_field.validate().unwrap_err();
+        assert_eq!(report.issues().len(), 3);
+        assert_eq!(
+            report.issues()[0],
+            ValidationIssue::LateralLength {
+                expected: 3,
+                actual: 4
+            }
+        );
+        assert!(matches!(
+            report.issues()[1],
+            ValidationIssue::Lateral { x: 2, y: 1, .. }
+        ));
+        assert!(matches!(
+            report.issues()[2],
+            ValidationIssue::Straight { x: 3, y: 2, .. }
+        ));
+        assert!(report.to_string().contains("and 2 more issues"));
+    }
+}
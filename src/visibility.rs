@@ -0,0 +1,148 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::kernel::{self, Visibility};
+use crate::{FluxField, Fov};
+
+/// The field of vision of the common roguelike case: the rays pass through
+/// the cells in proportion to their transparency and a cell is visible if
+/// enough of them reach it.
+///
+/// This is `kernel::threshold` over a `Fov<Visibility>` without having to
+/// write the update function by hand:
+///
+/// ```
+/// # use flux_fov::{FluxField, VisibilityMap};
+/// let mut sight = VisibilityMap::new(Box::new(FluxField::new(8)), 8, 0.5);
+/// sight.update(|x, y| if (x, y) == (2, 0) { 1.0 } else { 0.0 });
+/// assert!(sight.is_visible(2, 0));
+/// assert!(!sight.is_visible(5, 0));
+/// assert!(!sight.is_visible(9, 0));
+/// assert!(sight.visible_cells().any(|cell| cell == (5, 3)));
+/// ```
+///
+pub struct VisibilityMap<X>
+where
+    X: AsRef<FluxField>,
+{
+    fov: Fov<Visibility, X>,
+    threshold: f32,
+}
+
+impl<X> VisibilityMap<X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Constructs a map of the given `radius` where a cell is visible if
+    /// the rays reaching it exceed `threshold`, e.g., 0.5.
+    ///
+    pub fn new(flux_field: X, radius: usize, threshold: f32) -> Self {
+        VisibilityMap {
+            fov: Fov::new(flux_field, radius, Visibility::default()),
+            threshold,
+        }
+    }
+
+    /// The radius of the map.
+    pub fn radius(&self) -> usize {
+        self.fov.radius()
+    }
+
+    /// The threshold of the rays a cell needs to be visible.
+    pub fn threshold(&self) -> f32 {
+        self.threshold
+    }
+
+    /// Change the threshold for the following updates.
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    /// Recompute the visibility with the given `opacity` of the cells
+    /// relative to the viewer, from 0.0 (transparent) to 1.0 (opaque).
+    ///
+    pub fn update<F>(&mut self, opacity: F)
+    where
+        F: Fn(i32, i32) -> f32,
+    {
+        self.fov.update(kernel::threshold(self.threshold, opacity));
+    }
+
+    /// Whether the cell (`x`, `y`) relative to the viewer is visible; the
+    /// cells beyond the radius are not.
+    ///
+    pub fn is_visible(&self, x: i32, y: i32) -> bool {
+        let r = self.fov.radius() as i32;
+        x.abs() <= r && y.abs() <= r && self.fov.at(x, y).is_visible
+    }
+
+    /// Iterate over the visible cells relative to the viewer in row-major
+    /// order.
+    ///
+    pub fn visible_cells(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        let r = self.fov.radius() as i32;
+        let width = 2 * r + 1;
+        self.fov
+            .as_slice()
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.is_visible)
+            .map(move |(ix, _)| (ix as i32 % width - r, ix as i32 / width - r))
+    }
+
+    /// The underlying field of vision.
+    pub fn fov(&self) -> &Fov<Visibility, X> {
+        &self.fov
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn matches_the_hand_rolled_update() {
+        let opacity = |x: i32, y: i32| if (x * 7 + y * 3) % 11 == 0 { 1.0 } else { 0.1 };
+        let mut sight = VisibilityMap::new(Box::new(FluxField::new(10)), 10, 0.75);
+        sight.update(opacity);
+        let mut fov = Fov::new(Box::new(FluxField::new(10)), 10, Visibility::default());
+        fov.update(|x, y, influxes| {
+            if x == 0 && y == 0 {
+                Visibility::ORIGIN
+            } else {
+                let ray_input: f32 = influxes.iter().map(|f| f.weight * f.value.ray_output).sum();
+                Visibility {
+                    is_visible: ray_input > 0.75,
+                    ray_output: ray_input * (1.0 - opacity(x, y)),
+                }
+            }
+        });
+        let mut expected = Vec::new();
+        for y in -10..11 {
+            for x in -10..11 {
+                assert_eq!(sight.is_visible(x, y), fov.at(x, y).is_visible);
+                if fov.at(x, y).is_visible {
+                    expected.push((x, y));
+                }
+            }
+        }
+        assert_eq!(sight.visible_cells().collect::<Vec<_>>(), expected);
+        sight.set_threshold(2.0);
+        sight.update(opacity);
+        assert_eq!(sight.visible_cells().collect::<Vec<_>>(), [(0, 0)]);
+    }
+}
@@ -0,0 +1,149 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::cell::{Cell, RefCell};
+
+use crate::Rect;
+
+/// Opacity layered on top of the per-cell opacity of the map, such as rain,
+/// darkness, or magical fog.
+///
+/// The weather consists of a global opacity affecting every cell and any
+/// number of regional opacities affecting the cells within a rectangle of
+/// the map.  The layers combine like stacked translucent sheets: a cell
+/// passes on the fraction of the rays that every layer lets through.
+///
+/// The weather can be changed through a shared reference so that a kernel
+/// built once with `layer` sees the changes without rebuilding it:
+///
+/// ```
+/// # use flux_fov::{kernel, FluxField, Fov, Rect, Weather};
+/// let weather = Weather::new(0.0);
+/// let mut fov = Fov::new(Box::new(FluxField::new(8)), 8, kernel::Illumination::default());
+/// let mut kernel = kernel::soft_shadow(weather.layer((10, 10), |_, _| 0.0));
+/// fov.update(&mut kernel);
+/// assert_eq!(fov.at(8, 0).brightness, 1.0);
+/// weather.set_global(0.1);
+/// weather.add_region(Rect::new(0, 0, 20, 10), 0.5);
+/// fov.update(&mut kernel);
+/// assert!(fov.at(8, -1).brightness < 0.5);
+/// assert!(fov.at(0, -8).brightness < fov.at(0, 8).brightness);
+/// ```
+///
+#[derive(Debug, Default)]
+pub struct Weather {
+    global: Cell<f32>,
+    regions: RefCell<Vec<(Rect, f32)>>,
+}
+
+impl Weather {
+    /// Constructs weather with the given global opacity and no regions.
+    ///
+    pub fn new(global: f32) -> Self {
+        Weather {
+            global: Cell::new(global),
+            regions: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// The global opacity.
+    pub fn global(&self) -> f32 {
+        self.global.get()
+    }
+
+    /// Set the global opacity.
+    pub fn set_global(&self, opacity: f32) {
+        self.global.set(opacity);
+    }
+
+    /// Add a regional opacity covering the `region` of the map.  Returns the
+    /// index of the region for `set_region`.
+    ///
+    pub fn add_region(&self, region: Rect, opacity: f32) -> usize {
+        let mut regions = self.regions.borrow_mut();
+        regions.push((region, opacity));
+        regions.len() - 1
+    }
+
+    /// Change the opacity of the region with the given index.
+    ///
+    pub fn set_region(&self, index: usize, opacity: f32) {
+        self.regions.borrow_mut()[index].1 = opacity;
+    }
+
+    /// Remove all the regions.
+    pub fn clear_regions(&self) {
+        self.regions.borrow_mut().clear();
+    }
+
+    /// The opacity of the weather at the map cell (`x`, `y`).
+    ///
+    pub fn opacity(&self, x: i32, y: i32) -> f32 {
+        let transmittance = self
+            .regions
+            .borrow()
+            .iter()
+            .filter(|(region, _)| region.contains(x, y))
+            .fold(1.0 - self.global.get(), |t, &(_, opacity)| {
+                t * (1.0 - opacity)
+            });
+        1.0 - transmittance
+    }
+
+    /// Layer the weather on top of the per-cell `opacity` for use with the
+    /// standard kernels.
+    ///
+    /// The `opacity` is called with the coordinates relative to the origin
+    /// like the kernels are whereas the regions are in map coordinates; the
+    /// `origin` is the map cell of the origin of the field.
+    ///
+    pub fn layer<F>(&self, origin: (i32, i32), opacity: F) -> impl Fn(i32, i32) -> f32 + '_
+    where
+        F: Fn(i32, i32) -> f32 + 'static,
+    {
+        let (origin_x, origin_y) = origin;
+        move |x, y| {
+            let weather = self.opacity(origin_x + x, origin_y + y);
+            1.0 - (1.0 - weather) * (1.0 - opacity(x, y))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn layers_combine() {
+        let weather = Weather::new(0.5);
+        assert_eq!(weather.opacity(3, 3), 0.5);
+        let fog = weather.add_region(Rect::new(0, 0, 4, 4), 0.5);
+        weather.add_region(Rect::new(2, 2, 4, 4), 1.0);
+        assert_eq!(weather.opacity(1, 1), 0.75);
+        assert_eq!(weather.opacity(3, 3), 1.0);
+        assert_eq!(weather.opacity(9, 9), 0.5);
+        weather.set_region(fog, 0.0);
+        weather.set_global(0.0);
+        assert_eq!(weather.opacity(1, 1), 0.0);
+        let opacity = weather.layer((1, 1), |x, _| if x == 0 { 0.5 } else { 0.0 });
+        assert_eq!(opacity(0, 0), 0.5);
+        assert_eq!(opacity(1, 1), 1.0);
+        assert_eq!(opacity(-1, 0), 0.0);
+        weather.clear_regions();
+        assert_eq!(opacity(1, 1), 0.0);
+    }
+}
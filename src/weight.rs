@@ -0,0 +1,201 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use std::ops::{Add, Sub};
+
+use crate::sweep::sweep;
+use crate::{FluxField, Fov, Influx, FIXED_ONE};
+
+/// A type of influx weights; see `Fov::update_weighted`.
+///
+/// Implemented for `f32`, the weights of `Fov::update`, for `f64`, and for
+/// `u32`, the fixed-point weights of `Fov::update_fixed` with 16 fractional
+/// bits.  With the fixed-point weights the whole propagation runs in
+/// integer arithmetic and gives bit-identical results on every target as
+/// long as the update function does and the flux field is built with the
+/// `deterministic` feature.  The fixed-point weights are rounded from the
+/// floating point ones whose last bits otherwise depend on the platform's
+/// sine and cosine.
+///
+pub trait Weight: Copy + PartialOrd + Add<Output = Self> + Sub<Output = Self> {
+    /// The weight 0.0.
+    const ZERO: Self;
+    /// The weight 1.0.
+    const ONE: Self;
+
+    /// The weight of an influx given the weight stored in the flux field
+    /// both as a floating point number and as a fixed-point number with
+    /// `FIXED_ONE` standing for 1.0.  The fixed-point weights of the
+    /// influxes into a cell sum to exactly `FIXED_ONE`.
+    fn from_flux(weight: f32, fixed: u32) -> Self;
+
+    /// The product of two numbers in the units of the weight, e.g., the
+    /// part of the flux of a neighbour carried by an influx.
+    fn scale(self, value: Self) -> Self;
+}
+
+impl Weight for f32 {
+    const ZERO: f32 = 0.0;
+    const ONE: f32 = 1.0;
+
+    #[inline]
+    fn from_flux(weight: f32, _: u32) -> f32 {
+        weight
+    }
+
+    #[inline]
+    fn scale(self, value: f32) -> f32 {
+        self * value
+    }
+}
+
+impl Weight for f64 {
+    const ZERO: f64 = 0.0;
+    const ONE: f64 = 1.0;
+
+    #[inline]
+    fn from_flux(weight: f32, _: u32) -> f64 {
+        weight as f64
+    }
+
+    #[inline]
+    fn scale(self, value: f64) -> f64 {
+        self * value
+    }
+}
+
+impl Weight for u32 {
+    const ZERO: u32 = 0;
+    const ONE: u32 = FIXED_ONE;
+
+    #[inline]
+    fn from_flux(_: f32, fixed: u32) -> u32 {
+        fixed
+    }
+
+    #[inline]
+    fn scale(self, value: u32) -> u32 {
+        ((self as u64 * value as u64) >> 16) as u32
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Update the field of vision with influx weights of the type `W`.
+    ///
+    /// Works like `update` but the weights are converted to `W` first, so
+    /// the same update function can be written once for both the floating
+    /// point and the fixed-point weights:
+    ///
+    /// ```
+    /// # use flux_fov::{FluxField, Fov, Influx, Weight};
+    /// fn light<W: Weight>(x: i32, y: i32, influxes: &[Influx<&W, W>]) -> W {
+    ///     if x == 0 && y == 0 {
+    ///         W::ONE
+    ///     } else if x == 2 {
+    ///         W::ZERO
+    ///     } else {
+    ///         influxes
+    ///             .iter()
+    ///             .fold(W::ZERO, |sum, f| sum + f.weight.scale(*f.value))
+    ///     }
+    /// }
+    ///
+    /// let mut fixed = Fov::new(Box::new(FluxField::new(4)), 4, 0_u32);
+    /// fixed.update_weighted(light);
+    /// let mut float = Fov::new(Box::new(FluxField::new(4)), 4, 0.0_f32);
+    /// float.update_weighted(light);
+    /// assert_eq!(*fixed.at(1, 0), 1 << 16);
+    /// assert_eq!(*fixed.at(4, 0), 0);
+    /// assert!((*fixed.at(-3, 2) as f32 / 65536.0 - *float.at(-3, 2)).abs() < 1e-3);
+    /// ```
+    ///
+    pub fn update_weighted<W, F>(&mut self, update_fn: F)
+    where
+        W: Weight,
+        F: FnMut(i32, i32, &[Influx<&T, W>]) -> T,
+    {
+        let mut update_fn = update_fn;
+        let data = &mut self.data;
+        sweep(
+            self.radius,
+            self.width,
+            self.ix_origin,
+            self.flux_field.as_ref(),
+            |step| {
+                let value = {
+                    let influx = |ix: usize| {
+                        let tap = &step.taps()[ix];
+                        Influx {
+                            weight: W::from_flux(tap.weight, tap.fixed),
+                            dx: tap.dx,
+                            dy: tap.dy,
+                            value: &data[tap.ix],
+                        }
+                    };
+                    match step.taps().len() {
+                        0 => update_fn(step.x, step.y, &[]),
+                        1 => update_fn(step.x, step.y, &[influx(0)]),
+                        2 => update_fn(step.x, step.y, &[influx(0), influx(1)]),
+                        _ => update_fn(step.x, step.y, &[influx(0), influx(1), influx(2)]),
+                    }
+                };
+                data[step.ix] = value;
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::all_params;
+
+    #[test]
+    fn weighted_update_matches_update() {
+        for params in all_params() {
+            let flux_field = std::rc::Rc::new(FluxField::with_params(8, params));
+            let mut expected = Fov::new(flux_field.clone(), 8, 0.0f32);
+            expected.update(|x, y, influxes| {
+                if x == 0 && y == 0 {
+                    1.0
+                } else {
+                    influxes.iter().map(|f| f.weight * f.value).sum()
+                }
+            });
+            let mut actual = Fov::new(flux_field.clone(), 8, 0.0f32);
+            actual.update_weighted(|x, y, influxes: &[Influx<&f32, f32>]| {
+                if x == 0 && y == 0 {
+                    1.0
+                } else {
+                    influxes.iter().map(|f| f.weight * f.value).sum()
+                }
+            });
+            assert_eq!(actual.as_slice(), expected.as_slice());
+            // The fixed-point weights of a cell sum to exactly one.
+            let mut fixed = Fov::new(flux_field, 8, ());
+            fixed.update_weighted(|x, y, influxes: &[Influx<&(), u32>]| {
+                if x != 0 || y != 0 {
+                    let sum = influxes.iter().fold(u32::ZERO, |sum, f| sum + f.weight);
+                    assert_eq!(sum, u32::ONE);
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,169 @@
+// flux-fov -- Experimental field-of-vision calculator
+// Copyright (C) 2019 Matti Hänninen
+//
+// This program is free software: you can redistribute it and/or modify it
+// under the terms of the GNU General Public License as published by the Free
+// Software Foundation, either version 3 of the License, or (at your option)
+// any later version.
+//
+// This program is distributed in the hope that it will be useful, but WITHOUT
+// ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or
+// FITNESS FOR A PARTICULAR PURPOSE.  See the GNU General Public License for
+// more details.
+//
+// You should have received a copy of the GNU General Public License along
+// with this program.  If not, see <http://www.gnu.org/licenses/>.
+
+use crate::compose::for_each_tile;
+use crate::{FluxField, Fov};
+
+/// A map-sized grid combining the fields of vision of several observers in
+/// world coordinates; see `Fov::merge_into`.
+///
+/// For a party the grid can hold, e.g., whether anybody sees a tile or how
+/// many do.  One field of vision can be reused for all the observers:
+///
+/// ```
+/// # use flux_fov::{kernel, FluxField, Fov, WorldVisibility};
+/// let mut fov = Fov::new(Box::new(FluxField::new(4)), 4, kernel::Visibility::default());
+/// let mut seen = WorldVisibility::new(20, 10, false);
+/// for &(x, y) in &[(3, 3), (15, 6)] {
+///     fov.update(kernel::threshold(0.5, |_, _| 0.0));
+///     fov.merge_into(&mut seen, x, y, |seen, v| *seen |= v.is_visible);
+/// }
+/// assert!(*seen.at(1, 1) && *seen.at(18, 9));
+/// assert!(!*seen.at(10, 5));
+/// ```
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorldVisibility<V> {
+    width: usize,
+    height: usize,
+    data: Vec<V>,
+}
+
+impl<V> WorldVisibility<V>
+where
+    V: Clone,
+{
+    /// Constructs a `width` by `height` grid with every tile set to `init`.
+    ///
+    pub fn new(width: usize, height: usize, init: V) -> Self {
+        WorldVisibility {
+            width,
+            height,
+            data: vec![init; width * height],
+        }
+    }
+
+    /// Set every tile to `value` without reallocating, e.g., at the start of
+    /// every frame.
+    ///
+    pub fn fill(&mut self, value: V) {
+        for tile in self.data.iter_mut() {
+            *tile = value.clone();
+        }
+    }
+}
+
+impl<V> WorldVisibility<V> {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The value at the given tile.
+    pub fn at(&self, x: usize, y: usize) -> &V {
+        assert!(x < self.width && y < self.height, "tile out of range");
+        &self.data[y * self.width + x]
+    }
+
+    /// Expose the values of the tiles as a slice in row-major order.
+    pub fn as_slice(&self) -> &[V] {
+        self.data.as_slice()
+    }
+}
+
+impl<T, X> Fov<T, X>
+where
+    X: AsRef<FluxField>,
+{
+    /// Combine the field of vision centered at the tile (`origin_x`,
+    /// `origin_y`) into the grid.
+    ///
+    /// The `reduce` function is called for every cell of the field falling
+    /// on the grid with the value of the tile and that of the cell, and it
+    /// updates the tile in place, e.g., with `|seen, v| *seen |= v.is_visible`
+    /// for the union of what the observers see.
+    ///
+    pub fn merge_into<V, R>(
+        &self,
+        target: &mut WorldVisibility<V>,
+        origin_x: i32,
+        origin_y: i32,
+        reduce: R,
+    ) where
+        R: FnMut(&mut V, &T),
+    {
+        let mut reduce = reduce;
+        let data = &mut target.data;
+        for_each_tile(
+            target.width,
+            target.height,
+            origin_x,
+            origin_y,
+            self,
+            |ix, value| reduce(&mut data[ix], value),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kernel::{self, Visibility};
+
+    #[test]
+    fn party_visibility() {
+        // A wall at x = 6 splits the map.
+        let wall = |x: i32, _: i32| (x == 6) as u8 as f32;
+        let mut fov = Fov::new(Box::new(FluxField::new(5)), 5, Visibility::default());
+        let mut count = WorldVisibility::new(13, 5, 0_u8);
+        let party = [(2, 2), (4, 2), (10, 2)];
+        for _ in 0..2 {
+            count.fill(0);
+            for &(x, y) in &party {
+                fov.reset(Visibility::default());
+                fov.update(kernel::threshold(0.5, |dx, dy| wall(x + dx, y + dy)));
+                fov.merge_into(&mut count, x, y, |n, v| *n += v.is_visible as u8);
+            }
+            assert_eq!(*count.at(3, 2), 2);
+            assert_eq!(*count.at(6, 2), 3);
+            assert_eq!(*count.at(7, 2), 1);
+            assert_eq!(*count.at(12, 0), 1);
+            assert_eq!(*count.at(0, 4), 2);
+        }
+    }
+
+    #[test]
+    fn reset_reuses_the_buffer() {
+        let mut fov = Fov::new(Box::new(FluxField::new(2)), 2, 1);
+        let ptr = fov.as_slice().as_ptr();
+        fov.update(|x, y, _| x * y);
+        fov.reset(7);
+        assert!(fov.as_slice().iter().all(|&v| v == 7));
+        assert_eq!(fov.as_slice().as_ptr(), ptr);
+    }
+
+    #[test]
+    #[should_panic(expected = "tile out of range")]
+    fn at_out_of_range() {
+        // Within the map but past the right edge of the first row.
+        let seen = WorldVisibility::new(4, 3, false);
+        seen.at(4, 0);
+    }
+}